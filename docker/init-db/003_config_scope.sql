@@ -0,0 +1,3 @@
+-- 设备级配置，device_id 为空时按分组生效，group_id = 0 为全局默认
+ALTER TABLE config_history ADD COLUMN IF NOT EXISTS device_id VARCHAR(32);
+CREATE INDEX IF NOT EXISTS config_history_device_id_idx ON config_history (device_id);
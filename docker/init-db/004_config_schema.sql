@@ -0,0 +1,18 @@
+-- 配置模板：每个产品定义自己的配置字段（类型、范围、默认值），按字段顺序大端编码下发
+-- fields: [{"name": "interval", "type": "u8", "min": 1, "max": 60, "default": 10}, ...]
+CREATE TABLE IF NOT EXISTS config_schema (
+    id            SERIAL       PRIMARY KEY,
+    name          VARCHAR(255) NOT NULL,
+    fields        JSONB        NOT NULL,
+    created_at    TIMESTAMP    NOT NULL DEFAULT(NOW()),
+    updated_at    TIMESTAMP    NOT NULL DEFAULT(NOW())
+);
+
+-- schema_id 为空时按旧格式下发 interval / t_max / t_min / human
+ALTER TABLE config_history ADD COLUMN IF NOT EXISTS schema_id INTEGER REFERENCES config_schema (id);
+ALTER TABLE config_history ADD COLUMN IF NOT EXISTS params JSONB;
+
+DROP TRIGGER IF EXISTS config_schema_notify ON config_schema;
+CREATE TRIGGER config_schema_notify
+    AFTER INSERT OR UPDATE OR DELETE ON config_schema
+    FOR EACH ROW EXECUTE PROCEDURE ota_notify_change();
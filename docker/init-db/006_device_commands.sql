@@ -0,0 +1,21 @@
+-- 设备命令队列，设备下次连接时下发
+-- device_id 为空的是分组命令，设备上线时复制一份到设备名下，parent_id 指向分组命令
+-- status: queued -> delivered -> acked / failed
+CREATE TABLE IF NOT EXISTS device_commands (
+    id            SERIAL      PRIMARY KEY,
+    device_id     VARCHAR(32),
+    group_id      INTEGER,
+    parent_id     INTEGER     REFERENCES device_commands (id) ON DELETE CASCADE,
+    command       VARCHAR(32) NOT NULL,
+    param         INTEGER     NOT NULL DEFAULT 0,
+    status        VARCHAR(16) NOT NULL DEFAULT 'queued',
+    result        INTEGER,
+    delivered_at  TIMESTAMP,
+    acked_at      TIMESTAMP,
+    created_at    TIMESTAMP   NOT NULL DEFAULT(NOW()),
+    updated_at    TIMESTAMP   NOT NULL DEFAULT(NOW()),
+    CHECK (device_id IS NOT NULL OR group_id IS NOT NULL)
+);
+
+CREATE INDEX IF NOT EXISTS device_commands_device_id_idx ON device_commands (device_id, status);
+CREATE INDEX IF NOT EXISTS device_commands_group_id_idx ON device_commands (group_id, status);
@@ -14,8 +14,8 @@ echo "Removing existing pod/container names if present (safe to ignore errors)"
 podman pod rm -f "$POD" >/dev/null 2>&1 || true
 podman rm -f ota-database ota-backend ota-server >/dev/null 2>&1 || true
 
-echo "Creating pod '$POD' with host ports 20000 and 9999"
-podman pod create --name "$POD" -p 20000:20000 -p 9999:9999
+echo "Creating pod '$POD' with host ports 20000, 9999 and 9898"
+podman pod create --name "$POD" -p 20000:20000 -p 9999:9999 -p 9898:9898
 
 echo "Ensure volume exists: $VOLUME"
 podman volume inspect "$VOLUME" >/dev/null 2>&1 || podman volume create "$VOLUME"
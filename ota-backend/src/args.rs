@@ -1,4 +1,5 @@
 use clap::Parser;
+use ota_telemetry::LogFormat;
 
 #[derive(Parser)]
 #[clap(author, version, about)]
@@ -18,4 +19,12 @@ pub struct Cli {
     /// Listing Port
     #[clap(long, default_value = "20000")]
     pub port: u32,
+
+    /// Log output format
+    #[clap(long, value_enum, default_value = "text")]
+    pub log_format: LogFormat,
+
+    /// OTLP/HTTP collector for traces, e.g. http://localhost:4318
+    #[clap(long)]
+    pub otlp_endpoint: Option<String>,
 }
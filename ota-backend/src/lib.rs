@@ -1,4 +1,6 @@
 pub mod args;
+pub mod metrics;
+pub mod trace;
 
 /// LogicPi Logo
 pub const LOGO: &str = r"
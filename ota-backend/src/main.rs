@@ -1,26 +1,38 @@
 use actix_cors::Cors;
-use actix_web::middleware::Logger;
+use actix_web::middleware::from_fn;
 use actix_web::{http::header, web, App, HttpServer};
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use dotenv::dotenv;
 
 use ota_backend::LOGO;
 
-use log::info;
-use ota_backend::args::Cli;
-use ota_database::{db::Database, routes::total::apis};
+use ota_backend::{args::Cli, metrics, trace};
+use ota_database::{db::Database, events::ChangeEvents, routes::total::apis};
+use ota_telemetry::{init_tracing, LogFormat, TelemetryOptions};
 use std::env;
+use tracing::info;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     // Get Parameters
     let cli = Cli::parse();
 
-    if std::env::var_os("RUST_LOG").is_none() {
-        std::env::set_var("RUST_LOG", "actix_web=debug");
-    }
     dotenv().ok();
-    env_logger::init();
+
+    // 日志和链路追踪
+    let log_format = env::var("LOG_FORMAT")
+        .ok()
+        .and_then(|v| LogFormat::from_str(&v, true).ok())
+        .unwrap_or(cli.log_format);
+    let otlp_endpoint = env::var("OTLP_ENDPOINT").ok().or(cli.otlp_endpoint.clone());
+    let telemetry = init_tracing(&TelemetryOptions {
+        service: "ota-backend",
+        filter_env: "RUST_LOG",
+        default_filter: "info,actix_web=debug",
+        format: log_format,
+        otlp_endpoint: otlp_endpoint.as_deref(),
+    })
+    .map_err(std::io::Error::other)?;
 
     // print logo
     println!("{}", LOGO);
@@ -39,7 +51,12 @@ async fn main() -> std::io::Result<()> {
     let server = format!("0.0.0.0:{}", port);
     info!("Server listening on {}", &server);
 
+    // 数据库变化通知，推送给 ota-server
+    let events = ChangeEvents::new();
+    tokio::spawn(events.clone().listen(db.pool.clone()));
+
     let db_data = web::Data::new(db);
+    let events_data = web::Data::new(events);
 
     HttpServer::new(move || {
         let cors = Cors::default()
@@ -53,11 +70,18 @@ async fn main() -> std::io::Result<()> {
             .supports_credentials();
         App::new()
             .wrap(cors)
-            .wrap(Logger::default())
+            .wrap(from_fn(trace::trace_requests))
+            .wrap(from_fn(metrics::track_requests))
             .app_data(db_data.clone())
+            .app_data(events_data.clone())
+            .service(metrics::index)
             .service(apis())
     })
     .bind(server)?
     .run()
-    .await
+    .await?;
+
+    telemetry.shutdown();
+
+    Ok(())
 }
@@ -0,0 +1,111 @@
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    get,
+    middleware::Next,
+    web, Error, HttpResponse, Responder,
+};
+use ota_database::db::Database;
+use prometheus::{Encoder, HistogramOpts, HistogramVec, IntGauge, Registry, TextEncoder};
+use std::{sync::LazyLock, time::Instant};
+use tracing::error;
+
+/// 后端指标，通过 `/metrics` 提供给 Prometheus
+pub struct Metrics {
+    registry: Registry,
+    /// 请求耗时，按方法、路由和状态码区分
+    pub requests: HistogramVec,
+    /// 连接池当前连接数
+    pub pool_size: IntGauge,
+    /// 连接池空闲连接数
+    pub pool_idle: IntGauge,
+    /// 连接池最大连接数
+    pub pool_max: IntGauge,
+}
+
+static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+/// 全局指标
+pub fn metrics() -> &'static Metrics {
+    &METRICS
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new_custom(Some("ota_backend".to_string()), None).unwrap();
+
+        let requests = HistogramVec::new(
+            HistogramOpts::new("http_request_duration_seconds", "Request latency by route"),
+            &["method", "route", "status"],
+        )
+        .unwrap();
+        let pool_size = IntGauge::new("db_pool_connections", "Open database connections").unwrap();
+        let pool_idle =
+            IntGauge::new("db_pool_idle_connections", "Idle database connections").unwrap();
+        let pool_max =
+            IntGauge::new("db_pool_max_connections", "Maximum database connections").unwrap();
+
+        registry.register(Box::new(requests.clone())).unwrap();
+        registry.register(Box::new(pool_size.clone())).unwrap();
+        registry.register(Box::new(pool_idle.clone())).unwrap();
+        registry.register(Box::new(pool_max.clone())).unwrap();
+
+        Metrics {
+            registry,
+            requests,
+            pool_size,
+            pool_idle,
+            pool_max,
+        }
+    }
+
+    /// 更新连接池使用情况
+    pub fn observe_pool(&self, db: &Database) {
+        self.pool_size.set(db.pool.size() as i64);
+        self.pool_idle.set(db.pool.num_idle() as i64);
+        self.pool_max
+            .set(db.pool.options().get_max_connections() as i64);
+    }
+
+    /// Prometheus 文本格式
+    pub fn encode(&self) -> String {
+        let mut buffer = Vec::new();
+        if let Err(e) = TextEncoder::new().encode(&self.registry.gather(), &mut buffer) {
+            error!("Metrics encode error: {}", e);
+        }
+        String::from_utf8(buffer).unwrap_or_default()
+    }
+}
+
+/// 请求耗时中间件，按路由模板统计，未匹配的路由归为 unmatched
+pub async fn track_requests(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let start = Instant::now();
+    let method = req.method().to_string();
+    let route = req
+        .match_pattern()
+        .unwrap_or_else(|| "unmatched".to_string());
+
+    let res = next.call(req).await;
+    let status = match &res {
+        Ok(res) => res.status(),
+        Err(e) => e.as_response_error().status_code(),
+    };
+
+    metrics()
+        .requests
+        .with_label_values(&[&method, &route, status.as_str()])
+        .observe(start.elapsed().as_secs_f64());
+
+    res
+}
+
+#[get("/metrics")]
+pub async fn index(db: web::Data<Database>) -> impl Responder {
+    metrics().observe_pool(&db);
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(metrics().encode())
+}
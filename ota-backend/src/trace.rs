@@ -0,0 +1,60 @@
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    middleware::Next,
+    Error,
+};
+use ota_telemetry::link_parent;
+use std::{collections::HashMap, time::Instant};
+use tracing::{field, info, info_span, Instrument};
+
+/// 传递 trace context 的请求头
+const TRACE_HEADERS: [&str; 2] = ["traceparent", "tracestate"];
+
+/// 每个请求一个 span，请求头带有 trace context 时接到上游的 trace 中
+pub async fn trace_requests(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let route = req
+        .match_pattern()
+        .unwrap_or_else(|| "unmatched".to_string());
+    let method = req.method().to_string();
+    let path = req.path().to_string();
+    let span = info_span!(
+        "http_request",
+        method = %method,
+        route = %route,
+        path = %path,
+        status = field::Empty,
+    );
+
+    let headers: HashMap<String, String> = TRACE_HEADERS
+        .iter()
+        .filter_map(|key| {
+            let value = req.headers().get(*key)?.to_str().ok()?;
+            Some((key.to_string(), value.to_string()))
+        })
+        .collect();
+    link_parent(&span, &headers);
+
+    let start = Instant::now();
+    let res = next.call(req).instrument(span.clone()).await;
+    let status = match &res {
+        Ok(res) => res.status(),
+        Err(e) => e.as_response_error().status_code(),
+    };
+
+    span.record("status", status.as_u16());
+    span.in_scope(|| {
+        info!(
+            "{} {} {} {:?}",
+            method,
+            path,
+            status.as_u16(),
+            start.elapsed()
+        )
+    });
+
+    res
+}
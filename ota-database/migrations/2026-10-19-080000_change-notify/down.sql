@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TRIGGER IF EXISTS firmware_data_notify ON firmware_data;
+DROP TRIGGER IF EXISTS config_history_notify ON config_history;
+DROP FUNCTION IF EXISTS ota_notify_change();
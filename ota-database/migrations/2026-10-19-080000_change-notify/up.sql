@@ -0,0 +1,28 @@
+-- 固件和配置变化时通过 pg_notify 通知后端
+-- 通道：ota_changes，内容：{"table": "...", "op": "INSERT|UPDATE|DELETE", "id": 1}
+
+CREATE OR REPLACE FUNCTION ota_notify_change() RETURNS trigger AS $$
+DECLARE
+    row_id INTEGER;
+BEGIN
+    IF (TG_OP = 'DELETE') THEN
+        row_id := OLD.id;
+    ELSE
+        row_id := NEW.id;
+    END IF;
+
+    PERFORM pg_notify(
+        'ota_changes',
+        json_build_object('table', TG_TABLE_NAME, 'op', TG_OP, 'id', row_id)::text
+    );
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER firmware_data_notify
+    AFTER INSERT OR UPDATE OR DELETE ON firmware_data
+    FOR EACH ROW EXECUTE PROCEDURE ota_notify_change();
+
+CREATE TRIGGER config_history_notify
+    AFTER INSERT OR UPDATE OR DELETE ON config_history
+    FOR EACH ROW EXECUTE PROCEDURE ota_notify_change();
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS config_history_device_id_idx;
+ALTER TABLE config_history DROP COLUMN IF EXISTS device_id;
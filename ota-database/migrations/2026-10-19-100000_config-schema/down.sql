@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+DROP TRIGGER IF EXISTS config_schema_notify ON config_schema;
+ALTER TABLE config_history DROP COLUMN IF EXISTS params;
+ALTER TABLE config_history DROP COLUMN IF EXISTS schema_id;
+DROP TABLE IF EXISTS config_schema;
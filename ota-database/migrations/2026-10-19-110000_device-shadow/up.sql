@@ -0,0 +1,13 @@
+-- 设备影子：期望配置由后台设置，实际配置由设备上报，两者的差异需要下发
+CREATE TABLE IF NOT EXISTS device_shadow (
+    id                   SERIAL      PRIMARY KEY,
+    device_id            VARCHAR(32) NOT NULL UNIQUE,
+    desired_schema_id    INTEGER     REFERENCES config_schema (id),
+    desired              JSONB,
+    desired_at           TIMESTAMP,
+    reported_schema_id   INTEGER     REFERENCES config_schema (id),
+    reported             JSONB,
+    reported_at          TIMESTAMP,
+    created_at           TIMESTAMP   NOT NULL DEFAULT(NOW()),
+    updated_at           TIMESTAMP   NOT NULL DEFAULT(NOW())
+);
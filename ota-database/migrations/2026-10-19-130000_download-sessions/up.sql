@@ -0,0 +1,25 @@
+-- 固件下载会话统计，下载结束或超时后由 ota-server 写入
+-- outcome: success / failed / abandoned
+CREATE TABLE IF NOT EXISTS download_sessions (
+    id                  SERIAL        PRIMARY KEY,
+    device_id           VARCHAR(32),
+    peer                VARCHAR(64),
+    fwcode              INTEGER       NOT NULL,
+    version_m           INTEGER       NOT NULL,
+    version_n           INTEGER       NOT NULL,
+    version_l           INTEGER       NOT NULL,
+    slice_size          INTEGER       NOT NULL DEFAULT 0,
+    slices_served       INTEGER       NOT NULL DEFAULT 0,
+    slices_repeated     INTEGER       NOT NULL DEFAULT 0,
+    bytes_served        BIGINT        NOT NULL DEFAULT 0,
+    started_at          TIMESTAMP     NOT NULL,
+    ended_at            TIMESTAMP     NOT NULL,
+    duration_ms         BIGINT        NOT NULL DEFAULT 0,
+    throughput          DOUBLE PRECISION NOT NULL DEFAULT 0,
+    outcome             VARCHAR(16)   NOT NULL,
+    upgrade_history_id  INTEGER       REFERENCES upgrade_history (id) ON DELETE SET NULL,
+    created_at          TIMESTAMP     NOT NULL DEFAULT(NOW())
+);
+
+CREATE INDEX IF NOT EXISTS download_sessions_device_id_idx ON download_sessions (device_id);
+CREATE INDEX IF NOT EXISTS download_sessions_history_idx ON download_sessions (upgrade_history_id);
@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+-- 不可逆：转换后的固件不再转回 base64
@@ -0,0 +1,10 @@
+-- 固件内容统一以原始字节保存
+-- 早期上传的固件以 base64 文本保存：长度为 4 * ceil(fwsize / 3) 而不是 fwsize，且只含 base64 字符
+-- 原始字节的长度等于 fwsize，不会被误判
+UPDATE firmware_data
+SET fwdata = decode(encode(fwdata, 'escape'), 'base64'),
+    updated_at = NOW()
+WHERE fwsize > 0
+  AND octet_length(fwdata) <> fwsize
+  AND octet_length(fwdata) = 4 * ((fwsize + 2) / 3)
+  AND encode(fwdata, 'escape') ~ '^[A-Za-z0-9+/]*={0,2}$';
@@ -0,0 +1,8 @@
+-- 分组命令复制给同一设备只保留一份，防止设备并发查询时重复复制
+DELETE FROM device_commands a
+USING device_commands b
+WHERE a.parent_id = b.parent_id
+  AND a.device_id = b.device_id
+  AND a.id > b.id;
+
+CREATE UNIQUE INDEX IF NOT EXISTS device_commands_parent_device_idx ON device_commands (parent_id, device_id);
@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE firmware_data DROP COLUMN IF EXISTS hash;
@@ -0,0 +1,4 @@
+-- 固件内容的md5在写入时保存，查询元数据时不再逐个计算
+ALTER TABLE firmware_data ADD COLUMN IF NOT EXISTS hash VARCHAR(32) NOT NULL DEFAULT '';
+
+UPDATE firmware_data SET hash = md5(fwdata);
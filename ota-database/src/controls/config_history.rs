@@ -1,30 +1,67 @@
 use crate::{
+    controls::config_schema::check_params,
     db::Database,
     models::{
         basic::CrudOperations,
-        config_history::{ConfigHistory, NewConfigHistory, UpdateConfigHistory},
+        config_history::{ConfigHistory, ConfigQuery, NewConfigHistory, UpdateConfigHistory},
     },
 };
+use serde_json::Value;
 
 use actix_web::{delete, get, patch, post, web, Error, HttpResponse};
 
+/// 查询配置，可按 group_id / device_id 过滤
 #[get("")]
 pub async fn index(
+    query: web::Query<ConfigQuery>,
     db: web::Data<Database>,
 ) -> Result<HttpResponse, Error> {
-    let items: Vec<ConfigHistory> = <ConfigHistory as CrudOperations<ConfigHistory, NewConfigHistory, UpdateConfigHistory>>::all(&db.pool)
+    let items: Vec<ConfigHistory> = ConfigHistory::filter(&query, &db.pool)
         .await
         .map_err(actix_web::error::ErrorInternalServerError)?;
 
     Ok(HttpResponse::Ok().json(items))
 }
 
+/// 设备实际生效的配置：设备 -> 分组 -> 全局默认
+#[get("/effective")]
+pub async fn effective(
+    query: web::Query<ConfigQuery>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, Error> {
+    let items: Vec<ConfigHistory> = <ConfigHistory as CrudOperations<ConfigHistory, NewConfigHistory, UpdateConfigHistory>>::all(&db.pool)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    match ConfigHistory::resolve(&items, query.device_id.as_deref(), query.group_id) {
+        Some(item) => Ok(HttpResponse::Ok().json(item)),
+        None => Ok(HttpResponse::NotFound().finish()),
+    }
+}
+
+/// 有模板时校验参数，没有参数按空对象处理
+async fn check_schema_params(
+    schema_id: Option<i32>,
+    params: Option<Value>,
+    db: &Database,
+) -> Result<Option<Value>, Error> {
+    let Some(schema_id) = schema_id else {
+        return Ok(params);
+    };
+
+    let params = params.unwrap_or_else(|| Value::Object(Default::default()));
+    Ok(Some(check_params(schema_id, &params, db).await?))
+}
+
 #[post("")]
 pub async fn create(
     db: web::Data<Database>,
     payload: web::Json<NewConfigHistory>,
 ) -> Result<HttpResponse, Error> {
-    let item: ConfigHistory = <ConfigHistory as CrudOperations<ConfigHistory, NewConfigHistory, UpdateConfigHistory>>::create(payload.into_inner(), &db.pool)
+    let mut payload = payload.into_inner();
+    payload.params = check_schema_params(payload.schema_id, payload.params.take(), &db).await?;
+
+    let item: ConfigHistory = <ConfigHistory as CrudOperations<ConfigHistory, NewConfigHistory, UpdateConfigHistory>>::create(payload, &db.pool)
         .await
         .map_err(actix_web::error::ErrorInternalServerError)?;
 
@@ -49,7 +86,10 @@ pub async fn update(
     payload: web::Json<UpdateConfigHistory>,
     db: web::Data<Database>,
 ) -> Result<HttpResponse, Error> {
-    let item: ConfigHistory = <ConfigHistory as CrudOperations<ConfigHistory, NewConfigHistory, UpdateConfigHistory>>::update(id.into_inner(), payload.into_inner(), &db.pool)
+    let mut payload = payload.into_inner();
+    payload.params = check_schema_params(payload.schema_id, payload.params.take(), &db).await?;
+
+    let item: ConfigHistory = <ConfigHistory as CrudOperations<ConfigHistory, NewConfigHistory, UpdateConfigHistory>>::update(id.into_inner(), payload, &db.pool)
         .await
         .map_err(actix_web::error::ErrorInternalServerError)?;
 
@@ -0,0 +1,86 @@
+use crate::{
+    db::Database,
+    models::{
+        basic::CrudOperations,
+        config_schema::{ConfigSchema, NewConfigSchema, UpdateConfigSchema},
+    },
+};
+
+use actix_web::{delete, get, patch, post, web, Error, HttpResponse};
+use serde_json::Value;
+
+/// 按配置模板校验参数，返回补全默认值并规范化后的参数
+/// 模板不存在或参数不符合模板时返回 400
+pub(crate) async fn check_params(schema_id: i32, params: &Value, db: &Database) -> Result<Value, Error> {
+    let schema: ConfigSchema = <ConfigSchema as CrudOperations<ConfigSchema, NewConfigSchema, UpdateConfigSchema>>::find(schema_id, &db.pool)
+        .await
+        .map_err(actix_web::error::ErrorBadRequest)?;
+
+    schema
+        .validate(params)
+        .map_err(actix_web::error::ErrorBadRequest)
+}
+
+#[get("")]
+pub async fn index(
+    db: web::Data<Database>,
+) -> Result<HttpResponse, Error> {
+    let items: Vec<ConfigSchema> = <ConfigSchema as CrudOperations<ConfigSchema, NewConfigSchema, UpdateConfigSchema>>::all(&db.pool)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(items))
+}
+
+#[post("")]
+pub async fn create(
+    db: web::Data<Database>,
+    payload: web::Json<NewConfigSchema>,
+) -> Result<HttpResponse, Error> {
+    ConfigSchema::check_fields(&payload.fields).map_err(actix_web::error::ErrorBadRequest)?;
+
+    let item: ConfigSchema = <ConfigSchema as CrudOperations<ConfigSchema, NewConfigSchema, UpdateConfigSchema>>::create(payload.into_inner(), &db.pool)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(item))
+}
+
+#[get("/{id}")]
+pub async fn find(
+    id: web::Path<i32>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, Error> {
+    let item: ConfigSchema = <ConfigSchema as CrudOperations<ConfigSchema, NewConfigSchema, UpdateConfigSchema>>::find(id.into_inner(), &db.pool)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(item))
+}
+
+#[patch("/{id}")]
+pub async fn update(
+    id: web::Path<i32>,
+    payload: web::Json<UpdateConfigSchema>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, Error> {
+    ConfigSchema::check_fields(&payload.fields).map_err(actix_web::error::ErrorBadRequest)?;
+
+    let item: ConfigSchema = <ConfigSchema as CrudOperations<ConfigSchema, NewConfigSchema, UpdateConfigSchema>>::update(id.into_inner(), payload.into_inner(), &db.pool)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(item))
+}
+
+#[delete("/{id}")]
+pub async fn delete(
+    id: web::Path<i32>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, Error> {
+    let result: u64 = <ConfigSchema as CrudOperations<ConfigSchema, NewConfigSchema, UpdateConfigSchema>>::delete(id.into_inner(), &db.pool)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(result))
+}
@@ -0,0 +1,108 @@
+use crate::{
+    db::Database,
+    models::device_command::{
+        CommandProgress, CommandQuery, DeviceCommand, NewDeviceCommand, UpdateCommandStatus,
+    },
+};
+
+use actix_web::{delete, get, patch, post, web, Error, HttpResponse};
+use serde::Deserialize;
+
+/// 设备查询待执行命令的参数
+#[derive(Debug, Deserialize)]
+pub struct PendingQuery {
+    pub device_id: String,
+    pub group_id: Option<i32>,
+}
+
+/// 查询命令，可按 device_id / group_id / status 过滤
+#[get("")]
+pub async fn index(
+    query: web::Query<CommandQuery>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, Error> {
+    let items: Vec<DeviceCommand> = DeviceCommand::filter(&query, &db.pool)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(items))
+}
+
+/// 下发命令到设备或分组
+#[post("")]
+pub async fn create(
+    db: web::Data<Database>,
+    payload: web::Json<NewDeviceCommand>,
+) -> Result<HttpResponse, Error> {
+    if payload.device_id.is_none() && payload.group_id.is_none() {
+        return Err(actix_web::error::ErrorBadRequest(
+            "device_id or group_id is required",
+        ));
+    }
+
+    let item: DeviceCommand = DeviceCommand::create(&payload, &db.pool)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(item))
+}
+
+/// 设备待执行的命令，由 ota-server 调用
+#[get("/pending")]
+pub async fn pending(
+    query: web::Query<PendingQuery>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, Error> {
+    let items: Vec<DeviceCommand> =
+        DeviceCommand::pending(&query.device_id, query.group_id, &db.pool)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(items))
+}
+
+#[get("/{id}")]
+pub async fn find(id: web::Path<i32>, db: web::Data<Database>) -> Result<HttpResponse, Error> {
+    let item: DeviceCommand = DeviceCommand::find(id.into_inner(), &db.pool)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(item))
+}
+
+/// 更新命令状态，由 ota-server 调用，状态不允许变化时返回 409
+#[patch("/{id}")]
+pub async fn update(
+    id: web::Path<i32>,
+    payload: web::Json<UpdateCommandStatus>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, Error> {
+    let item: Option<DeviceCommand> =
+        DeviceCommand::update_status(id.into_inner(), &payload, &db.pool)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    match item {
+        Some(item) => Ok(HttpResponse::Ok().json(item)),
+        None => Err(actix_web::error::ErrorConflict("invalid status transition")),
+    }
+}
+
+/// 分组命令的执行进度
+#[get("/{id}/progress")]
+pub async fn progress(id: web::Path<i32>, db: web::Data<Database>) -> Result<HttpResponse, Error> {
+    let item: CommandProgress = DeviceCommand::progress(id.into_inner(), &db.pool)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(item))
+}
+
+#[delete("/{id}")]
+pub async fn delete(id: web::Path<i32>, db: web::Data<Database>) -> Result<HttpResponse, Error> {
+    let result: u64 = DeviceCommand::delete(id.into_inner(), &db.pool)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(result))
+}
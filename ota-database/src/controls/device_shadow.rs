@@ -0,0 +1,82 @@
+use crate::{
+    controls::config_schema::check_params,
+    db::Database,
+    models::device_shadow::{DesiredConfig, DeviceShadow, ReportedConfig, ShadowQuery, ShadowState},
+};
+
+use actix_web::{delete, get, post, put, web, Error, HttpResponse};
+
+/// 查询设备影子，`?out_of_sync=true` 只返回期望与上报不一致的设备
+#[get("")]
+pub async fn index(
+    query: web::Query<ShadowQuery>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, Error> {
+    let items: Vec<ShadowState> = DeviceShadow::all(&db.pool)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+        .into_iter()
+        .filter(|shadow| !query.out_of_sync || !shadow.in_sync())
+        .map(ShadowState::from)
+        .collect();
+
+    Ok(HttpResponse::Ok().json(items))
+}
+
+#[get("/{device_id}")]
+pub async fn find(
+    device_id: web::Path<String>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, Error> {
+    let item: DeviceShadow = DeviceShadow::find(&device_id, &db.pool)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(ShadowState::from(item)))
+}
+
+/// 设置期望配置
+#[put("/{device_id}/desired")]
+pub async fn desired(
+    device_id: web::Path<String>,
+    payload: web::Json<DesiredConfig>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, Error> {
+    let mut payload = payload.into_inner();
+    payload.desired = check_params(payload.schema_id, &payload.desired, &db).await?;
+
+    let item: DeviceShadow = DeviceShadow::set_desired(&device_id, &payload, &db.pool)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(ShadowState::from(item)))
+}
+
+/// 设备上报实际配置，由 ota-server 调用，返回差异
+#[post("/{device_id}/reported")]
+pub async fn reported(
+    device_id: web::Path<String>,
+    payload: web::Json<ReportedConfig>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, Error> {
+    let mut payload = payload.into_inner();
+    payload.reported = check_params(payload.schema_id, &payload.reported, &db).await?;
+
+    let item: DeviceShadow = DeviceShadow::report(&device_id, &payload, &db.pool)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(ShadowState::from(item)))
+}
+
+#[delete("/{device_id}")]
+pub async fn delete(
+    device_id: web::Path<String>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, Error> {
+    let result: u64 = DeviceShadow::delete(&device_id, &db.pool)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(result))
+}
@@ -0,0 +1,56 @@
+use crate::{
+    db::Database,
+    models::download_session::{DownloadSession, DownloadSessionQuery, NewDownloadSession},
+};
+
+use actix_web::{delete, get, post, web, Error, HttpResponse};
+
+/// 查询下载会话，可按 device_id / fwcode / outcome / upgrade_history_id 过滤
+#[get("")]
+pub async fn index(
+    query: web::Query<DownloadSessionQuery>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, Error> {
+    let items: Vec<DownloadSession> = DownloadSession::filter(&query, &db.pool)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(items))
+}
+
+/// 记录下载会话，由 ota-server 调用
+#[post("")]
+pub async fn create(
+    db: web::Data<Database>,
+    payload: web::Json<NewDownloadSession>,
+) -> Result<HttpResponse, Error> {
+    let item: DownloadSession = DownloadSession::create(&payload, &db.pool)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(item))
+}
+
+#[get("/{id}")]
+pub async fn find(
+    id: web::Path<i32>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, Error> {
+    let item: DownloadSession = DownloadSession::find(id.into_inner(), &db.pool)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(item))
+}
+
+#[delete("/{id}")]
+pub async fn delete(
+    id: web::Path<i32>,
+    db: web::Data<Database>,
+) -> Result<HttpResponse, Error> {
+    let result: u64 = DownloadSession::delete(id.into_inner(), &db.pool)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(result))
+}
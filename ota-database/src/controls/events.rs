@@ -0,0 +1,40 @@
+use crate::events::{ChangeEvent, ChangeEvents};
+
+use actix_web::{get, http::header, web, web::Bytes, Error, HttpResponse};
+use futures::stream;
+use tokio::{
+    sync::broadcast::error::RecvError,
+    time::{timeout, Duration},
+};
+
+/// 心跳间隔，防止代理断开空闲连接
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+/// 生成SSE事件，事件名为表名
+fn sse_event(event: &ChangeEvent) -> String {
+    let data = serde_json::to_string(event).unwrap_or_default();
+    format!("event: {}\ndata: {}\n\n", event.table, data)
+}
+
+/// 数据变化事件流 (Server-Sent Events)
+/// - event: firmware_data / config_history
+/// - event: resync，订阅者处理太慢丢失了事件，需要全量刷新
+#[get("")]
+pub async fn index(events: web::Data<ChangeEvents>) -> HttpResponse {
+    let rx = events.subscribe();
+
+    let body = stream::unfold(rx, |mut rx| async move {
+        let chunk = match timeout(KEEP_ALIVE, rx.recv()).await {
+            Ok(Ok(event)) => sse_event(&event),
+            Ok(Err(RecvError::Lagged(missed))) => format!("event: resync\ndata: {}\n\n", missed),
+            Ok(Err(RecvError::Closed)) => return None,
+            Err(_) => String::from(": ping\n\n"),
+        };
+        Some((Ok::<_, Error>(Bytes::from(chunk)), rx))
+    });
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        .streaming(body)
+}
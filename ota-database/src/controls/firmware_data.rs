@@ -2,7 +2,7 @@ use crate::{
     db::Database,
     models::{
         basic::CrudOperations,
-        firmware_data::{FirmwareData, NewFirmwareData, UpdateFirmwareData},
+        firmware_data::{FirmwareData, FirmwareMeta, NewFirmwareData, UpdateFirmwareData},
     },
 };
 
@@ -19,6 +19,17 @@ pub async fn index(
     Ok(HttpResponse::Ok().json(items))
 }
 
+#[get("/meta")]
+pub async fn meta(
+    db: web::Data<Database>,
+) -> Result<HttpResponse, Error> {
+    let items: Vec<FirmwareMeta> = FirmwareData::all_meta(&db.pool)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(items))
+}
+
 #[post("")]
 pub async fn create(
     db: web::Data<Database>,
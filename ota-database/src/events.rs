@@ -0,0 +1,103 @@
+use log::{debug, error, info};
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::PgListener;
+use std::fmt;
+use tokio::{
+    sync::broadcast,
+    time::{self, Duration},
+};
+
+use crate::db::DbPool;
+
+/// pg_notify 通道名，见 migrations/*_change-notify
+pub const CHANGE_CHANNEL: &str = "ota_changes";
+
+/// 发生变化的表
+pub const TABLE_FIRMWARE_DATA: &str = "firmware_data";
+pub const TABLE_CONFIG_HISTORY: &str = "config_history";
+pub const TABLE_CONFIG_SCHEMA: &str = "config_schema";
+
+/// 数据变化事件
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct ChangeEvent {
+    pub table: String,
+    pub op: String,
+    pub id: i32,
+}
+
+/// 格式化打印
+impl fmt::Display for ChangeEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ChangeEvent -> {} {} id:{}", self.table, self.op, self.id)
+    }
+}
+
+/// 数据变化广播
+#[derive(Debug, Clone)]
+pub struct ChangeEvents {
+    tx: broadcast::Sender<ChangeEvent>,
+}
+
+impl Default for ChangeEvents {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ChangeEvents {
+    pub fn new() -> Self {
+        let (tx, _) = broadcast::channel(256);
+        ChangeEvents { tx }
+    }
+
+    /// 订阅变化事件
+    pub fn subscribe(&self) -> broadcast::Receiver<ChangeEvent> {
+        self.tx.subscribe()
+    }
+
+    /// 监听数据库通知并广播，连接断开后自动重连
+    pub async fn listen(self, pool: DbPool) {
+        let retry_duration = Duration::from_secs(5);
+
+        loop {
+            let mut listener = match PgListener::connect_with(&pool).await {
+                Ok(listener) => listener,
+                Err(e) => {
+                    error!("Failed to connect listener: {}", e);
+                    time::sleep(retry_duration).await;
+                    continue;
+                }
+            };
+
+            if let Err(e) = listener.listen(CHANGE_CHANNEL).await {
+                error!("Failed to listen on {}: {}", CHANGE_CHANNEL, e);
+                time::sleep(retry_duration).await;
+                continue;
+            }
+            info!("Listening on channel {}", CHANGE_CHANNEL);
+
+            loop {
+                match listener.recv().await {
+                    Ok(notification) => {
+                        match serde_json::from_str::<ChangeEvent>(notification.payload()) {
+                            Ok(event) => {
+                                debug!("{}", event);
+                                // 没有订阅者时发送失败，忽略
+                                let _ = self.tx.send(event);
+                            }
+                            Err(e) => {
+                                error!("Invalid notification {}: {}", notification.payload(), e);
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        error!("Listener error: {}", e);
+                        break;
+                    }
+                }
+            }
+
+            time::sleep(retry_duration).await;
+        }
+    }
+}
@@ -1,6 +1,5 @@
 use std::sync::Arc;
 
-use base64::{engine::general_purpose, Engine};
 use log::{debug, error, info};
 use reqwest::Error;
 use tokio::{
@@ -8,7 +7,10 @@ use tokio::{
     time::{self, Duration},
 };
 
-use crate::models::{config_history::ConfigHistory, firmware_data::FirmwareData};
+use crate::models::{
+    config_history::ConfigHistory,
+    firmware_data::{FirmwareData, FirmwareMeta},
+};
 
 /// 获取最新的配置
 pub fn get_latest_config(configs: &Vec<ConfigHistory>) -> Option<&ConfigHistory> {
@@ -32,12 +34,15 @@ pub async fn read_config_from_pg(fw_server: &str) -> Result<Vec<ConfigHistory>,
                 let new_data = ConfigHistory {
                     id: one_data.id,
                     group_id: one_data.group_id,
+                    device_id: one_data.device_id,
                     op_code: one_data.op_code,
                     sync_ts: one_data.sync_ts,
                     interval: one_data.interval,
                     t_max: one_data.t_max,
                     t_min: one_data.t_min,
                     human: one_data.human,
+                    schema_id: one_data.schema_id,
+                    params: one_data.params,
                     created_at: one_data.created_at,
                     updated_at: one_data.updated_at,
                 };
@@ -65,22 +70,11 @@ pub async fn read_all_fw_from_pg(fw_server: &str) -> Result<Vec<FirmwareData>, E
             let fw_datas: Vec<FirmwareData> = response.json().await?;
             debug!("Found {} firmware files.", fw_datas.len());
 
-            for fw_data in fw_datas {
+            // 固件内容以原始字节保存，不需要解码
+            for fw_data in &fw_datas {
                 debug!("Downloading... {}", fw_data);
-                let new_data = FirmwareData {
-                    id: fw_data.id,
-                    fwcode: fw_data.fwcode,
-                    version_m: fw_data.version_m,
-                    version_n: fw_data.version_n,
-                    version_l: fw_data.version_l,
-                    fwdata: general_purpose::STANDARD.decode(&fw_data.fwdata).unwrap(),
-                    fwsize: fw_data.fwsize,
-                    created_at: fw_data.created_at,
-                    updated_at: fw_data.updated_at,
-                };
-
-                result_data.push(new_data);
             }
+            result_data = fw_datas;
         }
         Err(e) => {
             error!("Error:{}, fw_server={}", e, fw_server);
@@ -90,6 +84,36 @@ pub async fn read_all_fw_from_pg(fw_server: &str) -> Result<Vec<FirmwareData>, E
     Ok(result_data)
 }
 
+/// 从postgres数据库读取所有固件的元数据（不含固件内容）
+pub async fn read_fw_meta_from_pg(fw_server: &str) -> Result<Vec<FirmwareMeta>, Error> {
+    let client = reqwest::Client::new();
+    let response = client
+        .get(format!("{}/firmware/meta", fw_server))
+        .send()
+        .await?
+        .error_for_status()?;
+
+    let metas: Vec<FirmwareMeta> = response.json().await?;
+    debug!("Found {} firmware metas.", metas.len());
+
+    Ok(metas)
+}
+
+/// 从postgres数据库读取单个固件
+pub async fn read_fw_from_pg(fw_server: &str, id: i32) -> Result<FirmwareData, Error> {
+    let client = reqwest::Client::new();
+    let response = client
+        .get(format!("{}/firmware/{}", fw_server, id))
+        .send()
+        .await?
+        .error_for_status()?;
+
+    let fw_data: FirmwareData = response.json().await?;
+    debug!("Downloading... {}", fw_data);
+
+    Ok(fw_data)
+}
+
 /// 定时刷新固件数据
 /// ## 参数
 /// - fw_server   : 服务器地址
@@ -1,5 +1,6 @@
 pub mod controls;
 pub mod db;
+pub mod events;
 pub mod from_pg;
 pub mod middleware;
 pub mod models;
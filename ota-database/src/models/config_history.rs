@@ -4,6 +4,7 @@ use crate::db::DatabaseError;
 use crate::models::basic::{CrudOperations, HasId};
 use chrono::{NaiveDateTime, Utc};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use sqlx::{FromRow, PgPool};
 
 use super::basic::random_i32;
@@ -12,16 +13,79 @@ use super::basic::random_i32;
 pub struct ConfigHistory {
     pub id: i32,
     pub group_id: i32,
+    #[serde(default)]
+    pub device_id: Option<String>,
     pub op_code: i32,
     pub sync_ts: NaiveDateTime,
     pub interval: i32,
     pub t_max: i32,
     pub t_min: i32,
     pub human: bool,
+    #[serde(default)]
+    pub schema_id: Option<i32>,
+    #[serde(default)]
+    pub params: Option<Value>,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
 }
 
+/// 全局默认配置的分组号
+pub const GLOBAL_GROUP: i32 = 0;
+
+/// 配置查询条件
+#[derive(Debug, Deserialize, Serialize, Default, PartialEq, Clone)]
+pub struct ConfigQuery {
+    pub group_id: Option<i32>,
+    pub device_id: Option<String>,
+}
+
+impl ConfigHistory {
+    /// 按照 设备 -> 分组 -> 全局默认 的顺序查找生效的配置，同一级取最新的一条
+    pub fn resolve<'a>(
+        configs: &'a [ConfigHistory],
+        device_id: Option<&str>,
+        group_id: Option<i32>,
+    ) -> Option<&'a ConfigHistory> {
+        let latest = |pred: &dyn Fn(&ConfigHistory) -> bool| {
+            configs.iter().filter(|c| pred(c)).max_by_key(|c| c.id)
+        };
+
+        device_id
+            .and_then(|target| {
+                latest(&|c| {
+                    c.device_id
+                        .as_deref()
+                        .is_some_and(|d| d.eq_ignore_ascii_case(target))
+                })
+            })
+            .or_else(|| {
+                group_id
+                    .and_then(|target| latest(&|c| c.device_id.is_none() && c.group_id == target))
+            })
+            .or_else(|| latest(&|c| c.device_id.is_none() && c.group_id == GLOBAL_GROUP))
+    }
+
+    /// 按条件过滤配置
+    pub async fn filter(
+        query: &ConfigQuery,
+        pool: &PgPool,
+    ) -> Result<Vec<ConfigHistory>, DatabaseError> {
+        let items = sqlx::query_as::<_, ConfigHistory>(
+            r#"
+            SELECT * FROM config_history
+            WHERE ($1::INTEGER IS NULL OR group_id = $1)
+              AND ($2::VARCHAR IS NULL OR UPPER(device_id) = UPPER($2))
+            ORDER BY id
+            "#,
+        )
+        .bind(query.group_id)
+        .bind(&query.device_id)
+        .fetch_all(pool)
+        .await?;
+        Ok(items)
+    }
+}
+
 impl HasId for ConfigHistory {
     fn id(&self) -> i32 {
         self.id
@@ -33,8 +97,13 @@ impl fmt::Display for ConfigHistory {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "ConfigHistory -> Group:{:08X}, OpCode:{:08X}, Interval:{}, Tmax:{}, Tmin:{}",
-            self.group_id, self.op_code, self.interval, self.t_max, self.t_min,
+            "ConfigHistory -> Group:{:08X}, Device:{}, OpCode:{:08X}, Interval:{}, Tmax:{}, Tmin:{}",
+            self.group_id,
+            self.device_id.as_deref().unwrap_or("-"),
+            self.op_code,
+            self.interval,
+            self.t_max,
+            self.t_min,
         )
     }
 }
@@ -42,24 +111,37 @@ impl fmt::Display for ConfigHistory {
 #[derive(Debug, Deserialize, Serialize, Default, PartialEq, Clone)]
 pub struct NewConfigHistory {
     pub group_id: i32,
+    #[serde(default)]
+    pub device_id: Option<String>,
     pub op_code: i32,
     pub sync_ts: NaiveDateTime,
+    #[serde(default)]
     pub interval: i32,
+    #[serde(default)]
     pub t_max: i32,
+    #[serde(default)]
     pub t_min: i32,
+    #[serde(default)]
     pub human: bool,
+    #[serde(default)]
+    pub schema_id: Option<i32>,
+    #[serde(default)]
+    pub params: Option<Value>,
 }
 
 impl NewConfigHistory {
     pub fn random() -> Self {
         NewConfigHistory {
             group_id: random_i32(),
+            device_id: None,
             op_code: random_i32(),
             sync_ts: Utc::now().naive_utc(),
             interval: random_i32(),
             t_max: random_i32(),
             t_min: random_i32(),
             human: false,
+            schema_id: None,
+            params: None,
         }
     }
 }
@@ -78,12 +160,22 @@ impl fmt::Display for NewConfigHistory {
 #[derive(Debug, Deserialize, Serialize, Default, Clone, PartialEq)]
 pub struct UpdateConfigHistory {
     pub group_id: i32,
+    #[serde(default)]
+    pub device_id: Option<String>,
     pub op_code: i32,
     pub sync_ts: NaiveDateTime,
+    #[serde(default)]
     pub interval: i32,
+    #[serde(default)]
     pub t_max: i32,
+    #[serde(default)]
     pub t_min: i32,
+    #[serde(default)]
     pub human: bool,
+    #[serde(default)]
+    pub schema_id: Option<i32>,
+    #[serde(default)]
+    pub params: Option<Value>,
     pub updated_at: Option<NaiveDateTime>,
 }
 
@@ -91,12 +183,15 @@ impl UpdateConfigHistory {
     pub fn random() -> Self {
         UpdateConfigHistory {
             group_id: random_i32(),
+            device_id: None,
             op_code: random_i32(),
             sync_ts: Utc::now().naive_utc(),
             interval: random_i32(),
             t_max: random_i32(),
             t_min: random_i32(),
             human: false,
+            schema_id: None,
+            params: None,
             updated_at: Some(Utc::now().naive_utc()),
         }
     }
@@ -134,18 +229,21 @@ impl CrudOperations<ConfigHistory, NewConfigHistory, UpdateConfigHistory> for Co
     async fn create(data: NewConfigHistory, pool: &PgPool) -> Result<ConfigHistory, DatabaseError> {
         let result = sqlx::query_as::<_, ConfigHistory>(
             r#"
-            INSERT INTO config_history (group_id, op_code, sync_ts, interval, t_max, t_min, human)
-            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            INSERT INTO config_history (group_id, device_id, op_code, sync_ts, interval, t_max, t_min, human, schema_id, params)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
             RETURNING *
             "#,
         )
         .bind(data.group_id)
+        .bind(&data.device_id)
         .bind(data.op_code)
         .bind(data.sync_ts)
         .bind(data.interval)
         .bind(data.t_max)
         .bind(data.t_min)
         .bind(data.human)
+        .bind(data.schema_id)
+        .bind(&data.params)
         .fetch_one(pool)
         .await?;
         Ok(result)
@@ -159,18 +257,22 @@ impl CrudOperations<ConfigHistory, NewConfigHistory, UpdateConfigHistory> for Co
         let result = sqlx::query_as::<_, ConfigHistory>(
             r#"
             UPDATE config_history
-            SET group_id = $1, op_code = $2, sync_ts = $3, interval = $4, t_max = $5, t_min = $6, human = $7, updated_at = $8
-            WHERE id = $9
+            SET group_id = $1, device_id = $2, op_code = $3, sync_ts = $4, interval = $5, t_max = $6, t_min = $7, human = $8,
+                schema_id = $9, params = $10, updated_at = $11
+            WHERE id = $12
             RETURNING *
             "#,
         )
         .bind(data.group_id)
+        .bind(&data.device_id)
         .bind(data.op_code)
         .bind(data.sync_ts)
         .bind(data.interval)
         .bind(data.t_max)
         .bind(data.t_min)
         .bind(data.human)
+        .bind(data.schema_id)
+        .bind(&data.params)
         .bind(data.updated_at.unwrap_or_else(|| Utc::now().naive_utc()))
         .bind(id)
         .fetch_one(pool)
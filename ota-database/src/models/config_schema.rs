@@ -0,0 +1,329 @@
+use std::{collections::HashSet, fmt};
+
+use crate::db::DatabaseError;
+use crate::models::basic::{CrudOperations, HasId};
+use chrono::{NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sqlx::{types::Json, FromRow, PgPool};
+use thiserror::Error;
+
+/// 参数校验、编码错误
+#[derive(Error, Debug, PartialEq)]
+pub enum SchemaError {
+    #[error("Params must be a JSON object")]
+    NotObject,
+    #[error("Duplicate field: {0}")]
+    DuplicateField(String),
+    #[error("Unknown field: {0}")]
+    UnknownField(String),
+    #[error("Missing field: {0}")]
+    MissingField(String),
+    #[error("Invalid value for {0}")]
+    InvalidValue(String),
+    #[error("Data too short, expected {expected} bytes, got {actual}")]
+    Truncated { expected: usize, actual: usize },
+    #[error("Value of {field} out of range [{min}, {max}]: {value}")]
+    OutOfRange {
+        field: String,
+        value: i64,
+        min: i64,
+        max: i64,
+    },
+}
+
+/// 字段类型，下行时按大端编码
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldType {
+    U8,
+    U16,
+    U32,
+    I8,
+    I16,
+    I32,
+    Bool,      // 1字节，0x01 / 0x00
+    Timestamp, // 4字节，unix 秒
+}
+
+impl FieldType {
+    /// 编码长度
+    pub fn size(&self) -> usize {
+        match self {
+            FieldType::U8 | FieldType::I8 | FieldType::Bool => 1,
+            FieldType::U16 | FieldType::I16 => 2,
+            FieldType::U32 | FieldType::I32 | FieldType::Timestamp => 4,
+        }
+    }
+
+    /// 类型本身的取值范围
+    pub fn range(&self) -> (i64, i64) {
+        match self {
+            FieldType::U8 => (0, u8::MAX as i64),
+            FieldType::U16 => (0, u16::MAX as i64),
+            FieldType::U32 | FieldType::Timestamp => (0, u32::MAX as i64),
+            FieldType::I8 => (i8::MIN as i64, i8::MAX as i64),
+            FieldType::I16 => (i16::MIN as i64, i16::MAX as i64),
+            FieldType::I32 => (i32::MIN as i64, i32::MAX as i64),
+            FieldType::Bool => (0, 1),
+        }
+    }
+}
+
+/// 配置字段定义
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct SchemaField {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub field_type: FieldType,
+    #[serde(default)]
+    pub min: Option<i64>,
+    #[serde(default)]
+    pub max: Option<i64>,
+    #[serde(default)]
+    pub default: Option<Value>,
+}
+
+impl SchemaField {
+    /// 合并类型范围和字段范围
+    fn range(&self) -> (i64, i64) {
+        let (min, max) = self.field_type.range();
+        (
+            self.min.map_or(min, |v| v.max(min)),
+            self.max.map_or(max, |v| v.min(max)),
+        )
+    }
+
+    /// JSON 值转换为整数并检查范围
+    /// - bool 接受 true/false
+    /// - timestamp 接受 unix 秒或 "YYYY-MM-DDTHH:MM:SS"
+    pub fn to_i64(&self, value: &Value) -> Result<i64, SchemaError> {
+        let invalid = || SchemaError::InvalidValue(self.name.clone());
+
+        let number = match (self.field_type, value) {
+            (FieldType::Bool, Value::Bool(b)) => *b as i64,
+            (FieldType::Timestamp, Value::String(s)) => s
+                .parse::<NaiveDateTime>()
+                .map_err(|_| invalid())?
+                .and_utc()
+                .timestamp(),
+            (FieldType::Bool, _) => return Err(invalid()),
+            (_, Value::Number(n)) => n.as_i64().ok_or_else(invalid)?,
+            _ => return Err(invalid()),
+        };
+
+        let (min, max) = self.range();
+        if number < min || number > max {
+            return Err(SchemaError::OutOfRange {
+                field: self.name.clone(),
+                value: number,
+                min,
+                max,
+            });
+        }
+
+        Ok(number)
+    }
+
+    /// 整数转换为规范化的 JSON 值
+    fn canonical(&self, number: i64) -> Value {
+        match self.field_type {
+            FieldType::Bool => Value::Bool(number != 0),
+            _ => Value::from(number),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Default, FromRow, Clone)]
+pub struct ConfigSchema {
+    pub id: i32,
+    pub name: String,
+    pub fields: Json<Vec<SchemaField>>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+impl HasId for ConfigSchema {
+    fn id(&self) -> i32 {
+        self.id
+    }
+}
+
+/// 格式化打印
+impl fmt::Display for ConfigSchema {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "ConfigSchema -> Id:{}, Name:{}, Fields:{}",
+            self.id,
+            self.name,
+            self.fields.len()
+        )
+    }
+}
+
+impl ConfigSchema {
+    /// 检查字段定义：名称不重复，默认值符合范围
+    pub fn check_fields(fields: &[SchemaField]) -> Result<(), SchemaError> {
+        let mut names = HashSet::new();
+
+        for field in fields {
+            if !names.insert(field.name.as_str()) {
+                return Err(SchemaError::DuplicateField(field.name.clone()));
+            }
+            if let Some(default) = &field.default {
+                field.to_i64(default)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// 校验参数，缺少的字段使用默认值，返回补全并规范化后的参数
+    /// 规范化后 bool 为 true/false，其它类型（包括 timestamp）都是整数，可以直接比较
+    pub fn validate(&self, params: &Value) -> Result<Value, SchemaError> {
+        let params = params.as_object().ok_or(SchemaError::NotObject)?;
+
+        if let Some(name) = params
+            .keys()
+            .find(|name| !self.fields.iter().any(|field| &field.name == *name))
+        {
+            return Err(SchemaError::UnknownField(name.clone()));
+        }
+
+        let mut result = Map::new();
+        for field in self.fields.iter() {
+            let value = params
+                .get(&field.name)
+                .or(field.default.as_ref())
+                .ok_or_else(|| SchemaError::MissingField(field.name.clone()))?;
+            result.insert(field.name.clone(), field.canonical(field.to_i64(value)?));
+        }
+
+        Ok(Value::Object(result))
+    }
+
+    /// 按字段顺序编码参数
+    pub fn encode(&self, params: &Value) -> Result<Vec<u8>, SchemaError> {
+        let params = self.validate(params)?;
+        let mut data = Vec::new();
+
+        for field in self.fields.iter() {
+            let number = field.to_i64(&params[&field.name])?;
+            let bytes = number.to_be_bytes();
+            data.extend_from_slice(&bytes[bytes.len() - field.field_type.size()..]);
+        }
+
+        Ok(data)
+    }
+
+    /// 编码后的长度
+    pub fn encoded_len(&self) -> usize {
+        self.fields.iter().map(|field| field.field_type.size()).sum()
+    }
+
+    /// 按字段顺序解码设备上报的参数
+    pub fn decode(&self, data: &[u8]) -> Result<Value, SchemaError> {
+        let expected = self.encoded_len();
+        if data.len() < expected {
+            return Err(SchemaError::Truncated {
+                expected,
+                actual: data.len(),
+            });
+        }
+
+        let mut result = Map::new();
+        let mut offset = 0;
+        for field in self.fields.iter() {
+            let bytes = &data[offset..offset + field.field_type.size()];
+            offset += bytes.len();
+
+            let unsigned = bytes.iter().fold(0u64, |acc, b| acc << 8 | *b as u64);
+            let number = match field.field_type {
+                FieldType::I8 => unsigned as u8 as i8 as i64,
+                FieldType::I16 => unsigned as u16 as i16 as i64,
+                FieldType::I32 => unsigned as u32 as i32 as i64,
+                _ => unsigned as i64,
+            };
+            result.insert(field.name.clone(), field.canonical(number));
+        }
+
+        Ok(Value::Object(result))
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Default, PartialEq, Clone)]
+pub struct NewConfigSchema {
+    pub name: String,
+    pub fields: Json<Vec<SchemaField>>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Default, Clone, PartialEq)]
+pub struct UpdateConfigSchema {
+    pub name: String,
+    pub fields: Json<Vec<SchemaField>>,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+#[async_trait::async_trait]
+impl CrudOperations<ConfigSchema, NewConfigSchema, UpdateConfigSchema> for ConfigSchema {
+    async fn all(pool: &PgPool) -> Result<Vec<ConfigSchema>, DatabaseError> {
+        let items = sqlx::query_as::<_, ConfigSchema>("SELECT * FROM config_schema")
+            .fetch_all(pool)
+            .await?;
+        Ok(items)
+    }
+
+    async fn find(target_id: i32, pool: &PgPool) -> Result<ConfigSchema, DatabaseError> {
+        let result = sqlx::query_as::<_, ConfigSchema>("SELECT * FROM config_schema WHERE id = $1")
+            .bind(target_id)
+            .fetch_one(pool)
+            .await?;
+        Ok(result)
+    }
+
+    async fn create(data: NewConfigSchema, pool: &PgPool) -> Result<ConfigSchema, DatabaseError> {
+        let result = sqlx::query_as::<_, ConfigSchema>(
+            r#"
+            INSERT INTO config_schema (name, fields)
+            VALUES ($1, $2)
+            RETURNING *
+            "#,
+        )
+        .bind(data.name)
+        .bind(data.fields)
+        .fetch_one(pool)
+        .await?;
+        Ok(result)
+    }
+
+    async fn update(
+        id: i32,
+        data: UpdateConfigSchema,
+        pool: &PgPool,
+    ) -> Result<ConfigSchema, DatabaseError> {
+        let result = sqlx::query_as::<_, ConfigSchema>(
+            r#"
+            UPDATE config_schema
+            SET name = $1, fields = $2, updated_at = $3
+            WHERE id = $4
+            RETURNING *
+            "#,
+        )
+        .bind(data.name)
+        .bind(data.fields)
+        .bind(data.updated_at.unwrap_or_else(|| Utc::now().naive_utc()))
+        .bind(id)
+        .fetch_one(pool)
+        .await?;
+        Ok(result)
+    }
+
+    async fn delete(id: i32, pool: &PgPool) -> Result<u64, DatabaseError> {
+        let result = sqlx::query("DELETE FROM config_schema WHERE id = $1")
+            .bind(id)
+            .execute(pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+}
@@ -0,0 +1,285 @@
+use std::fmt;
+
+use crate::db::DatabaseError;
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool};
+
+/// 设备命令
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone, Copy, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "VARCHAR", rename_all = "snake_case")]
+pub enum CommandKind {
+    Reboot = 0x01,        // 重启
+    FactoryReset = 0x02,  // 恢复出厂设置
+    CheckFirmware = 0x03, // 立即检查固件
+    UploadLogs = 0x04,    // 上传日志
+}
+
+/// 命令状态：queued -> delivered -> acked / failed
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone, Copy, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "VARCHAR", rename_all = "snake_case")]
+pub enum CommandStatus {
+    Queued,
+    Delivered,
+    Acked,
+    Failed,
+}
+
+impl CommandStatus {
+    /// 状态只能按 queued -> delivered -> acked / failed 前进
+    pub fn can_become(self, next: CommandStatus) -> bool {
+        matches!(
+            (self, next),
+            (CommandStatus::Queued, CommandStatus::Delivered)
+                | (CommandStatus::Delivered, CommandStatus::Acked)
+                | (CommandStatus::Delivered, CommandStatus::Failed)
+        )
+    }
+}
+
+/// 命令队列
+/// - device_id 不为空：发给单个设备
+/// - device_id 为空：发给分组，设备上线时复制一份到设备名下（parent_id 指向分组命令）
+#[derive(Deserialize, Serialize, Debug, PartialEq, FromRow, Clone)]
+pub struct DeviceCommand {
+    pub id: i32,
+    pub device_id: Option<String>,
+    pub group_id: Option<i32>,
+    pub parent_id: Option<i32>,
+    pub command: CommandKind,
+    pub param: i32,
+    pub status: CommandStatus,
+    pub result: Option<i32>,
+    pub delivered_at: Option<NaiveDateTime>,
+    pub acked_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+/// 格式化打印
+impl fmt::Display for DeviceCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "DeviceCommand -> Id:{}, Device:{}, Group:{:?}, Command:{:?}, Param:{}, Status:{:?}",
+            self.id,
+            self.device_id.as_deref().unwrap_or("-"),
+            self.group_id,
+            self.command,
+            self.param,
+            self.status,
+        )
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
+pub struct NewDeviceCommand {
+    #[serde(default)]
+    pub device_id: Option<String>,
+    #[serde(default)]
+    pub group_id: Option<i32>,
+    pub command: CommandKind,
+    #[serde(default)]
+    pub param: i32,
+}
+
+/// 更新命令状态，由 ota-server 调用
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
+pub struct UpdateCommandStatus {
+    pub status: CommandStatus,
+    #[serde(default)]
+    pub result: Option<i32>,
+}
+
+/// 分组命令的执行进度，按复制给各设备的命令统计
+#[derive(Debug, Deserialize, Serialize, Default, PartialEq, Eq, Clone, FromRow)]
+pub struct CommandProgress {
+    pub queued: i64,
+    pub delivered: i64,
+    pub acked: i64,
+    pub failed: i64,
+}
+
+/// 命令查询条件
+#[derive(Debug, Deserialize, Serialize, Default, PartialEq, Clone)]
+pub struct CommandQuery {
+    pub device_id: Option<String>,
+    pub group_id: Option<i32>,
+    pub status: Option<CommandStatus>,
+}
+
+impl DeviceCommand {
+    pub async fn filter(
+        query: &CommandQuery,
+        pool: &PgPool,
+    ) -> Result<Vec<DeviceCommand>, DatabaseError> {
+        let items = sqlx::query_as::<_, DeviceCommand>(
+            r#"
+            SELECT * FROM device_commands
+            WHERE ($1::VARCHAR IS NULL OR UPPER(device_id) = UPPER($1))
+              AND ($2::INTEGER IS NULL OR group_id = $2)
+              AND ($3::VARCHAR IS NULL OR status = $3)
+            ORDER BY id
+            "#,
+        )
+        .bind(&query.device_id)
+        .bind(query.group_id)
+        .bind(query.status)
+        .fetch_all(pool)
+        .await?;
+        Ok(items)
+    }
+
+    pub async fn find(id: i32, pool: &PgPool) -> Result<DeviceCommand, DatabaseError> {
+        let result =
+            sqlx::query_as::<_, DeviceCommand>("SELECT * FROM device_commands WHERE id = $1")
+                .bind(id)
+                .fetch_one(pool)
+                .await?;
+        Ok(result)
+    }
+
+    pub async fn create(
+        data: &NewDeviceCommand,
+        pool: &PgPool,
+    ) -> Result<DeviceCommand, DatabaseError> {
+        let result = sqlx::query_as::<_, DeviceCommand>(
+            r#"
+            INSERT INTO device_commands (device_id, group_id, command, param)
+            VALUES (UPPER($1), $2, $3, $4)
+            RETURNING *
+            "#,
+        )
+        .bind(&data.device_id)
+        .bind(data.group_id)
+        .bind(data.command)
+        .bind(data.param)
+        .fetch_one(pool)
+        .await?;
+        Ok(result)
+    }
+
+    /// 设备待执行的命令
+    /// 同时把分组中尚未复制给该设备的命令复制一份，并发查询时由唯一索引去重
+    pub async fn pending(
+        device_id: &str,
+        group_id: Option<i32>,
+        pool: &PgPool,
+    ) -> Result<Vec<DeviceCommand>, DatabaseError> {
+        if let Some(group_id) = group_id {
+            sqlx::query(
+                r#"
+                INSERT INTO device_commands (device_id, group_id, parent_id, command, param)
+                SELECT UPPER($1), g.group_id, g.id, g.command, g.param
+                FROM device_commands g
+                WHERE g.device_id IS NULL AND g.group_id = $2
+                  AND g.status IN ('queued', 'delivered')
+                  AND NOT EXISTS (
+                      SELECT 1 FROM device_commands d
+                      WHERE d.parent_id = g.id AND d.device_id = UPPER($1)
+                  )
+                ON CONFLICT (parent_id, device_id) DO NOTHING
+                "#,
+            )
+            .bind(device_id)
+            .bind(group_id)
+            .execute(pool)
+            .await?;
+        }
+
+        let items = sqlx::query_as::<_, DeviceCommand>(
+            r#"
+            SELECT * FROM device_commands
+            WHERE device_id = UPPER($1) AND status IN ('queued', 'delivered')
+            ORDER BY id
+            "#,
+        )
+        .bind(device_id)
+        .fetch_all(pool)
+        .await?;
+        Ok(items)
+    }
+
+    /// 更新命令状态，只允许 queued -> delivered -> acked / failed，不允许时返回 None
+    /// 复制给设备的命令送达时，分组命令同时标记为已送达
+    pub async fn update_status(
+        id: i32,
+        data: &UpdateCommandStatus,
+        pool: &PgPool,
+    ) -> Result<Option<DeviceCommand>, DatabaseError> {
+        let mut tx = pool.begin().await?;
+
+        let current = sqlx::query_as::<_, DeviceCommand>(
+            "SELECT * FROM device_commands WHERE id = $1 FOR UPDATE",
+        )
+        .bind(id)
+        .fetch_one(&mut *tx)
+        .await?;
+        if !current.status.can_become(data.status) {
+            return Ok(None);
+        }
+
+        let result = sqlx::query_as::<_, DeviceCommand>(
+            r#"
+            UPDATE device_commands
+            SET status = $1,
+                result = COALESCE($2, result),
+                delivered_at = CASE WHEN $1 = 'delivered' THEN NOW() ELSE delivered_at END,
+                acked_at = CASE WHEN $1 IN ('acked', 'failed') THEN NOW() ELSE acked_at END,
+                updated_at = NOW()
+            WHERE id = $3
+            RETURNING *
+            "#,
+        )
+        .bind(data.status)
+        .bind(data.result)
+        .bind(id)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        if let (Some(parent_id), CommandStatus::Delivered) = (result.parent_id, data.status) {
+            sqlx::query(
+                r#"
+                UPDATE device_commands
+                SET status = 'delivered', delivered_at = NOW(), updated_at = NOW()
+                WHERE id = $1 AND status = 'queued'
+                "#,
+            )
+            .bind(parent_id)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(Some(result))
+    }
+
+    /// 分组命令的执行进度
+    pub async fn progress(id: i32, pool: &PgPool) -> Result<CommandProgress, DatabaseError> {
+        let result = sqlx::query_as::<_, CommandProgress>(
+            r#"
+            SELECT COUNT(*) FILTER (WHERE status = 'queued') AS queued,
+                   COUNT(*) FILTER (WHERE status = 'delivered') AS delivered,
+                   COUNT(*) FILTER (WHERE status = 'acked') AS acked,
+                   COUNT(*) FILTER (WHERE status = 'failed') AS failed
+            FROM device_commands
+            WHERE parent_id = $1
+            "#,
+        )
+        .bind(id)
+        .fetch_one(pool)
+        .await?;
+        Ok(result)
+    }
+
+    /// 取消命令，分组命令连同复制给设备的命令一起删除
+    pub async fn delete(id: i32, pool: &PgPool) -> Result<u64, DatabaseError> {
+        let result = sqlx::query("DELETE FROM device_commands WHERE id = $1 OR parent_id = $1")
+            .bind(id)
+            .execute(pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+}
@@ -0,0 +1,175 @@
+use std::fmt;
+
+use crate::db::DatabaseError;
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sqlx::{FromRow, PgPool};
+
+/// 设备影子：期望配置和设备上报的实际配置
+#[derive(Deserialize, Serialize, Debug, PartialEq, Default, FromRow, Clone)]
+#[serde(default)]
+pub struct DeviceShadow {
+    pub id: i32,
+    pub device_id: String,
+    pub desired_schema_id: Option<i32>,
+    pub desired: Option<Value>,
+    pub desired_at: Option<NaiveDateTime>,
+    pub reported_schema_id: Option<i32>,
+    pub reported: Option<Value>,
+    pub reported_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+/// 格式化打印
+impl fmt::Display for DeviceShadow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "DeviceShadow -> Device:{}, Desired:{:?}, Reported:{:?}, Delta:{}",
+            self.device_id,
+            self.desired_schema_id,
+            self.reported_schema_id,
+            self.delta().len()
+        )
+    }
+}
+
+/// 设置期望配置
+#[derive(Debug, Deserialize, Serialize, Default, PartialEq, Clone)]
+pub struct DesiredConfig {
+    pub schema_id: i32,
+    pub desired: Value,
+}
+
+/// 设备上报的实际配置
+#[derive(Debug, Deserialize, Serialize, Default, PartialEq, Clone)]
+pub struct ReportedConfig {
+    pub schema_id: i32,
+    pub reported: Value,
+}
+
+/// 带差异的设备影子，API 返回使用
+#[derive(Debug, Deserialize, Serialize, Default, PartialEq, Clone)]
+pub struct ShadowState {
+    #[serde(flatten)]
+    pub shadow: DeviceShadow,
+    pub delta: Map<String, Value>,
+}
+
+impl From<DeviceShadow> for ShadowState {
+    fn from(shadow: DeviceShadow) -> Self {
+        ShadowState {
+            delta: shadow.delta(),
+            shadow,
+        }
+    }
+}
+
+/// 设备影子查询条件
+#[derive(Debug, Deserialize, Serialize, Default, PartialEq, Clone)]
+pub struct ShadowQuery {
+    #[serde(default)]
+    pub out_of_sync: bool,
+}
+
+impl DeviceShadow {
+    /// 期望配置中与上报不一致的字段，值为期望值
+    /// 模板不同时所有期望字段都视为不一致
+    pub fn delta(&self) -> Map<String, Value> {
+        let Some(Value::Object(desired)) = &self.desired else {
+            return Map::new();
+        };
+
+        let reported = match &self.reported {
+            Some(Value::Object(reported)) if self.reported_schema_id == self.desired_schema_id => {
+                Some(reported)
+            }
+            _ => None,
+        };
+
+        desired
+            .iter()
+            .filter(|(name, value)| reported.and_then(|r| r.get(*name)) != Some(value))
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect()
+    }
+
+    /// 期望配置和上报一致
+    pub fn in_sync(&self) -> bool {
+        self.delta().is_empty()
+    }
+
+    pub async fn all(pool: &PgPool) -> Result<Vec<DeviceShadow>, DatabaseError> {
+        let items =
+            sqlx::query_as::<_, DeviceShadow>("SELECT * FROM device_shadow ORDER BY device_id")
+                .fetch_all(pool)
+                .await?;
+        Ok(items)
+    }
+
+    pub async fn find(device_id: &str, pool: &PgPool) -> Result<DeviceShadow, DatabaseError> {
+        let result = sqlx::query_as::<_, DeviceShadow>(
+            "SELECT * FROM device_shadow WHERE UPPER(device_id) = UPPER($1)",
+        )
+        .bind(device_id)
+        .fetch_one(pool)
+        .await?;
+        Ok(result)
+    }
+
+    /// 设置期望配置，设备不存在时创建
+    pub async fn set_desired(
+        device_id: &str,
+        data: &DesiredConfig,
+        pool: &PgPool,
+    ) -> Result<DeviceShadow, DatabaseError> {
+        let result = sqlx::query_as::<_, DeviceShadow>(
+            r#"
+            INSERT INTO device_shadow (device_id, desired_schema_id, desired, desired_at)
+            VALUES (UPPER($1), $2, $3, NOW())
+            ON CONFLICT (device_id) DO UPDATE
+            SET desired_schema_id = $2, desired = $3, desired_at = NOW(), updated_at = NOW()
+            RETURNING *
+            "#,
+        )
+        .bind(device_id)
+        .bind(data.schema_id)
+        .bind(&data.desired)
+        .fetch_one(pool)
+        .await?;
+        Ok(result)
+    }
+
+    /// 记录设备上报的配置，设备不存在时创建
+    pub async fn report(
+        device_id: &str,
+        data: &ReportedConfig,
+        pool: &PgPool,
+    ) -> Result<DeviceShadow, DatabaseError> {
+        let result = sqlx::query_as::<_, DeviceShadow>(
+            r#"
+            INSERT INTO device_shadow (device_id, reported_schema_id, reported, reported_at)
+            VALUES (UPPER($1), $2, $3, NOW())
+            ON CONFLICT (device_id) DO UPDATE
+            SET reported_schema_id = $2, reported = $3, reported_at = NOW(), updated_at = NOW()
+            RETURNING *
+            "#,
+        )
+        .bind(device_id)
+        .bind(data.schema_id)
+        .bind(&data.reported)
+        .fetch_one(pool)
+        .await?;
+        Ok(result)
+    }
+
+    pub async fn delete(device_id: &str, pool: &PgPool) -> Result<u64, DatabaseError> {
+        let result = sqlx::query("DELETE FROM device_shadow WHERE UPPER(device_id) = UPPER($1)")
+            .bind(device_id)
+            .execute(pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+}
@@ -0,0 +1,180 @@
+use std::fmt;
+
+use crate::db::DatabaseError;
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool};
+
+/// 下载结果
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone, Copy, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "VARCHAR", rename_all = "snake_case")]
+pub enum DownloadOutcome {
+    Success,   // 设备上报升级成功
+    Failed,    // 设备上报升级失败
+    Abandoned, // 超时未结束
+}
+
+/// 固件下载会话统计
+#[derive(Deserialize, Serialize, Debug, PartialEq, FromRow, Clone)]
+pub struct DownloadSession {
+    pub id: i32,
+    pub device_id: Option<String>,
+    pub peer: Option<String>,
+    pub fwcode: i32,
+    pub version_m: i32,
+    pub version_n: i32,
+    pub version_l: i32,
+    pub slice_size: i32,
+    pub slices_served: i32,
+    pub slices_repeated: i32,
+    pub bytes_served: i64,
+    pub started_at: NaiveDateTime,
+    pub ended_at: NaiveDateTime,
+    pub duration_ms: i64,
+    pub throughput: f64, // 字节/秒
+    pub outcome: DownloadOutcome,
+    pub upgrade_history_id: Option<i32>,
+    pub created_at: NaiveDateTime,
+}
+
+/// 格式化打印
+impl fmt::Display for DownloadSession {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "DownloadSession -> Device:{}, Code:{:04X}, Version:{}.{}.{}, Slices:{}/{}, Outcome:{:?}",
+            self.device_id.as_deref().unwrap_or("-"),
+            self.fwcode,
+            self.version_m,
+            self.version_n,
+            self.version_l,
+            self.slices_served,
+            self.slices_repeated,
+            self.outcome,
+        )
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
+pub struct NewDownloadSession {
+    #[serde(default)]
+    pub device_id: Option<String>,
+    #[serde(default)]
+    pub peer: Option<String>,
+    pub fwcode: i32,
+    pub version_m: i32,
+    pub version_n: i32,
+    pub version_l: i32,
+    pub slice_size: i32,
+    pub slices_served: i32,
+    pub slices_repeated: i32,
+    pub bytes_served: i64,
+    pub started_at: NaiveDateTime,
+    pub ended_at: NaiveDateTime,
+    pub outcome: DownloadOutcome,
+    #[serde(default)]
+    pub upgrade_history_id: Option<i32>,
+}
+
+impl NewDownloadSession {
+    /// 持续时间（毫秒）
+    pub fn duration_ms(&self) -> i64 {
+        (self.ended_at - self.started_at).num_milliseconds().max(0)
+    }
+
+    /// 吞吐量（字节/秒）
+    pub fn throughput(&self) -> f64 {
+        match self.duration_ms() {
+            0 => 0.0,
+            ms => self.bytes_served as f64 * 1000.0 / ms as f64,
+        }
+    }
+}
+
+/// 下载会话查询条件
+#[derive(Debug, Deserialize, Serialize, Default, PartialEq, Clone)]
+pub struct DownloadSessionQuery {
+    pub device_id: Option<String>,
+    pub fwcode: Option<i32>,
+    pub outcome: Option<DownloadOutcome>,
+    pub upgrade_history_id: Option<i32>,
+}
+
+impl DownloadSession {
+    pub async fn filter(
+        query: &DownloadSessionQuery,
+        pool: &PgPool,
+    ) -> Result<Vec<DownloadSession>, DatabaseError> {
+        let items = sqlx::query_as::<_, DownloadSession>(
+            r#"
+            SELECT * FROM download_sessions
+            WHERE ($1::VARCHAR IS NULL OR UPPER(device_id) = UPPER($1))
+              AND ($2::INTEGER IS NULL OR fwcode = $2)
+              AND ($3::VARCHAR IS NULL OR outcome = $3)
+              AND ($4::INTEGER IS NULL OR upgrade_history_id = $4)
+            ORDER BY id
+            "#,
+        )
+        .bind(&query.device_id)
+        .bind(query.fwcode)
+        .bind(query.outcome)
+        .bind(query.upgrade_history_id)
+        .fetch_all(pool)
+        .await?;
+        Ok(items)
+    }
+
+    pub async fn find(id: i32, pool: &PgPool) -> Result<DownloadSession, DatabaseError> {
+        let result =
+            sqlx::query_as::<_, DownloadSession>("SELECT * FROM download_sessions WHERE id = $1")
+                .bind(id)
+                .fetch_one(pool)
+                .await?;
+        Ok(result)
+    }
+
+    pub async fn create(
+        data: &NewDownloadSession,
+        pool: &PgPool,
+    ) -> Result<DownloadSession, DatabaseError> {
+        let result = sqlx::query_as::<_, DownloadSession>(
+            r#"
+            INSERT INTO download_sessions (
+                device_id, peer, fwcode, version_m, version_n, version_l,
+                slice_size, slices_served, slices_repeated, bytes_served,
+                started_at, ended_at, duration_ms, throughput, outcome, upgrade_history_id
+            )
+            VALUES (UPPER($1), $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16)
+            RETURNING *
+            "#,
+        )
+        .bind(&data.device_id)
+        .bind(&data.peer)
+        .bind(data.fwcode)
+        .bind(data.version_m)
+        .bind(data.version_n)
+        .bind(data.version_l)
+        .bind(data.slice_size)
+        .bind(data.slices_served)
+        .bind(data.slices_repeated)
+        .bind(data.bytes_served)
+        .bind(data.started_at)
+        .bind(data.ended_at)
+        .bind(data.duration_ms())
+        .bind(data.throughput())
+        .bind(data.outcome)
+        .bind(data.upgrade_history_id)
+        .fetch_one(pool)
+        .await?;
+        Ok(result)
+    }
+
+    pub async fn delete(id: i32, pool: &PgPool) -> Result<u64, DatabaseError> {
+        let result = sqlx::query("DELETE FROM download_sessions WHERE id = $1")
+            .bind(id)
+            .execute(pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+}
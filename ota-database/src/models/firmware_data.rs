@@ -55,6 +55,65 @@ impl fmt::Display for FirmwareData {
     }
 }
 
+/// 固件元数据，不含固件内容
+#[derive(Deserialize, Serialize, Debug, PartialEq, Default, Eq, Clone, FromRow)]
+pub struct FirmwareMeta {
+    pub id: i32,
+    pub fwcode: i32,
+    pub version_m: i32,
+    pub version_n: i32,
+    pub version_l: i32,
+    pub fwsize: i32,
+    pub hash: String, // 固件内容的md5，写入时保存
+    pub updated_at: NaiveDateTime,
+}
+
+impl FirmwareMeta {
+    pub fn version(&self) -> FirmwareVersion {
+        FirmwareVersion {
+            m: self.version_m,
+            n: self.version_n,
+            l: self.version_l,
+        }
+    }
+
+    /// 生成下行数据包使用的固件信息
+    pub fn info(&self) -> FirmwareInfo {
+        FirmwareInfo {
+            code: self.fwcode,
+            version: self.version(),
+            size: self.fwsize,
+            path: String::from(""),
+        }
+    }
+}
+
+/// 格式化打印
+impl fmt::Display for FirmwareMeta {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "FwMeta -> Code:{:04X}, Version: {}.{}.{}, Size: {} bytes, Hash: {}",
+            self.fwcode, self.version_m, self.version_n, self.version_l, self.fwsize, self.hash
+        )
+    }
+}
+
+impl FirmwareData {
+    /// 查询所有固件的元数据，不读取固件内容
+    pub async fn all_meta(pool: &PgPool) -> Result<Vec<FirmwareMeta>, DatabaseError> {
+        let items = sqlx::query_as::<_, FirmwareMeta>(
+            r#"
+            SELECT id, fwcode, version_m, version_n, version_l, fwsize, hash, updated_at
+            FROM firmware_data
+            "#,
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(items)
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize, Default, PartialEq, Clone, Eq)]
 pub struct NewFirmwareData {
     pub fwcode: i32,
@@ -144,8 +203,8 @@ impl CrudOperations<FirmwareData, NewFirmwareData, UpdateFirmwareData> for Firmw
     async fn create(data: NewFirmwareData, pool: &PgPool) -> Result<FirmwareData, DatabaseError> {
         let result = sqlx::query_as::<_, FirmwareData>(
             r#"
-            INSERT INTO firmware_data (fwcode, version_m, version_n, version_l, fwsize, fwdata)
-            VALUES ($1, $2, $3, $4, $5, $6)
+            INSERT INTO firmware_data (fwcode, version_m, version_n, version_l, fwsize, fwdata, hash)
+            VALUES ($1, $2, $3, $4, $5, $6, md5($6))
             RETURNING *
             "#
         )
@@ -168,7 +227,7 @@ impl CrudOperations<FirmwareData, NewFirmwareData, UpdateFirmwareData> for Firmw
         let result = sqlx::query_as::<_, FirmwareData>(
             r#"
             UPDATE firmware_data
-            SET fwcode = $1, version_m = $2, version_n = $3, version_l = $4, fwsize = $5, fwdata = $6, updated_at = $7
+            SET fwcode = $1, version_m = $2, version_n = $3, version_l = $4, fwsize = $5, fwdata = $6, hash = md5($6), updated_at = $7
             WHERE id = $8
             RETURNING *
             "#
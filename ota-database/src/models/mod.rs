@@ -1,5 +1,9 @@
 pub mod basic;
 pub mod config_history;
+pub mod config_schema;
+pub mod device_command;
+pub mod device_shadow;
+pub mod download_session;
 pub mod firmware_data;
 pub mod upgrade_history;
 pub mod user;
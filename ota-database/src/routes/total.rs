@@ -1,4 +1,7 @@
-use crate::controls::{config_history, firmware_data, upgrade_history, user};
+use crate::controls::{
+    config_history, config_schema, device_command, device_shadow, download_session, events,
+    firmware_data, upgrade_history, user,
+};
 use actix_web::{get, web, Scope, HttpResponse, Responder};
 use serde_json::json;
 
@@ -13,6 +16,7 @@ async fn health_checker_handler() -> impl Responder {
 fn firmware_data_scope(path: &str) -> Scope {
     web::scope(path)
         .service(firmware_data::index)
+        .service(firmware_data::meta)
         .service(firmware_data::create)
         .service(firmware_data::find)
         .service(firmware_data::update)
@@ -32,11 +36,53 @@ fn config_history_scope(path: &str) -> Scope {
     web::scope(path)
         .service(config_history::index)
         .service(config_history::create)
+        .service(config_history::effective)
         .service(config_history::find)
         .service(config_history::update)
         .service(config_history::delete)
 }
 
+fn config_schema_scope(path: &str) -> Scope {
+    web::scope(path)
+        .service(config_schema::index)
+        .service(config_schema::create)
+        .service(config_schema::find)
+        .service(config_schema::update)
+        .service(config_schema::delete)
+}
+
+fn device_shadow_scope(path: &str) -> Scope {
+    web::scope(path)
+        .service(device_shadow::index)
+        .service(device_shadow::find)
+        .service(device_shadow::desired)
+        .service(device_shadow::reported)
+        .service(device_shadow::delete)
+}
+
+fn device_command_scope(path: &str) -> Scope {
+    web::scope(path)
+        .service(device_command::index)
+        .service(device_command::create)
+        .service(device_command::pending)
+        .service(device_command::find)
+        .service(device_command::progress)
+        .service(device_command::update)
+        .service(device_command::delete)
+}
+
+fn download_session_scope(path: &str) -> Scope {
+    web::scope(path)
+        .service(download_session::index)
+        .service(download_session::create)
+        .service(download_session::find)
+        .service(download_session::delete)
+}
+
+fn events_scope(path: &str) -> Scope {
+    web::scope(path).service(events::index)
+}
+
 fn auth_scope(path: &str) -> Scope {
     web::scope(path)
         .service(user::register)
@@ -52,4 +98,9 @@ pub fn apis() -> Scope {
         .service(upgrade_history_scope("/history"))
         .service(firmware_data_scope("/firmware"))
         .service(config_history_scope("/config"))
+        .service(config_schema_scope("/schema"))
+        .service(device_shadow_scope("/shadow"))
+        .service(device_command_scope("/commands"))
+        .service(download_session_scope("/downloads"))
+        .service(events_scope("/events"))
 }
@@ -0,0 +1,68 @@
+use clap::Parser;
+use ota_telemetry::LogFormat;
+use std::str::FromStr;
+
+/// 串口及可选的波特率，格式 `PATH[@BAUD]`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SerialSpec {
+    pub path: String,
+    pub baud: Option<u32>,
+}
+
+impl FromStr for SerialSpec {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (path, baud) = match value.rsplit_once('@') {
+            Some((path, baud)) => (
+                path,
+                Some(
+                    baud.parse::<u32>()
+                        .map_err(|e| format!("Bad baud rate {}: {}", baud, e))?,
+                ),
+            ),
+            None => (value, None),
+        };
+        if path.is_empty() {
+            return Err("Serial device path is empty".to_string());
+        }
+
+        Ok(SerialSpec {
+            path: path.to_string(),
+            baud,
+        })
+    }
+}
+
+#[derive(Parser, Debug, PartialEq, Clone)]
+#[clap(author, version, about)]
+/// Serial/UART gateway forwarding OTA protocol frames to ota-server
+pub struct Cli {
+    /// ota-server address
+    #[clap(long, default_value = "127.0.0.1:9999")]
+    pub server: String,
+
+    /// Serial device as PATH or PATH@BAUD, repeat for several ports
+    #[clap(long = "serial", required = true)]
+    pub serial: Vec<SerialSpec>,
+
+    /// Baud rate of ports given without one
+    #[clap(long, default_value = "115200")]
+    pub baud: u32,
+
+    /// Milliseconds to wait for a response before forwarding the next frame
+    #[clap(long, default_value = "500")]
+    pub turnaround: u64,
+
+    /// Milliseconds between attempts to reopen a serial port
+    #[clap(long, default_value = "2000")]
+    pub reopen: u64,
+
+    /// Largest frame payload accepted from a serial port, longer frames are treated as noise
+    #[clap(long, default_value = "1024")]
+    pub max_payload: u16,
+
+    /// Log output format
+    #[clap(long, value_enum, default_value = "text")]
+    pub log_format: LogFormat,
+}
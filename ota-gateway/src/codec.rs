@@ -0,0 +1,95 @@
+use crc::{Crc, CRC_8_MAXIM_DOW};
+
+const CRC_8: Crc<u8> = Crc::<u8>::new(&CRC_8_MAXIM_DOW);
+
+const HEADER: [u8; 2] = [0xAA, 0x55];
+/// 包头(2) + 类型(1) + 长度(2)
+const HEADER_LEN: usize = 5;
+
+/// 丢弃字节的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DropReason {
+    /// 包头之前的字节，如线路噪声
+    Noise,
+    /// CRC 错误，丢弃包头后从下一个包头重新同步
+    Crc,
+    /// 长度超过上限，多半是噪声中碰巧出现的包头
+    Oversize,
+}
+
+/// 从字节流中取出的内容
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Extracted {
+    Frame(Vec<u8>),
+    Dropped { len: usize, reason: DropReason },
+}
+
+/// 串口字节流分帧，帧格式：AA 55 类型 长度(2) 数据 CRC
+/// 串口没有消息边界，一帧可能分多次到达，前后也可能夹带噪声
+#[derive(Debug)]
+pub struct FrameExtractor {
+    buffer: Vec<u8>,
+    max_payload: usize,
+}
+
+impl FrameExtractor {
+    pub fn new(max_payload: usize) -> Self {
+        FrameExtractor {
+            buffer: Vec::new(),
+            max_payload,
+        }
+    }
+
+    pub fn push(&mut self, data: &[u8]) {
+        self.buffer.extend_from_slice(data);
+    }
+
+    /// 未处理的字节数
+    pub fn buffered(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// 取出下一帧或一段丢弃的字节，数据不够时返回 None
+    pub fn extract(&mut self) -> Option<Extracted> {
+        // 对齐到包头，末尾的 AA 可能是下一个包头的开始
+        let start = match self.buffer.windows(2).position(|window| window == HEADER) {
+            Some(start) => start,
+            None if self.buffer.last() == Some(&HEADER[0]) => self.buffer.len() - 1,
+            None => self.buffer.len(),
+        };
+        if start > 0 {
+            self.buffer.drain(..start);
+            return Some(Extracted::Dropped {
+                len: start,
+                reason: DropReason::Noise,
+            });
+        }
+
+        if self.buffer.len() < HEADER_LEN {
+            return None;
+        }
+        let payload_len = u16::from_be_bytes([self.buffer[3], self.buffer[4]]) as usize;
+        if payload_len > self.max_payload {
+            return Some(self.drop_header(DropReason::Oversize));
+        }
+
+        let frame_len = HEADER_LEN + payload_len + 1;
+        if self.buffer.len() < frame_len {
+            return None;
+        }
+        if CRC_8.checksum(&self.buffer[..frame_len - 1]) != self.buffer[frame_len - 1] {
+            return Some(self.drop_header(DropReason::Crc));
+        }
+
+        Some(Extracted::Frame(self.buffer.drain(..frame_len).collect()))
+    }
+
+    /// 只丢弃包头，帧内如果还有包头可以从那里重新同步
+    fn drop_header(&mut self, reason: DropReason) -> Extracted {
+        self.buffer.drain(..HEADER.len());
+        Extracted::Dropped {
+            len: HEADER.len(),
+            reason,
+        }
+    }
+}
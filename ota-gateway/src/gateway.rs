@@ -0,0 +1,278 @@
+use std::{
+    collections::VecDeque,
+    io,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::TcpStream,
+    task::JoinSet,
+    time::{sleep, sleep_until, timeout, Instant},
+};
+use tokio_serial::SerialPortBuilderExt;
+use tracing::{debug, info, info_span, warn, Instrument};
+
+use crate::{
+    args::{Cli, SerialSpec},
+    codec::{DropReason, Extracted, FrameExtractor},
+};
+
+/// 等待转发的帧数上限，超出时丢弃最早的帧
+const MAX_PENDING: usize = 16;
+
+const BUFFER_SIZE: usize = 1024;
+
+/// 转发参数，所有串口共用
+#[derive(Debug, Clone)]
+pub struct BridgeConfig {
+    pub server: String,
+    /// 转发一帧后等待回复的时间，超时后转发下一帧
+    pub turnaround: Duration,
+    pub connect_timeout: Duration,
+    pub max_payload: usize,
+}
+
+impl From<&Cli> for BridgeConfig {
+    fn from(cli: &Cli) -> Self {
+        BridgeConfig {
+            server: cli.server.clone(),
+            turnaround: Duration::from_millis(cli.turnaround),
+            connect_timeout: Duration::from_secs(5),
+            max_payload: cli.max_payload as usize,
+        }
+    }
+}
+
+/// 一个串口的统计
+#[derive(Debug, Default)]
+pub struct PortStats {
+    /// 转发给 ota-server 的帧
+    pub frames_up: AtomicU64,
+    /// 转发给串口的字节
+    pub bytes_down: AtomicU64,
+    pub noise_bytes: AtomicU64,
+    pub crc_errors: AtomicU64,
+    pub oversize: AtomicU64,
+    /// 排队过多或连接失败丢弃的帧
+    pub frames_dropped: AtomicU64,
+    pub upstream_connects: AtomicU64,
+}
+
+impl PortStats {
+    fn add(counter: &AtomicU64, value: u64) {
+        counter.fetch_add(value, Ordering::Relaxed);
+    }
+}
+
+/// 在串口和 ota-server 之间转发，串口关闭或读写出错时返回
+/// - 串口上的字节流按 AA 55 分帧，噪声和 CRC 错误的帧丢弃
+/// - ota-server 每次读取只处理一个包，所以一次只转发一帧，
+///   收到回复或等待 turnaround 后再转发下一帧（下载结束等请求没有回复）
+/// - 回复原样写回串口
+/// - 到 ota-server 的连接在第一帧时建立，断开后在下一帧时重连
+pub async fn bridge<S: AsyncRead + AsyncWrite + Unpin>(
+    serial: &mut S,
+    port: &str,
+    config: &BridgeConfig,
+    stats: &PortStats,
+) -> io::Result<()> {
+    let mut extractor = FrameExtractor::new(config.max_payload);
+    let mut pending: VecDeque<Vec<u8>> = VecDeque::new();
+    let mut upstream: Option<TcpStream> = None;
+    // 等待回复的截止时间
+    let mut in_flight: Option<Instant> = None;
+
+    let mut serial_buffer = [0u8; BUFFER_SIZE];
+    let mut upstream_buffer = [0u8; BUFFER_SIZE];
+
+    loop {
+        tokio::select! {
+            res = serial.read(&mut serial_buffer) => {
+                let bytes_read = res?;
+                if bytes_read == 0 {
+                    return Ok(());
+                }
+                extractor.push(&serial_buffer[..bytes_read]);
+
+                while let Some(extracted) = extractor.extract() {
+                    match extracted {
+                        Extracted::Frame(frame) => {
+                            if pending.len() >= MAX_PENDING {
+                                pending.pop_front();
+                                PortStats::add(&stats.frames_dropped, 1);
+                            }
+                            pending.push_back(frame);
+                        }
+                        Extracted::Dropped { len, reason } => {
+                            debug!("Dropped {} bytes: {:?}", len, reason);
+                            match reason {
+                                DropReason::Noise => PortStats::add(&stats.noise_bytes, len as u64),
+                                DropReason::Crc => PortStats::add(&stats.crc_errors, 1),
+                                DropReason::Oversize => PortStats::add(&stats.oversize, 1),
+                            }
+                        }
+                    }
+                }
+            }
+            res = read_upstream(&mut upstream, &mut upstream_buffer) => match res {
+                Ok(bytes_read) if bytes_read > 0 => {
+                    serial.write_all(&upstream_buffer[..bytes_read]).await?;
+                    serial.flush().await?;
+                    PortStats::add(&stats.bytes_down, bytes_read as u64);
+                    in_flight = None;
+                }
+                res => {
+                    if let Err(e) = res {
+                        warn!("Upstream error: {}", e);
+                    }
+                    info!("Upstream closed");
+                    upstream = None;
+                    in_flight = None;
+                }
+            },
+            _ = wait_deadline(in_flight) => {
+                in_flight = None;
+            }
+        }
+
+        // 转发下一帧
+        if in_flight.is_none() {
+            if let Some(frame) = pending.pop_front() {
+                match forward(&mut upstream, &frame, port, config, stats).await {
+                    Ok(()) => {
+                        PortStats::add(&stats.frames_up, 1);
+                        in_flight = Some(Instant::now() + config.turnaround);
+                    }
+                    Err(e) => {
+                        // 设备会重发，排队的帧一并丢弃，避免逐帧等待连接超时
+                        warn!("Failed to forward frame to {}: {}", config.server, e);
+                        PortStats::add(&stats.frames_dropped, 1 + pending.len() as u64);
+                        pending.clear();
+                        upstream = None;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// 读取 ota-server 的回复，没有连接时一直等待
+async fn read_upstream(upstream: &mut Option<TcpStream>, buffer: &mut [u8]) -> io::Result<usize> {
+    match upstream {
+        Some(socket) => socket.read(buffer).await,
+        None => std::future::pending().await,
+    }
+}
+
+async fn wait_deadline(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// 转发一帧，需要时建立连接
+async fn forward(
+    upstream: &mut Option<TcpStream>,
+    frame: &[u8],
+    port: &str,
+    config: &BridgeConfig,
+    stats: &PortStats,
+) -> io::Result<()> {
+    if upstream.is_none() {
+        let socket = timeout(config.connect_timeout, TcpStream::connect(&config.server))
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "Connect timeout"))??;
+        socket.set_nodelay(true)?;
+        info!(
+            "Upstream {} -> {} for {}",
+            socket.local_addr()?,
+            config.server,
+            port
+        );
+        PortStats::add(&stats.upstream_connects, 1);
+        *upstream = Some(socket);
+    }
+
+    if let Some(socket) = upstream {
+        socket.write_all(frame).await?;
+    }
+    Ok(())
+}
+
+/// 打开串口并转发，串口断开（如 USB 串口拔出）后定期重新打开
+pub async fn serve_port(
+    spec: SerialSpec,
+    baud: u32,
+    reopen: Duration,
+    config: Arc<BridgeConfig>,
+    stats: Arc<PortStats>,
+) {
+    let baud = spec.baud.unwrap_or(baud);
+
+    loop {
+        match tokio_serial::new(&spec.path, baud).open_native_async() {
+            Ok(mut serial) => {
+                info!("Serial port opened at {} baud", baud);
+                match bridge(&mut serial, &spec.path, &config, &stats).await {
+                    Ok(()) => info!("Serial port closed"),
+                    Err(e) => warn!("Serial port error: {}", e),
+                }
+            }
+            Err(e) => warn!("Failed to open serial port: {}", e),
+        }
+
+        sleep(reopen).await;
+    }
+}
+
+/// 多个串口的网关，每个串口一个任务，各自连接 ota-server
+pub struct Gateway {
+    pub ports: Vec<SerialSpec>,
+    pub baud: u32,
+    pub reopen: Duration,
+    pub config: Arc<BridgeConfig>,
+}
+
+impl From<&Cli> for Gateway {
+    fn from(cli: &Cli) -> Self {
+        Gateway {
+            ports: cli.serial.clone(),
+            baud: cli.baud,
+            reopen: Duration::from_millis(cli.reopen),
+            config: Arc::new(BridgeConfig::from(cli)),
+        }
+    }
+}
+
+impl Gateway {
+    /// 启动所有串口，返回每个串口的统计
+    pub fn spawn(&self) -> (JoinSet<()>, Vec<(String, Arc<PortStats>)>) {
+        let mut tasks = JoinSet::new();
+        let mut stats = Vec::new();
+
+        for spec in &self.ports {
+            let port_stats = Arc::new(PortStats::default());
+            stats.push((spec.path.clone(), port_stats.clone()));
+
+            // 每个串口的日志和上游连接都带上串口名
+            let span = info_span!("port", port = %spec.path);
+            tasks.spawn(
+                serve_port(
+                    spec.clone(),
+                    self.baud,
+                    self.reopen,
+                    self.config.clone(),
+                    port_stats,
+                )
+                .instrument(span),
+            );
+        }
+
+        (tasks, stats)
+    }
+}
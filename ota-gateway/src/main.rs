@@ -0,0 +1,68 @@
+use clap::Parser;
+use ota_gateway::{
+    args::Cli,
+    gateway::{Gateway, PortStats},
+};
+use ota_telemetry::{init_tracing, TelemetryOptions};
+use std::{error::Error, sync::atomic::Ordering, sync::Arc, time::Duration};
+use tracing::info;
+
+/// 统计输出间隔
+const STATS_INTERVAL: Duration = Duration::from_secs(60);
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let cli = Cli::parse();
+
+    let telemetry = init_tracing(&TelemetryOptions {
+        service: "ota-gateway",
+        filter_env: "RUST_LOG",
+        default_filter: "info",
+        format: cli.log_format,
+        otlp_endpoint: None,
+    })?;
+
+    info!(
+        "Forwarding {} serial ports to {}",
+        cli.serial.len(),
+        cli.server
+    );
+
+    let gateway = Gateway::from(&cli);
+    let (mut tasks, stats) = gateway.spawn();
+
+    let mut ticker = tokio::time::interval(STATS_INTERVAL);
+    ticker.tick().await;
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => log_stats(&stats),
+            _ = tasks.join_next() => break,
+            _ = tokio::signal::ctrl_c() => {
+                info!("Ctrl-C received");
+                break;
+            }
+        }
+    }
+
+    tasks.abort_all();
+    log_stats(&stats);
+    telemetry.shutdown();
+
+    Ok(())
+}
+
+fn log_stats(stats: &[(String, Arc<PortStats>)]) {
+    for (port, stats) in stats {
+        info!(
+            "{}: {} frames up, {} bytes down, {} noise bytes, {} CRC errors, {} oversize, {} dropped, {} connects",
+            port,
+            stats.frames_up.load(Ordering::Relaxed),
+            stats.bytes_down.load(Ordering::Relaxed),
+            stats.noise_bytes.load(Ordering::Relaxed),
+            stats.crc_errors.load(Ordering::Relaxed),
+            stats.oversize.load(Ordering::Relaxed),
+            stats.frames_dropped.load(Ordering::Relaxed),
+            stats.upstream_connects.load(Ordering::Relaxed),
+        );
+    }
+}
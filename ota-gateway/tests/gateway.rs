@@ -0,0 +1,230 @@
+#[cfg(test)]
+mod tests {
+    use ota_gateway::{
+        args::SerialSpec,
+        codec::{DropReason, Extracted, FrameExtractor},
+        gateway::{bridge, BridgeConfig, Gateway, PortStats},
+    };
+    use ota_simulator::conformance::frame;
+    use std::{
+        sync::{atomic::Ordering, Arc},
+        time::Duration,
+    };
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+        sync::mpsc,
+        time::timeout,
+    };
+    use tokio_serial::{SerialPort, SerialStream};
+
+    fn response(request: &[u8]) -> Vec<u8> {
+        frame(0xFF - request[2], &request[5..request.len() - 1])
+    }
+
+    /// 模拟 ota-server：每次读取作为一个请求，回复同样的数据，下载结束不回复
+    /// 返回地址和收到的 (连接序号, 请求)
+    async fn upstream() -> (String, mpsc::UnboundedReceiver<(usize, Vec<u8>)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        tokio::spawn(async move {
+            let mut connection = 0;
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    let mut buffer = [0u8; 1024];
+                    while let Ok(bytes_read) = socket.read(&mut buffer).await {
+                        if bytes_read == 0 {
+                            break;
+                        }
+                        let request = buffer[..bytes_read].to_vec();
+                        if request[2] != 0xA3 {
+                            socket.write_all(&response(&request)).await.unwrap();
+                        }
+                        let _ = tx.send((connection, request));
+                    }
+                });
+                connection += 1;
+            }
+        });
+
+        (addr, rx)
+    }
+
+    fn config(server: String) -> BridgeConfig {
+        BridgeConfig {
+            server,
+            turnaround: Duration::from_millis(100),
+            connect_timeout: Duration::from_secs(1),
+            max_payload: 64,
+        }
+    }
+
+    async fn read_exact(device: &mut SerialStream, len: usize) -> Vec<u8> {
+        let mut data = vec![0u8; len];
+        timeout(Duration::from_secs(2), device.read_exact(&mut data))
+            .await
+            .expect("No response on the serial port")
+            .unwrap();
+        data
+    }
+
+    #[test]
+    fn extract_frames() {
+        let query = frame(0xA1, &[0x19, 0x87]);
+        let mut bad_crc = frame(0xA4, &[0x00, 0x01]);
+        *bad_crc.last_mut().unwrap() ^= 0xFF;
+        let mut extractor = FrameExtractor::new(64);
+
+        // 噪声，帧分两次到达
+        extractor.push(&[0x00, 0xFF]);
+        extractor.push(&query[..4]);
+        assert_eq!(
+            extractor.extract(),
+            Some(Extracted::Dropped {
+                len: 2,
+                reason: DropReason::Noise
+            })
+        );
+        assert_eq!(extractor.extract(), None);
+        extractor.push(&query[4..]);
+        assert_eq!(extractor.extract(), Some(Extracted::Frame(query.clone())));
+
+        // CRC 错误，超长的帧
+        extractor.push(&bad_crc);
+        extractor.push(&[0xAA, 0x55, 0xA2, 0xFF, 0xFF]);
+        extractor.push(&query);
+        let mut extracted = Vec::new();
+        while let Some(item) = extractor.extract() {
+            extracted.push(item);
+        }
+        assert_eq!(
+            extracted,
+            vec![
+                Extracted::Dropped {
+                    len: 2,
+                    reason: DropReason::Crc
+                },
+                Extracted::Dropped {
+                    len: bad_crc.len() - 2,
+                    reason: DropReason::Noise
+                },
+                Extracted::Dropped {
+                    len: 2,
+                    reason: DropReason::Oversize
+                },
+                Extracted::Dropped {
+                    len: 3,
+                    reason: DropReason::Noise
+                },
+                Extracted::Frame(query.clone()),
+            ]
+        );
+
+        // 末尾的 AA 留到下次
+        extractor.push(&[0x01, 0xAA]);
+        assert!(matches!(
+            extractor.extract(),
+            Some(Extracted::Dropped { len: 1, .. })
+        ));
+        assert_eq!(extractor.extract(), None);
+        assert_eq!(extractor.buffered(), 1);
+    }
+
+    #[test]
+    fn serial_spec() {
+        assert_eq!(
+            "/dev/ttyUSB0@9600".parse::<SerialSpec>(),
+            Ok(SerialSpec {
+                path: "/dev/ttyUSB0".to_string(),
+                baud: Some(9600),
+            })
+        );
+        assert_eq!("/dev/ttyS1".parse::<SerialSpec>().unwrap().baud, None);
+        assert!("/dev/ttyS1@fast".parse::<SerialSpec>().is_err());
+    }
+
+    #[tokio::test]
+    async fn bridge_pty() {
+        let (server, mut requests) = upstream().await;
+        let (mut device, mut serial) = SerialStream::pair().unwrap();
+        let stats = Arc::new(PortStats::default());
+
+        let bridge_stats = stats.clone();
+        tokio::spawn(async move {
+            let _ = bridge(&mut serial, "pty", &config(server), &bridge_stats).await;
+        });
+
+        // 三帧一次写入，前面带噪声；下载结束没有回复
+        let query = frame(0xA1, &[0x19, 0x87]);
+        let end = frame(0xA3, &[0x19, 0x87, 0x00, 0x03, 0x00]);
+        let sync = frame(0xA8, &[0x02]);
+        let mut data = vec![0x00, 0x13];
+        data.extend(&query);
+        data.extend(&end);
+        data.extend(&sync);
+        device.write_all(&data).await.unwrap();
+
+        assert_eq!(read_exact(&mut device, 8).await, response(&query));
+        assert_eq!(read_exact(&mut device, 7).await, response(&sync));
+
+        // ota-server 每次读取到一帧
+        for expected in [&query, &end, &sync] {
+            let (connection, request) = requests.recv().await.unwrap();
+            assert_eq!(connection, 0);
+            assert_eq!(&request, expected);
+        }
+
+        assert_eq!(stats.frames_up.load(Ordering::Relaxed), 3);
+        assert_eq!(stats.bytes_down.load(Ordering::Relaxed), 15);
+        assert_eq!(stats.noise_bytes.load(Ordering::Relaxed), 2);
+        assert_eq!(stats.upstream_connects.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn multiplex_ports() {
+        let (server, mut requests) = upstream().await;
+        let (mut device_a, port_a) = SerialStream::pair().unwrap();
+        let (mut device_b, port_b) = SerialStream::pair().unwrap();
+
+        let gateway = Gateway {
+            ports: vec![
+                SerialSpec {
+                    path: port_a.name().unwrap(),
+                    baud: None,
+                },
+                SerialSpec {
+                    path: port_b.name().unwrap(),
+                    baud: Some(9600),
+                },
+            ],
+            baud: 115200,
+            reopen: Duration::from_millis(100),
+            config: Arc::new(config(server)),
+        };
+        // 网关按路径重新打开
+        drop((port_a, port_b));
+        let (_tasks, stats) = gateway.spawn();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let request_a = frame(0xA4, &[0x00, 0x00, 0x00, 0x00, 0xAA, 0xAA, 0xAA, 0x01]);
+        let request_b = frame(0xA4, &[0x00, 0x00, 0x00, 0x00, 0xBB, 0xBB, 0xBB, 0x02]);
+        device_a.write_all(&request_a).await.unwrap();
+        assert_eq!(read_exact(&mut device_a, 14).await, response(&request_a));
+        device_b.write_all(&request_b).await.unwrap();
+        assert_eq!(read_exact(&mut device_b, 14).await, response(&request_b));
+
+        // 每个串口一个上游连接
+        let (connection_a, received_a) = requests.recv().await.unwrap();
+        let (connection_b, received_b) = requests.recv().await.unwrap();
+        assert_eq!((received_a, received_b), (request_a, request_b));
+        assert_ne!(connection_a, connection_b);
+
+        for (_, stats) in &stats {
+            assert_eq!(stats.frames_up.load(Ordering::Relaxed), 1);
+            assert_eq!(stats.upstream_connects.load(Ordering::Relaxed), 1);
+        }
+    }
+}
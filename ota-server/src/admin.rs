@@ -0,0 +1,188 @@
+use chrono::NaiveDateTime;
+use ota_database::models::firmware_data::FirmwareVersion;
+use serde::Serialize;
+use serde_json::json;
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::watch,
+    time,
+};
+use tracing::{error, info};
+
+use crate::{
+    download::DownloadTracker,
+    fw_cache::{FirmwareCache, FirmwareSnapshot},
+    registry::{ConnectionInfo, SessionRegistry},
+};
+
+/// 管理接口使用的服务
+#[derive(Debug, Clone)]
+pub struct AdminState {
+    pub fw_cache: Arc<FirmwareCache>,
+    pub registry: Arc<SessionRegistry>,
+    pub downloads: Arc<DownloadTracker>,
+    /// 为 true 时正在排空，就绪检查失败
+    pub stopping: watch::Receiver<bool>,
+}
+
+/// 会话列表中的一项
+#[derive(Debug, Serialize)]
+pub struct SessionView {
+    pub id: u64,
+    pub peer: SocketAddr,
+    pub connected_at: NaiveDateTime,
+    pub device_id: Option<String>,
+    pub fw_code: Option<i32>,
+    /// 进行中的下载，没有时为空
+    pub download: Option<DownloadView>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DownloadView {
+    pub fw_code: i32,
+    pub version: String,
+    pub slice_size: u16,
+    /// 已发送的不同分片数
+    pub slices: usize,
+    /// 固件总分片数，固件已不在快照中时为空
+    pub total_slices: Option<usize>,
+    /// 百分比，按已发送的不同分片计算
+    pub progress: Option<u8>,
+    pub bytes_served: i64,
+    pub last_active_at: NaiveDateTime,
+}
+
+impl AdminState {
+    /// 当前连接和下载进度
+    pub fn sessions(&self) -> Vec<SessionView> {
+        let snapshot = self.fw_cache.load();
+        self.registry
+            .connections()
+            .into_iter()
+            .map(|info| self.session_view(&snapshot, info))
+            .collect()
+    }
+
+    fn session_view(&self, snapshot: &FirmwareSnapshot, info: ConnectionInfo) -> SessionView {
+        let download = self
+            .downloads
+            .progress(info.device_id.as_deref(), info.id)
+            .map(|progress| {
+                let (code, m, n, l) = progress.fw;
+                let total_slices = snapshot
+                    .find(code, &FirmwareVersion { m, n, l })
+                    .filter(|_| progress.slice_size > 0)
+                    .map(|meta| {
+                        (meta.fwsize.max(0) as usize).div_ceil(progress.slice_size as usize)
+                    });
+                DownloadView {
+                    fw_code: code,
+                    version: format!("{}.{}.{}", m, n, l),
+                    slice_size: progress.slice_size,
+                    slices: progress.slices,
+                    total_slices,
+                    progress: total_slices.map(|total| match total {
+                        0 => 100,
+                        total => (progress.slices.min(total) * 100 / total) as u8,
+                    }),
+                    bytes_served: progress.bytes_served,
+                    last_active_at: progress.last_active_at,
+                }
+            });
+
+        SessionView {
+            id: info.id,
+            peer: info.peer,
+            connected_at: info.connected_at,
+            device_id: info.device_id,
+            fw_code: info.fw_code,
+            download,
+        }
+    }
+
+    /// 固件元数据已加载且没有在排空
+    pub fn is_ready(&self) -> bool {
+        self.fw_cache.is_loaded() && !*self.stopping.borrow()
+    }
+}
+
+/// 管理接口
+/// - `GET /healthz` : 存活检查
+/// - `GET /readyz` : 就绪检查，固件元数据加载前和排空时返回 503
+/// - `POST /cache/reload` : 立即刷新固件元数据
+/// - `GET /sessions` : 当前连接、设备ID、固件和下载进度
+/// - `DELETE /sessions/{id}` : 断开连接
+pub async fn serve_admin(listener: TcpListener, state: AdminState) {
+    if let Ok(addr) = listener.local_addr() {
+        info!("Admin listening on {}", addr);
+    }
+
+    loop {
+        match listener.accept().await {
+            Ok((socket, _)) => {
+                let state = state.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = admin_response(socket, &state).await {
+                        error!("Admin Error: {}", e);
+                    }
+                });
+            }
+            Err(e) => error!("Admin Accept Error: {}", e),
+        }
+    }
+}
+
+async fn admin_response(mut socket: TcpStream, state: &AdminState) -> std::io::Result<()> {
+    let mut buffer = [0u8; 1024];
+    let bytes_read = time::timeout(Duration::from_secs(5), socket.read(&mut buffer))
+        .await
+        .unwrap_or(Ok(0))?;
+
+    let request = String::from_utf8_lossy(&buffer[..bytes_read]);
+    let mut request_line = request.lines().next().unwrap_or_default().split(' ');
+    let method = request_line.next().unwrap_or_default();
+    let path = request_line.next().unwrap_or_default();
+    let path = path.split('?').next().unwrap_or_default();
+
+    let (status, body) = match (method, path) {
+        ("GET", "/healthz") => ("200 OK", json!({ "status": "ok" })),
+        ("GET", "/readyz") => match state.is_ready() {
+            true => ("200 OK", json!({ "status": "ready" })),
+            false => ("503 Service Unavailable", json!({ "status": "not ready" })),
+        },
+        ("POST", "/cache/reload") => match state.fw_cache.refresh().await {
+            Ok(count) => {
+                info!("Firmware cache reloaded by admin, {} images", count);
+                ("200 OK", json!({ "firmware": count }))
+            }
+            Err(e) => {
+                error!("Firmware cache reload failed: {}", e);
+                ("502 Bad Gateway", json!({ "error": e.to_string() }))
+            }
+        },
+        ("GET", "/sessions") => ("200 OK", json!(state.sessions())),
+        ("DELETE", path) if path.starts_with("/sessions/") => {
+            match path["/sessions/".len()..].parse::<u64>() {
+                Ok(id) if state.registry.disconnect(id) => {
+                    info!("Session {} disconnected by admin", id);
+                    ("200 OK", json!({ "disconnected": id }))
+                }
+                Ok(_) => ("404 Not Found", json!({ "error": "session not found" })),
+                Err(_) => ("400 Bad Request", json!({ "error": "invalid session id" })),
+            }
+        }
+        _ => ("404 Not Found", json!({ "error": "not found" })),
+    };
+
+    let body = body.to_string();
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    socket.write_all(response.as_bytes()).await?;
+    socket.shutdown().await
+}
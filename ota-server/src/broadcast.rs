@@ -0,0 +1,449 @@
+use crc::{Crc, CRC_8_MAXIM_DOW};
+use ota_database::models::firmware_data::FirmwareVersion;
+use reed_solomon_erasure::galois_8::ReedSolomon;
+use std::{collections::HashMap, net::SocketAddr, sync::Arc, time::Duration};
+use thiserror::Error;
+use tokio::{
+    net::UdpSocket,
+    time::{self, MissedTickBehavior},
+};
+use tracing::{debug, error, info, warn};
+
+use crate::{
+    fw_cache::{FirmwareCache, FirmwareImage},
+    metrics::metrics,
+    shutdown::Shutdown,
+};
+
+/// 组播分片的包类型
+pub const FRAGMENT_TYPE: u8 = 0xB0;
+
+/// 分片头长度：代号(2) + 版本(3) + 大小(4) + 分片大小(2) + 数据分片数(1) + 冗余分片数(1) + 块号(2) + 块内序号(1)
+pub const FRAGMENT_HEADER_LEN: usize = 16;
+
+const CRC_8: Crc<u8> = Crc::<u8>::new(&CRC_8_MAXIM_DOW);
+
+#[derive(Debug, Error)]
+pub enum BroadcastError {
+    #[error("Invalid fragment: {0}")]
+    Invalid(&'static str),
+    #[error("Fragment of another firmware")]
+    Mismatch,
+    #[error("Invalid FEC parameters: {0}")]
+    Params(&'static str),
+    #[error("FEC error: {0}")]
+    Fec(#[from] reed_solomon_erasure::Error),
+}
+
+/// 纠删码参数
+/// - 固件按 `fragment_size` 切成数据分片，与 `FirmwareDownload` 切片大小相同时分片序号即切片序号
+/// - 每 `data_shards` 个数据分片为一块，每块附加 `parity_shards` 个冗余分片
+/// - 一块内收到任意 `data_shards` 个分片即可恢复该块
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FecParams {
+    pub fragment_size: usize,
+    pub data_shards: usize,
+    pub parity_shards: usize,
+}
+
+impl Default for FecParams {
+    fn default() -> Self {
+        FecParams {
+            fragment_size: 512,
+            data_shards: 16,
+            parity_shards: 4,
+        }
+    }
+}
+
+impl FecParams {
+    pub fn validate(&self) -> Result<(), BroadcastError> {
+        if self.fragment_size == 0 || self.fragment_size > u16::MAX as usize - FRAGMENT_HEADER_LEN {
+            return Err(BroadcastError::Params("fragment size"));
+        }
+        if self.data_shards == 0 || self.parity_shards == 0 {
+            return Err(BroadcastError::Params("shard count"));
+        }
+        // 块内序号为1字节
+        if self.data_shards + self.parity_shards > 256 {
+            return Err(BroadcastError::Params("more than 256 shards per block"));
+        }
+        Ok(())
+    }
+
+    fn total_shards(&self) -> usize {
+        self.data_shards + self.parity_shards
+    }
+}
+
+/// 分片所属的固件和编码参数，同一轮广播的所有分片相同
+#[derive(Debug, Clone, PartialEq)]
+pub struct FragmentHeader {
+    pub code: i32,
+    pub version: FirmwareVersion,
+    pub size: u32,
+    pub fec: FecParams,
+}
+
+impl FragmentHeader {
+    /// 数据分片总数，最后一片不足时补零
+    pub fn fragments(&self) -> usize {
+        (self.size as usize).div_ceil(self.fec.fragment_size)
+    }
+
+    pub fn blocks(&self) -> usize {
+        self.fragments().div_ceil(self.fec.data_shards)
+    }
+}
+
+/// 一个组播分片
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fragment {
+    pub header: FragmentHeader,
+    pub block: u16,
+    /// 块内序号，小于数据分片数的为数据分片
+    pub shard: u8,
+    pub data: Vec<u8>,
+}
+
+impl Fragment {
+    /// 数据分片的序号，冗余分片为 None
+    pub fn index(&self) -> Option<usize> {
+        let shard = self.shard as usize;
+        (shard < self.header.fec.data_shards)
+            .then(|| self.block as usize * self.header.fec.data_shards + shard)
+    }
+
+    /// 生成组播数据报：AA 55 B0 长度(2) 分片头 数据 CRC
+    pub fn encode(&self) -> Vec<u8> {
+        let header = &self.header;
+        let len = FRAGMENT_HEADER_LEN + self.data.len();
+
+        let mut data = vec![0xAA, 0x55, FRAGMENT_TYPE, (len >> 8) as u8, len as u8];
+        data.extend_from_slice(&(header.code as u16).to_be_bytes());
+        data.extend_from_slice(&[
+            header.version.m as u8,
+            header.version.n as u8,
+            header.version.l as u8,
+        ]);
+        data.extend_from_slice(&header.size.to_be_bytes());
+        data.extend_from_slice(&(header.fec.fragment_size as u16).to_be_bytes());
+        data.push(header.fec.data_shards as u8);
+        data.push(header.fec.parity_shards as u8);
+        data.extend_from_slice(&self.block.to_be_bytes());
+        data.push(self.shard);
+        data.extend_from_slice(&self.data);
+        data.push(CRC_8.checksum(&data));
+
+        data
+    }
+
+    /// 解析组播数据报
+    pub fn decode(datagram: &[u8]) -> Result<Fragment, BroadcastError> {
+        if datagram.len() < 5 + FRAGMENT_HEADER_LEN + 1 {
+            return Err(BroadcastError::Invalid("too short"));
+        }
+        if datagram[..3] != [0xAA, 0x55, FRAGMENT_TYPE] {
+            return Err(BroadcastError::Invalid("not a fragment"));
+        }
+        let len = u16::from_be_bytes([datagram[3], datagram[4]]) as usize;
+        if datagram.len() != 5 + len + 1 {
+            return Err(BroadcastError::Invalid("length"));
+        }
+        let (body, crc) = datagram.split_at(datagram.len() - 1);
+        if CRC_8.checksum(body) != crc[0] {
+            return Err(BroadcastError::Invalid("crc"));
+        }
+
+        let payload = &body[5..];
+        let read_u16 = |at: usize| u16::from_be_bytes([payload[at], payload[at + 1]]);
+        let header = FragmentHeader {
+            code: read_u16(0) as i32,
+            version: FirmwareVersion {
+                m: payload[2] as i32,
+                n: payload[3] as i32,
+                l: payload[4] as i32,
+            },
+            size: u32::from_be_bytes([payload[5], payload[6], payload[7], payload[8]]),
+            fec: FecParams {
+                fragment_size: read_u16(9) as usize,
+                data_shards: payload[11] as usize,
+                parity_shards: payload[12] as usize,
+            },
+        };
+        header.fec.validate()?;
+
+        let fragment = Fragment {
+            header,
+            block: read_u16(13),
+            shard: payload[15],
+            data: payload[FRAGMENT_HEADER_LEN..].to_vec(),
+        };
+        if fragment.data.len() != fragment.header.fec.fragment_size
+            || fragment.block as usize >= fragment.header.blocks()
+            || fragment.shard as usize >= fragment.header.fec.total_shards()
+        {
+            return Err(BroadcastError::Invalid("fragment out of range"));
+        }
+
+        Ok(fragment)
+    }
+}
+
+/// 编码一个固件，返回按发送顺序排列的分片
+/// - 最后一块不足的数据分片视为全零，只参与编码，不发送
+/// - 各块交织发送，连续丢包分散到不同的块
+pub fn encode_image(
+    image: &FirmwareImage,
+    fec: FecParams,
+) -> Result<Vec<Fragment>, BroadcastError> {
+    fec.validate()?;
+    let header = FragmentHeader {
+        code: image.code,
+        version: image.version.clone(),
+        size: image.data.len() as u32,
+        fec,
+    };
+    // 分片序号与下载切片序号相同，为2字节
+    if header.fragments() > u16::MAX as usize {
+        return Err(BroadcastError::Params("too many fragments"));
+    }
+
+    let rs = ReedSolomon::new(fec.data_shards, fec.parity_shards)?;
+    let mut blocks = Vec::with_capacity(header.blocks());
+    for block in 0..header.blocks() {
+        let mut shards = vec![vec![0u8; fec.fragment_size]; fec.total_shards()];
+        for (shard, buffer) in shards.iter_mut().take(fec.data_shards).enumerate() {
+            if let Some(data) = image.slice(block * fec.data_shards + shard, fec.fragment_size) {
+                buffer[..data.len()].copy_from_slice(&data);
+            }
+        }
+        rs.encode(&mut shards)?;
+        blocks.push(shards);
+    }
+
+    let fragments = header.fragments();
+    let mut output = Vec::new();
+    for shard in 0..fec.total_shards() {
+        for (block, shards) in blocks.iter_mut().enumerate() {
+            if shard < fec.data_shards && block * fec.data_shards + shard >= fragments {
+                continue;
+            }
+            output.push(Fragment {
+                header: header.clone(),
+                block: block as u16,
+                shard: shard as u8,
+                data: std::mem::take(&mut shards[shard]),
+            });
+        }
+    }
+
+    Ok(output)
+}
+
+/// 设备端重组的参考实现
+/// - 收到的分片按块保存，块内分片足够时恢复缺失的数据分片
+/// - 无法恢复的数据分片通过 `FirmwareDownload` 补齐，切片大小为分片大小
+pub struct Reassembler {
+    header: FragmentHeader,
+    rs: ReedSolomon,
+    blocks: Vec<Vec<Option<Vec<u8>>>>,
+}
+
+impl Reassembler {
+    pub fn new(header: FragmentHeader) -> Result<Reassembler, BroadcastError> {
+        let fec = header.fec;
+        fec.validate()?;
+        let rs = ReedSolomon::new(fec.data_shards, fec.parity_shards)?;
+
+        // 不发送的补零分片直接填入
+        let fragments = header.fragments();
+        let blocks = (0..header.blocks())
+            .map(|block| {
+                (0..fec.total_shards())
+                    .map(|shard| {
+                        let virtual_shard =
+                            shard < fec.data_shards && block * fec.data_shards + shard >= fragments;
+                        virtual_shard.then(|| vec![0u8; fec.fragment_size])
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Ok(Reassembler { header, rs, blocks })
+    }
+
+    pub fn header(&self) -> &FragmentHeader {
+        &self.header
+    }
+
+    /// 加入一个组播分片，返回是否为新分片
+    pub fn push(&mut self, fragment: Fragment) -> Result<bool, BroadcastError> {
+        if fragment.header != self.header {
+            return Err(BroadcastError::Mismatch);
+        }
+        let block = fragment.block as usize;
+        let slot = &mut self.blocks[block][fragment.shard as usize];
+        if slot.is_some() {
+            return Ok(false);
+        }
+        *slot = Some(fragment.data);
+        self.recover(block)?;
+        Ok(true)
+    }
+
+    /// 加入单播下载的数据分片，最后一片长度可以不足
+    pub fn insert_fragment(&mut self, index: usize, data: &[u8]) -> Result<(), BroadcastError> {
+        let fec = self.header.fec;
+        if index >= self.header.fragments() || data.len() > fec.fragment_size {
+            return Err(BroadcastError::Invalid("fragment out of range"));
+        }
+        let mut buffer = vec![0u8; fec.fragment_size];
+        buffer[..data.len()].copy_from_slice(data);
+
+        let block = index / fec.data_shards;
+        self.blocks[block][index % fec.data_shards] = Some(buffer);
+        self.recover(block)
+    }
+
+    /// 块内分片足够时恢复缺失的数据分片
+    fn recover(&mut self, block: usize) -> Result<(), BroadcastError> {
+        let shards = &mut self.blocks[block];
+        let received = shards.iter().filter(|shard| shard.is_some()).count();
+        let complete = shards[..self.header.fec.data_shards]
+            .iter()
+            .all(Option::is_some);
+
+        if !complete && received >= self.header.fec.data_shards {
+            self.rs.reconstruct_data(shards)?;
+        }
+        Ok(())
+    }
+
+    /// 仍然缺少的数据分片序号，需要通过单播下载
+    pub fn missing(&self) -> Vec<usize> {
+        let fec = self.header.fec;
+        let fragments = self.header.fragments();
+        self.blocks
+            .iter()
+            .enumerate()
+            .flat_map(|(block, shards)| {
+                shards[..fec.data_shards]
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, shard)| shard.is_none())
+                    .map(move |(shard, _)| block * fec.data_shards + shard)
+            })
+            .filter(|index| *index < fragments)
+            .collect()
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.missing().is_empty()
+    }
+
+    /// 完整的固件，还有缺失时为 None
+    pub fn image(&self) -> Option<Vec<u8>> {
+        let data_shards = self.header.fec.data_shards;
+        let mut image = Vec::with_capacity(self.header.size as usize);
+        for shards in &self.blocks {
+            for shard in &shards[..data_shards] {
+                image.extend_from_slice(shard.as_ref()?);
+            }
+        }
+        image.truncate(self.header.size as usize);
+        Some(image)
+    }
+}
+
+/// 组播广播参数
+#[derive(Debug, Clone)]
+pub struct BroadcastOptions {
+    /// 广播的固件代号，每轮发送各自的最新固件
+    pub codes: Vec<i32>,
+    /// 组播地址
+    pub group: SocketAddr,
+    pub ttl: u32,
+    /// 两轮广播之间的间隔
+    pub interval: Duration,
+    /// 每秒发送的数据报数
+    pub rate: u32,
+    pub fec: FecParams,
+}
+
+/// 组播广播任务，循环发送各代号的最新固件，收到退出通知后停止
+/// - 固件缓存变化时立即开始下一轮，启动时缓存为空也不必等待一个间隔
+pub async fn serve_broadcast(
+    socket: UdpSocket,
+    options: BroadcastOptions,
+    fw_cache: Arc<FirmwareCache>,
+    mut shutdown: Shutdown,
+) {
+    if let Err(e) = socket.set_multicast_ttl_v4(options.ttl) {
+        warn!("Failed to set multicast TTL: {}", e);
+    }
+    info!("Broadcasting {:04X?} to {}", options.codes, options.group);
+
+    // 编码结果按固件缓存，固件更新后重新编码
+    let mut encoded: HashMap<i32, (i32, String, Vec<Vec<u8>>)> = HashMap::new();
+    let period = Duration::from_secs_f64(1.0 / options.rate.max(1) as f64);
+    let mut changes = fw_cache.subscribe();
+    let mut pace = time::interval(period);
+    pace.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        for &code in &options.codes {
+            let snapshot = fw_cache.load();
+            let Some(meta) = snapshot.find_latest(code) else {
+                debug!("No firmware to broadcast for {:04X}", code);
+                continue;
+            };
+
+            let cached = encoded
+                .get(&code)
+                .is_some_and(|(id, hash, _)| *id == meta.id && *hash == meta.hash);
+            if !cached {
+                let Some(image) = fw_cache.image(meta).await else {
+                    warn!("Failed to load firmware {:04X} for broadcast", code);
+                    continue;
+                };
+                match encode_image(&image, options.fec) {
+                    Ok(fragments) => {
+                        let datagrams = fragments.iter().map(Fragment::encode).collect();
+                        encoded.insert(code, (meta.id, meta.hash.clone(), datagrams));
+                    }
+                    Err(e) => {
+                        error!("Failed to encode firmware {:04X}: {}", code, e);
+                        continue;
+                    }
+                }
+            }
+
+            let datagrams = &encoded[&code].2;
+            info!(
+                "Broadcasting firmware {:04X} {}.{}.{}, {} datagrams",
+                code,
+                meta.version_m,
+                meta.version_n,
+                meta.version_l,
+                datagrams.len()
+            );
+            for datagram in datagrams {
+                tokio::select! {
+                    _ = pace.tick() => {}
+                    _ = shutdown.recv() => return,
+                }
+                match socket.send_to(datagram, options.group).await {
+                    Ok(_) => metrics().firmware_broadcast(code, datagram.len()),
+                    Err(e) => warn!("Broadcast send error: {}", e),
+                }
+            }
+        }
+
+        tokio::select! {
+            _ = time::sleep(options.interval) => {}
+            _ = changes.changed() => {}
+            _ = shutdown.recv() => return,
+        }
+    }
+}
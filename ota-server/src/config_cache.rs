@@ -0,0 +1,92 @@
+use arc_swap::ArcSwapOption;
+use ota_database::models::{config_history::ConfigHistory, config_schema::ConfigSchema};
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tokio::time::Instant;
+use tracing::debug;
+
+use crate::source::{FirmwareSource, SourceResult};
+
+/// 配置及其模板
+#[derive(Debug, Default)]
+pub struct ConfigSet {
+    pub configs: Vec<ConfigHistory>,
+    pub schemas: HashMap<i32, ConfigSchema>,
+}
+
+impl ConfigSet {
+    pub fn new(configs: Vec<ConfigHistory>, schemas: Vec<ConfigSchema>) -> Self {
+        ConfigSet {
+            configs,
+            schemas: schemas
+                .into_iter()
+                .map(|schema| (schema.id, schema))
+                .collect(),
+        }
+    }
+
+    /// 配置使用的模板，旧格式配置返回 None
+    pub fn schema(&self, config: &ConfigHistory) -> Option<&ConfigSchema> {
+        config.schema_id.and_then(|id| self.schemas.get(&id))
+    }
+}
+
+/// 已加载的配置
+#[derive(Debug)]
+struct ConfigEntry {
+    loaded_at: Instant,
+    configs: Arc<ConfigSet>,
+}
+
+/// 配置缓存
+/// - 收到变化通知时失效
+/// - 超过 ttl 后重新读取，作为通知丢失时的兜底
+#[derive(Debug)]
+pub struct ConfigCache {
+    source: Arc<dyn FirmwareSource>,
+    ttl: Duration,
+    current: ArcSwapOption<ConfigEntry>,
+}
+
+impl ConfigCache {
+    pub fn new(source: Arc<dyn FirmwareSource>, ttl: Duration) -> Self {
+        ConfigCache {
+            source,
+            ttl,
+            current: ArcSwapOption::empty(),
+        }
+    }
+
+    /// 读取所有配置，缓存失效时从配置来源读取
+    pub async fn get(&self) -> SourceResult<Arc<ConfigSet>> {
+        if let Some(entry) = self.current.load_full() {
+            if entry.loaded_at.elapsed() < self.ttl {
+                return Ok(Arc::clone(&entry.configs));
+            }
+        }
+
+        let configs = self.source.list_configs().await?;
+        let schemas = self.source.list_config_schemas().await?;
+        let configs = Arc::new(ConfigSet::new(configs, schemas));
+        debug!(
+            "Config cache loaded, {} configs, {} schemas",
+            configs.configs.len(),
+            configs.schemas.len()
+        );
+
+        // 空列表不缓存
+        if !configs.configs.is_empty() {
+            self.current.store(Some(Arc::new(ConfigEntry {
+                loaded_at: Instant::now(),
+                configs: Arc::clone(&configs),
+            })));
+        }
+
+        Ok(configs)
+    }
+
+    /// 使缓存失效
+    pub fn invalidate(&self) {
+        debug!("Config cache invalidated");
+        self.current.store(None);
+    }
+}
@@ -0,0 +1,32 @@
+use std::sync::Arc;
+
+use crate::{
+    config_cache::ConfigCache, download::DownloadTracker, fw_cache::FirmwareCache,
+    handler::HandlerRegistry, history::HistoryQueue, record::Recorder, registry::SessionRegistry,
+    source::Backend, ProtocolVersion,
+};
+
+/// 协议选项
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ProtocolOptions {
+    /// 默认协议版本，设备可在时间同步请求中声明自己的版本
+    pub version: ProtocolVersion,
+    /// 时区偏移（分钟），未配置时时间同步包不带偏移
+    pub tz_offset: Option<i16>,
+}
+
+/// 连接任务共享的服务，每个连接持有一份克隆
+#[derive(Clone)]
+pub struct ServerContext {
+    pub fw_cache: Arc<FirmwareCache>,
+    pub config_cache: Arc<ConfigCache>,
+    pub history: HistoryQueue,
+    pub backend: Arc<dyn Backend>,
+    pub registry: Arc<SessionRegistry>,
+    pub downloads: Arc<DownloadTracker>,
+    pub protocol: ProtocolOptions,
+    /// 会话录制，未开启时为 None
+    pub recorder: Option<Arc<Recorder>>,
+    /// 包处理器，内置包类型和产品自定义的包类型
+    pub handlers: Arc<HandlerRegistry>,
+}
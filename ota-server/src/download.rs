@@ -0,0 +1,257 @@
+use chrono::{NaiveDateTime, Utc};
+use ota_database::models::download_session::{DownloadOutcome, NewDownloadSession};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+};
+use tokio::time::{self, Duration, Instant};
+use tracing::{debug, info};
+
+use crate::{
+    fw_cache::FirmwareKey, history::HistoryQueue, session::DeviceSession, shutdown::Shutdown,
+};
+
+/// 超过此时间没有下载请求的会话视为放弃
+pub const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// 下载会话所属：已识别的设备按设备ID，未识别的设备按连接
+/// NAT 或网关后的多台设备共用同一IP，不能按IP区分
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Owner {
+    Device(String),
+    Connection(u64),
+}
+
+impl Owner {
+    fn new(device_id: Option<&str>, conn_id: u64) -> Self {
+        match device_id {
+            Some(device_id) => Owner::Device(device_id.to_string()),
+            None => Owner::Connection(conn_id),
+        }
+    }
+}
+
+/// 下载会话索引：设备 + 固件
+type DownloadKey = (Owner, FirmwareKey);
+
+/// 进行中的下载
+#[derive(Debug)]
+struct ActiveDownload {
+    device_id: Option<String>,
+    peer: String,
+    started_at: NaiveDateTime,
+    last_active_at: NaiveDateTime,
+    last_active: Instant,
+    slice_size: u16,
+    served: HashSet<u16>,
+    slices_served: i32,
+    slices_repeated: i32,
+    bytes_served: i64,
+}
+
+impl ActiveDownload {
+    fn summary(
+        self,
+        fw: FirmwareKey,
+        ended_at: NaiveDateTime,
+        outcome: DownloadOutcome,
+    ) -> NewDownloadSession {
+        NewDownloadSession {
+            device_id: self.device_id,
+            peer: Some(self.peer),
+            fwcode: fw.0,
+            version_m: fw.1,
+            version_n: fw.2,
+            version_l: fw.3,
+            slice_size: self.slice_size as i32,
+            slices_served: self.slices_served,
+            slices_repeated: self.slices_repeated,
+            bytes_served: self.bytes_served,
+            started_at: self.started_at,
+            ended_at,
+            outcome,
+            upgrade_history_id: None,
+        }
+    }
+}
+
+/// 进行中下载的进度，供管理接口查看
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DownloadProgress {
+    pub fw: FirmwareKey,
+    pub slice_size: u16,
+    /// 已发送的不同分片数
+    pub slices: usize,
+    pub bytes_served: i64,
+    pub last_active_at: NaiveDateTime,
+}
+
+/// 下载会话跟踪，已识别的设备断线重连后继续累计同一会话
+#[derive(Debug, Default)]
+pub struct DownloadTracker {
+    sessions: Mutex<HashMap<DownloadKey, ActiveDownload>>,
+}
+
+impl DownloadTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 记录一次已发送的分片
+    /// 设备ID在下载中途才获得时，把按连接记录的会话转到设备ID下
+    pub fn record_slice(
+        &self,
+        session: &DeviceSession,
+        fw: FirmwareKey,
+        index: u16,
+        slice_size: u16,
+        bytes: usize,
+    ) {
+        let device_id = session.device_id.as_deref();
+        let peer = &session.peer;
+        let mut sessions = self.sessions.lock().unwrap();
+        let key = (Owner::new(device_id, session.conn_id), fw);
+
+        if device_id.is_some() && !sessions.contains_key(&key) {
+            if let Some(active) = sessions.remove(&(Owner::Connection(session.conn_id), fw)) {
+                sessions.insert(key.clone(), active);
+            }
+        }
+
+        let now = Utc::now().naive_utc();
+        let active = sessions.entry(key).or_insert_with(|| {
+            debug!(
+                "Download started, device:{:?}, peer:{}, fw:{:?}",
+                device_id, peer, fw
+            );
+            ActiveDownload {
+                device_id: None,
+                peer: peer.to_string(),
+                started_at: now,
+                last_active_at: now,
+                last_active: Instant::now(),
+                slice_size,
+                served: HashSet::new(),
+                slices_served: 0,
+                slices_repeated: 0,
+                bytes_served: 0,
+            }
+        });
+
+        if let Some(device_id) = device_id {
+            active.device_id = Some(device_id.to_string());
+        }
+        active.peer = peer.to_string();
+        active.last_active_at = now;
+        active.last_active = Instant::now();
+        active.slice_size = active.slice_size.max(slice_size);
+        active.slices_served += 1;
+        active.bytes_served += bytes as i64;
+        if !active.served.insert(index) {
+            active.slices_repeated += 1;
+        }
+    }
+
+    /// 下载结束，返回会话统计，没有对应会话时返回 None
+    pub fn finish(
+        &self,
+        device_id: Option<&str>,
+        conn_id: u64,
+        fw: FirmwareKey,
+        outcome: DownloadOutcome,
+    ) -> Option<NewDownloadSession> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let active = device_id
+            .and_then(|device_id| sessions.remove(&(Owner::Device(device_id.to_string()), fw)))
+            .or_else(|| sessions.remove(&(Owner::Connection(conn_id), fw)))?;
+
+        let mut summary = active.summary(fw, Utc::now().naive_utc(), outcome);
+        if summary.device_id.is_none() {
+            summary.device_id = device_id.map(str::to_string);
+        }
+        Some(summary)
+    }
+
+    /// 取出超时的会话，结束时间为最后一次请求的时间
+    pub fn expire(&self, now: Instant, timeout: Duration) -> Vec<NewDownloadSession> {
+        self.drain(|active| now.duration_since(active.last_active) >= timeout)
+    }
+
+    /// 取出所有会话，退出时使用
+    pub fn expire_all(&self) -> Vec<NewDownloadSession> {
+        self.drain(|_| true)
+    }
+
+    /// 设备（未识别时为连接）最近活动的下载
+    pub fn progress(&self, device_id: Option<&str>, conn_id: u64) -> Option<DownloadProgress> {
+        let owner = Owner::new(device_id, conn_id);
+        self.sessions
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|((key, _), _)| *key == owner)
+            .max_by_key(|(_, active)| active.last_active)
+            .map(|((_, fw), active)| DownloadProgress {
+                fw: *fw,
+                slice_size: active.slice_size,
+                slices: active.served.len(),
+                bytes_served: active.bytes_served,
+                last_active_at: active.last_active_at,
+            })
+    }
+
+    pub fn len(&self) -> usize {
+        self.sessions.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sessions.lock().unwrap().is_empty()
+    }
+
+    fn drain<F: Fn(&ActiveDownload) -> bool>(&self, f: F) -> Vec<NewDownloadSession> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let expired: Vec<DownloadKey> = sessions
+            .iter()
+            .filter(|(_, active)| f(active))
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        expired
+            .into_iter()
+            .filter_map(|key| {
+                let active = sessions.remove(&key)?;
+                let ended_at = active.last_active_at;
+                Some(active.summary(key.1, ended_at, DownloadOutcome::Abandoned))
+            })
+            .collect()
+    }
+}
+
+/// 定时清理超时的下载会话，退出时把进行中的会话都记为放弃
+pub async fn expire_downloads(
+    tracker: Arc<DownloadTracker>,
+    history: HistoryQueue,
+    mut shutdown: Shutdown,
+) {
+    let mut interval = time::interval(DOWNLOAD_TIMEOUT / 4);
+
+    loop {
+        let expired = tokio::select! {
+            _ = interval.tick() => tracker.expire(Instant::now(), DOWNLOAD_TIMEOUT),
+            _ = shutdown.recv() => {
+                for download in tracker.expire_all() {
+                    history.push_download(download);
+                }
+                break;
+            }
+        };
+
+        for download in expired {
+            info!(
+                "Download abandoned, device:{:?}, code:{:04X}, slices:{}",
+                download.device_id, download.fwcode, download.slices_served
+            );
+            history.push_download(download);
+        }
+    }
+}
@@ -0,0 +1,463 @@
+use arc_swap::ArcSwap;
+use bytes::Bytes;
+use ota_database::models::firmware_data::{FirmwareInfo, FirmwareMeta, FirmwareVersion};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+use tokio::{
+    sync::{watch, OnceCell},
+    task,
+    time::{self, Duration},
+};
+use tracing::{debug, error, info, warn};
+
+use crate::{
+    merkle::{slice_size_allowed, MerkleTree},
+    metrics::metrics,
+    source::{FirmwareSource, SourceResult},
+};
+
+/// 每个固件最多缓存的 Merkle 树个数，超出时丢弃最早构建的
+pub const MERKLE_CACHE_LIMIT: usize = 4;
+
+type MerkleCell = Arc<OnceCell<Arc<MerkleTree>>>;
+
+/// 固件索引：(code, m, n, l)
+pub type FirmwareKey = (i32, i32, i32, i32);
+
+/// 元数据对应的索引
+pub fn meta_key(meta: &FirmwareMeta) -> FirmwareKey {
+    (meta.fwcode, meta.version_m, meta.version_n, meta.version_l)
+}
+
+/// 固件镜像，数据以 `Bytes` 保存，切片时只增加引用计数
+#[derive(Debug, Clone)]
+pub struct FirmwareImage {
+    pub id: i32,
+    pub code: i32,
+    pub version: FirmwareVersion,
+    pub size: i32,
+    pub hash: String,
+    pub data: Bytes,
+    /// 按切片大小缓存的 Merkle 树，克隆之间共享
+    merkle: Arc<Mutex<VecDeque<(usize, MerkleCell)>>>,
+}
+
+impl FirmwareImage {
+    pub fn new(meta: &FirmwareMeta, data: Bytes) -> Self {
+        FirmwareImage {
+            id: meta.id,
+            code: meta.fwcode,
+            version: meta.version(),
+            size: meta.fwsize,
+            hash: meta.hash.clone(),
+            data,
+            merkle: Arc::default(),
+        }
+    }
+
+    /// 生成下行数据包使用的固件信息
+    pub fn info(&self) -> FirmwareInfo {
+        FirmwareInfo {
+            code: self.code,
+            version: self.version.clone(),
+            size: self.size,
+            path: String::from(""),
+        }
+    }
+
+    /// 切片上的 Merkle 树，切片大小不允许时返回 None
+    /// - 同一切片大小并发请求时只构建一次
+    /// - 构建在阻塞线程中进行，不持有锁
+    pub async fn merkle(&self, slice_size: usize) -> Option<Arc<MerkleTree>> {
+        if !slice_size_allowed(slice_size) {
+            return None;
+        }
+
+        let cell = {
+            let mut trees = self.merkle.lock().unwrap();
+            match trees.iter().find(|(size, _)| *size == slice_size) {
+                Some((_, cell)) => cell.clone(),
+                None => {
+                    if trees.len() >= MERKLE_CACHE_LIMIT {
+                        trees.pop_front();
+                    }
+                    let cell = MerkleCell::default();
+                    trees.push_back((slice_size, cell.clone()));
+                    cell
+                }
+            }
+        };
+
+        let data = self.data.clone();
+        cell.get_or_try_init(|| async move {
+            task::spawn_blocking(move || Arc::new(MerkleTree::build(&data, slice_size))).await
+        })
+        .await
+        .ok()
+        .cloned()
+    }
+
+    /// 切片固件数据，不复制
+    pub fn slice(&self, index: usize, slice_size: usize) -> Option<Bytes> {
+        let start_position = index.checked_mul(slice_size)?;
+
+        // 先判断start_position有没有越界
+        if start_position >= self.data.len() {
+            return None;
+        }
+
+        // 再判断end_position有没有越界
+        let end_position = std::cmp::min(start_position + slice_size, self.data.len());
+
+        if end_position <= start_position {
+            return None;
+        }
+
+        Some(self.data.slice(start_position..end_position))
+    }
+}
+
+/// 固件元数据快照，创建后只读
+#[derive(Debug, Default)]
+pub struct FirmwareSnapshot {
+    metas: HashMap<FirmwareKey, Arc<FirmwareMeta>>,
+    latest: HashMap<i32, Arc<FirmwareMeta>>,
+}
+
+impl FirmwareSnapshot {
+    pub fn new(metas: Vec<FirmwareMeta>) -> Self {
+        let mut snapshot = FirmwareSnapshot::default();
+
+        for meta in metas {
+            let meta = Arc::new(meta);
+
+            // 每个code只保留最新版本
+            let newer = match snapshot.latest.get(&meta.fwcode) {
+                Some(current) => meta_key(&meta) > meta_key(current),
+                None => true,
+            };
+            if newer {
+                snapshot.latest.insert(meta.fwcode, Arc::clone(&meta));
+            }
+
+            snapshot.metas.insert(meta_key(&meta), meta);
+        }
+
+        snapshot
+    }
+
+    /// 根据code查找最新版本的固件
+    pub fn find_latest(&self, code: i32) -> Option<&Arc<FirmwareMeta>> {
+        self.latest.get(&code)
+    }
+
+    /// 根据code和version查找具体固件
+    pub fn find(&self, code: i32, version: &FirmwareVersion) -> Option<&Arc<FirmwareMeta>> {
+        self.metas.get(&(code, version.m, version.n, version.l))
+    }
+
+    /// 各code的最新版本是否相同
+    pub fn same_latest(&self, other: &FirmwareSnapshot) -> bool {
+        self.latest.len() == other.latest.len()
+            && self.latest.iter().all(|(code, meta)| {
+                other.latest.get(code).is_some_and(|other| {
+                    meta_key(meta) == meta_key(other) && meta.hash == other.hash
+                })
+            })
+    }
+
+    /// 固件id和hash是否仍然有效
+    pub fn contains(&self, id: i32, hash: &str) -> bool {
+        self.metas
+            .values()
+            .any(|meta| meta.id == id && meta.hash == hash)
+    }
+
+    /// 固件id是否仍在快照中
+    pub fn contains_id(&self, id: i32) -> bool {
+        self.metas.values().any(|meta| meta.id == id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.metas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.metas.is_empty()
+    }
+}
+
+/// 按字节数限制大小的LRU固件缓存
+#[derive(Debug)]
+pub struct BlobCache {
+    max_bytes: usize,
+    used_bytes: usize,
+    images: HashMap<i32, FirmwareImage>,
+    order: VecDeque<i32>, // 队首最久未使用
+    /// 最近一个超过容量的固件，单独保存，不计入容量，避免每个分片都重新下载
+    pinned: Option<FirmwareImage>,
+}
+
+impl BlobCache {
+    pub fn new(max_bytes: usize) -> Self {
+        BlobCache {
+            max_bytes,
+            used_bytes: 0,
+            images: HashMap::new(),
+            order: VecDeque::new(),
+            pinned: None,
+        }
+    }
+
+    pub fn max_bytes(&self) -> usize {
+        self.max_bytes
+    }
+
+    /// 读取固件，hash不一致视为未缓存
+    pub fn get(&mut self, id: i32, hash: &str) -> Option<FirmwareImage> {
+        if let Some(image) = self
+            .pinned
+            .as_ref()
+            .filter(|image| image.id == id && image.hash == hash)
+        {
+            return Some(image.clone());
+        }
+
+        let image = self
+            .images
+            .get(&id)
+            .filter(|image| image.hash == hash)?
+            .clone();
+        self.touch(id);
+        Some(image)
+    }
+
+    /// 加入缓存，超出容量时淘汰最久未使用的固件
+    pub fn insert(&mut self, image: FirmwareImage) {
+        self.remove(image.id);
+
+        // 单个固件超过容量时单独保存，替换之前超过容量的固件
+        if image.data.len() > self.max_bytes {
+            warn!(
+                "Firmware id:{} ({} bytes) exceeds cache size {}, kept outside the cache",
+                image.id,
+                image.data.len(),
+                self.max_bytes
+            );
+            self.pinned = Some(image);
+            return;
+        }
+
+        while self.used_bytes + image.data.len() > self.max_bytes {
+            match self.order.pop_front() {
+                Some(oldest) => {
+                    if let Some(evicted) = self.images.remove(&oldest) {
+                        debug!("Evict firmware id:{} from cache", evicted.id);
+                        self.used_bytes -= evicted.data.len();
+                    }
+                }
+                None => break,
+            }
+        }
+
+        self.used_bytes += image.data.len();
+        self.order.push_back(image.id);
+        self.images.insert(image.id, image);
+    }
+
+    /// 删除缓存
+    pub fn remove(&mut self, id: i32) {
+        if self.pinned.as_ref().is_some_and(|image| image.id == id) {
+            self.pinned = None;
+        }
+        if let Some(image) = self.images.remove(&id) {
+            self.used_bytes -= image.data.len();
+            self.order.retain(|x| *x != id);
+        }
+    }
+
+    /// 只保留满足条件的固件
+    pub fn retain<F: Fn(&FirmwareImage) -> bool>(&mut self, f: F) {
+        let stale: Vec<i32> = self
+            .images
+            .values()
+            .chain(self.pinned.as_ref())
+            .filter(|image| !f(image))
+            .map(|image| image.id)
+            .collect();
+
+        for id in stale {
+            self.remove(id);
+        }
+    }
+
+    pub fn used_bytes(&self) -> usize {
+        self.used_bytes
+    }
+
+    pub fn len(&self) -> usize {
+        self.images.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.images.is_empty()
+    }
+
+    fn touch(&mut self, id: i32) {
+        self.order.retain(|x| *x != id);
+        self.order.push_back(id);
+    }
+}
+
+/// 固件缓存
+/// - 元数据快照：读取时无锁获取，刷新时整体替换
+/// - 固件内容：首次使用或hash变化时才下载，放入LRU缓存
+#[derive(Debug)]
+pub struct FirmwareCache {
+    source: Arc<dyn FirmwareSource>,
+    current: ArcSwap<FirmwareSnapshot>,
+    blobs: Mutex<BlobCache>,
+    /// 按固件id的下载锁，不同固件的下载互不阻塞
+    fetch_locks: Mutex<HashMap<i32, Arc<tokio::sync::Mutex<()>>>>,
+    changed: watch::Sender<u64>, // 最新固件变化次数
+    loaded: AtomicBool,          // 至少刷新成功过一次
+}
+
+impl FirmwareCache {
+    pub fn new(source: Arc<dyn FirmwareSource>, max_bytes: usize) -> Self {
+        FirmwareCache {
+            source,
+            current: ArcSwap::default(),
+            blobs: Mutex::new(BlobCache::new(max_bytes)),
+            fetch_locks: Mutex::default(),
+            changed: watch::Sender::new(0),
+            loaded: AtomicBool::new(false),
+        }
+    }
+
+    /// 获取当前快照
+    pub fn load(&self) -> Arc<FirmwareSnapshot> {
+        self.current.load_full()
+    }
+
+    /// 替换快照，同时丢弃已删除或内容已变化的固件
+    /// 最新固件有变化时通知订阅者
+    pub fn store(&self, snapshot: FirmwareSnapshot) {
+        self.blobs
+            .lock()
+            .unwrap()
+            .retain(|image| snapshot.contains(image.id, &image.hash));
+        self.fetch_locks
+            .lock()
+            .unwrap()
+            .retain(|id, _| snapshot.contains_id(*id));
+        let previous = self.current.swap(Arc::new(snapshot));
+        if !previous.same_latest(&self.current.load()) {
+            self.changed.send_modify(|count| *count += 1);
+        }
+        self.loaded.store(true, Ordering::Release);
+    }
+
+    /// 是否已加载过元数据，来源为空也算已加载
+    pub fn is_loaded(&self) -> bool {
+        self.loaded.load(Ordering::Acquire)
+    }
+
+    /// 订阅最新固件变化
+    pub fn subscribe(&self) -> watch::Receiver<u64> {
+        self.changed.subscribe()
+    }
+
+    /// 获取固件内容，未缓存时从固件来源读取
+    pub async fn image(&self, meta: &FirmwareMeta) -> Option<FirmwareImage> {
+        if let Some(image) = self.blobs.lock().unwrap().get(meta.id, &meta.hash) {
+            return Some(image);
+        }
+
+        // 同一固件同一时间只下载一次，避免大量设备同时请求时重复下载
+        let fetch_lock = self
+            .fetch_locks
+            .lock()
+            .unwrap()
+            .entry(meta.id)
+            .or_default()
+            .clone();
+        let _guard = fetch_lock.lock().await;
+        if let Some(image) = self.blobs.lock().unwrap().get(meta.id, &meta.hash) {
+            return Some(image);
+        }
+
+        match self.source.read_firmware(meta).await {
+            Ok(fw_data) => {
+                info!("Firmware loaded -> {}", meta);
+                let image = FirmwareImage::new(meta, Bytes::from(fw_data));
+                self.blobs.lock().unwrap().insert(image.clone());
+                Some(image)
+            }
+            Err(e) => {
+                error!("Error:{}, id={}", e, meta.id);
+                None
+            }
+        }
+    }
+
+    /// 刷新元数据
+    pub async fn refresh(&self) -> SourceResult<usize> {
+        // 失败的刷新同样计时
+        let _timer = metrics().cache_refresh.start_timer();
+        let metas = self.source.list_firmware().await.inspect_err(|_| {
+            metrics().cache_refresh_failures.inc();
+        })?;
+
+        // 新增的固件超过缓存容量时提示调整
+        let max_bytes = self.blobs.lock().unwrap().max_bytes();
+        let previous = self.load();
+        for meta in metas.iter().filter(|meta| meta.fwsize as usize > max_bytes) {
+            if !previous.contains(meta.id, &meta.hash) {
+                warn!(
+                    "Firmware {} ({} bytes) exceeds cache size {}, only the latest such image is kept",
+                    meta, meta.fwsize, max_bytes
+                );
+            }
+        }
+
+        let snapshot = FirmwareSnapshot::new(metas);
+        let count = snapshot.len();
+        self.store(snapshot);
+        Ok(count)
+    }
+}
+
+/// 刷新固件元数据并记录日志，失败时保留原来的快照
+pub async fn refresh_and_log(cache: &FirmwareCache) {
+    match cache.refresh().await {
+        Ok(count) => {
+            let blobs = cache.blobs.lock().unwrap();
+            info!(
+                "Firmware cache refreshed, {} images, {} cached ({} bytes)",
+                count,
+                blobs.len(),
+                blobs.used_bytes()
+            );
+        }
+        Err(e) => {
+            error!("Error:{}", e);
+        }
+    }
+}
+
+/// 定时刷新固件元数据，第一次刷新在一个周期之后
+/// ## 参数
+/// - cache : 固件缓存
+/// - refresh_duration : 刷新周期
+pub async fn refresh_firmware_cache(cache: Arc<FirmwareCache>, refresh_duration: Duration) {
+    loop {
+        time::sleep(refresh_duration).await;
+        refresh_and_log(&cache).await;
+    }
+}
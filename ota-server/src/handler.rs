@@ -0,0 +1,112 @@
+use async_trait::async_trait;
+use std::{collections::HashMap, error::Error, fmt, sync::Arc};
+
+use crate::{
+    context::ServerContext,
+    package::tx_package::{send_custom_pkg, send_failed_package},
+    session::DeviceSession,
+    transport::Transport,
+    ErrorCode,
+};
+
+/// 处理一个包时可用的连接和共享服务
+pub struct ConnectionContext<'a> {
+    pub socket: &'a mut dyn Transport,
+    /// 固件缓存、后端等连接共享的服务
+    pub server: &'a ServerContext,
+    /// 连接状态，设备ID、协议版本等
+    pub session: &'a mut DeviceSession,
+}
+
+impl ConnectionContext<'_> {
+    /// 回复数据段，包类型为 0xFF - 请求类型
+    pub async fn reply(&mut self, package_type: u8, payload: &[u8]) -> Result<(), Box<dyn Error>> {
+        send_custom_pkg(package_type, payload, self.socket).await
+    }
+
+    /// 回复错误码
+    pub async fn reply_error(&mut self, code: ErrorCode) -> Result<(), Box<dyn Error>> {
+        send_failed_package(self.socket, code as u8).await
+    }
+}
+
+/// 包处理器，按包类型注册
+/// - 请求已通过长度和 CRC 检查，数据段不短于 `min_payload`，`request` 为整帧（含包头和 CRC）
+/// - 返回错误时断开连接，可恢复的错误应回复错误码后返回 Ok
+#[async_trait]
+pub trait PacketHandler: Send + Sync {
+    /// 名称，用于日志和指标
+    fn name(&self) -> &str;
+
+    /// 数据段的最小长度，更短的请求回复长度错误，不交给处理器
+    fn min_payload(&self) -> usize {
+        0
+    }
+
+    async fn handle(
+        &self,
+        request: &[u8],
+        conn: &mut ConnectionContext<'_>,
+    ) -> Result<(), Box<dyn Error>>;
+}
+
+/// 包类型到处理器的映射
+#[derive(Clone, Default)]
+pub struct HandlerRegistry {
+    handlers: HashMap<u8, Arc<dyn PacketHandler>>,
+}
+
+impl fmt::Debug for HandlerRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(
+                self.types()
+                    .into_iter()
+                    .map(|package_type| (package_type, self.handlers[&package_type].name())),
+            )
+            .finish()
+    }
+}
+
+impl HandlerRegistry {
+    /// 空的注册表
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 注册了内置包类型的注册表
+    pub fn builtin() -> Self {
+        let mut registry = Self::new();
+        crate::process_pg::register_builtin(&mut registry);
+        registry
+    }
+
+    /// 注册处理器，返回被替换的处理器，内置包类型可以被替换
+    ///
+    /// # Panics
+    /// 包类型或其回复类型与错误码冲突时
+    pub fn register<H: PacketHandler + 'static>(
+        &mut self,
+        package_type: u8,
+        handler: H,
+    ) -> Option<Arc<dyn PacketHandler>> {
+        assert!(
+            ErrorCode::from_u8(package_type).is_none()
+                && ErrorCode::from_u8(0xFF - package_type).is_none(),
+            "Package type 0x{:02X} conflicts with error codes",
+            package_type
+        );
+        self.handlers.insert(package_type, Arc::new(handler))
+    }
+
+    pub fn get(&self, package_type: u8) -> Option<&Arc<dyn PacketHandler>> {
+        self.handlers.get(&package_type)
+    }
+
+    /// 已注册的包类型，从小到大
+    pub fn types(&self) -> Vec<u8> {
+        let mut types: Vec<u8> = self.handlers.keys().copied().collect();
+        types.sort_unstable();
+        types
+    }
+}
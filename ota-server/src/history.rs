@@ -0,0 +1,116 @@
+use ota_database::models::{
+    download_session::NewDownloadSession, upgrade_history::NewUpgradeHistory,
+};
+use std::sync::Arc;
+use tokio::{
+    sync::mpsc,
+    task::JoinHandle,
+    time::{self, Instant},
+};
+use tracing::{error, info, warn, Instrument, Span};
+
+use crate::source::HistorySink;
+
+/// 待上传的记录
+#[derive(Debug)]
+enum HistoryRecord {
+    /// 升级记录，下载会话上传时关联到该升级记录
+    Upgrade(NewUpgradeHistory, Option<NewDownloadSession>),
+    /// 没有升级记录的下载会话（超时放弃）
+    Download(NewDownloadSession),
+}
+
+/// 升级记录上传队列
+/// 连接任务只负责入队，由后台任务统一上传，退出时可以等待队列排空
+#[derive(Clone)]
+pub struct HistoryQueue {
+    /// 附带入队时的 span，上传请求接到该连接的 trace 中
+    tx: mpsc::UnboundedSender<(HistoryRecord, Span)>,
+}
+
+impl HistoryQueue {
+    /// 启动上传任务
+    pub fn spawn(sink: Arc<dyn HistorySink>) -> (HistoryQueue, HistoryWorker) {
+        let (tx, mut rx) = mpsc::unbounded_channel::<(HistoryRecord, Span)>();
+
+        let handle = tokio::spawn(async move {
+            while let Some((record, span)) = rx.recv().await {
+                upload(sink.as_ref(), record).instrument(span).await;
+            }
+        });
+
+        (HistoryQueue { tx }, HistoryWorker { handle })
+    }
+
+    /// 升级记录入队，附带对应的下载会话
+    pub fn push(&self, new_history: NewUpgradeHistory, download: Option<NewDownloadSession>) {
+        if let Err(e) = self.tx.send((
+            HistoryRecord::Upgrade(new_history, download),
+            Span::current(),
+        )) {
+            warn!("Upgrade history dropped, uploader stopped: {:?}", e.0 .0);
+        }
+    }
+
+    /// 没有升级记录的下载会话入队
+    pub fn push_download(&self, download: NewDownloadSession) {
+        if let Err(e) = self
+            .tx
+            .send((HistoryRecord::Download(download), Span::current()))
+        {
+            warn!("Download session dropped, uploader stopped: {:?}", e.0 .0);
+        }
+    }
+}
+
+/// 上传一条记录，下载会话关联到刚上传的升级记录
+async fn upload(sink: &dyn HistorySink, record: HistoryRecord) {
+    let download = match record {
+        HistoryRecord::Upgrade(new_history, download) => {
+            match sink.push_history(&new_history).await {
+                Ok(history_id) => {
+                    info!("Upgrade history added successfully");
+                    download.map(|download| NewDownloadSession {
+                        upgrade_history_id: history_id,
+                        ..download
+                    })
+                }
+                Err(e) => {
+                    error!("Failed to upload upgrade_history: {}", e);
+                    download
+                }
+            }
+        }
+        HistoryRecord::Download(download) => Some(download),
+    };
+
+    if let Some(download) = download {
+        if let Err(e) = sink.push_download_session(&download).await {
+            error!("Failed to upload download session: {}", e);
+        }
+    }
+}
+
+/// 上传任务句柄
+pub struct HistoryWorker {
+    handle: JoinHandle<()>,
+}
+
+impl HistoryWorker {
+    /// 等待队列中剩余的记录上传完毕
+    /// 调用前需要释放所有 `HistoryQueue`，否则只能等到截止时间
+    pub async fn flush(self, deadline: Instant) -> bool {
+        let HistoryWorker { mut handle } = self;
+
+        match time::timeout_at(deadline, &mut handle).await {
+            Ok(_) => {
+                info!("Upgrade history flushed");
+                true
+            }
+            Err(_) => {
+                handle.abort();
+                false
+            }
+        }
+    }
+}
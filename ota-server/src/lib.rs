@@ -1,6 +1,26 @@
+use clap::ValueEnum;
+
+pub mod admin;
 pub mod args;
+pub mod broadcast;
+pub mod config_cache;
+pub mod context;
+pub mod download;
+pub mod fw_cache;
+pub mod handler;
+pub mod history;
+pub mod merkle;
+pub mod metrics;
+pub mod notify;
 pub mod package;
 pub mod process_pg;
+pub mod record;
+pub mod registry;
+pub mod server;
+pub mod session;
+pub mod shutdown;
+pub mod source;
+pub mod transport;
 
 /// LogicPi Logo
 pub const LOGO: &str = r"
@@ -17,12 +37,57 @@ pub enum PackageType {
     FirmwareDownload = 0xA2, // 固件下载
     DownloadEnd = 0xA3,      // 下载结束
     QueryConfig = 0xA4,      // 参数查询
+    ReportConfig = 0xA5,     // 上报实际配置
+    Command = 0xA6,          // 设备命令确认
+    UpdateAvailable = 0xA7,  // 新固件推送
+    TimeSync = 0xA8,         // 时间同步
 }
 
 impl PackageType {
     pub fn to_response(&self) -> u8 {
         0xFF - *self as u8
     }
+
+    pub fn from_u8(package_type: u8) -> Option<Self> {
+        match package_type {
+            0xA1 => Some(PackageType::FirmwareQuery),
+            0xA2 => Some(PackageType::FirmwareDownload),
+            0xA3 => Some(PackageType::DownloadEnd),
+            0xA4 => Some(PackageType::QueryConfig),
+            0xA5 => Some(PackageType::ReportConfig),
+            0xA6 => Some(PackageType::Command),
+            0xA7 => Some(PackageType::UpdateAvailable),
+            0xA8 => Some(PackageType::TimeSync),
+            _ => None,
+        }
+    }
+
+    /// 由回复的包类型得到请求的包类型
+    pub fn from_response(response: u8) -> Option<Self> {
+        Self::from_u8(0xFF - response)
+    }
+}
+
+/// 协议版本
+/// - V1 : 配置包中的年份为年份的高字节（旧设备依赖此行为）
+/// - V2 : 配置包中的年份为年份后两位
+#[derive(ValueEnum, Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum ProtocolVersion {
+    #[default]
+    #[value(name = "1")]
+    V1 = 1,
+    #[value(name = "2")]
+    V2 = 2,
+}
+
+impl ProtocolVersion {
+    pub fn from_u8(version: u8) -> Option<Self> {
+        match version {
+            1 => Some(ProtocolVersion::V1),
+            2 => Some(ProtocolVersion::V2),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -32,4 +97,19 @@ pub enum ErrorCode {
     NoFirmwareFound = 0xF2,
     FirmwareReadError = 0xF3,
     UnknownPackageType = 0xF4,
+    ConfigError = 0xF5,
+}
+
+impl ErrorCode {
+    pub fn from_u8(code: u8) -> Option<Self> {
+        match code {
+            0xF0 => Some(ErrorCode::CrcError),
+            0xF1 => Some(ErrorCode::LengthError),
+            0xF2 => Some(ErrorCode::NoFirmwareFound),
+            0xF3 => Some(ErrorCode::FirmwareReadError),
+            0xF4 => Some(ErrorCode::UnknownPackageType),
+            0xF5 => Some(ErrorCode::ConfigError),
+            _ => None,
+        }
+    }
 }
@@ -1,8 +1,23 @@
 use clap::Parser;
-use log::{error, info};
-use ota_server::{args::Cli, process_pg::handle_client, LOGO};
+use ota_server::{
+    args::{Cli, SourceKind},
+    broadcast::{BroadcastOptions, FecParams},
+    context::ProtocolOptions,
+    metrics::serve_metrics,
+    notify::{listen_pg_changes, subscribe_changes},
+    record::Recorder,
+    server::OtaServer,
+    shutdown::wait_for_signal,
+    source::{dir::DirSource, http::HttpBackend, metered::MeteredBackend, pg::PgBackend, Backend},
+    ProtocolVersion, LOGO,
+};
+use ota_telemetry::{init_tracing, LogFormat, TelemetryOptions};
+use tracing::info;
 
+use clap::ValueEnum;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
+use std::time::Duration;
 use std::{env, error::Error};
 use tokio::net::TcpListener;
 
@@ -16,43 +31,164 @@ async fn main() -> Result<(), Box<dyn Error>> {
     let version = env!("CARGO_PKG_VERSION");
     println!("OTA Server for IoT Devices, Version: {}", version);
 
-    // set log level
-    env::set_var("RUST_APP_LOG", "debug");
-    pretty_env_logger::init_custom_env("RUST_APP_LOG");
+    // 日志和链路追踪
+    let log_format = env::var("LOG_FORMAT")
+        .ok()
+        .and_then(|v| LogFormat::from_str(&v, true).ok())
+        .unwrap_or(cli.log_format);
+    let otlp_endpoint = env::var("OTLP_ENDPOINT").ok().or(cli.otlp_endpoint.clone());
+    let telemetry = init_tracing(&TelemetryOptions {
+        service: "ota-server",
+        filter_env: "RUST_APP_LOG",
+        default_filter: "debug,hyper=info,hyper_util=info,reqwest=info,h2=info",
+        format: log_format,
+        otlp_endpoint: otlp_endpoint.as_deref(),
+    })?;
 
     // parameters
-    let fw_server =
-        Arc::new(env::var("FW_SERVER").unwrap_or_else(|_| (cli.fw_server.clone()).to_string()));
+    let fw_server = env::var("FW_SERVER").unwrap_or_else(|_| cli.fw_server.clone());
+    let fw_db = env::var("FW_DB").unwrap_or_else(|_| cli.fw_db.clone());
+    let fw_dir = env::var("FW_DIR").unwrap_or_else(|_| cli.fw_dir.clone());
+    let source_kind = env::var("SOURCE")
+        .ok()
+        .and_then(|v| SourceKind::from_str(&v, true).ok())
+        .unwrap_or(cli.source);
     let port = env::var("PORT").unwrap_or_else(|_| (cli.port.clone() as u32).to_string());
+    let ws_port = env::var("WS_PORT")
+        .ok()
+        .and_then(|v| v.parse::<u16>().ok())
+        .or(cli.ws_port);
+    let admin_port = env::var("ADMIN_PORT")
+        .ok()
+        .and_then(|v| v.parse::<u16>().ok())
+        .or(cli.admin_port);
+    let admin_bind = env::var("ADMIN_BIND")
+        .ok()
+        .and_then(|v| v.parse::<IpAddr>().ok())
+        .unwrap_or(cli.admin_bind);
+    let metrics_port = env::var("METRICS_PORT")
+        .ok()
+        .and_then(|v| v.parse::<u16>().ok())
+        .unwrap_or(cli.metrics_port);
+    let drain_timeout = env::var("DRAIN_TIMEOUT")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(cli.drain_timeout);
+    let cache_size = env::var("CACHE_SIZE")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(cli.cache_size);
+    let protocol = ProtocolOptions {
+        version: env::var("PROTOCOL_VERSION")
+            .ok()
+            .and_then(|v| ProtocolVersion::from_str(&v, true).ok())
+            .unwrap_or(cli.protocol_version),
+        tz_offset: env::var("TZ_OFFSET")
+            .ok()
+            .and_then(|v| v.parse::<i16>().ok())
+            .or(cli.tz_offset),
+    };
 
-    // Create a listener
-    let server = format!("0.0.0.0:{}", port);
-    let listener = TcpListener::bind(&server).await?;
-    info!("Server listening on {}", &server);
+    // 会话录制，目标为设备ID或IP，逗号分隔
+    let record_targets = env::var("RECORD")
+        .map(|v| v.split(',').map(str::to_string).collect())
+        .unwrap_or_else(|_| cli.record.clone());
+    let record_dir = env::var("RECORD_DIR").unwrap_or_else(|_| cli.record_dir.clone());
+    let recorder = Some(Recorder::new(&record_dir, &record_targets))
+        .filter(|recorder| !recorder.is_empty())
+        .map(Arc::new);
+    if recorder.is_some() {
+        info!(
+            "Recording sessions of {:?} to {}",
+            record_targets, record_dir
+        );
+    }
 
-    // Create an Arc Mutex to hold firmware data
-    let fw_data_all = Arc::new(tokio::sync::Mutex::new(Vec::new()));
+    // 组播广播的固件代号，十六进制，逗号分隔
+    let broadcast_codes: Vec<i32> = env::var("BROADCAST")
+        .map(|v| {
+            v.split(',')
+                .filter_map(|code| i32::from_str_radix(code.trim(), 16).ok())
+                .collect()
+        })
+        .unwrap_or_else(|_| cli.broadcast.iter().map(|code| *code as i32).collect());
+    let broadcast = BroadcastOptions {
+        codes: broadcast_codes,
+        group: env::var("BROADCAST_GROUP")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(cli.broadcast_group),
+        ttl: cli.broadcast_ttl,
+        interval: Duration::from_secs(cli.broadcast_interval),
+        rate: cli.broadcast_rate,
+        fec: FecParams {
+            fragment_size: cli.fec_fragment_size as usize,
+            data_shards: cli.fec_data as usize,
+            parity_shards: cli.fec_parity as usize,
+        },
+    };
 
-    // Spawn the background refresh task
-    let fw_server_bg = Arc::clone(&fw_server);
-    let fw_data_bg = Arc::clone(&fw_data_all);
-    tokio::spawn(async move {
-        ota_database::from_pg::refresh_firmware_data(&fw_server_bg, fw_data_bg).await;
-    });
+    // 指标服务
+    let metrics_listener = TcpListener::bind(format!("0.0.0.0:{}", metrics_port)).await?;
+    tokio::spawn(serve_metrics(metrics_listener));
 
-    loop {
-        // 接受一个新的客户端连接
-        let (socket, _) = listener.accept().await?;
+    // 固件、配置来源，升级记录、设备影子和命令队列
+    let mut pg_db = None;
+    let backend: Arc<dyn Backend> = match source_kind {
+        SourceKind::Http => {
+            info!("Firmware source: {}", fw_server);
+            Arc::new(HttpBackend::new(&fw_server))
+        }
+        SourceKind::Pg => {
+            info!("Firmware source: postgres");
+            let backend = PgBackend::connect(&fw_db).await?;
+            pg_db = Some(backend.database().clone());
+            Arc::new(backend)
+        }
+        SourceKind::Dir => {
+            info!("Firmware source: {}", fw_dir);
+            Arc::new(DirSource::new(&fw_dir))
+        }
+    };
+    let backend: Arc<dyn Backend> = Arc::new(MeteredBackend::new(backend));
 
-        // 原子变量引用
-        let fw_server_clone = Arc::clone(&fw_server);
-        let fw_data_clone = Arc::clone(&fw_data_all);
+    // 监听、缓存和后台任务
+    let mut builder = OtaServer::builder()
+        .bind(format!("0.0.0.0:{}", port))
+        .backend(backend)
+        .protocol(protocol)
+        .recorder(recorder)
+        .broadcast(broadcast)
+        .cache_size(cache_size * 1024 * 1024)
+        .drain_timeout(Duration::from_secs(drain_timeout));
+    if let Some(ws_port) = ws_port {
+        builder = builder.websocket(format!("0.0.0.0:{}", ws_port));
+    }
+    if let Some(admin_port) = admin_port {
+        builder = builder.admin(SocketAddr::new(admin_bind, admin_port).to_string());
+    }
+    let server = builder.build().await?;
 
-        // 使用tokio的spawn函数，在独立的任务中处理每个客户端连接
+    // 订阅变化通知，本地目录没有通知，只靠定时刷新
+    let fw_cache_bg = Arc::clone(server.fw_cache());
+    let config_cache_bg = Arc::clone(server.config_cache());
+    if source_kind == SourceKind::Http {
         tokio::spawn(async move {
-            if let Err(error) = handle_client(socket, fw_data_clone, &fw_server_clone).await {
-                error!("Error handling client: {}", error);
-            }
+            subscribe_changes(&fw_server, fw_cache_bg, config_cache_bg).await;
         });
+    } else if let Some(db) = pg_db {
+        tokio::spawn(listen_pg_changes(db, fw_cache_bg, config_cache_bg));
     }
+
+    // 收到退出信号后停止接受连接并排空
+    let server_bg = server.clone();
+    tokio::spawn(async move {
+        wait_for_signal().await;
+        server_bg.shutdown();
+    });
+
+    server.run().await?;
+    telemetry.shutdown();
+
+    Ok(())
 }
@@ -0,0 +1,126 @@
+use sha2::{Digest, Sha256};
+
+/// SHA-256 长度
+pub const HASH_LEN: usize = 32;
+
+pub type Hash = [u8; HASH_LEN];
+
+/// 补齐到2的幂时使用的空节点
+const EMPTY: Hash = [0u8; HASH_LEN];
+
+/// 最小切片大小，切片过小时叶子过多
+pub const MIN_SLICE_SIZE: usize = 64;
+
+/// 最大切片大小，切片数据和认证路径要放进一帧
+pub const MAX_SLICE_SIZE: usize = 4096;
+
+/// 是否允许按该切片大小构建 Merkle 树：64 到 4096 之间的2的幂
+pub fn slice_size_allowed(slice_size: usize) -> bool {
+    slice_size.is_power_of_two() && (MIN_SLICE_SIZE..=MAX_SLICE_SIZE).contains(&slice_size)
+}
+
+/// 叶子哈希：SHA-256(0x00 || 切片)
+pub fn leaf_hash(slice: &[u8]) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update([0x00]);
+    hasher.update(slice);
+    hasher.finalize().into()
+}
+
+/// 内部节点哈希：SHA-256(0x01 || 左 || 右)，前缀区分叶子和内部节点
+pub fn node_hash(left: &Hash, right: &Hash) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update([0x01]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+/// 固件切片上的 Merkle 树
+/// - 每个切片一个叶子，最后一片按实际长度计算
+/// - 叶子数补齐到2的幂，补齐的节点为全零，所有切片的认证路径长度相同
+/// - 只有一个切片时根即叶子哈希，认证路径为空
+#[derive(Debug, Clone)]
+pub struct MerkleTree {
+    slice_size: usize,
+    slices: usize,
+    /// 第0层为叶子，最后一层为根
+    levels: Vec<Vec<Hash>>,
+}
+
+impl MerkleTree {
+    pub fn build(data: &[u8], slice_size: usize) -> MerkleTree {
+        let mut leaves: Vec<Hash> = data.chunks(slice_size.max(1)).map(leaf_hash).collect();
+        if leaves.is_empty() {
+            leaves.push(leaf_hash(&[]));
+        }
+        let slices = leaves.len();
+        leaves.resize(leaves.len().next_power_of_two(), EMPTY);
+
+        let mut levels = vec![leaves];
+        while let Some(level) = levels.last().filter(|level| level.len() > 1) {
+            let parent = level
+                .chunks(2)
+                .map(|pair| node_hash(&pair[0], &pair[1]))
+                .collect();
+            levels.push(parent);
+        }
+
+        MerkleTree {
+            slice_size,
+            slices,
+            levels,
+        }
+    }
+
+    pub fn slice_size(&self) -> usize {
+        self.slice_size
+    }
+
+    /// 切片数
+    pub fn slices(&self) -> usize {
+        self.slices
+    }
+
+    pub fn root(&self) -> Hash {
+        self.levels[self.levels.len() - 1][0]
+    }
+
+    /// 认证路径的长度
+    pub fn depth(&self) -> usize {
+        self.levels.len() - 1
+    }
+
+    /// 切片的认证路径，从叶子的兄弟节点到根的下一层
+    pub fn proof(&self, index: usize) -> Option<Vec<Hash>> {
+        if index >= self.slices {
+            return None;
+        }
+        let proof = self.levels[..self.depth()]
+            .iter()
+            .enumerate()
+            .map(|(level, nodes)| nodes[(index >> level) ^ 1])
+            .collect();
+        Some(proof)
+    }
+}
+
+/// 用认证路径验证一个切片，设备端的参考实现
+/// - 第 i 层：序号第 i 位为0时当前节点在左，为1时在右
+pub fn verify(root: &Hash, index: usize, slice: &[u8], proof: &[Hash]) -> bool {
+    if index >> proof.len() != 0 {
+        return false;
+    }
+
+    let node = proof
+        .iter()
+        .enumerate()
+        .fold(leaf_hash(slice), |node, (level, sibling)| {
+            if (index >> level) & 1 == 0 {
+                node_hash(&node, sibling)
+            } else {
+                node_hash(sibling, &node)
+            }
+        });
+    node == *root
+}
@@ -0,0 +1,246 @@
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, Opts,
+    Registry, TextEncoder,
+};
+use std::{
+    future::Future,
+    sync::LazyLock,
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    time,
+};
+use tracing::{error, info};
+
+use crate::ErrorCode;
+
+/// 服务指标，通过 `/metrics` 提供给 Prometheus
+pub struct Metrics {
+    registry: Registry,
+    /// 当前连接数
+    pub connections: IntGauge,
+    /// 按包类型统计的请求数
+    pub packets: IntCounterVec,
+    /// 按错误码统计的错误回复数
+    pub error_replies: IntCounterVec,
+    /// 按固件代号统计的已发送固件字节数
+    pub bytes_served: IntCounterVec,
+    /// 按固件代号统计的组播发送字节数
+    pub bytes_broadcast: IntCounterVec,
+    /// 固件缓存刷新耗时
+    pub cache_refresh: Histogram,
+    /// 固件缓存刷新失败次数
+    pub cache_refresh_failures: IntCounter,
+    /// 后端调用耗时，按操作和结果区分
+    pub backend_calls: HistogramVec,
+}
+
+static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+/// 全局指标
+pub fn metrics() -> &'static Metrics {
+    &METRICS
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new_custom(Some("ota_server".to_string()), None).unwrap();
+
+        let connections = IntGauge::new("connections", "Number of connected devices").unwrap();
+        let packets = IntCounterVec::new(
+            Opts::new("packets_total", "Packets received by package type"),
+            &["type"],
+        )
+        .unwrap();
+        let error_replies = IntCounterVec::new(
+            Opts::new("error_replies_total", "Error replies sent by error code"),
+            &["code"],
+        )
+        .unwrap();
+        let bytes_served = IntCounterVec::new(
+            Opts::new(
+                "firmware_bytes_served_total",
+                "Firmware bytes sent by firmware code",
+            ),
+            &["fwcode"],
+        )
+        .unwrap();
+        let bytes_broadcast = IntCounterVec::new(
+            Opts::new(
+                "firmware_bytes_broadcast_total",
+                "Multicast firmware bytes sent by firmware code",
+            ),
+            &["fwcode"],
+        )
+        .unwrap();
+        let cache_refresh = Histogram::with_opts(HistogramOpts::new(
+            "cache_refresh_duration_seconds",
+            "Firmware cache refresh duration",
+        ))
+        .unwrap();
+        let cache_refresh_failures = IntCounter::new(
+            "cache_refresh_failures_total",
+            "Failed firmware cache refreshes",
+        )
+        .unwrap();
+        let backend_calls = HistogramVec::new(
+            HistogramOpts::new(
+                "backend_call_duration_seconds",
+                "Backend call latency by operation",
+            ),
+            &["operation", "outcome"],
+        )
+        .unwrap();
+
+        registry.register(Box::new(connections.clone())).unwrap();
+        registry.register(Box::new(packets.clone())).unwrap();
+        registry.register(Box::new(error_replies.clone())).unwrap();
+        registry.register(Box::new(bytes_served.clone())).unwrap();
+        registry
+            .register(Box::new(bytes_broadcast.clone()))
+            .unwrap();
+        registry.register(Box::new(cache_refresh.clone())).unwrap();
+        registry
+            .register(Box::new(cache_refresh_failures.clone()))
+            .unwrap();
+        registry.register(Box::new(backend_calls.clone())).unwrap();
+
+        Metrics {
+            registry,
+            connections,
+            packets,
+            error_replies,
+            bytes_served,
+            bytes_broadcast,
+            cache_refresh,
+            cache_refresh_failures,
+            backend_calls,
+        }
+    }
+
+    /// 记录收到的数据包，按处理器名称统计
+    pub fn packet(&self, name: &str) {
+        self.packets.with_label_values(&[name]).inc();
+    }
+
+    /// 记录错误回复，未知错误码按十六进制记录
+    pub fn error_reply(&self, code: u8) {
+        let label = match ErrorCode::from_u8(code) {
+            Some(code) => format!("{:?}", code),
+            None => format!("{:02X}", code),
+        };
+        self.error_replies.with_label_values(&[&label]).inc();
+    }
+
+    /// 记录发送的固件数据
+    pub fn firmware_served(&self, code: i32, bytes: usize) {
+        self.bytes_served
+            .with_label_values(&[&format!("{:04X}", code)])
+            .inc_by(bytes as u64);
+    }
+
+    /// 记录组播发送的数据报
+    pub fn firmware_broadcast(&self, code: i32, bytes: usize) {
+        self.bytes_broadcast
+            .with_label_values(&[&format!("{:04X}", code)])
+            .inc_by(bytes as u64);
+    }
+
+    /// 记录后端调用耗时
+    pub fn backend_call(&self, operation: &str, ok: bool, elapsed: Duration) {
+        let outcome = if ok { "ok" } else { "error" };
+        self.backend_calls
+            .with_label_values(&[operation, outcome])
+            .observe(elapsed.as_secs_f64());
+    }
+
+    /// Prometheus 文本格式
+    pub fn encode(&self) -> String {
+        let mut buffer = Vec::new();
+        if let Err(e) = TextEncoder::new().encode(&self.registry.gather(), &mut buffer) {
+            error!("Metrics encode error: {}", e);
+        }
+        String::from_utf8(buffer).unwrap_or_default()
+    }
+}
+
+/// 连接计数，释放时减一
+pub struct ConnectionGuard;
+
+impl ConnectionGuard {
+    pub fn new() -> Self {
+        metrics().connections.inc();
+        ConnectionGuard
+    }
+}
+
+impl Default for ConnectionGuard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        metrics().connections.dec();
+    }
+}
+
+/// 计时执行后端调用
+pub async fn observe_backend<T, E, F>(operation: &str, call: F) -> Result<T, E>
+where
+    F: Future<Output = Result<T, E>>,
+{
+    let start = Instant::now();
+    let result = call.await;
+    metrics().backend_call(operation, result.is_ok(), start.elapsed());
+    result
+}
+
+/// 指标HTTP服务，只响应 `GET /metrics`
+pub async fn serve_metrics(listener: TcpListener) {
+    if let Ok(addr) = listener.local_addr() {
+        info!("Metrics listening on {}", addr);
+    }
+
+    loop {
+        match listener.accept().await {
+            Ok((socket, _)) => {
+                tokio::spawn(async move {
+                    if let Err(e) = metrics_response(socket).await {
+                        error!("Metrics Error: {}", e);
+                    }
+                });
+            }
+            Err(e) => error!("Metrics Accept Error: {}", e),
+        }
+    }
+}
+
+async fn metrics_response(mut socket: TcpStream) -> std::io::Result<()> {
+    let mut buffer = [0u8; 1024];
+    let bytes_read = time::timeout(Duration::from_secs(5), socket.read(&mut buffer))
+        .await
+        .unwrap_or(Ok(0))?;
+
+    let request = String::from_utf8_lossy(&buffer[..bytes_read]);
+    let mut request_line = request.lines().next().unwrap_or_default().split(' ');
+    let method = request_line.next().unwrap_or_default();
+    let path = request_line.next().unwrap_or_default();
+
+    let (status, body) = match (method, path.split('?').next()) {
+        ("GET", Some("/metrics")) => ("200 OK", metrics().encode()),
+        _ => ("404 Not Found", String::from("Not Found\n")),
+    };
+
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    socket.write_all(response.as_bytes()).await?;
+    socket.shutdown().await
+}
@@ -0,0 +1,162 @@
+use ota_database::{
+    db::Database,
+    events::{
+        ChangeEvent, ChangeEvents, TABLE_CONFIG_HISTORY, TABLE_CONFIG_SCHEMA, TABLE_FIRMWARE_DATA,
+    },
+};
+use std::sync::Arc;
+use tokio::{
+    sync::broadcast::error::RecvError,
+    time::{self, Duration},
+};
+use tracing::{debug, error, info, warn};
+
+use crate::{config_cache::ConfigCache, fw_cache::FirmwareCache};
+
+/// SSE 事件
+#[derive(Debug, Default, PartialEq)]
+pub struct SseEvent {
+    pub event: String,
+    pub data: String,
+}
+
+/// 从缓冲区中取出完整的SSE事件，剩余不完整的部分留在缓冲区
+pub fn parse_sse_events(buffer: &mut String) -> Vec<SseEvent> {
+    let mut events = Vec::new();
+
+    while let Some(pos) = buffer.find("\n\n") {
+        let block: String = buffer.drain(..pos + 2).collect();
+        let mut event = SseEvent::default();
+
+        for line in block.lines() {
+            if let Some(value) = line.strip_prefix("event:") {
+                event.event = value.trim().to_string();
+            } else if let Some(value) = line.strip_prefix("data:") {
+                if !event.data.is_empty() {
+                    event.data.push('\n');
+                }
+                event.data.push_str(value.trim());
+            }
+            // 以 ':' 开头的是心跳注释，忽略
+        }
+
+        if !event.event.is_empty() || !event.data.is_empty() {
+            events.push(event);
+        }
+    }
+
+    events
+}
+
+/// 订阅后端的数据变化事件，收到后立即刷新缓存
+/// 断开后自动重连，定时轮询仍然保留作为兜底
+pub async fn subscribe_changes(
+    fw_server: &str,
+    fw_cache: Arc<FirmwareCache>,
+    config_cache: Arc<ConfigCache>,
+) {
+    let retry_duration = Duration::from_secs(30);
+    let client = reqwest::Client::new();
+
+    loop {
+        let response = client
+            .get(format!("{}/events", fw_server))
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+
+        match response {
+            Ok(mut response) => {
+                info!("Subscribed to change events");
+
+                // 连接断开期间的事件已丢失，全量刷新一次
+                refresh_all(&fw_cache, &config_cache).await;
+
+                let mut buffer = String::new();
+                loop {
+                    match response.chunk().await {
+                        Ok(Some(chunk)) => {
+                            buffer.push_str(&String::from_utf8_lossy(&chunk));
+                            for event in parse_sse_events(&mut buffer) {
+                                handle_event(&event, &fw_cache, &config_cache).await;
+                            }
+                        }
+                        Ok(None) => {
+                            warn!("Change event stream closed");
+                            break;
+                        }
+                        Err(e) => {
+                            error!("Change event stream error: {}", e);
+                            break;
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                warn!("Failed to subscribe change events: {}", e);
+            }
+        }
+
+        time::sleep(retry_duration).await;
+    }
+}
+
+/// 处理单个事件
+async fn handle_event(event: &SseEvent, fw_cache: &FirmwareCache, config_cache: &ConfigCache) {
+    if event.event == "resync" {
+        warn!("Change events lost, resync");
+        refresh_all(fw_cache, config_cache).await;
+        return;
+    }
+
+    if let Ok(change) = serde_json::from_str::<ChangeEvent>(&event.data) {
+        info!("{}", change);
+    }
+    apply_change(&event.event, fw_cache, config_cache).await;
+}
+
+/// 直连数据库时，监听 pg_notify 通知，收到后立即刷新缓存
+pub async fn listen_pg_changes(
+    db: Database,
+    fw_cache: Arc<FirmwareCache>,
+    config_cache: Arc<ConfigCache>,
+) {
+    let events = ChangeEvents::new();
+    let mut rx = events.subscribe();
+    tokio::spawn(events.listen(db.pool));
+
+    loop {
+        match rx.recv().await {
+            Ok(change) => {
+                info!("{}", change);
+                apply_change(&change.table, &fw_cache, &config_cache).await;
+            }
+            Err(RecvError::Lagged(_)) => {
+                warn!("Change events lost, resync");
+                refresh_all(&fw_cache, &config_cache).await;
+            }
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
+
+/// 根据发生变化的表刷新对应的缓存
+async fn apply_change(table: &str, fw_cache: &FirmwareCache, config_cache: &ConfigCache) {
+    match table {
+        TABLE_FIRMWARE_DATA => refresh_firmware(fw_cache).await,
+        TABLE_CONFIG_HISTORY | TABLE_CONFIG_SCHEMA => config_cache.invalidate(),
+        other => debug!("Ignore event: {}", other),
+    }
+}
+
+async fn refresh_all(fw_cache: &FirmwareCache, config_cache: &ConfigCache) {
+    config_cache.invalidate();
+    refresh_firmware(fw_cache).await;
+}
+
+async fn refresh_firmware(fw_cache: &FirmwareCache) {
+    match fw_cache.refresh().await {
+        Ok(count) => info!("Firmware cache refreshed, {} images", count),
+        Err(e) => error!("Error:{}", e),
+    }
+}
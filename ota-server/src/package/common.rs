@@ -1,7 +1,10 @@
 use chrono::prelude::*;
 use chrono::NaiveDateTime;
 use crc::{Crc, CRC_8_MAXIM_DOW};
-use log::error;
+use ota_database::models::config_history::ConfigQuery;
+use tracing::error;
+
+use crate::ProtocolVersion;
 
 /// crc checksum
 fn crc_check(input: &[u8], len: usize) -> bool {
@@ -30,8 +33,10 @@ pub fn package_check(input: &[u8], len: usize) -> bool {
     }
 }
 
-/// 日期转成Vec<u8>
-pub fn datetime_to_vec(datetime: NaiveDateTime) -> Vec<u8> {
+/// 日期转成Vec<u8>，年份只取1字节
+/// - V1 : 年份的高字节（2024 -> 0x07），保留给旧设备
+/// - V2 : 年份后两位（2024 -> 24）
+pub fn datetime_to_vec(datetime: NaiveDateTime, version: ProtocolVersion) -> Vec<u8> {
     let year = datetime.year() as u16;
     let month = datetime.month() as u8;
     let day = datetime.day() as u8;
@@ -39,15 +44,99 @@ pub fn datetime_to_vec(datetime: NaiveDateTime) -> Vec<u8> {
     let minute = datetime.minute() as u8;
     let second = datetime.second() as u8;
 
-    let year_bytes = year.to_le_bytes(); // Convert year to two bytes
-
-    vec![
-        // year_bytes[0],
-        year_bytes[1], // 这里只取年份后2位
-        month,
-        day,
-        hour,
-        minute,
-        second,
-    ]
+    let year_byte = match version {
+        ProtocolVersion::V1 => year.to_le_bytes()[1],
+        ProtocolVersion::V2 => (year % 100) as u8,
+    };
+
+    vec![year_byte, month, day, hour, minute, second]
+}
+
+/// 解析配置查询包的数据段
+/// - 无数据：旧设备，使用全局默认配置
+/// - 4字节：分组号
+/// - 8字节：设备ID
+/// - 12字节：设备ID + 分组号
+pub fn parse_config_query(request: &[u8]) -> ConfigQuery {
+    let payload = match request.len() {
+        len if len > 6 => &request[5..len - 1],
+        _ => &[][..],
+    };
+
+    let read_u32 = |bytes: &[u8]| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    let read_u64 = |bytes: &[u8]| {
+        u64::from_be_bytes([
+            bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7],
+        ])
+    };
+
+    match payload.len() {
+        4..=7 => ConfigQuery {
+            group_id: Some(read_u32(payload) as i32),
+            device_id: None,
+        },
+        8..=11 => ConfigQuery {
+            group_id: None,
+            device_id: Some(format!("{:08X}", read_u64(payload))),
+        },
+        len if len >= 12 => ConfigQuery {
+            group_id: Some(read_u32(&payload[8..]) as i32),
+            device_id: Some(format!("{:08X}", read_u64(payload))),
+        },
+        _ => ConfigQuery::default(),
+    }
+}
+
+/// 配置上报
+#[derive(Debug, PartialEq)]
+pub struct ConfigReport<'a> {
+    pub device_id: String,
+    pub schema_id: i32,
+    pub data: &'a [u8],
+}
+
+/// 解析配置上报包的数据段：设备ID(8) + 模板号(2) + 按模板编码的参数
+pub fn parse_config_report(request: &[u8]) -> Option<ConfigReport<'_>> {
+    if request.len() < 5 + 10 + 1 {
+        return None;
+    }
+    let payload = &request[5..request.len() - 1];
+
+    let mut device_id = [0u8; 8];
+    device_id.copy_from_slice(&payload[..8]);
+
+    Some(ConfigReport {
+        device_id: format!("{:08X}", u64::from_be_bytes(device_id)),
+        schema_id: u16::from_be_bytes([payload[8], payload[9]]) as i32,
+        data: &payload[10..],
+    })
+}
+
+/// 命令执行确认
+#[derive(Debug, PartialEq)]
+pub struct CommandAck {
+    pub command_id: i32,
+    pub result: u8, // 0 成功，其它为设备的错误码
+}
+
+/// 解析命令确认包的数据段：命令号(4) + 执行结果(1)
+/// 没有数据段时只是查询下一条命令
+pub fn parse_command_ack(request: &[u8]) -> Option<CommandAck> {
+    if request.len() < 5 + 5 + 1 {
+        return None;
+    }
+    let payload = &request[5..request.len() - 1];
+
+    Some(CommandAck {
+        command_id: u32::from_be_bytes([payload[0], payload[1], payload[2], payload[3]]) as i32,
+        result: payload[4],
+    })
+}
+
+/// 解析时间同步请求的数据段：协议版本(1)，可省略
+pub fn parse_time_sync(request: &[u8]) -> Option<ProtocolVersion> {
+    match request.len() {
+        len if len > 6 => ProtocolVersion::from_u8(request[5]),
+        _ => None,
+    }
 }
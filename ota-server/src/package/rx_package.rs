@@ -38,6 +38,30 @@ pub async fn send_fw_query_end_pkg(
     Ok(())
 }
 
+// 发送下载结束包，带设备信息和升级结果
+pub async fn send_download_end_pkg(
+    fw_info: &FirmwareInfo,
+    device_id: u64,
+    sn: u32,
+    success: bool,
+    socket: &mut TcpStream,
+) -> Result<(), Box<dyn Error>> {
+    let response = gen_download_end_package(fw_info, device_id, sn, success);
+    send_query_package(&response, socket).await?;
+    Ok(())
+}
+
+// 发送配置查询包
+pub async fn send_config_query_pkg(
+    device_id: Option<u64>,
+    group_id: Option<u32>,
+    socket: &mut TcpStream,
+) -> Result<(), Box<dyn Error>> {
+    let response = gen_config_query_package(device_id, group_id);
+    send_query_package(&response, socket).await?;
+    Ok(())
+}
+
 // 发送返回包   Server->MCU
 async fn send_query_package(
     package: &Vec<u8>,
@@ -51,7 +75,7 @@ async fn send_query_package(
 }
 
 // 固件信息请求包
-fn gen_fw_query_info_package(fw_info: &FirmwareInfo) -> Vec<u8> {
+pub fn gen_fw_query_info_package(fw_info: &FirmwareInfo) -> Vec<u8> {
     // 包头
     let mut data: Vec<u8> = vec![
         0xAA,
@@ -74,7 +98,7 @@ fn gen_fw_query_info_package(fw_info: &FirmwareInfo) -> Vec<u8> {
 }
 
 // 固件下载请求包
-fn gen_fw_query_data_package(fw_info: &FirmwareInfo, index: u16, slice: u16) -> Vec<u8> {
+pub fn gen_fw_query_data_package(fw_info: &FirmwareInfo, index: u16, slice: u16) -> Vec<u8> {
     // 包头
     let mut data: Vec<u8> = vec![
         0xAA,
@@ -103,8 +127,8 @@ fn gen_fw_query_data_package(fw_info: &FirmwareInfo, index: u16, slice: u16) ->
     data
 }
 
-// 请求固件结束包
-fn gen_fw_query_end_package(fw_info: &FirmwareInfo) -> Vec<u8> {
+// 请求固件结束包，旧格式不带设备信息和升级结果，服务器回复长度错误
+pub fn gen_fw_query_end_package(fw_info: &FirmwareInfo) -> Vec<u8> {
     // 包头
     let mut data: Vec<u8> = vec![
         0xAA,
@@ -128,3 +152,68 @@ fn gen_fw_query_end_package(fw_info: &FirmwareInfo) -> Vec<u8> {
 
     data
 }
+
+// 下载结束包，数据段：代号(2) + 版本(3) + 设备ID(8) + SN(4) + 结果(1)
+// 结果 0xA1 为升级成功，其它为失败
+pub fn gen_download_end_package(
+    fw_info: &FirmwareInfo,
+    device_id: u64,
+    sn: u32,
+    success: bool,
+) -> Vec<u8> {
+    // 包头
+    let mut data: Vec<u8> = vec![
+        0xAA,
+        0x55,                           // 包头
+        PackageType::DownloadEnd as u8, // 包类型：结束
+        0x00,                           // 长度
+        0x12,                           // 长度
+        (fw_info.code >> 8) as u8,      // Code 高8位
+        (fw_info.code & 0xFF) as u8,    // Code 低8位
+        fw_info.version.m as u8,        // 版本号 大
+        fw_info.version.n as u8,        // 版本号 中
+        fw_info.version.l as u8,        // 版本号 小
+    ];
+    data.extend_from_slice(&device_id.to_be_bytes()); // 设备ID
+    data.extend_from_slice(&sn.to_be_bytes()); // SN
+    data.push(if success { 0xA1 } else { 0xA0 }); // 升级结果
+
+    // 计算crc
+    let crc8_checksum: Crc<u8> = Crc::<u8>::new(&CRC_8_MAXIM_DOW);
+    let crc = crc8_checksum.checksum(&data);
+
+    // 添加CRC
+    data.push(crc);
+
+    data
+}
+
+// 配置查询包，数据段为 设备ID(8) + 分组号(4)，都可省略
+pub fn gen_config_query_package(device_id: Option<u64>, group_id: Option<u32>) -> Vec<u8> {
+    let mut payload: Vec<u8> = Vec::new();
+    if let Some(device_id) = device_id {
+        payload.extend_from_slice(&device_id.to_be_bytes());
+    }
+    if let Some(group_id) = group_id {
+        payload.extend_from_slice(&group_id.to_be_bytes());
+    }
+
+    // 包头
+    let mut data: Vec<u8> = vec![
+        0xAA,
+        0x55,                           // 包头
+        PackageType::QueryConfig as u8, // 包类型：配置查询
+        (payload.len() >> 8) as u8,     // 长度
+        (payload.len() & 0xFF) as u8,   // 长度
+    ];
+    data.extend_from_slice(&payload);
+
+    // 计算crc
+    let crc8_checksum: Crc<u8> = Crc::<u8>::new(&CRC_8_MAXIM_DOW);
+    let crc = crc8_checksum.checksum(&data);
+
+    // 添加CRC
+    data.push(crc);
+
+    data
+}
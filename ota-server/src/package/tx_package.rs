@@ -1,17 +1,32 @@
 use crc::{Crc, CRC_8_MAXIM_DOW};
-use ota_database::models::{config_history::ConfigHistory, firmware_data::FirmwareInfo};
+use ota_database::models::{
+    config_history::ConfigHistory,
+    config_schema::{ConfigSchema, SchemaError},
+    device_command::DeviceCommand,
+    device_shadow::DeviceShadow,
+    firmware_data::FirmwareInfo,
+};
+use serde_json::{Map, Value};
 use std::error::Error;
-use tokio::{io::AsyncWriteExt, net::TcpStream};
+use tokio::io::AsyncWriteExt;
+use tracing::error;
 
-use crate::PackageType;
+use crate::{
+    merkle::Hash,
+    metrics::metrics,
+    record::{record_frame, RecordDirection},
+    transport::Transport,
+    ErrorCode, PackageType, ProtocolVersion,
+};
 
 use super::common::datetime_to_vec;
 
 /// 发送失败数据包
 pub async fn send_failed_package(
-    socket: &mut TcpStream,
+    socket: &mut dyn Transport,
     failed_code: u8,
 ) -> Result<(), Box<dyn Error>> {
+    metrics().error_reply(failed_code);
     let response = gen_failed_package(failed_code);
     send_response_package(&response, socket).await?;
     Ok(())
@@ -20,9 +35,31 @@ pub async fn send_failed_package(
 /// 发送固件信息
 pub async fn send_fw_info(
     fw_info: &FirmwareInfo,
-    socket: &mut TcpStream,
+    socket: &mut dyn Transport,
 ) -> Result<(), Box<dyn Error>> {
-    let response = gen_fw_info_package(&fw_info);
+    let response = gen_fw_info_package(&fw_info, PackageType::FirmwareQuery);
+    send_response_package(&response, socket).await?;
+    Ok(())
+}
+
+/// 发送固件信息，附带切片大小和 Merkle 根
+pub async fn send_fw_info_merkle(
+    fw_info: &FirmwareInfo,
+    slice_size: u16,
+    root: &Hash,
+    socket: &mut dyn Transport,
+) -> Result<(), Box<dyn Error>> {
+    let response = gen_fw_info_merkle_package(fw_info, slice_size, root);
+    send_response_package(&response, socket).await?;
+    Ok(())
+}
+
+/// 推送新固件通知，数据段与固件信息相同
+pub async fn send_update_available_pkg(
+    fw_info: &FirmwareInfo,
+    socket: &mut dyn Transport,
+) -> Result<(), Box<dyn Error>> {
+    let response = gen_fw_info_package(fw_info, PackageType::UpdateAvailable);
     send_response_package(&response, socket).await?;
     Ok(())
 }
@@ -30,19 +67,32 @@ pub async fn send_fw_info(
 /// 发送固件数据
 pub async fn send_fw_data(
     fw_info: &FirmwareInfo,
-    data: &Vec<u8>,
+    data: &[u8],
     index: u16,
-    socket: &mut TcpStream,
+    socket: &mut dyn Transport,
 ) -> Result<(), Box<dyn Error>> {
     let response = gen_fw_data_package(&fw_info, data, index);
     send_response_package(&response, socket).await?;
     Ok(())
 }
 
+/// 发送固件数据，附带切片的认证路径
+pub async fn send_fw_data_proof(
+    fw_info: &FirmwareInfo,
+    data: &[u8],
+    index: u16,
+    proof: &[Hash],
+    socket: &mut dyn Transport,
+) -> Result<(), Box<dyn Error>> {
+    let response = gen_fw_data_proof_package(fw_info, data, index, proof);
+    send_response_package(&response, socket).await?;
+    Ok(())
+}
+
 /// 发送固件结束包
 pub async fn send_fw_end(
     fw_info: &FirmwareInfo,
-    socket: &mut TcpStream,
+    socket: &mut dyn Transport,
 ) -> Result<(), Box<dyn Error>> {
     let response = gen_fw_end_package(&fw_info);
     send_response_package(&response, socket).await?;
@@ -52,9 +102,70 @@ pub async fn send_fw_end(
 /// 发送配置数据
 pub async fn send_config_pkg(
     last_config: &ConfigHistory,
-    socket: &mut TcpStream,
+    version: ProtocolVersion,
+    socket: &mut dyn Transport,
+) -> Result<(), Box<dyn Error>> {
+    let response = gen_config_package(last_config, version);
+    send_response_package(&response, socket).await?;
+    Ok(())
+}
+
+/// 按配置模板编码并发送配置数据，参数不符合模板时回复配置错误
+/// 只有写入失败时返回错误
+pub async fn send_schema_config_pkg(
+    config: &ConfigHistory,
+    schema: &ConfigSchema,
+    socket: &mut dyn Transport,
+) -> Result<(), Box<dyn Error>> {
+    match gen_schema_config_package(config, schema) {
+        Ok(response) => send_response_package(&response, socket).await?,
+        Err(e) => {
+            error!("Error:{}, {}", e, config);
+            send_failed_package(socket, ErrorCode::ConfigError as u8).await?;
+        }
+    }
+    Ok(())
+}
+
+/// 发送设备影子差异
+pub async fn send_shadow_delta_pkg(
+    shadow: &DeviceShadow,
+    schema: Option<&ConfigSchema>,
+    socket: &mut dyn Transport,
+) -> Result<(), Box<dyn Error>> {
+    let response = gen_shadow_delta_package(shadow, schema)?;
+    send_response_package(&response, socket).await?;
+    Ok(())
+}
+
+/// 发送设备命令，没有待执行的命令时发送空包
+pub async fn send_command_pkg(
+    command: Option<&DeviceCommand>,
+    socket: &mut dyn Transport,
+) -> Result<(), Box<dyn Error>> {
+    let response = gen_command_package(command);
+    send_response_package(&response, socket).await?;
+    Ok(())
+}
+
+/// 发送自定义包类型的回复，包类型为 0xFF - 请求类型
+pub async fn send_custom_pkg(
+    package_type: u8,
+    payload: &[u8],
+    socket: &mut dyn Transport,
 ) -> Result<(), Box<dyn Error>> {
-    let response = gen_config_package(last_config);
+    let response = gen_custom_package(package_type, payload)?;
+    send_response_package(&response, socket).await?;
+    Ok(())
+}
+
+/// 发送时间同步包
+pub async fn send_time_sync_pkg(
+    timestamp: i64,
+    tz_offset: Option<i16>,
+    socket: &mut dyn Transport,
+) -> Result<(), Box<dyn Error>> {
+    let response = gen_time_sync_package(timestamp, tz_offset);
     send_response_package(&response, socket).await?;
     Ok(())
 }
@@ -62,8 +173,9 @@ pub async fn send_config_pkg(
 /// 发送返回包   Server->MCU
 async fn send_response_package(
     package: &Vec<u8>,
-    socket: &mut TcpStream,
+    socket: &mut dyn Transport,
 ) -> Result<(), Box<dyn Error>> {
+    record_frame(RecordDirection::Out, package);
     // 返回数据包
     socket.write_all(&package).await?;
     // 确保数据立即发送
@@ -72,8 +184,8 @@ async fn send_response_package(
 }
 
 /// 生成配置数据包
-fn gen_config_package(last_config: &ConfigHistory) -> Vec<u8> {
-    let data_vec = datetime_to_vec(last_config.sync_ts);
+fn gen_config_package(last_config: &ConfigHistory, version: ProtocolVersion) -> Vec<u8> {
+    let data_vec = datetime_to_vec(last_config.sync_ts, version);
 
     let mut data: Vec<u8> = vec![
         0xAA,
@@ -107,6 +219,173 @@ fn gen_config_package(last_config: &ConfigHistory) -> Vec<u8> {
     data
 }
 
+/// 生成模板配置数据包
+/// 数据段：分组号(4) + 操作码(4) + 模板号(2) + 按模板字段顺序编码的参数
+fn gen_schema_config_package(
+    config: &ConfigHistory,
+    schema: &ConfigSchema,
+) -> Result<Vec<u8>, SchemaError> {
+    // 没有参数时按空对象处理，由模板检查必填字段
+    let empty = Value::Object(Map::new());
+    let params = schema.encode(config.params.as_ref().unwrap_or(&empty))?;
+    let len = 10 + params.len();
+
+    let mut data: Vec<u8> = vec![
+        0xAA,
+        0x55,
+        PackageType::QueryConfig.to_response(), // 配置查询
+        (len >> 8) as u8,                       // 数据长度
+        (len & 0xFF) as u8,                     // 数据长度
+    ];
+    data.extend_from_slice(&(config.group_id as u32).to_be_bytes()); // 分组号
+    data.extend_from_slice(&(config.op_code as u32).to_be_bytes()); // 操作码
+    data.extend_from_slice(&(schema.id as u16).to_be_bytes()); // 模板号
+    data.extend_from_slice(&params);
+
+    // 计算crc
+    let crc8_checksum: Crc<u8> = Crc::<u8>::new(&CRC_8_MAXIM_DOW);
+    let crc = crc8_checksum.checksum(&data);
+
+    // 添加CRC
+    data.push(crc);
+
+    Ok(data)
+}
+
+/// 生成设备影子差异包
+/// 数据段：模板号(2) + 不一致字段数(1) + 字段序号(n) + 按模板编码的期望配置
+/// 没有差异或没有期望配置时，字段数为0且不带配置
+fn gen_shadow_delta_package(
+    shadow: &DeviceShadow,
+    schema: Option<&ConfigSchema>,
+) -> Result<Vec<u8>, SchemaError> {
+    let delta = shadow.delta();
+    let mut payload: Vec<u8> = Vec::new();
+
+    match (schema, shadow.desired.as_ref()) {
+        (Some(schema), Some(desired)) if !delta.is_empty() => {
+            let indexes: Vec<u8> = schema
+                .fields
+                .iter()
+                .enumerate()
+                .filter(|(_, field)| delta.contains_key(&field.name))
+                .map(|(index, _)| index as u8)
+                .collect();
+
+            payload.extend_from_slice(&(schema.id as u16).to_be_bytes());
+            payload.push(indexes.len() as u8);
+            payload.extend_from_slice(&indexes);
+            payload.extend_from_slice(&schema.encode(desired)?);
+        }
+        _ => {
+            let schema_id = shadow.reported_schema_id.unwrap_or_default();
+            payload.extend_from_slice(&(schema_id as u16).to_be_bytes());
+            payload.push(0);
+        }
+    }
+
+    let mut data: Vec<u8> = vec![
+        0xAA,
+        0x55,
+        PackageType::ReportConfig.to_response(), // 配置上报
+        (payload.len() >> 8) as u8,              // 数据长度
+        (payload.len() & 0xFF) as u8,            // 数据长度
+    ];
+    data.extend_from_slice(&payload);
+
+    // 计算crc
+    let crc8_checksum: Crc<u8> = Crc::<u8>::new(&CRC_8_MAXIM_DOW);
+    let crc = crc8_checksum.checksum(&data);
+
+    // 添加CRC
+    data.push(crc);
+
+    Ok(data)
+}
+
+/// 生成设备命令包
+/// 数据段：命令号(4) + 命令码(1) + 参数(4)，没有命令时数据段为空
+fn gen_command_package(command: Option<&DeviceCommand>) -> Vec<u8> {
+    let mut payload: Vec<u8> = Vec::new();
+    if let Some(command) = command {
+        payload.extend_from_slice(&(command.id as u32).to_be_bytes()); // 命令号
+        payload.push(command.command as u8); // 命令码
+        payload.extend_from_slice(&(command.param as u32).to_be_bytes()); // 参数
+    }
+
+    let mut data: Vec<u8> = vec![
+        0xAA,
+        0x55,
+        PackageType::Command.to_response(), // 设备命令
+        (payload.len() >> 8) as u8,         // 数据长度
+        (payload.len() & 0xFF) as u8,       // 数据长度
+    ];
+    data.extend_from_slice(&payload);
+
+    // 计算crc
+    let crc8_checksum: Crc<u8> = Crc::<u8>::new(&CRC_8_MAXIM_DOW);
+    let crc = crc8_checksum.checksum(&data);
+
+    // 添加CRC
+    data.push(crc);
+
+    data
+}
+
+/// 生成时间同步包
+/// 数据段：UTC unix 秒(4) + 时区偏移分钟(2)，未配置时区时不带偏移
+fn gen_time_sync_package(timestamp: i64, tz_offset: Option<i16>) -> Vec<u8> {
+    let mut payload: Vec<u8> = Vec::new();
+    payload.extend_from_slice(&(timestamp as u32).to_be_bytes()); // unix 秒
+    if let Some(tz_offset) = tz_offset {
+        payload.extend_from_slice(&tz_offset.to_be_bytes()); // 时区偏移
+    }
+
+    let mut data: Vec<u8> = vec![
+        0xAA,
+        0x55,
+        PackageType::TimeSync.to_response(), // 时间同步
+        (payload.len() >> 8) as u8,          // 数据长度
+        (payload.len() & 0xFF) as u8,        // 数据长度
+    ];
+    data.extend_from_slice(&payload);
+
+    // 计算crc
+    let crc8_checksum: Crc<u8> = Crc::<u8>::new(&CRC_8_MAXIM_DOW);
+    let crc = crc8_checksum.checksum(&data);
+
+    // 添加CRC
+    data.push(crc);
+
+    data
+}
+
+/// 生成自定义包类型的回复
+fn gen_custom_package(package_type: u8, payload: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let len = u16::try_from(payload.len()).map_err(|_| "Payload too long")?;
+
+    // 包头
+    let mut data: Vec<u8> = vec![
+        0xAA,
+        0x55,                // 包头
+        0xFF - package_type, // 包类型
+        (len >> 8) as u8,    // Len 高8位
+        (len & 0xFF) as u8,  // Len 低8位
+    ];
+
+    // 追加数据
+    data.extend_from_slice(payload);
+
+    // 计算crc
+    let crc8_checksum: Crc<u8> = Crc::<u8>::new(&CRC_8_MAXIM_DOW);
+    let crc = crc8_checksum.checksum(&data);
+
+    // 添加CRC
+    data.push(crc);
+
+    Ok(data)
+}
+
 /// 错误包生成
 fn gen_failed_package(failed_code: u8) -> Vec<u8> {
     // 包头
@@ -123,19 +402,19 @@ fn gen_failed_package(failed_code: u8) -> Vec<u8> {
 }
 
 /// 生成固件信息包
-fn gen_fw_info_package(fw_info: &FirmwareInfo) -> Vec<u8> {
+fn gen_fw_info_package(fw_info: &FirmwareInfo, package_type: PackageType) -> Vec<u8> {
     // 包头
     let mut data: Vec<u8> = vec![
         0xAA,
-        0x55,                                     // 包头
-        PackageType::FirmwareQuery.to_response(), // 包类型：固件查询
-        0x00,                                     // 长度
-        0x09,                                     // 长度
-        (fw_info.code >> 8) as u8,                // Code 高8位
-        (fw_info.code & 0xFF) as u8,              // Code 低8位
-        fw_info.version.m as u8,                  // 版本号 大
-        fw_info.version.n as u8,                  // 版本号 中
-        fw_info.version.l as u8,                  // 版本号 小
+        0x55,                        // 包头
+        package_type.to_response(),  // 包类型：固件查询 / 新固件推送
+        0x00,                        // 长度
+        0x09,                        // 长度
+        (fw_info.code >> 8) as u8,   // Code 高8位
+        (fw_info.code & 0xFF) as u8, // Code 低8位
+        fw_info.version.m as u8,     // 版本号 大
+        fw_info.version.n as u8,     // 版本号 中
+        fw_info.version.l as u8,     // 版本号 小
         (fw_info.size >> 24) as u8,
         (fw_info.size >> 16) as u8,
         (fw_info.size >> 8) as u8,
@@ -152,8 +431,48 @@ fn gen_fw_info_package(fw_info: &FirmwareInfo) -> Vec<u8> {
     data
 }
 
+/// 在已生成的包后追加数据，重新计算长度和CRC
+fn extend_package(mut data: Vec<u8>, extra: &[u8]) -> Vec<u8> {
+    // 去掉原来的CRC
+    data.pop();
+    data.extend_from_slice(extra);
+
+    let len = data.len() - 5;
+    data[3] = (len >> 8) as u8;
+    data[4] = (len & 0xFF) as u8;
+
+    let crc8_checksum: Crc<u8> = Crc::<u8>::new(&CRC_8_MAXIM_DOW);
+    let crc = crc8_checksum.checksum(&data);
+    data.push(crc);
+
+    data
+}
+
+/// 生成带 Merkle 根的固件信息包：固件信息 + 切片大小(2) + 根(32)
+fn gen_fw_info_merkle_package(fw_info: &FirmwareInfo, slice_size: u16, root: &Hash) -> Vec<u8> {
+    let mut extra = slice_size.to_be_bytes().to_vec();
+    extra.extend_from_slice(root);
+    extend_package(
+        gen_fw_info_package(fw_info, PackageType::FirmwareQuery),
+        &extra,
+    )
+}
+
+/// 生成带认证路径的固件数据包：固件数据 + 兄弟节点(32 x 层数) + 层数(1)
+/// 层数放在最后，设备从包尾解析出认证路径，剩下的是切片数据
+fn gen_fw_data_proof_package(
+    fw_info: &FirmwareInfo,
+    input_data: &[u8],
+    index: u16,
+    proof: &[Hash],
+) -> Vec<u8> {
+    let mut extra: Vec<u8> = proof.iter().flatten().copied().collect();
+    extra.push(proof.len() as u8);
+    extend_package(gen_fw_data_package(fw_info, input_data, index), &extra)
+}
+
 /// 生成固件数据包
-fn gen_fw_data_package(fw_info: &FirmwareInfo, input_data: &Vec<u8>, index: u16) -> Vec<u8> {
+fn gen_fw_data_package(fw_info: &FirmwareInfo, input_data: &[u8], index: u16) -> Vec<u8> {
     // 数据总长度
     let total_len = 7 + input_data.len();
 
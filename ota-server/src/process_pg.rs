@@ -1,43 +1,130 @@
-use log::{debug, error, info};
+use async_trait::async_trait;
+use chrono::Utc;
 use ota_database::{
-    from_pg::{get_latest_config, read_config_from_pg},
+    from_pg::get_latest_config,
     models::{
-        firmware_data::{
-            find_firmware, find_latest_fw, slice_fw_data_from_vector, FirmwareData, FirmwareInfo,
-            FirmwareVersion,
-        },
+        config_history::ConfigHistory,
+        device_command::{CommandStatus, DeviceCommand, UpdateCommandStatus},
+        device_shadow::ReportedConfig,
+        download_session::DownloadOutcome,
+        firmware_data::FirmwareVersion,
         upgrade_history::NewUpgradeHistory,
     },
 };
-use std::error::Error;
-use std::sync::Arc;
-use tokio::{io::AsyncReadExt, net::TcpStream};
+use std::{error::Error, net::SocketAddr};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    time::Instant,
+};
+use tracing::{debug, error, field, info, info_span, Instrument, Span};
 
 use crate::{
-    package::{common::package_check, tx_package::*},
-    ErrorCode, PackageType,
+    config_cache::ConfigCache,
+    context::{ProtocolOptions, ServerContext},
+    download::DownloadTracker,
+    fw_cache::{meta_key, FirmwareCache},
+    handler::{ConnectionContext, HandlerRegistry, PacketHandler},
+    history::HistoryQueue,
+    metrics::{metrics, ConnectionGuard},
+    package::{
+        common::{
+            package_check, parse_command_ack, parse_config_query, parse_config_report,
+            parse_time_sync,
+        },
+        tx_package::*,
+    },
+    record::{self, record_device, record_frame, RecordDirection},
+    registry::{SessionEvent, SessionHandle},
+    session::DeviceSession,
+    shutdown::Shutdown,
+    source::{CommandQueue, ShadowStore},
+    transport::Transport,
+    ErrorCode, PackageType, ProtocolVersion,
 };
 
-
 /// Buffer size for TCP communication
 const BUFFER_SIZE: usize = 1024;
 
 /// 处理tcp请求入口
 pub async fn handle_client(
-    mut socket: TcpStream,
-    fw_data_all: Arc<tokio::sync::Mutex<Vec<FirmwareData>>>,
-    fw_server: &str,
+    socket: TcpStream,
+    ctx: ServerContext,
+    shutdown: Shutdown,
 ) -> Result<(), Box<dyn Error>> {
-    info!("New client connected: {:?}", socket.peer_addr()?);
+    let peer = socket.peer_addr()?;
+    handle_connection(socket, peer, ctx, shutdown).await
+}
 
-    let mut buffer = [0; BUFFER_SIZE];
+/// 处理一个设备连接，TCP 和 WebSocket 共用
+pub async fn handle_connection<S: Transport>(
+    socket: S,
+    peer: SocketAddr,
+    ctx: ServerContext,
+    shutdown: Shutdown,
+) -> Result<(), Box<dyn Error>> {
+    // 按设备ID或IP录制会话
+    let recording = ctx
+        .recorder
+        .as_ref()
+        .and_then(|recorder| recorder.session(peer, ctx.protocol.version));
+
+    record::scope(recording, serve_client(socket, peer, ctx, shutdown)).await
+}
 
-    loop {
-        // 从客户端读取数据
-        let bytes_read = match socket.read(&mut buffer).await {
-            Ok(bytes) => bytes,
-            Err(e) => {
-                error!("Socket Error :{}", e);
+async fn serve_client<S: Transport>(
+    mut socket: S,
+    peer: SocketAddr,
+    ctx: ServerContext,
+    mut shutdown: Shutdown,
+) -> Result<(), Box<dyn Error>> {
+    info!("New client connected: {:?}", peer);
+    let _connection = ConnectionGuard::new();
+
+    let mut buffer = [0; BUFFER_SIZE];
+    let mut session = DeviceSession::new(ctx.protocol.version);
+    session.peer = peer.ip().to_string();
+    let mut registration: Option<SessionHandle> = None;
+    let mut connection = ctx.registry.connect(peer);
+    session.conn_id = connection.id();
+    // 下载过程中收到的推送，下载结束或中断后再发送
+    let mut held: Option<SessionEvent> = None;
+
+    while !shutdown.is_shutdown() {
+        let idle_at = session.download_idle_at(Instant::now());
+        let kicked = connection.kicked();
+
+        // 从客户端读取数据，收到退出通知时不再等待下一个请求
+        let bytes_read = tokio::select! {
+            res = socket.read(&mut buffer) => match res {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    error!("Socket Error :{}", e);
+                    break;
+                }
+            },
+            event = connection.recv() => {
+                match idle_at {
+                    Some(_) => {
+                        debug!("Download in progress, push held: {:?}", peer);
+                        held = Some(event);
+                    }
+                    None => push_event(event, &mut socket, &ctx.fw_cache, &mut session).await?,
+                }
+                continue;
+            }
+            _ = sleep_until(idle_at), if held.is_some() => {
+                if let Some(event) = held.take() {
+                    push_event(event, &mut socket, &ctx.fw_cache, &mut session).await?;
+                }
+                continue;
+            }
+            _ = shutdown.recv() => {
+                info!("Server shutting down, closing {:?}", peer);
+                break;
+            }
+            _ = kicked => {
+                info!("Disconnected by admin: {:?}", peer);
                 break;
             }
         };
@@ -49,68 +136,151 @@ pub async fn handle_client(
 
         // 处理接收到的数据
         let request = &buffer[..bytes_read].to_vec();
+        record_frame(RecordDirection::In, request);
+
+        package_process(request, &mut socket, &ctx, &mut session)
+            .instrument(info_span!(
+                "packet",
+                package_type = field::Empty,
+                device_id = field::Empty,
+                len = bytes_read
+            ))
+            .await?;
+
+        // 识别到设备后登记，用于推送
+        if let Some(device_id) = &session.device_id {
+            if registration.as_ref().map(|handle| handle.device_id()) != Some(device_id.as_str()) {
+                Span::current().record("device_id", device_id.as_str());
+                registration = Some(ctx.registry.register(device_id, connection.id()));
+                record_device(device_id);
+            }
+        }
+        connection.update(session.device_id.as_deref(), session.fw_code);
 
-        package_process(request, &mut socket, Arc::clone(&fw_data_all), &fw_server).await?;
+        // 下载已结束，发送暂缓的推送
+        if held.is_some() && session.download_idle_at(Instant::now()).is_none() {
+            if let Some(event) = held.take() {
+                push_event(event, &mut socket, &ctx.fw_cache, &mut session).await?;
+            }
+        }
 
         // 清空缓冲区
         buffer.fill(0);
     }
 
-    info!("Client disconnected: {:?}", socket.peer_addr());
+    info!("Client disconnected: {:?}", peer);
+    // 先注销连接，设备看到连接关闭时会话列表中已经没有该连接
+    drop(registration);
+    drop(connection);
+    // WebSocket 连接发送关闭帧
+    let _ = socket.shutdown().await;
+
+    Ok(())
+}
+
+/// 等待到指定时间，没有时一直等待
+async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// 发送推送
+async fn push_event(
+    event: SessionEvent,
+    socket: &mut dyn Transport,
+    fw_cache: &FirmwareCache,
+    session: &mut DeviceSession,
+) -> Result<(), Box<dyn Error>> {
+    process_session_event(event, socket, fw_cache, session)
+        .instrument(info_span!("push", event = ?event))
+        .await
+}
+
+/// 处理推送事件
+async fn process_session_event(
+    event: SessionEvent,
+    socket: &mut dyn Transport,
+    fw_cache: &FirmwareCache,
+    session: &mut DeviceSession,
+) -> Result<(), Box<dyn Error>> {
+    match event {
+        SessionEvent::FirmwareChanged => {
+            if let Some(fw_meta) = session.update_available(&fw_cache.load()) {
+                info!(
+                    "[Push] Update Available. device:{:?}, {}",
+                    session.device_id, fw_meta
+                );
+                send_update_available_pkg(&fw_meta.info(), socket).await?;
+            }
+        }
+    }
 
     Ok(())
 }
 
-/// 数据包处理入口
+/// 数据包处理入口，按包类型交给注册的处理器
 async fn package_process(
     request: &[u8],
-    socket: &mut TcpStream,
-    fw_data_all: Arc<tokio::sync::Mutex<Vec<FirmwareData>>>,
-    fw_server: &str,
+    socket: &mut dyn Transport,
+    ctx: &ServerContext,
+    session: &mut DeviceSession,
 ) -> Result<(), Box<dyn Error>> {
-    // 最低长度为7
-    if request.len() >= 4 {
-        // CRC检查
-        if package_check(request, request.len()) {
+    let backend = ctx.backend.as_ref();
+
+    // 最低长度为6：包头(2) + 类型(1) + 长度(2) + CRC(1)
+    if request.len() >= 6 {
+        // CRC检查，截断的帧回复CRC错误
+        if !package_check(request, request.len()) {
+            error!("Package CRC Error!");
+            send_failed_package(socket, ErrorCode::CrcError as u8).await?;
+        } else if u16::from_be_bytes([request[3], request[4]]) as usize != request.len() - 6 {
+            // 长度字段与数据段不一致
+            error!("Package Length Error!");
+            send_failed_package(socket, ErrorCode::LengthError as u8).await?;
+        } else {
             // 从请求中获取包类型
-            let package_type = match request[2] {
-                x if x == PackageType::FirmwareQuery as u8 => PackageType::FirmwareQuery,
-                x if x == PackageType::FirmwareDownload as u8 => PackageType::FirmwareDownload,
-                x if x == PackageType::DownloadEnd as u8 => PackageType::DownloadEnd,
-                x if x == PackageType::QueryConfig as u8 => PackageType::QueryConfig,
-                _ => {
-                    error!("Unknown package type!");
-                    send_failed_package(socket, ErrorCode::UnknownPackageType as u8).await?;
-                    return Ok(());
-                }
+            let package_type = request[2];
+            let Some(handler) = ctx.handlers.get(package_type) else {
+                error!("Unknown package type!");
+                send_failed_package(socket, ErrorCode::UnknownPackageType as u8).await?;
+                return Ok(());
             };
+            if request.len() - 6 < handler.min_payload() {
+                error!("Package Length Error! {} payload too short", handler.name());
+                send_failed_package(socket, ErrorCode::LengthError as u8).await?;
+                return Ok(());
+            }
+
+            metrics().packet(handler.name());
+            Span::current().record("package_type", handler.name());
+
+            // 记录设备ID，用于下发命令
+            if let Some(package_type) = PackageType::from_u8(package_type) {
+                session.observe(package_type, request);
+            }
+            if let Some(device_id) = &session.device_id {
+                Span::current().record("device_id", device_id.as_str());
+            }
 
             // 根据包类型处理请求
-            match package_type {
-                PackageType::FirmwareQuery => {
-                    // 固件代号
-                    let _code = (request[5] as u16) << 8 | request[6] as u16;
-                    process_fw_query_request(request, socket, _code as i32, Arc::clone(&fw_data_all)).await?
-                }
-                PackageType::FirmwareDownload => {
-                    // 固件代号
-                    let _code = (request[5] as u16) << 8 | request[6] as u16;
-                    process_fw_download_request(request, socket, _code as i32, Arc::clone(&fw_data_all)).await?
-                }
-                PackageType::DownloadEnd => {
-                    // 固件代号
-                    let _code = (request[5] as u16) << 8 | request[6] as u16;
-                    process_fw_end_request(request, socket, _code as i32, Arc::clone(&fw_data_all), fw_server)
-                        .await?
-                }
-                PackageType::QueryConfig => {
-                    process_query_config(request, socket, fw_server).await?
-                }
+            let mut conn = ConnectionContext {
+                socket: &mut *socket,
+                server: ctx,
+                session: &mut *session,
             };
-        } else {
-            // CRC失败
-            error!("Package CRC Error!");
-            send_failed_package(socket, ErrorCode::CrcError as u8).await?;
+            handler.handle(request, &mut conn).await?;
+
+            // 记录设备已知的最新固件，之后有更新的固件时推送
+            session.track_firmware(&ctx.fw_cache.load());
+
+            // 顺带下发待执行的命令，命令确认包已经回复了下一条命令
+            if package_type != PackageType::Command as u8 && session.should_poll(Instant::now()) {
+                if let Some(command) = poll_command(backend, session).await {
+                    deliver_command(Some(command), socket, backend, session).await?;
+                }
+            }
         }
     } else {
         // 长度不对
@@ -121,23 +291,229 @@ async fn package_process(
     Ok(())
 }
 
+/// 注册内置包类型，新固件推送只由服务器发出，不注册
+pub fn register_builtin(registry: &mut HandlerRegistry) {
+    registry.register(PackageType::FirmwareQuery as u8, FirmwareQueryHandler);
+    registry.register(PackageType::FirmwareDownload as u8, FirmwareDownloadHandler);
+    registry.register(PackageType::DownloadEnd as u8, DownloadEndHandler);
+    registry.register(PackageType::QueryConfig as u8, QueryConfigHandler);
+    registry.register(PackageType::ReportConfig as u8, ReportConfigHandler);
+    registry.register(PackageType::Command as u8, CommandHandler);
+    registry.register(PackageType::TimeSync as u8, TimeSyncHandler);
+}
+
+/// 请求中的固件代号
+fn request_fw_code(request: &[u8]) -> i32 {
+    ((request[5] as u16) << 8 | request[6] as u16) as i32
+}
+
+struct FirmwareQueryHandler;
+
+#[async_trait]
+impl PacketHandler for FirmwareQueryHandler {
+    fn name(&self) -> &str {
+        "FirmwareQuery"
+    }
+
+    /// 代号(2)，切片大小可省略
+    fn min_payload(&self) -> usize {
+        2
+    }
+
+    async fn handle(
+        &self,
+        request: &[u8],
+        conn: &mut ConnectionContext<'_>,
+    ) -> Result<(), Box<dyn Error>> {
+        let code = request_fw_code(request);
+        process_fw_query_request(request, conn.socket, code, &conn.server.fw_cache).await
+    }
+}
+
+struct FirmwareDownloadHandler;
+
+#[async_trait]
+impl PacketHandler for FirmwareDownloadHandler {
+    fn name(&self) -> &str {
+        "FirmwareDownload"
+    }
+
+    /// 代号(2) + 版本(3) + 序号(2) + 切片大小(2)，标志可省略
+    fn min_payload(&self) -> usize {
+        9
+    }
+
+    async fn handle(
+        &self,
+        request: &[u8],
+        conn: &mut ConnectionContext<'_>,
+    ) -> Result<(), Box<dyn Error>> {
+        process_fw_download_request(
+            request,
+            conn.socket,
+            request_fw_code(request),
+            &conn.server.fw_cache,
+            &conn.server.downloads,
+            conn.session,
+        )
+        .await
+    }
+}
+
+struct DownloadEndHandler;
+
+#[async_trait]
+impl PacketHandler for DownloadEndHandler {
+    fn name(&self) -> &str {
+        "DownloadEnd"
+    }
+
+    /// 代号(2) + 版本(3) + 设备ID(8) + SN(4) + 结果(1)，旧格式只有代号和版本，没有升级结果
+    fn min_payload(&self) -> usize {
+        18
+    }
+
+    async fn handle(
+        &self,
+        request: &[u8],
+        conn: &mut ConnectionContext<'_>,
+    ) -> Result<(), Box<dyn Error>> {
+        process_fw_end_request(
+            request,
+            conn.socket,
+            request_fw_code(request),
+            &conn.server.history,
+            &conn.server.downloads,
+            conn.session,
+        )
+        .await
+    }
+}
+
+struct QueryConfigHandler;
+
+#[async_trait]
+impl PacketHandler for QueryConfigHandler {
+    fn name(&self) -> &str {
+        "QueryConfig"
+    }
+
+    async fn handle(
+        &self,
+        request: &[u8],
+        conn: &mut ConnectionContext<'_>,
+    ) -> Result<(), Box<dyn Error>> {
+        let version = conn.session.protocol;
+        process_query_config(request, conn.socket, &conn.server.config_cache, version).await
+    }
+}
+
+struct ReportConfigHandler;
+
+#[async_trait]
+impl PacketHandler for ReportConfigHandler {
+    fn name(&self) -> &str {
+        "ReportConfig"
+    }
+
+    /// 设备ID(8) + 模板号(2)
+    fn min_payload(&self) -> usize {
+        10
+    }
+
+    async fn handle(
+        &self,
+        request: &[u8],
+        conn: &mut ConnectionContext<'_>,
+    ) -> Result<(), Box<dyn Error>> {
+        let server = conn.server;
+        process_report_config(
+            request,
+            conn.socket,
+            &server.config_cache,
+            server.backend.as_ref(),
+        )
+        .await
+    }
+}
+
+struct CommandHandler;
+
+#[async_trait]
+impl PacketHandler for CommandHandler {
+    fn name(&self) -> &str {
+        "Command"
+    }
+
+    async fn handle(
+        &self,
+        request: &[u8],
+        conn: &mut ConnectionContext<'_>,
+    ) -> Result<(), Box<dyn Error>> {
+        let backend = conn.server.backend.as_ref();
+        process_command_ack(request, conn.socket, backend, conn.session).await
+    }
+}
+
+struct TimeSyncHandler;
+
+#[async_trait]
+impl PacketHandler for TimeSyncHandler {
+    fn name(&self) -> &str {
+        "TimeSync"
+    }
+
+    async fn handle(
+        &self,
+        request: &[u8],
+        conn: &mut ConnectionContext<'_>,
+    ) -> Result<(), Box<dyn Error>> {
+        process_time_sync(request, conn.socket, &conn.server.protocol, conn.session).await
+    }
+}
+
 /// 配置查询
 async fn process_query_config(
-    _request: &[u8],
-    socket: &mut TcpStream,
-    fw_server: &str,
+    request: &[u8],
+    socket: &mut dyn Transport,
+    config_cache: &ConfigCache,
+    version: ProtocolVersion,
 ) -> Result<(), Box<dyn Error>> {
-    info!("[Command] Query Configuration.");
+    let query = parse_config_query(request);
+    info!(
+        "[Command] Query Configuration. device:{:?}, group:{:?}",
+        query.device_id, query.group_id
+    );
 
-    let all_config_history = read_config_from_pg(fw_server).await;
+    let all_config_history = config_cache.get().await;
 
     match all_config_history {
         Ok(datas) => {
-            let last_config_history = get_latest_config(&datas);
-            match last_config_history {
-                Some(config) => {
-                    send_config_pkg(config, socket).await?;
+            // 设备 -> 分组 -> 全局默认，旧设备没有全局默认时沿用最新一条
+            let config_history =
+                ConfigHistory::resolve(&datas.configs, query.device_id.as_deref(), query.group_id)
+                    .or_else(|| {
+                        if query == Default::default() {
+                            get_latest_config(&datas.configs)
+                        } else {
+                            None
+                        }
+                    });
+            match config_history {
+                // 旧格式配置
+                Some(config) if config.schema_id.is_none() => {
+                    send_config_pkg(config, version, socket).await?;
                 }
+                // 按模板编码
+                Some(config) => match datas.schema(config) {
+                    Some(schema) => {
+                        send_schema_config_pkg(config, schema, socket).await?;
+                    }
+                    None => {
+                        error!("Config schema not found, {}", config);
+                        send_failed_package(socket, ErrorCode::ConfigError as u8).await?;
+                    }
+                },
                 None => {
                     send_failed_package(socket, ErrorCode::NoFirmwareFound as u8).await?;
                 }
@@ -151,30 +527,218 @@ async fn process_query_config(
     Ok(())
 }
 
+/// 配置上报，返回期望配置中不一致的部分
+async fn process_report_config(
+    request: &[u8],
+    socket: &mut dyn Transport,
+    config_cache: &ConfigCache,
+    shadow: &dyn ShadowStore,
+) -> Result<(), Box<dyn Error>> {
+    let Some(report) = parse_config_report(request) else {
+        error!("Package Length Error!");
+        send_failed_package(socket, ErrorCode::LengthError as u8).await?;
+        return Ok(());
+    };
+    info!(
+        "[Command] Report Configuration. device:{}, schema:{}",
+        report.device_id, report.schema_id
+    );
+
+    let configs = match config_cache.get().await {
+        Ok(configs) => configs,
+        Err(e) => {
+            error!("Error:{}", e);
+            send_failed_package(socket, ErrorCode::ConfigError as u8).await?;
+            return Ok(());
+        }
+    };
+
+    // 按模板解码上报的参数
+    let reported = configs
+        .schemas
+        .get(&report.schema_id)
+        .ok_or_else(|| format!("Config schema {} not found", report.schema_id))
+        .and_then(|schema| schema.decode(report.data).map_err(|e| e.to_string()));
+    let reported = match reported {
+        Ok(reported) => reported,
+        Err(e) => {
+            error!("Error:{}, device:{}", e, report.device_id);
+            send_failed_package(socket, ErrorCode::ConfigError as u8).await?;
+            return Ok(());
+        }
+    };
+
+    let result = shadow
+        .report_shadow(
+            &report.device_id,
+            &ReportedConfig {
+                schema_id: report.schema_id,
+                reported,
+            },
+        )
+        .await;
+
+    match result {
+        Ok(device_shadow) => {
+            debug!("{}", device_shadow);
+            let schema = device_shadow
+                .desired_schema_id
+                .and_then(|id| configs.schemas.get(&id));
+            let sent = send_shadow_delta_pkg(&device_shadow, schema, socket)
+                .await
+                .map_err(|e| e.to_string());
+            if let Err(e) = sent {
+                error!("Error:{}, {}", e, device_shadow);
+                send_failed_package(socket, ErrorCode::ConfigError as u8).await?;
+            }
+        }
+        Err(e) => {
+            error!("Error:{}", e);
+            send_failed_package(socket, ErrorCode::ConfigError as u8).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// 时间同步，设备可在请求中声明协议版本
+async fn process_time_sync(
+    request: &[u8],
+    socket: &mut dyn Transport,
+    protocol: &ProtocolOptions,
+    session: &mut DeviceSession,
+) -> Result<(), Box<dyn Error>> {
+    if let Some(version) = parse_time_sync(request) {
+        session.protocol = version;
+    }
+    info!(
+        "[Command] Time Sync. device:{:?}, protocol:{:?}",
+        session.device_id, session.protocol
+    );
+
+    send_time_sync_pkg(Utc::now().timestamp(), protocol.tz_offset, socket).await?;
+
+    Ok(())
+}
+
+/// 命令确认，更新命令状态并回复下一条命令
+async fn process_command_ack(
+    request: &[u8],
+    socket: &mut dyn Transport,
+    commands: &dyn CommandQueue,
+    session: &mut DeviceSession,
+) -> Result<(), Box<dyn Error>> {
+    if let Some(ack) = parse_command_ack(request) {
+        info!(
+            "[Command] Command Ack. device:{:?}, command:{}, result:{}",
+            session.device_id, ack.command_id, ack.result
+        );
+
+        let status = UpdateCommandStatus {
+            status: match ack.result {
+                0 => CommandStatus::Acked,
+                _ => CommandStatus::Failed,
+            },
+            result: Some(ack.result as i32),
+        };
+        if let Err(e) = commands.update_command(ack.command_id, &status).await {
+            error!("Error:{}, command:{}", e, ack.command_id);
+        }
+
+        if session.in_flight == Some(ack.command_id) {
+            session.in_flight = None;
+        }
+    }
+
+    let command = match session.device_id {
+        Some(_) => poll_command(commands, session).await,
+        None => None,
+    };
+    deliver_command(command, socket, commands, session).await?;
+
+    Ok(())
+}
+
+/// 查询设备的下一条命令
+async fn poll_command(
+    commands: &dyn CommandQueue,
+    session: &mut DeviceSession,
+) -> Option<DeviceCommand> {
+    let device_id = session.device_id.clone()?;
+    session.polled(Instant::now());
+
+    match commands
+        .pending_commands(&device_id, session.group_id)
+        .await
+    {
+        Ok(pending) => pending.into_iter().next(),
+        Err(e) => {
+            error!("Error:{}, device:{}", e, device_id);
+            None
+        }
+    }
+}
+
+/// 下发命令，每次只下发一条，设备确认后再下发下一条
+async fn deliver_command(
+    command: Option<DeviceCommand>,
+    socket: &mut dyn Transport,
+    commands: &dyn CommandQueue,
+    session: &mut DeviceSession,
+) -> Result<(), Box<dyn Error>> {
+    send_command_pkg(command.as_ref(), socket).await?;
+
+    if let Some(command) = command {
+        info!("Command delivered, {}", command);
+        session.in_flight = Some(command.id);
+
+        if command.status == CommandStatus::Queued {
+            let status = UpdateCommandStatus {
+                status: CommandStatus::Delivered,
+                result: None,
+            };
+            if let Err(e) = commands.update_command(command.id, &status).await {
+                error!("Error:{}, command:{}", e, command.id);
+            }
+        }
+    }
+
+    Ok(())
+}
+
 /// 处理固件查询请求
 async fn process_fw_query_request(
     _request: &[u8],
-    socket: &mut TcpStream,
+    socket: &mut dyn Transport,
     code: i32,
-    fw_data_all: Arc<tokio::sync::Mutex<Vec<FirmwareData>>>,
+    fw_cache: &FirmwareCache,
 ) -> Result<(), Box<dyn Error>> {
     info!("[Command] Query Firmware Info.");
-    let fw_data_lock = fw_data_all.lock().await;
-    if let Some(fw_data) = find_latest_fw(&fw_data_lock, code) {
-        send_fw_info(
-            &FirmwareInfo {
-                code: fw_data.fwcode,
-                version: FirmwareVersion {
-                    m: fw_data.version_m,
-                    n: fw_data.version_n,
-                    l: fw_data.version_l,
-                },
-                size: fw_data.fwsize,
-                path: String::from(""),
-            },
-            socket,
-        )
-        .await?;
+
+    // 新设备在代号后附带切片大小，回复中附带该切片大小的 Merkle 根
+    let slice_size = match _request.len() {
+        len if len >= 10 => (_request[7] as u16) << 8 | _request[8] as u16,
+        _ => 0,
+    };
+
+    let snapshot = fw_cache.load();
+    if let Some(fw_meta) = snapshot.find_latest(code) {
+        if slice_size == 0 {
+            send_fw_info(&fw_meta.info(), socket).await?;
+        } else if let Some(fw_image) = fw_cache.image(fw_meta).await {
+            match fw_image.merkle(slice_size as usize).await {
+                Some(tree) => {
+                    send_fw_info_merkle(&fw_meta.info(), slice_size, &tree.root(), socket).await?
+                }
+                None => {
+                    error!("Unsupported slice size {} for Merkle tree", slice_size);
+                    send_failed_package(socket, ErrorCode::LengthError as u8).await?;
+                }
+            }
+        } else {
+            error!("Read Firmware Error!");
+            send_failed_package(socket, ErrorCode::FirmwareReadError as u8).await?;
+        }
     } else {
         error!("No firmware found!");
         send_failed_package(socket, ErrorCode::NoFirmwareFound as u8).await?;
@@ -186,9 +750,11 @@ async fn process_fw_query_request(
 /// 处理固件下载请求
 async fn process_fw_download_request(
     request: &[u8],
-    socket: &mut TcpStream,
+    socket: &mut dyn Transport,
     _code: i32,
-    fw_data_all: Arc<tokio::sync::Mutex<Vec<FirmwareData>>>,
+    fw_cache: &FirmwareCache,
+    downloads: &DownloadTracker,
+    session: &DeviceSession,
 ) -> Result<(), Box<dyn Error>> {
     info!("[Command] Download Firmware.");
 
@@ -200,10 +766,15 @@ async fn process_fw_download_request(
 
     let _index = (request[10] as u16) << 8 | request[11] as u16; // 切片序号
     let _slice = (request[12] as u16) << 8 | request[13] as u16; // 切片大小，一般默认512
+    let with_proof = request.len() >= 16 && request[14] & 0x01 != 0; // 需要认证路径
 
-    let fw_data_lock = fw_data_all.lock().await;
-    if let Some(fw_data) = find_firmware(&fw_data_lock, _code as i32, _version) {
-        let data = slice_fw_data_from_vector(&fw_data.fwdata, _index as usize, _slice as usize);
+    let snapshot = fw_cache.load();
+    if let Some(fw_meta) = snapshot.find(_code, &_version) {
+        // 固件内容按需加载
+        let fw_image = fw_cache.image(fw_meta).await;
+        let data = fw_image
+            .as_ref()
+            .and_then(|image| image.slice(_index as usize, _slice as usize));
 
         match data {
             Some(data) => {
@@ -214,22 +785,27 @@ async fn process_fw_download_request(
                     _slice,
                     data.len()
                 );
-                send_fw_data(
-                    &FirmwareInfo {
-                        code: fw_data.fwcode,
-                        version: FirmwareVersion {
-                            m: fw_data.version_m,
-                            n: fw_data.version_n,
-                            l: fw_data.version_l,
-                        },
-                        size: fw_data.fwsize,
-                        path: String::from(""),
+                let proof = match fw_image.as_ref().filter(|_| with_proof) {
+                    Some(image) => match image.merkle(_slice as usize).await {
+                        Some(tree) => tree.proof(_index as usize),
+                        None => {
+                            error!("Unsupported slice size {} for Merkle tree", _slice);
+                            send_failed_package(socket, ErrorCode::LengthError as u8).await?;
+                            return Ok(());
+                        }
                     },
-                    &data,
-                    _index,
-                    socket,
-                )
-                .await?;
+                    None => None,
+                };
+                match proof {
+                    Some(proof) => {
+                        send_fw_data_proof(&fw_meta.info(), &data, _index, &proof, socket).await?
+                    }
+                    None => send_fw_data(&fw_meta.info(), &data, _index, socket).await?,
+                }
+
+                // 下载统计
+                metrics().firmware_served(_code, data.len());
+                downloads.record_slice(session, meta_key(fw_meta), _index, _slice, data.len());
             }
             None => {
                 // 发送文件错误
@@ -248,10 +824,11 @@ async fn process_fw_download_request(
 /// 处理固件结束请求
 async fn process_fw_end_request(
     request: &[u8],
-    _socket: &mut TcpStream,
+    _socket: &mut dyn Transport,
     _code: i32,
-    _fw_data_all: Arc<tokio::sync::Mutex<Vec<FirmwareData>>>,
-    fw_server: &str,
+    history: &HistoryQueue,
+    downloads: &DownloadTracker,
+    session: &DeviceSession,
 ) -> Result<(), Box<dyn Error>> {
     info!("[Command] Download Firmware Over.");
 
@@ -288,31 +865,19 @@ async fn process_fw_end_request(
         success,
     };
 
-    // 插入数据库（固件升级记录）
-    push_new_history(fw_server, &new_history).await;
-
-    Ok(())
-}
+    // 结束下载会话，与升级记录一起上传
+    let download = downloads.finish(
+        Some(&new_history.device_id),
+        session.conn_id,
+        (_code, _version.m, _version.n, _version.l),
+        match success {
+            true => DownloadOutcome::Success,
+            false => DownloadOutcome::Failed,
+        },
+    );
 
-/// 上传固件升级成功历史记录
-pub async fn push_new_history(server: &str, new_data: &NewUpgradeHistory) {
-    let client = reqwest::Client::new();
-    let res = client
-        .post(format!("{}/history", server))
-        .json(&new_data)
-        .send()
-        .await;
+    // 插入数据库（固件升级记录），由后台任务上传
+    history.push(new_history, download);
 
-    match res {
-        Ok(response) => {
-            if response.status().is_success() {
-                info!("Upgrade history added successfully");
-            } else {
-                info!("Failed to upload upgrade_history: {}", response.status());
-            }
-        }
-        Err(e) => {
-            info!("Failed to upload upgrade_history: {}", e);
-        }
-    }
+    Ok(())
 }
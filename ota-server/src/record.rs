@@ -0,0 +1,421 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    cell::RefCell,
+    collections::HashSet,
+    future::Future,
+    io,
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Instant,
+};
+use tokio::{
+    fs,
+    io::AsyncWriteExt,
+    sync::{mpsc, watch},
+};
+use tracing::{debug, info, warn};
+
+use crate::ProtocolVersion;
+
+/// 设备识别前在内存中最多缓存的字节数，超出后写到临时文件，连接结束时仍未识别则删除
+pub const PENDING_LIMIT: usize = 64 * 1024;
+
+tokio::task_local! {
+    /// 当前连接的录制，发送返回包时从这里取
+    static RECORDING: RefCell<Option<SessionRecording>>;
+}
+
+/// 帧的方向
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordDirection {
+    /// 设备发给服务器
+    In,
+    /// 服务器发给设备
+    Out,
+}
+
+/// 录制文件的一行（JSON Lines）
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RecordLine {
+    /// 文件头，按IP录制时设备ID可能为空
+    Session {
+        peer: String,
+        device_id: Option<String>,
+        started_at: DateTime<Utc>,
+        protocol: u8,
+    },
+    /// 录制开始后才识别到设备
+    Identified { elapsed_us: u64, device_id: String },
+    /// 原始帧，16进制
+    Frame {
+        elapsed_us: u64,
+        direction: RecordDirection,
+        data: String,
+    },
+}
+
+impl RecordLine {
+    /// 帧的原始字节，不是帧或16进制格式错误时返回 None
+    pub fn frame(&self) -> Option<(RecordDirection, Vec<u8>)> {
+        match self {
+            RecordLine::Frame {
+                direction, data, ..
+            } => decode_hex(data).map(|bytes| (*direction, bytes)),
+            _ => None,
+        }
+    }
+}
+
+fn encode_hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{:02X}", byte)).collect()
+}
+
+/// 长度为奇数时最后一个字节取不到，返回 None
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    (0..text.len())
+        .step_by(2)
+        .map(|at| u8::from_str_radix(text.get(at..at + 2)?, 16).ok())
+        .collect()
+}
+
+/// 读取录制文件
+pub async fn read_recording(path: &Path) -> io::Result<Vec<RecordLine>> {
+    let content = fs::read_to_string(path).await?;
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line).map_err(io::Error::from))
+        .collect()
+}
+
+/// 会话录制配置，按设备ID或IP选择连接
+#[derive(Debug, Default)]
+pub struct Recorder {
+    dir: PathBuf,
+    device_ids: HashSet<String>,
+    peers: HashSet<IpAddr>,
+    /// 未写完的录制文件数
+    writers: watch::Sender<usize>,
+}
+
+impl Recorder {
+    /// 目标为IP地址或16进制设备ID，设备ID不区分大小写
+    pub fn new(dir: impl Into<PathBuf>, targets: &[String]) -> Self {
+        let mut recorder = Recorder {
+            dir: dir.into(),
+            ..Default::default()
+        };
+        for target in targets.iter().map(|target| target.trim()) {
+            match target.parse::<IpAddr>() {
+                Ok(ip) => {
+                    recorder.peers.insert(ip);
+                }
+                Err(_) if !target.is_empty() => {
+                    recorder.device_ids.insert(target.to_ascii_uppercase());
+                }
+                Err(_) => {}
+            }
+        }
+        recorder
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.device_ids.is_empty() && self.peers.is_empty()
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// 等待已结束连接的录制文件写完
+    pub async fn flushed(&self) {
+        let _ = self
+            .writers
+            .subscribe()
+            .wait_for(|writers| *writers == 0)
+            .await;
+    }
+
+    /// 连接建立时调用，不可能需要录制时返回 None
+    /// - IP 匹配：立即开始写文件
+    /// - 配置了设备ID：先缓存，识别到设备后再决定
+    pub fn session(
+        self: &Arc<Self>,
+        peer: SocketAddr,
+        protocol: ProtocolVersion,
+    ) -> Option<SessionRecording> {
+        let mut recording = SessionRecording {
+            recorder: Arc::clone(self),
+            peer,
+            protocol: protocol as u8,
+            started: Instant::now(),
+            started_at: Utc::now(),
+            state: RecordState::Stopped,
+        };
+
+        if self.peers.contains(&peer.ip()) {
+            recording.start(None);
+        } else if !self.device_ids.is_empty() {
+            recording.state = RecordState::Pending {
+                lines: Vec::new(),
+                bytes: 0,
+            };
+        } else {
+            return None;
+        }
+
+        Some(recording)
+    }
+}
+
+enum RecordState {
+    /// 等待识别设备
+    Pending {
+        lines: Vec<RecordLine>,
+        bytes: usize,
+    },
+    /// 识别前数据过多，先写到临时文件，识别到目标设备后改名
+    Spilled {
+        tx: mpsc::UnboundedSender<WriterMessage>,
+    },
+    /// 正在写文件
+    Writing {
+        tx: mpsc::UnboundedSender<WriterMessage>,
+        device_id: Option<String>,
+    },
+    Stopped,
+}
+
+enum WriterMessage {
+    Line(RecordLine),
+    /// 保留临时文件，改名为正式的录制文件
+    Keep(PathBuf),
+}
+
+/// 一个连接的录制
+pub struct SessionRecording {
+    recorder: Arc<Recorder>,
+    peer: SocketAddr,
+    protocol: u8,
+    started: Instant,
+    started_at: DateTime<Utc>,
+    state: RecordState,
+}
+
+impl SessionRecording {
+    fn elapsed_us(&self) -> u64 {
+        self.started.elapsed().as_micros() as u64
+    }
+
+    /// 记录一帧
+    pub fn frame(&mut self, direction: RecordDirection, data: &[u8]) {
+        let line = RecordLine::Frame {
+            elapsed_us: self.elapsed_us(),
+            direction,
+            data: encode_hex(data),
+        };
+
+        match &mut self.state {
+            RecordState::Pending { lines, bytes } => {
+                *bytes += data.len();
+                lines.push(line);
+                if *bytes > PENDING_LIMIT {
+                    debug!(
+                        "Device not identified after {} bytes, recording to temporary file",
+                        bytes
+                    );
+                    let lines = std::mem::take(lines);
+                    self.spill(lines);
+                }
+            }
+            RecordState::Spilled { tx } | RecordState::Writing { tx, .. } => {
+                let _ = tx.send(WriterMessage::Line(line));
+            }
+            RecordState::Stopped => {}
+        }
+    }
+
+    /// 识别到设备
+    pub fn identify(&mut self, device_id: &str) {
+        let elapsed_us = self.elapsed_us();
+
+        match &mut self.state {
+            RecordState::Pending { lines, .. } => {
+                if self.recorder.device_ids.contains(device_id) {
+                    let lines = std::mem::take(lines);
+                    self.start(Some(device_id.to_string()));
+                    if let RecordState::Writing { tx, .. } = &self.state {
+                        for line in lines {
+                            let _ = tx.send(WriterMessage::Line(line));
+                        }
+                    }
+                } else {
+                    self.state = RecordState::Stopped;
+                }
+            }
+            RecordState::Spilled { tx } => {
+                if self.recorder.device_ids.contains(device_id) {
+                    let tx = tx.clone();
+                    let path = self.path(Some(device_id));
+                    info!("Recording session of {} to {}", self.peer, path.display());
+                    let _ = tx.send(WriterMessage::Line(RecordLine::Identified {
+                        elapsed_us,
+                        device_id: device_id.to_string(),
+                    }));
+                    let _ = tx.send(WriterMessage::Keep(path));
+                    self.state = RecordState::Writing {
+                        tx,
+                        device_id: Some(device_id.to_string()),
+                    };
+                } else {
+                    // 释放发送端后临时文件被删除
+                    self.state = RecordState::Stopped;
+                }
+            }
+            RecordState::Writing {
+                tx,
+                device_id: known,
+            } => {
+                if known.as_deref() != Some(device_id) {
+                    *known = Some(device_id.to_string());
+                    let _ = tx.send(WriterMessage::Line(RecordLine::Identified {
+                        elapsed_us,
+                        device_id: device_id.to_string(),
+                    }));
+                }
+            }
+            RecordState::Stopped => {}
+        }
+    }
+
+    /// 录制文件路径：设备ID或IP-开始时间-端口.jsonl
+    fn path(&self, device_id: Option<&str>) -> PathBuf {
+        let label = match device_id {
+            Some(device_id) => device_id.to_string(),
+            None => self.peer.ip().to_string().replace(':', "_"),
+        };
+        self.recorder.dir.join(format!(
+            "{}-{}-{}.jsonl",
+            label,
+            self.started_at.format("%Y%m%dT%H%M%S%.3f"),
+            self.peer.port()
+        ))
+    }
+
+    fn header(&self, device_id: Option<String>) -> WriterMessage {
+        WriterMessage::Line(RecordLine::Session {
+            peer: self.peer.to_string(),
+            device_id,
+            started_at: self.started_at,
+            protocol: self.protocol,
+        })
+    }
+
+    /// 创建录制文件，写入文件头
+    fn start(&mut self, device_id: Option<String>) {
+        let path = self.path(device_id.as_deref());
+        info!("Recording session of {} to {}", self.peer, path.display());
+
+        let tx = spawn_writer(&self.recorder, path, false);
+        let _ = tx.send(self.header(device_id.clone()));
+        self.state = RecordState::Writing { tx, device_id };
+    }
+
+    /// 把缓存的帧写到临时文件，文件头中没有设备ID
+    fn spill(&mut self, lines: Vec<RecordLine>) {
+        let mut path = self.path(None).into_os_string();
+        path.push(".pending");
+
+        let tx = spawn_writer(&self.recorder, path.into(), true);
+        let _ = tx.send(self.header(None));
+        for line in lines {
+            let _ = tx.send(WriterMessage::Line(line));
+        }
+        self.state = RecordState::Spilled { tx };
+    }
+}
+
+/// 后台写文件，连接结束（发送端释放）后写完剩余的行
+/// 临时文件在收到 Keep 后改名，否则删除
+fn spawn_writer(
+    recorder: &Recorder,
+    path: PathBuf,
+    temporary: bool,
+) -> mpsc::UnboundedSender<WriterMessage> {
+    let (tx, mut rx) = mpsc::unbounded_channel::<WriterMessage>();
+
+    let writers = recorder.writers.clone();
+    writers.send_modify(|writers| *writers += 1);
+    tokio::spawn(async move {
+        let keep = match write_lines(&path, &mut rx).await {
+            Ok(keep) => keep,
+            Err(e) => {
+                warn!("Recording {} failed: {}", path.display(), e);
+                None
+            }
+        };
+        if temporary {
+            let result = match keep {
+                Some(target) => fs::rename(&path, &target).await,
+                None => fs::remove_file(&path).await,
+            };
+            if let Err(e) = result {
+                warn!("Recording {} cleanup failed: {}", path.display(), e);
+            }
+        }
+        writers.send_modify(|writers| *writers -= 1);
+    });
+
+    tx
+}
+
+async fn write_lines(
+    path: &Path,
+    rx: &mut mpsc::UnboundedReceiver<WriterMessage>,
+) -> io::Result<Option<PathBuf>> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).await?;
+    }
+    let mut file = fs::File::create(path).await?;
+    let mut keep = None;
+
+    while let Some(message) = rx.recv().await {
+        match message {
+            WriterMessage::Line(line) => {
+                let mut text = serde_json::to_string(&line)?;
+                text.push('\n');
+                file.write_all(text.as_bytes()).await?;
+            }
+            WriterMessage::Keep(target) => keep = Some(target),
+        }
+    }
+    file.flush().await?;
+    Ok(keep)
+}
+
+/// 在连接的录制范围内运行
+pub async fn scope<F: Future>(recording: Option<SessionRecording>, f: F) -> F::Output {
+    RECORDING.scope(RefCell::new(recording), f).await
+}
+
+/// 记录一帧，不在录制范围内或不需要录制时忽略
+pub fn record_frame(direction: RecordDirection, data: &[u8]) {
+    let _ = RECORDING.try_with(|recording| {
+        if let Some(recording) = recording.borrow_mut().as_mut() {
+            recording.frame(direction, data);
+        }
+    });
+}
+
+/// 记录识别到的设备
+pub fn record_device(device_id: &str) {
+    let _ = RECORDING.try_with(|recording| {
+        if let Some(recording) = recording.borrow_mut().as_mut() {
+            recording.identify(device_id);
+        }
+    });
+}
@@ -0,0 +1,254 @@
+use chrono::{NaiveDateTime, Utc};
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    future::Future,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+use tokio::sync::{mpsc, Notify};
+use tracing::{debug, info};
+
+use crate::fw_cache::FirmwareCache;
+
+/// 推送给连接任务的事件
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionEvent {
+    /// 最新固件有变化，连接任务自行判断是否需要通知设备
+    FirmwareChanged,
+}
+
+/// 连接信息，供管理接口查看
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ConnectionInfo {
+    pub id: u64,
+    pub peer: SocketAddr,
+    pub connected_at: NaiveDateTime,
+    /// 设备未识别时为空
+    pub device_id: Option<String>,
+    /// 固件代号，从固件相关请求中获得
+    pub fw_code: Option<i32>,
+}
+
+#[derive(Debug)]
+struct Connection {
+    info: ConnectionInfo,
+    kick: Arc<Notify>,
+    tx: mpsc::UnboundedSender<SessionEvent>,
+}
+
+/// 连接登记表，登记所有连接（包括未识别的设备），用于推送、管理接口查看和断开
+/// 另外按设备ID索引已识别的设备，同一设备重复连接时以最后一个连接为准
+#[derive(Debug, Default)]
+pub struct SessionRegistry {
+    /// 设备ID -> 连接ID
+    sessions: Mutex<HashMap<String, u64>>,
+    connections: Mutex<HashMap<u64, Connection>>,
+    next_id: AtomicU64,
+}
+
+impl SessionRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 登记连接识别到的设备，返回的句柄释放时自动注销
+    pub fn register(self: &Arc<Self>, device_id: &str, conn_id: u64) -> SessionHandle {
+        let replaced = self
+            .sessions
+            .lock()
+            .unwrap()
+            .insert(device_id.to_string(), conn_id);
+        if replaced.is_some() {
+            info!(
+                "Device {} reconnected, previous session replaced",
+                device_id
+            );
+        }
+
+        SessionHandle {
+            registry: Arc::clone(self),
+            device_id: device_id.to_string(),
+            conn_id,
+        }
+    }
+
+    /// 发送事件给指定设备，设备不在线时返回 false
+    pub fn send(&self, device_id: &str, event: SessionEvent) -> bool {
+        let Some(conn_id) = self.sessions.lock().unwrap().get(device_id).copied() else {
+            return false;
+        };
+        self.connections
+            .lock()
+            .unwrap()
+            .get(&conn_id)
+            .is_some_and(|connection| connection.tx.send(event).is_ok())
+    }
+
+    /// 发送事件给所有连接，包括未识别的设备，返回发送成功的数量
+    pub fn broadcast(&self, event: SessionEvent) -> usize {
+        self.connections
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|connection| connection.tx.send(event).is_ok())
+            .count()
+    }
+
+    /// 在线设备ID
+    pub fn devices(&self) -> Vec<String> {
+        let mut devices: Vec<String> = self.sessions.lock().unwrap().keys().cloned().collect();
+        devices.sort();
+        devices
+    }
+
+    pub fn len(&self) -> usize {
+        self.sessions.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sessions.lock().unwrap().is_empty()
+    }
+
+    /// 登记连接，返回的句柄释放时自动注销
+    pub fn connect(self: &Arc<Self>, peer: SocketAddr) -> ConnectionHandle {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let kick = Arc::new(Notify::new());
+        let (tx, rx) = mpsc::unbounded_channel();
+        let info = ConnectionInfo {
+            id,
+            peer,
+            connected_at: Utc::now().naive_utc(),
+            device_id: None,
+            fw_code: None,
+        };
+
+        self.connections.lock().unwrap().insert(
+            id,
+            Connection {
+                info,
+                kick: kick.clone(),
+                tx,
+            },
+        );
+
+        ConnectionHandle {
+            registry: Arc::clone(self),
+            id,
+            kick,
+            rx,
+        }
+    }
+
+    /// 当前连接，按连接先后排序
+    pub fn connections(&self) -> Vec<ConnectionInfo> {
+        let mut connections: Vec<ConnectionInfo> = self
+            .connections
+            .lock()
+            .unwrap()
+            .values()
+            .map(|connection| connection.info.clone())
+            .collect();
+        connections.sort_by_key(|info| info.id);
+        connections
+    }
+
+    /// 通知连接任务断开，连接不存在时返回 false
+    pub fn disconnect(&self, id: u64) -> bool {
+        match self.connections.lock().unwrap().get(&id) {
+            Some(connection) => {
+                connection.kick.notify_one();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 注销，只删除同一连接的登记
+    fn unregister(&self, device_id: &str, conn_id: u64) {
+        let mut sessions = self.sessions.lock().unwrap();
+        if sessions.get(device_id) == Some(&conn_id) {
+            sessions.remove(device_id);
+        }
+    }
+}
+
+/// 连接持有的设备登记句柄
+#[derive(Debug)]
+pub struct SessionHandle {
+    registry: Arc<SessionRegistry>,
+    device_id: String,
+    conn_id: u64,
+}
+
+impl SessionHandle {
+    pub fn device_id(&self) -> &str {
+        &self.device_id
+    }
+}
+
+impl Drop for SessionHandle {
+    fn drop(&mut self) {
+        self.registry.unregister(&self.device_id, self.conn_id);
+    }
+}
+
+/// 连接任务持有的连接句柄
+#[derive(Debug)]
+pub struct ConnectionHandle {
+    registry: Arc<SessionRegistry>,
+    id: u64,
+    kick: Arc<Notify>,
+    rx: mpsc::UnboundedReceiver<SessionEvent>,
+}
+
+impl ConnectionHandle {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// 更新识别到的设备ID和固件代号
+    pub fn update(&self, device_id: Option<&str>, fw_code: Option<i32>) {
+        if let Some(connection) = self.registry.connections.lock().unwrap().get_mut(&self.id) {
+            let info = &mut connection.info;
+            if info.device_id.as_deref() != device_id {
+                info.device_id = device_id.map(str::to_string);
+            }
+            info.fw_code = fw_code;
+        }
+    }
+
+    /// 等待断开通知，通知在等待之前发出时同样返回
+    /// 返回的 future 不借用句柄，可以和 `recv` 同时等待
+    pub fn kicked(&self) -> impl Future<Output = ()> {
+        let kick = self.kick.clone();
+        async move { kick.notified().await }
+    }
+
+    /// 等待推送事件
+    pub async fn recv(&mut self) -> SessionEvent {
+        match self.rx.recv().await {
+            Some(event) => event,
+            None => std::future::pending().await,
+        }
+    }
+}
+
+impl Drop for ConnectionHandle {
+    fn drop(&mut self) {
+        self.registry.connections.lock().unwrap().remove(&self.id);
+    }
+}
+
+/// 固件缓存中的最新固件变化时通知所有连接
+pub async fn push_firmware_updates(registry: Arc<SessionRegistry>, fw_cache: Arc<FirmwareCache>) {
+    let mut changes = fw_cache.subscribe();
+
+    while changes.changed().await.is_ok() {
+        let count = registry.broadcast(SessionEvent::FirmwareChanged);
+        debug!("Firmware changed, {} sessions notified", count);
+    }
+}
@@ -0,0 +1,510 @@
+use std::{
+    io,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+use thiserror::Error;
+use tokio::{
+    net::{TcpListener, UdpSocket},
+    sync::watch,
+    task::JoinHandle,
+    time::Instant,
+};
+use tracing::{error, field, info, info_span, warn, Instrument};
+
+use crate::{
+    admin::{serve_admin, AdminState},
+    broadcast::{serve_broadcast, BroadcastError, BroadcastOptions},
+    config_cache::ConfigCache,
+    context::{ProtocolOptions, ServerContext},
+    download::{expire_downloads, DownloadTracker},
+    fw_cache::{refresh_and_log, refresh_firmware_cache, FirmwareCache},
+    handler::{HandlerRegistry, PacketHandler},
+    history::{HistoryQueue, HistoryWorker},
+    process_pg::handle_client,
+    record::Recorder,
+    registry::{push_firmware_updates, SessionRegistry},
+    shutdown::ShutdownController,
+    source::{Backend, HistorySink},
+    transport::serve_websocket,
+};
+
+#[derive(Debug, Error)]
+pub enum BuildError {
+    #[error("Firmware source not set")]
+    NoBackend,
+    #[error("Failed to bind {addr}: {source}")]
+    Bind { addr: String, source: io::Error },
+    #[error("Broadcast error: {0}")]
+    Broadcast(#[from] BroadcastError),
+}
+
+/// 嵌入 OTA 服务的构建器
+/// ```no_run
+/// # use ota_server::{server::OtaServer, source::dir::DirSource};
+/// # use std::sync::Arc;
+/// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// let server = OtaServer::builder()
+///     .bind("127.0.0.1:0")
+///     .backend(Arc::new(DirSource::new("./firmware")))
+///     .build()
+///     .await?;
+/// println!("Listening on {}", server.local_addr());
+/// server.run().await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct OtaServerBuilder {
+    bind: String,
+    websocket: Option<String>,
+    admin: Option<String>,
+    backend: Option<Arc<dyn Backend>>,
+    history_sink: Option<Arc<dyn HistorySink>>,
+    handlers: HandlerRegistry,
+    protocol: ProtocolOptions,
+    recorder: Option<Arc<Recorder>>,
+    broadcast: Option<BroadcastOptions>,
+    cache_size: usize,
+    refresh_interval: Duration,
+    config_ttl: Duration,
+    drain_timeout: Duration,
+}
+
+impl Default for OtaServerBuilder {
+    fn default() -> Self {
+        OtaServerBuilder {
+            bind: "0.0.0.0:9999".to_string(),
+            websocket: None,
+            admin: None,
+            backend: None,
+            history_sink: None,
+            handlers: HandlerRegistry::builtin(),
+            protocol: ProtocolOptions::default(),
+            recorder: None,
+            broadcast: None,
+            cache_size: 64 * 1024 * 1024,
+            refresh_interval: Duration::from_secs(60),
+            config_ttl: Duration::from_secs(60),
+            drain_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+impl OtaServerBuilder {
+    /// TCP 监听地址，端口为0时由系统分配
+    pub fn bind(mut self, addr: impl Into<String>) -> Self {
+        self.bind = addr.into();
+        self
+    }
+
+    /// WebSocket 监听地址，不设置时不开启
+    pub fn websocket(mut self, addr: impl Into<String>) -> Self {
+        self.websocket = Some(addr.into());
+        self
+    }
+
+    /// 管理接口监听地址（健康检查、刷新缓存、会话管理），不设置时不开启
+    pub fn admin(mut self, addr: impl Into<String>) -> Self {
+        self.admin = Some(addr.into());
+        self
+    }
+
+    /// 固件和配置来源，同时提供设备影子和命令队列
+    pub fn backend(mut self, backend: Arc<dyn Backend>) -> Self {
+        self.backend = Some(backend);
+        self
+    }
+
+    /// 升级记录去向，不设置时使用 `backend`
+    pub fn history_sink(mut self, sink: Arc<dyn HistorySink>) -> Self {
+        self.history_sink = Some(sink);
+        self
+    }
+
+    /// 替换全部包处理器，默认为内置包类型
+    pub fn handlers(mut self, handlers: HandlerRegistry) -> Self {
+        self.handlers = handlers;
+        self
+    }
+
+    /// 注册一个包处理器，见 [`HandlerRegistry::register`]
+    pub fn handler<H: PacketHandler + 'static>(mut self, package_type: u8, handler: H) -> Self {
+        self.handlers.register(package_type, handler);
+        self
+    }
+
+    pub fn protocol(mut self, protocol: ProtocolOptions) -> Self {
+        self.protocol = protocol;
+        self
+    }
+
+    /// 会话录制
+    pub fn recorder(mut self, recorder: Option<Arc<Recorder>>) -> Self {
+        self.recorder = recorder;
+        self
+    }
+
+    /// 组播广播，代号为空时不开启
+    pub fn broadcast(mut self, options: BroadcastOptions) -> Self {
+        self.broadcast = Some(options);
+        self
+    }
+
+    /// 固件内容缓存大小（字节）
+    pub fn cache_size(mut self, bytes: usize) -> Self {
+        self.cache_size = bytes;
+        self
+    }
+
+    /// 固件元数据刷新周期
+    pub fn refresh_interval(mut self, interval: Duration) -> Self {
+        self.refresh_interval = interval;
+        self
+    }
+
+    /// 配置缓存时间，收到变化通知时提前失效
+    pub fn config_ttl(mut self, ttl: Duration) -> Self {
+        self.config_ttl = ttl;
+        self
+    }
+
+    /// 退出时等待连接和升级记录上传的时间
+    pub fn drain_timeout(mut self, timeout: Duration) -> Self {
+        self.drain_timeout = timeout;
+        self
+    }
+
+    /// 绑定端口，加载固件元数据，启动后台任务
+    /// 固件来源暂时不可用时同样启动，之后定时重试
+    pub async fn build(self) -> Result<OtaServer, BuildError> {
+        let backend = self.backend.ok_or(BuildError::NoBackend)?;
+        if let Some(broadcast) = &self.broadcast {
+            broadcast.fec.validate()?;
+        }
+
+        let listener = bind(&self.bind).await?;
+        let ws_listener = match &self.websocket {
+            Some(addr) => Some(bind(addr).await?),
+            None => None,
+        };
+        let admin_listener = match &self.admin {
+            Some(addr) => Some(bind(addr).await?),
+            None => None,
+        };
+        let local_addr = listener_addr(&listener, &self.bind)?;
+        let ws_local_addr = match (&ws_listener, &self.websocket) {
+            (Some(listener), Some(addr)) => Some(listener_addr(listener, addr)?),
+            _ => None,
+        };
+        let admin_local_addr = match (&admin_listener, &self.admin) {
+            (Some(listener), Some(addr)) => Some(listener_addr(listener, addr)?),
+            _ => None,
+        };
+
+        // 固件缓存，连接任务无锁读取快照，固件内容按需加载
+        let fw_cache = Arc::new(FirmwareCache::new(backend.clone(), self.cache_size));
+        refresh_and_log(&fw_cache).await;
+
+        let config_cache = Arc::new(ConfigCache::new(backend.clone(), self.config_ttl));
+        let registry = Arc::new(SessionRegistry::new());
+        let downloads = Arc::new(DownloadTracker::new());
+        let sink = self.history_sink.unwrap_or_else(|| backend.clone());
+        let (history, history_worker) = HistoryQueue::spawn(sink);
+        let controller = ShutdownController::new();
+
+        // 后台任务：定时刷新，最新固件变化时推送给在线设备，下载会话超时
+        let mut tasks = vec![
+            tokio::spawn(refresh_firmware_cache(
+                fw_cache.clone(),
+                self.refresh_interval,
+            )),
+            tokio::spawn(push_firmware_updates(registry.clone(), fw_cache.clone())),
+            tokio::spawn(expire_downloads(
+                downloads.clone(),
+                history.clone(),
+                controller.subscribe(),
+            )),
+        ];
+
+        // 组播广播，缺失的分片由设备通过固件下载补齐
+        if let Some(broadcast) = self.broadcast.filter(|options| !options.codes.is_empty()) {
+            let addr = "0.0.0.0:0".to_string();
+            let socket = UdpSocket::bind(&addr)
+                .await
+                .map_err(|source| BuildError::Bind { addr, source })?;
+            tasks.push(tokio::spawn(serve_broadcast(
+                socket,
+                broadcast,
+                fw_cache.clone(),
+                controller.subscribe(),
+            )));
+        }
+
+        let ctx = ServerContext {
+            fw_cache: fw_cache.clone(),
+            config_cache: config_cache.clone(),
+            history,
+            backend,
+            registry: registry.clone(),
+            downloads: downloads.clone(),
+            protocol: self.protocol,
+            recorder: self.recorder,
+            handlers: Arc::new(self.handlers),
+        };
+
+        Ok(OtaServer {
+            inner: Arc::new(Inner {
+                local_addr,
+                ws_local_addr,
+                admin_local_addr,
+                listeners: Mutex::new(Some(Listeners {
+                    tcp: listener,
+                    websocket: ws_listener,
+                    admin: admin_listener,
+                })),
+                ctx: Mutex::new(Some(ctx)),
+                fw_cache,
+                config_cache,
+                registry,
+                downloads,
+                connections: Arc::new(AtomicUsize::new(0)),
+                controller: Mutex::new(Some(controller)),
+                history_worker: Mutex::new(Some(history_worker)),
+                stop: watch::Sender::new(false),
+                tasks: Mutex::new(tasks),
+                drain_timeout: self.drain_timeout,
+            }),
+        })
+    }
+}
+
+async fn bind(addr: &str) -> Result<TcpListener, BuildError> {
+    TcpListener::bind(addr)
+        .await
+        .map_err(|source| BuildError::Bind {
+            addr: addr.to_string(),
+            source,
+        })
+}
+
+fn listener_addr(listener: &TcpListener, addr: &str) -> Result<SocketAddr, BuildError> {
+    listener.local_addr().map_err(|source| BuildError::Bind {
+        addr: addr.to_string(),
+        source,
+    })
+}
+
+/// 运行状态
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ServerStats {
+    /// 当前连接数，TCP 和 WebSocket
+    pub connections: usize,
+    /// 已识别的在线设备
+    pub devices: usize,
+    /// 进行中的下载会话
+    pub downloads: usize,
+    /// 固件快照中的固件数
+    pub firmware: usize,
+}
+
+/// 运行中的 OTA 服务，克隆后共享同一个服务
+/// - `run` 接受连接，直到 `shutdown` 后排空连接和升级记录再返回
+/// - 后台任务在 `build` 时启动，`run` 返回或最后一个句柄释放时停止
+#[derive(Clone)]
+pub struct OtaServer {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    local_addr: SocketAddr,
+    ws_local_addr: Option<SocketAddr>,
+    admin_local_addr: Option<SocketAddr>,
+    listeners: Mutex<Option<Listeners>>,
+    /// 排空时释放，升级记录队列才能结束
+    ctx: Mutex<Option<ServerContext>>,
+    fw_cache: Arc<FirmwareCache>,
+    config_cache: Arc<ConfigCache>,
+    registry: Arc<SessionRegistry>,
+    downloads: Arc<DownloadTracker>,
+    connections: Arc<AtomicUsize>,
+    controller: Mutex<Option<ShutdownController>>,
+    history_worker: Mutex<Option<HistoryWorker>>,
+    stop: watch::Sender<bool>,
+    tasks: Mutex<Vec<JoinHandle<()>>>,
+    drain_timeout: Duration,
+}
+
+struct Listeners {
+    tcp: TcpListener,
+    websocket: Option<TcpListener>,
+    admin: Option<TcpListener>,
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        for task in self.tasks.get_mut().unwrap().drain(..) {
+            task.abort();
+        }
+    }
+}
+
+/// 连接计数，连接任务结束时减一
+struct ConnectionCount(Arc<AtomicUsize>);
+
+impl ConnectionCount {
+    fn new(count: &Arc<AtomicUsize>) -> Self {
+        count.fetch_add(1, Ordering::Relaxed);
+        ConnectionCount(count.clone())
+    }
+}
+
+impl Drop for ConnectionCount {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl OtaServer {
+    pub fn builder() -> OtaServerBuilder {
+        OtaServerBuilder::default()
+    }
+
+    /// TCP 实际监听的地址
+    pub fn local_addr(&self) -> SocketAddr {
+        self.inner.local_addr
+    }
+
+    /// WebSocket 实际监听的地址
+    pub fn ws_local_addr(&self) -> Option<SocketAddr> {
+        self.inner.ws_local_addr
+    }
+
+    /// 管理接口实际监听的地址
+    pub fn admin_local_addr(&self) -> Option<SocketAddr> {
+        self.inner.admin_local_addr
+    }
+
+    /// 固件缓存，用于接入变化通知
+    pub fn fw_cache(&self) -> &Arc<FirmwareCache> {
+        &self.inner.fw_cache
+    }
+
+    /// 配置缓存，用于接入变化通知
+    pub fn config_cache(&self) -> &Arc<ConfigCache> {
+        &self.inner.config_cache
+    }
+
+    pub fn stats(&self) -> ServerStats {
+        ServerStats {
+            connections: self.inner.connections.load(Ordering::Relaxed),
+            devices: self.inner.registry.len(),
+            downloads: self.inner.downloads.len(),
+            firmware: self.inner.fw_cache.load().len(),
+        }
+    }
+
+    /// 通知 `run` 停止接受连接并排空，可以在 `run` 之前调用
+    pub fn shutdown(&self) {
+        self.inner.stop.send_replace(true);
+    }
+
+    /// 接受连接直到 `shutdown`，只能调用一次
+    /// 返回前等待连接完成当前请求、剩余的升级记录上传，最长等待 `drain_timeout`
+    pub async fn run(&self) -> io::Result<()> {
+        let inner = &self.inner;
+        let Some(listeners) = inner.listeners.lock().unwrap().take() else {
+            return Err(io::Error::other("Server already running"));
+        };
+        let ctx = inner.ctx.lock().unwrap().clone();
+        let (Some(ctx), Some(controller)) = (ctx, inner.controller.lock().unwrap().take()) else {
+            return Err(io::Error::other("Server already stopped"));
+        };
+        info!("Server listening on {}", inner.local_addr);
+
+        // WebSocket 接入，浏览器工具转发 USB 设备的帧
+        if let Some(ws_listener) = listeners.websocket {
+            tokio::spawn(serve_websocket(
+                ws_listener,
+                ctx.clone(),
+                controller.subscribe(),
+            ));
+        }
+
+        // 管理接口，排空期间仍然可用，就绪检查失败
+        if let Some(admin_listener) = listeners.admin {
+            let state = AdminState {
+                fw_cache: inner.fw_cache.clone(),
+                registry: inner.registry.clone(),
+                downloads: inner.downloads.clone(),
+                stopping: inner.stop.subscribe(),
+            };
+            let task = tokio::spawn(serve_admin(admin_listener, state));
+            inner.tasks.lock().unwrap().push(task);
+        }
+
+        let listener = listeners.tcp;
+        let mut stop = inner.stop.subscribe();
+        loop {
+            // 接受一个新的客户端连接，收到退出通知后停止接受
+            let (socket, addr) = tokio::select! {
+                res = listener.accept() => match res {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        error!("Accept Error: {}", e);
+                        continue;
+                    }
+                },
+                _ = stop.wait_for(|stop| *stop) => break,
+            };
+
+            let conn_ctx = ctx.clone();
+            let conn_shutdown = controller.subscribe();
+            let count = ConnectionCount::new(&inner.connections);
+
+            // 每个连接一个 span，设备ID在识别后记录
+            let span = info_span!("connection", peer = %addr, device_id = field::Empty);
+
+            tokio::spawn(
+                async move {
+                    let _count = count;
+                    if let Err(error) = handle_client(socket, conn_ctx, conn_shutdown).await {
+                        error!("Error handling client: {}", error);
+                    }
+                }
+                .instrument(span),
+            );
+        }
+
+        // 排空：停止监听，等待连接完成当前请求，再上传剩余的升级记录
+        drop(listener);
+        info!(
+            "Draining connections, deadline {}s",
+            inner.drain_timeout.as_secs()
+        );
+        let deadline = Instant::now() + inner.drain_timeout;
+
+        if !controller.shutdown(deadline).await {
+            warn!("Drain deadline reached, remaining connections dropped");
+        }
+
+        drop(ctx);
+        inner.ctx.lock().unwrap().take();
+        for task in inner.tasks.lock().unwrap().drain(..) {
+            task.abort();
+        }
+
+        let history_worker = inner.history_worker.lock().unwrap().take();
+        if let Some(history_worker) = history_worker {
+            if !history_worker.flush(deadline).await {
+                warn!("Drain deadline reached, pending upgrade history dropped");
+            }
+        }
+
+        info!("Server stopped");
+        Ok(())
+    }
+}
@@ -0,0 +1,158 @@
+use ota_database::models::firmware_data::FirmwareMeta;
+use std::{sync::Arc, time::Duration};
+use tokio::time::Instant;
+
+use crate::{
+    fw_cache::{meta_key, FirmwareKey, FirmwareSnapshot},
+    package::common::{parse_config_query, parse_config_report},
+    PackageType, ProtocolVersion,
+};
+
+/// 同一连接内查询待执行命令的最小间隔，避免固件下载时每个分片都访问后端
+pub const COMMAND_POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// 下载请求间隔超过该时间视为下载中断，不再暂缓推送
+pub const DOWNLOAD_IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 连接状态，设备ID从带设备信息的请求中获得
+#[derive(Debug, Default)]
+pub struct DeviceSession {
+    /// 对端IP，记录在下载会话中
+    pub peer: String,
+    /// 连接ID，设备未识别时用于区分下载会话
+    pub conn_id: u64,
+    pub device_id: Option<String>,
+    pub group_id: Option<i32>,
+    /// 已下发、等待确认的命令
+    pub in_flight: Option<i32>,
+    last_poll: Option<Instant>,
+    /// 固件代号，从固件相关请求中获得
+    pub fw_code: Option<i32>,
+    /// 设备已知的最新固件，更新的固件才推送
+    pub fw_known: Option<FirmwareKey>,
+    /// 最后一次分片请求的时间，下载结束时清空
+    download_active_at: Option<Instant>,
+    /// 协议版本，默认使用服务器配置，设备可在时间同步请求中声明
+    pub protocol: ProtocolVersion,
+}
+
+impl DeviceSession {
+    pub fn new(protocol: ProtocolVersion) -> Self {
+        DeviceSession {
+            protocol,
+            ..Default::default()
+        }
+    }
+
+    /// 从请求中提取设备ID和分组号，设备ID变化时返回 true
+    /// 同时记录固件代号和升级成功的版本
+    pub fn observe(&mut self, package_type: PackageType, request: &[u8]) -> bool {
+        self.observe_firmware(package_type, request);
+
+        let (device_id, group_id) = match package_type {
+            PackageType::QueryConfig => {
+                let query = parse_config_query(request);
+                (query.device_id, query.group_id)
+            }
+            PackageType::ReportConfig => match parse_config_report(request) {
+                Some(report) => (Some(report.device_id), None),
+                None => (None, None),
+            },
+            // 设备ID在版本号之后：代号(2) + 版本(3) + 设备ID(8)
+            PackageType::DownloadEnd if request.len() > 5 + 13 => {
+                let mut device_id = [0u8; 8];
+                device_id.copy_from_slice(&request[10..18]);
+                (Some(format!("{:08X}", u64::from_be_bytes(device_id))), None)
+            }
+            _ => (None, None),
+        };
+
+        if group_id.is_some() {
+            self.group_id = group_id;
+        }
+
+        match device_id {
+            Some(device_id) if self.device_id.as_ref() != Some(&device_id) => {
+                self.device_id = Some(device_id);
+                self.in_flight = None;
+                self.last_poll = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn observe_firmware(&mut self, package_type: PackageType, request: &[u8]) {
+        let fw_package = matches!(
+            package_type,
+            PackageType::FirmwareQuery | PackageType::FirmwareDownload | PackageType::DownloadEnd
+        );
+        if !fw_package || request.len() < 7 {
+            return;
+        }
+
+        self.download_active_at = match package_type {
+            PackageType::FirmwareDownload => Some(Instant::now()),
+            _ => None,
+        };
+
+        let code = ((request[5] as u16) << 8 | request[6] as u16) as i32;
+        if self.fw_code != Some(code) {
+            self.fw_code = Some(code);
+            self.fw_known = None;
+        }
+
+        // 升级成功：代号(2) + 版本(3) + 设备ID(8) + SN(4) + 结果(1)
+        if matches!(package_type, PackageType::DownloadEnd)
+            && request.len() > 5 + 18
+            && request[22] == 0xA1
+        {
+            let key = (
+                code,
+                request[7] as i32,
+                request[8] as i32,
+                request[9] as i32,
+            );
+            self.fw_known = self.fw_known.max(Some(key));
+        }
+    }
+
+    /// 首次获得固件代号时，以当前最新固件作为设备已知版本
+    pub fn track_firmware(&mut self, snapshot: &FirmwareSnapshot) {
+        if let (Some(code), None) = (self.fw_code, self.fw_known) {
+            self.fw_known = snapshot.find_latest(code).map(|meta| meta_key(meta));
+        }
+    }
+
+    /// 有比设备已知版本更新的固件时返回该固件，每个版本只返回一次
+    pub fn update_available(&mut self, snapshot: &FirmwareSnapshot) -> Option<Arc<FirmwareMeta>> {
+        let latest = snapshot.find_latest(self.fw_code?)?;
+        if self.fw_known.is_some_and(|known| meta_key(latest) <= known) {
+            return None;
+        }
+
+        self.fw_known = Some(meta_key(latest));
+        Some(Arc::clone(latest))
+    }
+
+    /// 下载进行中时返回下载超时的时间，推送要等到下载结束或超时之后
+    pub fn download_idle_at(&self, now: Instant) -> Option<Instant> {
+        self.download_active_at
+            .map(|active_at| active_at + DOWNLOAD_IDLE_TIMEOUT)
+            .filter(|idle_at| *idle_at > now)
+    }
+
+    /// 是否需要查询待执行命令：设备已识别、没有等待确认的命令、距上次查询超过间隔
+    pub fn should_poll(&self, now: Instant) -> bool {
+        self.device_id.is_some()
+            && self.in_flight.is_none()
+            && self
+                .last_poll
+                .is_none_or(|last| now.duration_since(last) >= COMMAND_POLL_INTERVAL)
+    }
+
+    /// 记录查询时间
+    pub fn polled(&mut self, now: Instant) {
+        self.last_poll = Some(now);
+    }
+}
@@ -0,0 +1,106 @@
+use tokio::{
+    sync::{mpsc, watch},
+    time::{self, Instant},
+};
+use tracing::{info, warn};
+
+/// 等待退出信号 (SIGTERM / Ctrl-C)
+pub async fn wait_for_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                tokio::select! {
+                    _ = sigterm.recv() => info!("SIGTERM received"),
+                    _ = tokio::signal::ctrl_c() => info!("Ctrl-C received"),
+                }
+            }
+            Err(e) => {
+                warn!("Failed to install SIGTERM handler: {}", e);
+                let _ = tokio::signal::ctrl_c().await;
+                info!("Ctrl-C received");
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+        info!("Ctrl-C received");
+    }
+}
+
+/// 退出控制器，由主循环持有
+/// - 广播退出通知
+/// - 等待所有连接结束（连接任务持有 `Shutdown`，全部释放后视为排空）
+pub struct ShutdownController {
+    notify_tx: watch::Sender<bool>,
+    drain_tx: mpsc::Sender<()>,
+    drain_rx: mpsc::Receiver<()>,
+}
+
+impl Default for ShutdownController {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ShutdownController {
+    pub fn new() -> Self {
+        let (notify_tx, _) = watch::channel(false);
+        let (drain_tx, drain_rx) = mpsc::channel(1);
+        ShutdownController {
+            notify_tx,
+            drain_tx,
+            drain_rx,
+        }
+    }
+
+    /// 为新连接生成退出监听器
+    pub fn subscribe(&self) -> Shutdown {
+        Shutdown {
+            notify_rx: self.notify_tx.subscribe(),
+            _drain_guard: self.drain_tx.clone(),
+        }
+    }
+
+    /// 通知所有连接退出，并在截止时间前等待它们结束
+    /// ## 返回
+    /// - true  : 所有连接已结束
+    /// - false : 超过截止时间仍有连接未结束
+    pub async fn shutdown(self, deadline: Instant) -> bool {
+        let ShutdownController {
+            notify_tx,
+            drain_tx,
+            mut drain_rx,
+        } = self;
+
+        let _ = notify_tx.send(true);
+        drop(drain_tx);
+
+        // 所有 Shutdown 被释放后 recv 返回 None
+        time::timeout_at(deadline, drain_rx.recv()).await.is_ok()
+    }
+}
+
+/// 连接级别的退出监听器，接受连接的任务为每个连接克隆一份
+#[derive(Clone)]
+pub struct Shutdown {
+    notify_rx: watch::Receiver<bool>,
+    _drain_guard: mpsc::Sender<()>,
+}
+
+impl Shutdown {
+    /// 是否已收到退出通知
+    pub fn is_shutdown(&self) -> bool {
+        *self.notify_rx.borrow()
+    }
+
+    /// 等待退出通知
+    pub async fn recv(&mut self) {
+        // 发送端只在控制器被释放时关闭，此时同样视为退出
+        let _ = self.notify_rx.wait_for(|stop| *stop).await;
+    }
+}
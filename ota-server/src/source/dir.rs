@@ -0,0 +1,227 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use crc::{Crc, CRC_32_ISO_HDLC};
+use ota_database::models::{
+    config_history::ConfigHistory,
+    config_schema::ConfigSchema,
+    device_command::{DeviceCommand, UpdateCommandStatus},
+    device_shadow::{DeviceShadow, ReportedConfig},
+    download_session::NewDownloadSession,
+    firmware_data::FirmwareMeta,
+    upgrade_history::NewUpgradeHistory,
+};
+use regex::Regex;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+use tokio::{fs, io::AsyncWriteExt};
+use tracing::{debug, warn};
+
+use super::{CommandQueue, FirmwareSource, HistorySink, ShadowStore, SourceError, SourceResult};
+
+/// 配置文件，内容为 `ConfigHistory` 的 JSON 数组
+pub const CONFIG_FILE: &str = "configs.json";
+
+/// 配置模板文件，内容为 `ConfigSchema` 的 JSON 数组
+pub const SCHEMA_FILE: &str = "config_schemas.json";
+
+/// 设备影子目录，每个设备一个 `<DEVICE_ID>.json`，期望配置手工编辑
+pub const SHADOW_DIR: &str = "shadows";
+
+/// 升级记录文件，每行一条 `NewUpgradeHistory` JSON
+pub const HISTORY_FILE: &str = "upgrade_history.jsonl";
+
+/// 下载会话文件，每行一条 `NewDownloadSession` JSON，不关联升级记录
+pub const DOWNLOAD_FILE: &str = "download_sessions.jsonl";
+
+const CRC_32: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+/// 本地目录，固件文件名为 `CODE-M.N.L.bin`，CODE 为16进制
+/// - 固件 id 取文件名的 CRC32
+/// - hash 由文件大小和修改时间生成，文件被替换后视为新固件
+#[derive(Debug)]
+pub struct DirSource {
+    path: PathBuf,
+    files: Mutex<HashMap<i32, PathBuf>>,
+    /// 设备影子先读后写，同一时间只允许一个写入
+    shadow_lock: tokio::sync::Mutex<()>,
+}
+
+impl DirSource {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        DirSource {
+            path: path.as_ref().to_path_buf(),
+            files: Mutex::new(HashMap::new()),
+            shadow_lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// 读取 JSON 数组文件，文件不存在时返回空列表
+    async fn read_json_list<T: DeserializeOwned>(&self, file_name: &str) -> SourceResult<Vec<T>> {
+        let path = self.path.join(file_name);
+
+        match fs::read(&path).await {
+            Ok(content) => Ok(serde_json::from_slice(&content)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                warn!("{} not found", path.display());
+                Ok(Vec::new())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// 追加一行 JSON
+    async fn append_json_line<T: Serialize>(&self, file_name: &str, value: &T) -> SourceResult<()> {
+        let mut line = serde_json::to_string(value)?;
+        line.push('\n');
+
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.path.join(file_name))
+            .await?;
+        file.write_all(line.as_bytes()).await?;
+        // tokio 的文件写入在后台完成，关闭前等待写入结束
+        file.flush().await?;
+
+        Ok(())
+    }
+}
+
+/// 解析固件文件名，返回 (code, m, n, l)
+pub fn parse_fw_file_name(file_name: &str) -> Option<(i32, i32, i32, i32)> {
+    let re = Regex::new(r"^([0-9A-Fa-f]+)-(\d+)\.(\d+)\.(\d+)\.bin$").unwrap();
+    let captures = re.captures(file_name)?;
+
+    let code = i32::from_str_radix(&captures[1], 16).ok()?;
+    let m = captures[2].parse::<i32>().ok()?;
+    let n = captures[3].parse::<i32>().ok()?;
+    let l = captures[4].parse::<i32>().ok()?;
+
+    Some((code, m, n, l))
+}
+
+#[async_trait]
+impl FirmwareSource for DirSource {
+    async fn list_firmware(&self) -> SourceResult<Vec<FirmwareMeta>> {
+        let mut metas = Vec::new();
+        let mut files = HashMap::new();
+        let mut entries = fs::read_dir(&self.path).await?;
+
+        while let Some(entry) = entries.next_entry().await? {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            let Some((code, m, n, l)) = parse_fw_file_name(&file_name) else {
+                continue;
+            };
+
+            let metadata = entry.metadata().await?;
+            if !metadata.is_file() {
+                continue;
+            }
+
+            let modified: DateTime<Utc> = metadata.modified()?.into();
+            let id = CRC_32.checksum(file_name.as_bytes()) as i32;
+
+            debug!("Found firmware file {}", file_name);
+            files.insert(id, entry.path());
+            metas.push(FirmwareMeta {
+                id,
+                fwcode: code,
+                version_m: m,
+                version_n: n,
+                version_l: l,
+                fwsize: metadata.len() as i32,
+                hash: format!("{:x}-{:x}", metadata.len(), modified.timestamp_millis()),
+                updated_at: modified.naive_utc(),
+            });
+        }
+
+        *self.files.lock().unwrap() = files;
+
+        Ok(metas)
+    }
+
+    async fn read_firmware(&self, meta: &FirmwareMeta) -> SourceResult<Vec<u8>> {
+        let path = self.files.lock().unwrap().get(&meta.id).cloned();
+
+        match path {
+            Some(path) => Ok(fs::read(path).await?),
+            None => Err(SourceError::NotFound(meta.to_string())),
+        }
+    }
+
+    async fn list_configs(&self) -> SourceResult<Vec<ConfigHistory>> {
+        self.read_json_list(CONFIG_FILE).await
+    }
+
+    async fn list_config_schemas(&self) -> SourceResult<Vec<ConfigSchema>> {
+        self.read_json_list(SCHEMA_FILE).await
+    }
+}
+
+#[async_trait]
+impl HistorySink for DirSource {
+    async fn push_history(&self, new_history: &NewUpgradeHistory) -> SourceResult<Option<i32>> {
+        self.append_json_line(HISTORY_FILE, new_history).await?;
+        Ok(None)
+    }
+
+    async fn push_download_session(&self, download: &NewDownloadSession) -> SourceResult<()> {
+        self.append_json_line(DOWNLOAD_FILE, download).await
+    }
+}
+
+#[async_trait]
+impl ShadowStore for DirSource {
+    async fn report_shadow(
+        &self,
+        device_id: &str,
+        reported: &ReportedConfig,
+    ) -> SourceResult<DeviceShadow> {
+        let device_id = device_id.to_uppercase();
+        let dir = self.path.join(SHADOW_DIR);
+        let path = dir.join(format!("{}.json", device_id));
+        let now = Utc::now().naive_utc();
+
+        let _guard = self.shadow_lock.lock().await;
+        let mut shadow = match fs::read(&path).await {
+            Ok(content) => serde_json::from_slice(&content)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => DeviceShadow {
+                device_id,
+                created_at: now,
+                ..Default::default()
+            },
+            Err(e) => return Err(e.into()),
+        };
+
+        shadow.reported_schema_id = Some(reported.schema_id);
+        shadow.reported = Some(reported.reported.clone());
+        shadow.reported_at = Some(now);
+        shadow.updated_at = now;
+
+        fs::create_dir_all(&dir).await?;
+        fs::write(&path, serde_json::to_vec_pretty(&shadow)?).await?;
+
+        Ok(shadow)
+    }
+}
+
+/// 本地目录没有命令队列
+#[async_trait]
+impl CommandQueue for DirSource {
+    async fn pending_commands(
+        &self,
+        _device_id: &str,
+        _group_id: Option<i32>,
+    ) -> SourceResult<Vec<DeviceCommand>> {
+        Ok(Vec::new())
+    }
+
+    async fn update_command(&self, id: i32, status: &UpdateCommandStatus) -> SourceResult<()> {
+        debug!("Command {} -> {:?}, ignored", id, status.status);
+        Ok(())
+    }
+}
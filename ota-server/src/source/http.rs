@@ -0,0 +1,170 @@
+use async_trait::async_trait;
+use ota_database::models::{
+    config_history::ConfigHistory,
+    config_schema::ConfigSchema,
+    device_command::{DeviceCommand, UpdateCommandStatus},
+    device_shadow::{DeviceShadow, ReportedConfig, ShadowState},
+    download_session::NewDownloadSession,
+    firmware_data::{FirmwareData, FirmwareMeta},
+    upgrade_history::{NewUpgradeHistory, UpgradeHistory},
+};
+use ota_telemetry::trace_headers;
+use reqwest::{Method, RequestBuilder};
+
+use super::{CommandQueue, FirmwareSource, HistorySink, ShadowStore, SourceResult};
+
+/// 通过后端 REST API 读取固件、上报升级记录
+#[derive(Debug, Clone)]
+pub struct HttpBackend {
+    fw_server: String,
+    client: reqwest::Client,
+}
+
+impl HttpBackend {
+    pub fn new(fw_server: &str) -> Self {
+        HttpBackend {
+            fw_server: fw_server.to_string(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    pub fn fw_server(&self) -> &str {
+        &self.fw_server
+    }
+
+    /// 请求带上当前 span 的 trace context，后端的请求 span 以此为父节点
+    fn request(&self, method: Method, url: String) -> RequestBuilder {
+        trace_headers()
+            .into_iter()
+            .fold(self.client.request(method, url), |request, (key, value)| {
+                request.header(key, value)
+            })
+    }
+}
+
+#[async_trait]
+impl FirmwareSource for HttpBackend {
+    async fn list_firmware(&self) -> SourceResult<Vec<FirmwareMeta>> {
+        let response = self
+            .request(Method::GET, format!("{}/firmware/meta", self.fw_server))
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(response.json().await?)
+    }
+
+    async fn read_firmware(&self, meta: &FirmwareMeta) -> SourceResult<Vec<u8>> {
+        let response = self
+            .request(
+                Method::GET,
+                format!("{}/firmware/{}", self.fw_server, meta.id),
+            )
+            .send()
+            .await?
+            .error_for_status()?;
+
+        let fw_data: FirmwareData = response.json().await?;
+        Ok(fw_data.fwdata)
+    }
+
+    async fn list_configs(&self) -> SourceResult<Vec<ConfigHistory>> {
+        let response = self
+            .request(Method::GET, format!("{}/config", self.fw_server))
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(response.json().await?)
+    }
+
+    async fn list_config_schemas(&self) -> SourceResult<Vec<ConfigSchema>> {
+        let response = self
+            .request(Method::GET, format!("{}/schema", self.fw_server))
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(response.json().await?)
+    }
+}
+
+#[async_trait]
+impl HistorySink for HttpBackend {
+    async fn push_history(&self, new_history: &NewUpgradeHistory) -> SourceResult<Option<i32>> {
+        let response = self
+            .request(Method::POST, format!("{}/history", self.fw_server))
+            .json(new_history)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        let history: UpgradeHistory = response.json().await?;
+        Ok(Some(history.id))
+    }
+
+    async fn push_download_session(&self, download: &NewDownloadSession) -> SourceResult<()> {
+        self.request(Method::POST, format!("{}/downloads", self.fw_server))
+            .json(download)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ShadowStore for HttpBackend {
+    async fn report_shadow(
+        &self,
+        device_id: &str,
+        reported: &ReportedConfig,
+    ) -> SourceResult<DeviceShadow> {
+        let response = self
+            .request(
+                Method::POST,
+                format!("{}/shadow/{}/reported", self.fw_server, device_id),
+            )
+            .json(reported)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        let state: ShadowState = response.json().await?;
+        Ok(state.shadow)
+    }
+}
+
+#[async_trait]
+impl CommandQueue for HttpBackend {
+    async fn pending_commands(
+        &self,
+        device_id: &str,
+        group_id: Option<i32>,
+    ) -> SourceResult<Vec<DeviceCommand>> {
+        let mut query = vec![("device_id", device_id.to_string())];
+        if let Some(group_id) = group_id {
+            query.push(("group_id", group_id.to_string()));
+        }
+
+        let response = self
+            .request(Method::GET, format!("{}/commands/pending", self.fw_server))
+            .query(&query)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(response.json().await?)
+    }
+
+    async fn update_command(&self, id: i32, status: &UpdateCommandStatus) -> SourceResult<()> {
+        self.request(Method::PATCH, format!("{}/commands/{}", self.fw_server, id))
+            .json(status)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,94 @@
+use async_trait::async_trait;
+use ota_database::models::{
+    config_history::ConfigHistory,
+    config_schema::ConfigSchema,
+    device_command::{DeviceCommand, UpdateCommandStatus},
+    device_shadow::{DeviceShadow, ReportedConfig},
+    download_session::NewDownloadSession,
+    firmware_data::FirmwareMeta,
+    upgrade_history::NewUpgradeHistory,
+};
+use std::sync::Arc;
+
+use super::{Backend, CommandQueue, FirmwareSource, HistorySink, ShadowStore, SourceResult};
+use crate::metrics::observe_backend;
+
+/// 记录后端调用耗时，包装任意后端
+#[derive(Debug, Clone)]
+pub struct MeteredBackend {
+    inner: Arc<dyn Backend>,
+}
+
+impl MeteredBackend {
+    pub fn new(inner: Arc<dyn Backend>) -> Self {
+        MeteredBackend { inner }
+    }
+}
+
+#[async_trait]
+impl FirmwareSource for MeteredBackend {
+    async fn list_firmware(&self) -> SourceResult<Vec<FirmwareMeta>> {
+        observe_backend("list_firmware", self.inner.list_firmware()).await
+    }
+
+    async fn read_firmware(&self, meta: &FirmwareMeta) -> SourceResult<Vec<u8>> {
+        observe_backend("read_firmware", self.inner.read_firmware(meta)).await
+    }
+
+    async fn list_configs(&self) -> SourceResult<Vec<ConfigHistory>> {
+        observe_backend("list_configs", self.inner.list_configs()).await
+    }
+
+    async fn list_config_schemas(&self) -> SourceResult<Vec<ConfigSchema>> {
+        observe_backend("list_config_schemas", self.inner.list_config_schemas()).await
+    }
+}
+
+#[async_trait]
+impl HistorySink for MeteredBackend {
+    async fn push_history(&self, new_history: &NewUpgradeHistory) -> SourceResult<Option<i32>> {
+        observe_backend("push_history", self.inner.push_history(new_history)).await
+    }
+
+    async fn push_download_session(&self, download: &NewDownloadSession) -> SourceResult<()> {
+        observe_backend(
+            "push_download_session",
+            self.inner.push_download_session(download),
+        )
+        .await
+    }
+}
+
+#[async_trait]
+impl ShadowStore for MeteredBackend {
+    async fn report_shadow(
+        &self,
+        device_id: &str,
+        reported: &ReportedConfig,
+    ) -> SourceResult<DeviceShadow> {
+        observe_backend(
+            "report_shadow",
+            self.inner.report_shadow(device_id, reported),
+        )
+        .await
+    }
+}
+
+#[async_trait]
+impl CommandQueue for MeteredBackend {
+    async fn pending_commands(
+        &self,
+        device_id: &str,
+        group_id: Option<i32>,
+    ) -> SourceResult<Vec<DeviceCommand>> {
+        observe_backend(
+            "pending_commands",
+            self.inner.pending_commands(device_id, group_id),
+        )
+        .await
+    }
+
+    async fn update_command(&self, id: i32, status: &UpdateCommandStatus) -> SourceResult<()> {
+        observe_backend("update_command", self.inner.update_command(id, status)).await
+    }
+}
@@ -0,0 +1,92 @@
+pub mod dir; // 本地目录
+pub mod http; // 后端 REST API
+pub mod metered; // 调用耗时统计
+pub mod pg; // 直连 postgres
+
+use async_trait::async_trait;
+use ota_database::{
+    db::DatabaseError,
+    models::{
+        config_history::ConfigHistory,
+        config_schema::ConfigSchema,
+        device_command::{DeviceCommand, UpdateCommandStatus},
+        device_shadow::{DeviceShadow, ReportedConfig},
+        download_session::NewDownloadSession,
+        firmware_data::FirmwareMeta,
+        upgrade_history::NewUpgradeHistory,
+    },
+};
+use std::fmt;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum SourceError {
+    #[error("Http error: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("Database error: {0}")]
+    Database(#[from] DatabaseError),
+    #[error("Io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Json error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Firmware not found: {0}")]
+    NotFound(String),
+}
+
+pub type SourceResult<T> = Result<T, SourceError>;
+
+/// 固件和配置来源
+#[async_trait]
+pub trait FirmwareSource: fmt::Debug + Send + Sync {
+    /// 读取所有固件的元数据，不含固件内容
+    async fn list_firmware(&self) -> SourceResult<Vec<FirmwareMeta>>;
+
+    /// 读取固件内容
+    async fn read_firmware(&self, meta: &FirmwareMeta) -> SourceResult<Vec<u8>>;
+
+    /// 读取所有配置
+    async fn list_configs(&self) -> SourceResult<Vec<ConfigHistory>>;
+
+    /// 读取所有配置模板
+    async fn list_config_schemas(&self) -> SourceResult<Vec<ConfigSchema>>;
+}
+
+/// 完整的后端，连接任务通过它上报升级记录、设备影子和读取命令
+pub trait Backend: FirmwareSource + HistorySink + ShadowStore + CommandQueue {}
+
+impl<T: FirmwareSource + HistorySink + ShadowStore + CommandQueue> Backend for T {}
+
+/// 升级记录上报
+#[async_trait]
+pub trait HistorySink: fmt::Debug + Send + Sync {
+    /// 上报升级记录，返回记录的 id，不支持时返回 None
+    async fn push_history(&self, new_history: &NewUpgradeHistory) -> SourceResult<Option<i32>>;
+
+    /// 上报下载会话统计
+    async fn push_download_session(&self, download: &NewDownloadSession) -> SourceResult<()>;
+}
+
+/// 设备影子存储
+#[async_trait]
+pub trait ShadowStore: fmt::Debug + Send + Sync {
+    /// 记录设备上报的配置，返回包含期望配置的设备影子
+    async fn report_shadow(
+        &self,
+        device_id: &str,
+        reported: &ReportedConfig,
+    ) -> SourceResult<DeviceShadow>;
+}
+
+/// 设备命令队列
+#[async_trait]
+pub trait CommandQueue: fmt::Debug + Send + Sync {
+    /// 设备待执行的命令，包括已下发但未确认的
+    async fn pending_commands(
+        &self,
+        device_id: &str,
+        group_id: Option<i32>,
+    ) -> SourceResult<Vec<DeviceCommand>>;
+
+    /// 更新命令状态
+    async fn update_command(&self, id: i32, status: &UpdateCommandStatus) -> SourceResult<()>;
+}
@@ -0,0 +1,120 @@
+use async_trait::async_trait;
+use ota_database::{
+    db::Database,
+    models::{
+        basic::CrudOperations,
+        config_history::{ConfigHistory, NewConfigHistory, UpdateConfigHistory},
+        config_schema::{ConfigSchema, NewConfigSchema, UpdateConfigSchema},
+        device_command::{DeviceCommand, UpdateCommandStatus},
+        device_shadow::{DeviceShadow, ReportedConfig},
+        download_session::{DownloadSession, NewDownloadSession},
+        firmware_data::{FirmwareData, FirmwareMeta, NewFirmwareData, UpdateFirmwareData},
+        upgrade_history::{NewUpgradeHistory, UpdateUpgradeHistory, UpgradeHistory},
+    },
+};
+
+use super::{CommandQueue, FirmwareSource, HistorySink, ShadowStore, SourceResult};
+
+/// 直连 postgres 数据库
+#[derive(Debug, Clone)]
+pub struct PgBackend {
+    db: Database,
+}
+
+impl PgBackend {
+    pub async fn connect(db_url: &str) -> SourceResult<Self> {
+        Ok(PgBackend {
+            db: Database::new(db_url).await?,
+        })
+    }
+
+    pub fn database(&self) -> &Database {
+        &self.db
+    }
+}
+
+#[async_trait]
+impl FirmwareSource for PgBackend {
+    async fn list_firmware(&self) -> SourceResult<Vec<FirmwareMeta>> {
+        Ok(FirmwareData::all_meta(&self.db.pool).await?)
+    }
+
+    async fn read_firmware(&self, meta: &FirmwareMeta) -> SourceResult<Vec<u8>> {
+        let fw_data = <FirmwareData as CrudOperations<
+            FirmwareData,
+            NewFirmwareData,
+            UpdateFirmwareData,
+        >>::find(meta.id, &self.db.pool)
+        .await?;
+
+        Ok(fw_data.fwdata)
+    }
+
+    async fn list_configs(&self) -> SourceResult<Vec<ConfigHistory>> {
+        let configs = <ConfigHistory as CrudOperations<
+            ConfigHistory,
+            NewConfigHistory,
+            UpdateConfigHistory,
+        >>::all(&self.db.pool)
+        .await?;
+
+        Ok(configs)
+    }
+
+    async fn list_config_schemas(&self) -> SourceResult<Vec<ConfigSchema>> {
+        let schemas = <ConfigSchema as CrudOperations<
+            ConfigSchema,
+            NewConfigSchema,
+            UpdateConfigSchema,
+        >>::all(&self.db.pool)
+        .await?;
+
+        Ok(schemas)
+    }
+}
+
+#[async_trait]
+impl HistorySink for PgBackend {
+    async fn push_history(&self, new_history: &NewUpgradeHistory) -> SourceResult<Option<i32>> {
+        let history = <UpgradeHistory as CrudOperations<
+            UpgradeHistory,
+            NewUpgradeHistory,
+            UpdateUpgradeHistory,
+        >>::create(new_history.clone(), &self.db.pool)
+        .await?;
+
+        Ok(Some(history.id))
+    }
+
+    async fn push_download_session(&self, download: &NewDownloadSession) -> SourceResult<()> {
+        DownloadSession::create(download, &self.db.pool).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ShadowStore for PgBackend {
+    async fn report_shadow(
+        &self,
+        device_id: &str,
+        reported: &ReportedConfig,
+    ) -> SourceResult<DeviceShadow> {
+        Ok(DeviceShadow::report(device_id, reported, &self.db.pool).await?)
+    }
+}
+
+#[async_trait]
+impl CommandQueue for PgBackend {
+    async fn pending_commands(
+        &self,
+        device_id: &str,
+        group_id: Option<i32>,
+    ) -> SourceResult<Vec<DeviceCommand>> {
+        Ok(DeviceCommand::pending(device_id, group_id, &self.db.pool).await?)
+    }
+
+    async fn update_command(&self, id: i32, status: &UpdateCommandStatus) -> SourceResult<()> {
+        DeviceCommand::update_status(id, status, &self.db.pool).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,163 @@
+use futures_util::{SinkExt, StreamExt};
+use std::{
+    io,
+    pin::Pin,
+    task::{ready, Context, Poll},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::TcpListener,
+    time,
+};
+use tokio_tungstenite::{accept_async, tungstenite::Message, WebSocketStream};
+use tracing::{error, field, info, info_span, warn, Instrument};
+
+use crate::{context::ServerContext, process_pg::handle_connection, shutdown::Shutdown};
+
+/// WebSocket 握手超时
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 设备连接，TCP 或 WebSocket
+pub trait Transport: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Transport for T {}
+
+/// WebSocket 上的二进制协议，一个二进制消息为一帧
+/// - 读：每次返回一个消息的内容，与 TCP 上每次读取一个包一致
+/// - 写：flush 时把已写入的数据作为一个消息发出，发送返回包时每帧 flush 一次
+pub struct WsTransport<S> {
+    ws: WebSocketStream<S>,
+    /// 未读完的消息
+    read_buf: Vec<u8>,
+    read_pos: usize,
+    /// 待发送的消息
+    write_buf: Vec<u8>,
+}
+
+impl<S> WsTransport<S> {
+    pub fn new(ws: WebSocketStream<S>) -> Self {
+        WsTransport {
+            ws,
+            read_buf: Vec::new(),
+            read_pos: 0,
+            write_buf: Vec::new(),
+        }
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncRead for WsTransport<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        loop {
+            if this.read_pos < this.read_buf.len() {
+                let len = buf.remaining().min(this.read_buf.len() - this.read_pos);
+                buf.put_slice(&this.read_buf[this.read_pos..this.read_pos + len]);
+                this.read_pos += len;
+                return Poll::Ready(Ok(()));
+            }
+
+            match ready!(this.ws.poll_next_unpin(cx)) {
+                Some(Ok(Message::Binary(data))) => {
+                    this.read_buf = data.to_vec();
+                    this.read_pos = 0;
+                }
+                // 对端关闭，按 EOF 处理
+                Some(Ok(Message::Close(_))) | None => return Poll::Ready(Ok(())),
+                // Ping 由 tungstenite 自动回复，文本消息忽略
+                Some(Ok(_)) => {}
+                Some(Err(e)) => return Poll::Ready(Err(io::Error::other(e))),
+            }
+        }
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncWrite for WsTransport<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.get_mut().write_buf.extend_from_slice(buf);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        if !this.write_buf.is_empty() {
+            ready!(this.ws.poll_ready_unpin(cx)).map_err(io::Error::other)?;
+            let data = std::mem::take(&mut this.write_buf);
+            this.ws
+                .start_send_unpin(Message::binary(data))
+                .map_err(io::Error::other)?;
+        }
+        this.ws.poll_flush_unpin(cx).map_err(io::Error::other)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        ready!(self.as_mut().poll_flush(cx))?;
+        self.get_mut()
+            .ws
+            .poll_close_unpin(cx)
+            .map_err(io::Error::other)
+    }
+}
+
+/// WebSocket 服务，握手后与 TCP 连接走相同的处理流程，收到退出通知后停止接受连接
+pub async fn serve_websocket(listener: TcpListener, ctx: ServerContext, mut shutdown: Shutdown) {
+    if let Ok(addr) = listener.local_addr() {
+        info!("WebSocket listening on {}", addr);
+    }
+
+    loop {
+        let (socket, addr) = tokio::select! {
+            res = listener.accept() => match res {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    error!("WebSocket Accept Error: {}", e);
+                    continue;
+                }
+            },
+            _ = shutdown.recv() => break,
+        };
+
+        let conn_ctx = ctx.clone();
+        let conn_shutdown = shutdown.clone();
+        let span = info_span!(
+            "connection",
+            peer = %addr,
+            transport = "websocket",
+            device_id = field::Empty
+        );
+
+        tokio::spawn(
+            async move {
+                let ws = match time::timeout(HANDSHAKE_TIMEOUT, accept_async(socket)).await {
+                    Ok(Ok(ws)) => ws,
+                    Ok(Err(e)) => {
+                        warn!("WebSocket handshake failed: {}", e);
+                        return;
+                    }
+                    Err(_) => {
+                        warn!("WebSocket handshake timeout");
+                        return;
+                    }
+                };
+
+                let transport = WsTransport::new(ws);
+                if let Err(error) =
+                    handle_connection(transport, addr, conn_ctx, conn_shutdown).await
+                {
+                    error!("Error handling client: {}", error);
+                }
+            }
+            .instrument(span),
+        );
+    }
+}
@@ -0,0 +1,141 @@
+#[cfg(test)]
+mod tests {
+    use ota_server::{
+        admin::AdminState, download::DownloadTracker, fw_cache::FirmwareCache,
+        registry::SessionRegistry, server::OtaServer, source::dir::DirSource,
+    };
+    use ota_simulator::conformance::frame;
+    use serde_json::{json, Value};
+    use std::{fs, net::SocketAddr, sync::Arc, time::Duration};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpStream,
+        sync::watch,
+        time::{sleep, timeout},
+    };
+
+    /// 发送 HTTP 请求，返回状态码和 JSON
+    async fn http(addr: SocketAddr, method: &str, path: &str) -> (u16, Value) {
+        let mut socket = TcpStream::connect(addr).await.unwrap();
+        let request = format!("{} {} HTTP/1.1\r\nHost: localhost\r\n\r\n", method, path);
+        socket.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        socket.read_to_string(&mut response).await.unwrap();
+
+        let status = response[9..12].parse().unwrap();
+        let (_, body) = response.split_once("\r\n\r\n").unwrap();
+        (status, serde_json::from_str(body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn sessions_and_cache() {
+        let path = std::env::temp_dir().join(format!("ota-admin-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        fs::write(path.join("1987-0.3.0.bin"), [0x5Au8; 5000]).unwrap();
+
+        let server = OtaServer::builder()
+            .bind("127.0.0.1:0")
+            .admin("127.0.0.1:0")
+            .backend(Arc::new(DirSource::new(&path)))
+            .build()
+            .await
+            .unwrap();
+        let admin = server.admin_local_addr().unwrap();
+        tokio::spawn({
+            let server = server.clone();
+            async move { server.run().await }
+        });
+
+        assert_eq!(http(admin, "GET", "/healthz").await.0, 200);
+        assert_eq!(
+            http(admin, "GET", "/readyz").await,
+            (200, json!({ "status": "ready" }))
+        );
+
+        // 下载第一片，切片 512 字节，共 10 片
+        let mut device = TcpStream::connect(server.local_addr()).await.unwrap();
+        device
+            .write_all(&frame(
+                0xA2,
+                &[0x19, 0x87, 0x00, 0x03, 0x00, 0x00, 0x00, 0x02, 0x00],
+            ))
+            .await
+            .unwrap();
+        let mut response = [0u8; 5 + 7 + 512 + 1];
+        device.read_exact(&mut response).await.unwrap();
+
+        // 回复之后才记录下载进度，等待记录完成
+        let mut sessions = Value::Null;
+        for _ in 0..100 {
+            let (status, body) = http(admin, "GET", "/sessions").await;
+            assert_eq!(status, 200);
+            sessions = body;
+            if !sessions[0]["download"].is_null() {
+                break;
+            }
+            sleep(Duration::from_millis(10)).await;
+        }
+        let session = &sessions[0];
+        assert_eq!(
+            session["peer"],
+            device.local_addr().unwrap().to_string().as_str()
+        );
+        assert_eq!(session["device_id"], Value::Null);
+        assert_eq!(session["fw_code"], 0x1987);
+        let download = &session["download"];
+        assert_eq!(download["version"], "0.3.0");
+        assert_eq!(download["slices"], 1);
+        assert_eq!(download["total_slices"], 10);
+        assert_eq!(download["progress"], 10);
+
+        // 新固件立即生效
+        fs::write(path.join("1987-0.4.0.bin"), [0xA5u8; 100]).unwrap();
+        assert_eq!(
+            http(admin, "POST", "/cache/reload").await,
+            (200, json!({ "firmware": 2 }))
+        );
+        assert_eq!(server.stats().firmware, 2);
+
+        // 断开连接
+        let id = session["id"].as_u64().unwrap();
+        assert_eq!(
+            http(admin, "DELETE", &format!("/sessions/{}", id)).await.0,
+            200
+        );
+        let mut buffer = [0u8; 16];
+        let closed = timeout(Duration::from_secs(5), device.read(&mut buffer)).await;
+        assert_eq!(closed.unwrap().unwrap(), 0);
+
+        assert_eq!(
+            http(admin, "DELETE", &format!("/sessions/{}", id)).await.0,
+            404
+        );
+        assert_eq!(http(admin, "DELETE", "/sessions/abc").await.0, 400);
+        assert_eq!(http(admin, "GET", "/sessions").await, (200, json!([])));
+        assert_eq!(http(admin, "GET", "/unknown").await.0, 404);
+    }
+
+    #[tokio::test]
+    async fn readiness() {
+        let path = std::env::temp_dir().join(format!("ota-admin-ready-{}", std::process::id()));
+        let fw_cache = Arc::new(FirmwareCache::new(Arc::new(DirSource::new(&path)), 1 << 20));
+        let (stop, stopping) = watch::channel(false);
+        let state = AdminState {
+            fw_cache: fw_cache.clone(),
+            registry: Arc::new(SessionRegistry::new()),
+            downloads: Arc::new(DownloadTracker::new()),
+            stopping,
+        };
+
+        // 元数据加载前未就绪，目录为空同样算已加载
+        assert!(!state.is_ready());
+        fs::create_dir_all(&path).unwrap();
+        fw_cache.refresh().await.unwrap();
+        assert!(state.is_ready());
+
+        // 排空时未就绪
+        stop.send_replace(true);
+        assert!(!state.is_ready());
+    }
+}
@@ -0,0 +1,188 @@
+#[cfg(test)]
+mod tests {
+    use ota_server::{
+        broadcast::{
+            encode_image, serve_broadcast, BroadcastOptions, FecParams, Fragment, Reassembler,
+        },
+        fw_cache::FirmwareCache,
+        server::OtaServer,
+        shutdown::ShutdownController,
+        source::dir::DirSource,
+    };
+    use ota_simulator::conformance::frame;
+    use std::{fs, sync::Arc, time::Duration};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpStream, UdpSocket},
+        time::{timeout, Instant},
+    };
+
+    const FEC: FecParams = FecParams {
+        fragment_size: 64,
+        data_shards: 8,
+        parity_shards: 2,
+    };
+
+    fn firmware() -> Vec<u8> {
+        (0..5000u32).map(|i| (i * 7 + i / 256) as u8).collect()
+    }
+
+    /// 本地目录中放一个 5000 字节的固件
+    async fn firmware_cache(name: &str) -> (Arc<FirmwareCache>, Arc<DirSource>) {
+        let path = std::env::temp_dir().join(format!("ota-bc-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        fs::write(path.join("1987-0.3.0.bin"), firmware()).unwrap();
+
+        let backend = Arc::new(DirSource::new(&path));
+        let fw_cache = Arc::new(FirmwareCache::new(backend.clone(), 1 << 20));
+        fw_cache.refresh().await.unwrap();
+        (fw_cache, backend)
+    }
+
+    /// 在同一固件目录上启动 TCP 服务，用于补齐缺失的分片
+    async fn start_server(backend: Arc<DirSource>) -> OtaServer {
+        let server = OtaServer::builder()
+            .bind("127.0.0.1:0")
+            .backend(backend)
+            .build()
+            .await
+            .unwrap();
+        tokio::spawn({
+            let server = server.clone();
+            async move { server.run().await }
+        });
+        server
+    }
+
+    /// 通过 FirmwareDownload 下载一片，切片大小为分片大小
+    async fn download(socket: &mut TcpStream, index: usize) -> Vec<u8> {
+        let mut payload = vec![0x19, 0x87, 0x00, 0x03, 0x00];
+        payload.extend_from_slice(&(index as u16).to_be_bytes());
+        payload.extend_from_slice(&(FEC.fragment_size as u16).to_be_bytes());
+        socket.write_all(&frame(0xA2, &payload)).await.unwrap();
+
+        let mut header = [0u8; 5];
+        socket.read_exact(&mut header).await.unwrap();
+        assert_eq!(header[2], 0x5D);
+        let mut body = vec![0u8; u16::from_be_bytes([header[3], header[4]]) as usize + 1];
+        socket.read_exact(&mut body).await.unwrap();
+        body[7..body.len() - 1].to_vec()
+    }
+
+    #[tokio::test]
+    async fn fragment_datagram() {
+        let (fw_cache, _) = firmware_cache("datagram").await;
+        let meta = fw_cache.load().find_latest(0x1987).unwrap().clone();
+        let image = fw_cache.image(&meta).await.unwrap();
+        let fragments = encode_image(&image, FEC).unwrap();
+
+        // 79 个数据分片分 10 块，最后一块补零的分片不发送
+        let header = &fragments[0].header;
+        assert_eq!((header.fragments(), header.blocks()), (79, 10));
+        assert_eq!(fragments.len(), 79 + 10 * 2);
+
+        // 交织发送：先发各块的第一个分片
+        let order: Vec<_> = fragments[..3]
+            .iter()
+            .map(|fragment| (fragment.block, fragment.shard))
+            .collect();
+        assert_eq!(order, vec![(0, 0), (1, 0), (2, 0)]);
+        assert_eq!(fragments[1].index(), Some(8));
+        assert_eq!(fragments[1].data, &firmware()[512..576]);
+
+        let datagram = fragments[1].encode();
+        assert_eq!(&datagram[..3], &[0xAA, 0x55, 0xB0]);
+        assert_eq!(datagram.len(), 5 + 16 + 64 + 1);
+        assert_eq!(Fragment::decode(&datagram).unwrap(), fragments[1]);
+
+        let mut bad_crc = datagram.clone();
+        *bad_crc.last_mut().unwrap() ^= 0xFF;
+        assert!(Fragment::decode(&bad_crc).is_err());
+        assert!(Fragment::decode(&datagram[..20]).is_err());
+    }
+
+    #[tokio::test]
+    async fn reassemble_with_losses() {
+        let (fw_cache, backend) = firmware_cache("losses").await;
+        let meta = fw_cache.load().find_latest(0x1987).unwrap().clone();
+        let image = fw_cache.image(&meta).await.unwrap();
+        let fragments = encode_image(&image, FEC).unwrap();
+
+        // 第 0 块丢 2 片可以恢复，第 1 块丢 3 片无法恢复，最后一块丢一个冗余分片
+        let lost = |fragment: &Fragment| {
+            matches!(
+                (fragment.block, fragment.shard),
+                (0, 1) | (0, 9) | (1, 0) | (1, 4) | (1, 5) | (9, 8)
+            )
+        };
+        let mut reassembler = Reassembler::new(fragments[0].header.clone()).unwrap();
+        for fragment in fragments.iter().filter(|fragment| !lost(fragment)) {
+            assert!(reassembler.push(fragment.clone()).unwrap());
+        }
+        assert!(!reassembler.push(fragments[0].clone()).unwrap());
+        assert_eq!(reassembler.missing(), vec![8, 12, 13]);
+        assert_eq!(reassembler.image(), None);
+
+        // 缺失的分片通过单播下载补齐
+        let server = start_server(backend).await;
+        let mut socket = TcpStream::connect(server.local_addr()).await.unwrap();
+        for index in reassembler.missing() {
+            let data = download(&mut socket, index).await;
+            reassembler.insert_fragment(index, &data).unwrap();
+        }
+        assert!(reassembler.is_complete());
+        assert_eq!(reassembler.image(), Some(firmware()));
+    }
+
+    #[tokio::test]
+    async fn broadcast_to_group() {
+        let (fw_cache, _) = firmware_cache("group").await;
+        let receiver = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+
+        // 用单播地址代替组播组
+        let options = BroadcastOptions {
+            codes: vec![0x1987, 0x2024],
+            group: receiver.local_addr().unwrap(),
+            ttl: 1,
+            interval: Duration::from_secs(60),
+            rate: 2000,
+            fec: FEC,
+        };
+        let shutdown = ShutdownController::new();
+        let task = tokio::spawn(serve_broadcast(
+            UdpSocket::bind("127.0.0.1:0").await.unwrap(),
+            options,
+            fw_cache,
+            shutdown.subscribe(),
+        ));
+
+        // 每 11 个数据报丢一个，每块最多丢一片，冗余分片足够恢复
+        let mut reassembler: Option<Reassembler> = None;
+        let mut buffer = [0u8; 1500];
+        let mut received = 0;
+        while !reassembler.as_ref().is_some_and(Reassembler::is_complete) {
+            let len = timeout(Duration::from_secs(2), receiver.recv(&mut buffer))
+                .await
+                .expect("No broadcast datagram")
+                .unwrap();
+            received += 1;
+            if received % 11 == 6 {
+                continue;
+            }
+            let fragment = Fragment::decode(&buffer[..len]).unwrap();
+            reassembler
+                .get_or_insert_with(|| Reassembler::new(fragment.header.clone()).unwrap())
+                .push(fragment)
+                .unwrap();
+        }
+        assert_eq!(reassembler.unwrap().image(), Some(firmware()));
+
+        assert!(
+            shutdown
+                .shutdown(Instant::now() + Duration::from_secs(2))
+                .await
+        );
+        task.await.unwrap();
+    }
+}
@@ -0,0 +1,165 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::request;
+    use ota_database::models::device_command::{
+        CommandKind, CommandProgress, CommandStatus, DeviceCommand, NewDeviceCommand,
+        UpdateCommandStatus,
+    };
+    use ota_server::{
+        package::common::{parse_command_ack, CommandAck},
+        session::{DeviceSession, COMMAND_POLL_INTERVAL},
+        source::pg::PgBackend,
+        PackageType,
+    };
+    use std::env;
+    use tokio::time::Instant;
+
+    #[test]
+    fn parse_ack_payload() {
+        assert_eq!(parse_command_ack(&request(0xA6, &[])), None);
+        assert_eq!(
+            parse_command_ack(&request(0xA6, &[0, 0, 0x01, 0x02, 0x03])),
+            Some(CommandAck {
+                command_id: 0x0102,
+                result: 3,
+            })
+        );
+    }
+
+    #[test]
+    fn session_learns_device() {
+        let mut session = DeviceSession::default();
+
+        // 固件查询不带设备ID
+        assert!(!session.observe(PackageType::FirmwareQuery, &request(0xA1, &[0, 1])));
+        assert!(!session.should_poll(Instant::now()));
+
+        // 配置查询带设备ID和分组号
+        let query = request(0xA4, &[0, 0, 0, 0, 0x12, 0x34, 0xAB, 0xCD, 0, 0, 0, 3]);
+        assert!(session.observe(PackageType::QueryConfig, &query));
+        assert_eq!(session.device_id.as_deref(), Some("1234ABCD"));
+        assert_eq!(session.group_id, Some(3));
+        assert!(!session.observe(PackageType::QueryConfig, &query));
+
+        // 下载结束包：代号(2) + 版本(3) + 设备ID(8) + SN(4) + 结果(1)
+        let end = request(
+            0xA3,
+            &[0, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x42, 0, 0, 0, 1, 0xA1],
+        );
+        assert!(session.observe(PackageType::DownloadEnd, &end));
+        assert_eq!(session.device_id.as_deref(), Some("00000042"));
+        assert_eq!(session.group_id, Some(3));
+    }
+
+    #[test]
+    fn session_poll_interval() {
+        let mut session = DeviceSession::default();
+        let report = request(0xA5, &[0, 0, 0, 0, 0, 0, 0, 7, 0, 1, 0]);
+        session.observe(PackageType::ReportConfig, &report);
+
+        let now = Instant::now();
+        assert!(session.should_poll(now));
+
+        session.polled(now);
+        assert!(!session.should_poll(now));
+        assert!(session.should_poll(now + COMMAND_POLL_INTERVAL));
+
+        // 等待确认时不再查询
+        session.in_flight = Some(1);
+        assert!(!session.should_poll(now + COMMAND_POLL_INTERVAL));
+    }
+
+    #[test]
+    fn status_transitions() {
+        use CommandStatus::*;
+
+        assert!(Queued.can_become(Delivered));
+        assert!(Delivered.can_become(Acked));
+        assert!(Delivered.can_become(Failed));
+
+        assert!(!Queued.can_become(Acked));
+        assert!(!Queued.can_become(Queued));
+        assert!(!Acked.can_become(Failed));
+        assert!(!Failed.can_become(Delivered));
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a postgres database in DATABASE_URL"]
+    async fn group_command_lifecycle() {
+        let db_url = env::var("DATABASE_URL").expect("DATABASE_URL");
+        let pg = PgBackend::connect(&db_url).await.unwrap();
+        let pool = &pg.database().pool;
+        let group_id = std::process::id() as i32;
+        let device_id = format!("{:08X}", group_id);
+
+        let group = DeviceCommand::create(
+            &NewDeviceCommand {
+                device_id: None,
+                group_id: Some(group_id),
+                command: CommandKind::Reboot,
+                param: 0,
+            },
+            pool,
+        )
+        .await
+        .unwrap();
+
+        // 同一设备并发查询只复制一份
+        let (first, second) = tokio::join!(
+            DeviceCommand::pending(&device_id, Some(group_id), pool),
+            DeviceCommand::pending(&device_id, Some(group_id), pool),
+        );
+        assert_eq!(first.unwrap().len(), 1);
+        assert_eq!(second.unwrap().len(), 1);
+        let copy = DeviceCommand::pending(&device_id, Some(group_id), pool)
+            .await
+            .unwrap()
+            .remove(0);
+        assert_eq!(copy.parent_id, Some(group.id));
+
+        let update = |status| UpdateCommandStatus {
+            status,
+            result: None,
+        };
+        // 未送达不能确认
+        assert_eq!(
+            DeviceCommand::update_status(copy.id, &update(CommandStatus::Acked), pool)
+                .await
+                .unwrap(),
+            None
+        );
+        DeviceCommand::update_status(copy.id, &update(CommandStatus::Delivered), pool)
+            .await
+            .unwrap()
+            .unwrap();
+        DeviceCommand::update_status(copy.id, &update(CommandStatus::Acked), pool)
+            .await
+            .unwrap()
+            .unwrap();
+
+        // 分组命令标记为已送达，仍会复制给后上线的设备
+        let group = DeviceCommand::find(group.id, pool).await.unwrap();
+        assert_eq!(group.status, CommandStatus::Delivered);
+        assert!(group.delivered_at.is_some());
+        let other = format!("{:08X}", group_id + 1);
+        assert_eq!(
+            DeviceCommand::pending(&other, Some(group_id), pool)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(
+            DeviceCommand::progress(group.id, pool).await.unwrap(),
+            CommandProgress {
+                queued: 1,
+                acked: 1,
+                ..Default::default()
+            }
+        );
+
+        DeviceCommand::delete(group.id, pool).await.unwrap();
+    }
+}
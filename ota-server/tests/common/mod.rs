@@ -0,0 +1,8 @@
+/// 构造请求数据包，用于测试解析
+pub fn request(package_type: u8, payload: &[u8]) -> Vec<u8> {
+    // CRC 不参与解析，末尾补一个字节即可
+    let mut data = vec![0xAA, 0x55, package_type, 0x00, payload.len() as u8];
+    data.extend_from_slice(payload);
+    data.push(0x00);
+    data
+}
@@ -0,0 +1,229 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::request;
+    use chrono::Utc;
+    use ota_database::models::{
+        config_history::{ConfigHistory, ConfigQuery, GLOBAL_GROUP},
+        config_schema::{ConfigSchema, SchemaError},
+        device_shadow::DeviceShadow,
+    };
+    use ota_server::package::{
+        common::{parse_config_query, parse_config_report},
+        tx_package::send_schema_config_pkg,
+    };
+    use serde_json::json;
+    use tokio::io::{duplex, AsyncReadExt};
+
+    fn config(id: i32, group_id: i32, device_id: Option<&str>) -> ConfigHistory {
+        ConfigHistory {
+            id,
+            group_id,
+            device_id: device_id.map(str::to_string),
+            sync_ts: Utc::now().naive_utc(),
+            created_at: Utc::now().naive_utc(),
+            updated_at: Utc::now().naive_utc(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn parse_query_payload() {
+        assert_eq!(
+            parse_config_query(&request(0xA4, &[])),
+            ConfigQuery::default()
+        );
+
+        let query = parse_config_query(&request(0xA4, &[0, 0, 0, 7]));
+        assert_eq!(query.group_id, Some(7));
+        assert_eq!(query.device_id, None);
+
+        let query = parse_config_query(&request(
+            0xA4,
+            &[0, 0, 0, 0, 0x12, 0x34, 0xAB, 0xCD, 0, 0, 0, 3],
+        ));
+        assert_eq!(query.device_id.as_deref(), Some("1234ABCD"));
+        assert_eq!(query.group_id, Some(3));
+    }
+
+    #[test]
+    fn resolve_device_group_global() {
+        let configs = vec![
+            config(1, GLOBAL_GROUP, None),
+            config(2, 3, None),
+            config(3, 3, None),
+            config(4, 3, Some("1234ABCD")),
+            config(5, GLOBAL_GROUP, None),
+            config(6, 9, None),
+        ];
+
+        // 设备级配置优先，忽略大小写
+        assert_eq!(
+            ConfigHistory::resolve(&configs, Some("1234abcd"), Some(3))
+                .unwrap()
+                .id,
+            4
+        );
+        // 分组内取最新
+        assert_eq!(
+            ConfigHistory::resolve(&configs, Some("FFFF"), Some(3))
+                .unwrap()
+                .id,
+            3
+        );
+        // 没有分组配置时使用全局默认
+        assert_eq!(
+            ConfigHistory::resolve(&configs, None, Some(1)).unwrap().id,
+            5
+        );
+        assert_eq!(ConfigHistory::resolve(&configs, None, None).unwrap().id, 5);
+
+        // 没有全局默认时不使用其他分组的配置
+        let configs = vec![config(1, 3, None)];
+        assert!(ConfigHistory::resolve(&configs, None, Some(9)).is_none());
+    }
+
+    fn schema() -> ConfigSchema {
+        serde_json::from_value(json!({
+            "id": 1,
+            "name": "sensor",
+            "fields": [
+                {"name": "interval", "type": "u8", "min": 1, "max": 60, "default": 10},
+                {"name": "t_max", "type": "i16"},
+                {"name": "enabled", "type": "bool", "default": true},
+                {"name": "sync_ts", "type": "timestamp", "default": 0},
+            ],
+            "created_at": "2026-10-19T00:00:00",
+            "updated_at": "2026-10-19T00:00:00",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn schema_validate_and_encode() {
+        let schema = schema();
+        assert!(ConfigSchema::check_fields(&schema.fields).is_ok());
+
+        // 缺少的字段使用默认值
+        let params = schema.validate(&json!({"t_max": -2})).unwrap();
+        assert_eq!(params["interval"], json!(10));
+
+        let data = schema
+            .encode(&json!({"t_max": -2, "sync_ts": "2026-01-01T00:00:00"}))
+            .unwrap();
+        assert_eq!(data, vec![10, 0xFF, 0xFE, 0x01, 0x69, 0x55, 0xB9, 0x00]);
+
+        assert_eq!(
+            schema.validate(&json!({"interval": 0, "t_max": 1})),
+            Err(SchemaError::OutOfRange {
+                field: "interval".to_string(),
+                value: 0,
+                min: 1,
+                max: 60
+            })
+        );
+        assert_eq!(
+            schema.validate(&json!({})),
+            Err(SchemaError::MissingField("t_max".to_string()))
+        );
+        assert_eq!(
+            schema.validate(&json!({"t_max": 1, "t_min": 1})),
+            Err(SchemaError::UnknownField("t_min".to_string()))
+        );
+    }
+
+    #[tokio::test]
+    async fn send_schema_config() {
+        let mut schema = schema();
+        schema.fields.retain(|field| field.default.is_some());
+        let config = ConfigHistory {
+            schema_id: Some(1),
+            params: None,
+            ..config(1, 2, None)
+        };
+
+        // 没有参数时全部使用默认值
+        let (mut socket, mut device) = duplex(1024);
+        send_schema_config_pkg(&config, &schema, &mut socket)
+            .await
+            .unwrap();
+        let mut response = [0u8; 5 + 10 + 6 + 1];
+        device.read_exact(&mut response).await.unwrap();
+        assert_eq!(&response[..5], &[0xAA, 0x55, 0x5B, 0x00, 0x10]);
+        assert_eq!(&response[15..21], &[10, 0x01, 0x00, 0x00, 0x00, 0x00]);
+
+        // 参数不符合模板时回复配置错误
+        let config = ConfigHistory {
+            params: Some(json!({"interval": 0})),
+            ..config
+        };
+        send_schema_config_pkg(&config, &schema, &mut socket)
+            .await
+            .unwrap();
+        let mut response = [0u8; 4];
+        device.read_exact(&mut response).await.unwrap();
+        assert_eq!(&response[..3], &[0xAA, 0x55, 0xF5]);
+
+        // 写入失败直接返回错误
+        drop(device);
+        assert!(send_schema_config_pkg(&config, &schema, &mut socket)
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn schema_decode_report() {
+        let schema = schema();
+        let data = schema
+            .encode(&json!({"t_max": -2, "enabled": false}))
+            .unwrap();
+
+        let mut payload = vec![0, 0, 0, 0, 0x12, 0x34, 0xAB, 0xCD, 0x00, 0x01];
+        payload.extend_from_slice(&data);
+        let request = request(0xA4, &payload);
+
+        let report = parse_config_report(&request).unwrap();
+        assert_eq!(report.device_id, "1234ABCD");
+        assert_eq!(report.schema_id, 1);
+
+        let reported = schema.decode(report.data).unwrap();
+        assert_eq!(
+            reported,
+            json!({"interval": 10, "t_max": -2, "enabled": false, "sync_ts": 0})
+        );
+
+        assert!(matches!(
+            schema.decode(&data[..3]),
+            Err(SchemaError::Truncated { .. })
+        ));
+        assert!(parse_config_report(&request[..10]).is_none());
+    }
+
+    #[test]
+    fn shadow_delta() {
+        let mut shadow = DeviceShadow {
+            device_id: "1234ABCD".to_string(),
+            desired_schema_id: Some(1),
+            desired: Some(json!({"interval": 10, "t_max": 5})),
+            ..Default::default()
+        };
+
+        // 没有上报时所有期望字段都不一致
+        assert_eq!(shadow.delta().len(), 2);
+
+        shadow.reported_schema_id = Some(1);
+        shadow.reported = Some(json!({"interval": 10, "t_max": 4, "enabled": true}));
+        assert_eq!(
+            shadow.delta(),
+            json!({"t_max": 5}).as_object().unwrap().clone()
+        );
+
+        shadow.reported = Some(json!({"interval": 10, "t_max": 5}));
+        assert!(shadow.in_sync());
+
+        // 模板不同
+        shadow.reported_schema_id = Some(2);
+        assert_eq!(shadow.delta().len(), 2);
+    }
+}
@@ -0,0 +1,112 @@
+#[cfg(test)]
+mod tests {
+    use ota_database::models::download_session::DownloadOutcome;
+    use ota_server::{
+        download::{DownloadTracker, DOWNLOAD_TIMEOUT},
+        session::DeviceSession,
+    };
+    use tokio::time::Instant;
+
+    const FW: (i32, i32, i32, i32) = (0x1987, 0, 2, 0);
+
+    fn session(device_id: Option<&str>, conn_id: u64, peer: &str) -> DeviceSession {
+        let mut session = DeviceSession::default();
+        session.device_id = device_id.map(str::to_string);
+        session.conn_id = conn_id;
+        session.peer = peer.to_string();
+        session
+    }
+
+    #[test]
+    fn count_slices_and_repeats() {
+        let tracker = DownloadTracker::new();
+        let device = session(Some("00000042"), 1, "10.0.0.1");
+        tracker.record_slice(&device, FW, 0, 512, 512);
+        tracker.record_slice(&device, FW, 1, 512, 512);
+        tracker.record_slice(&device, FW, 1, 512, 512);
+        tracker.record_slice(&device, FW, 2, 512, 100);
+
+        let download = tracker
+            .finish(Some("00000042"), 1, FW, DownloadOutcome::Success)
+            .unwrap();
+        assert_eq!(download.slices_served, 4);
+        assert_eq!(download.slices_repeated, 1);
+        assert_eq!(download.bytes_served, 512 * 3 + 100);
+        assert_eq!(download.slice_size, 512);
+        assert_eq!(download.outcome, DownloadOutcome::Success);
+        assert!(tracker.is_empty());
+
+        // 会话已结束
+        assert!(tracker
+            .finish(Some("00000042"), 1, FW, DownloadOutcome::Success)
+            .is_none());
+    }
+
+    #[test]
+    fn device_identified_midway() {
+        let tracker = DownloadTracker::new();
+
+        // 未识别设备时按连接记录，识别后转到设备ID下
+        tracker.record_slice(&session(None, 1, "10.0.0.1"), FW, 0, 512, 512);
+        tracker.record_slice(&session(Some("00000042"), 1, "10.0.0.1"), FW, 1, 512, 512);
+        assert_eq!(tracker.len(), 1);
+
+        // 重连后连接和IP变化，仍按设备ID累计
+        tracker.record_slice(&session(Some("00000042"), 2, "10.0.0.2"), FW, 2, 512, 512);
+        let download = tracker
+            .finish(Some("00000042"), 2, FW, DownloadOutcome::Failed)
+            .unwrap();
+        assert_eq!(download.slices_served, 3);
+        assert_eq!(download.device_id.as_deref(), Some("00000042"));
+        assert_eq!(download.peer.as_deref(), Some("10.0.0.2"));
+
+        // 整个下载都未识别设备，结束包中的设备ID补到统计中
+        tracker.record_slice(&session(None, 3, "10.0.0.3"), FW, 0, 512, 512);
+        let download = tracker
+            .finish(Some("00000043"), 3, FW, DownloadOutcome::Success)
+            .unwrap();
+        assert_eq!(download.device_id.as_deref(), Some("00000043"));
+    }
+
+    #[test]
+    fn same_ip_different_connections() {
+        let tracker = DownloadTracker::new();
+
+        // 同一网关后的两台设备，IP相同但连接不同
+        tracker.record_slice(&session(None, 1, "10.0.0.1"), FW, 0, 512, 512);
+        tracker.record_slice(&session(None, 2, "10.0.0.1"), FW, 0, 512, 512);
+        tracker.record_slice(&session(None, 2, "10.0.0.1"), FW, 1, 512, 512);
+        assert_eq!(tracker.len(), 2);
+        assert_eq!(tracker.progress(None, 1).unwrap().slices, 1);
+        assert_eq!(tracker.progress(None, 2).unwrap().slices, 2);
+        assert!(tracker.progress(None, 3).is_none());
+
+        let download = tracker
+            .finish(Some("00000044"), 2, FW, DownloadOutcome::Success)
+            .unwrap();
+        assert_eq!(download.slices_served, 2);
+        assert_eq!(tracker.progress(None, 1).unwrap().slices, 1);
+    }
+
+    #[test]
+    fn expire_abandoned() {
+        let tracker = DownloadTracker::new();
+        tracker.record_slice(&session(None, 1, "10.0.0.1"), FW, 0, 512, 512);
+        tracker.record_slice(
+            &session(None, 2, "10.0.0.2"),
+            (0x2000, 1, 0, 0),
+            0,
+            256,
+            256,
+        );
+
+        assert!(tracker.expire(Instant::now(), DOWNLOAD_TIMEOUT).is_empty());
+
+        let expired = tracker.expire(Instant::now() + DOWNLOAD_TIMEOUT, DOWNLOAD_TIMEOUT);
+        assert_eq!(expired.len(), 2);
+        assert!(expired
+            .iter()
+            .all(|download| download.outcome == DownloadOutcome::Abandoned));
+        assert!(tracker.is_empty());
+    }
+}
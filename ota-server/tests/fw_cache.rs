@@ -0,0 +1,229 @@
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+    use bytes::Bytes;
+    use chrono::Utc;
+    use ota_database::models::{
+        config_history::ConfigHistory,
+        config_schema::ConfigSchema,
+        firmware_data::{FirmwareMeta, FirmwareVersion},
+    };
+    use ota_server::{
+        fw_cache::{BlobCache, FirmwareCache, FirmwareImage, FirmwareSnapshot, MERKLE_CACHE_LIMIT},
+        merkle::MerkleTree,
+        source::{dir::DirSource, FirmwareSource, SourceResult},
+    };
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+    use tokio::time::timeout;
+
+    fn fw_meta(id: i32, code: i32, m: i32, n: i32, l: i32, hash: &str) -> FirmwareMeta {
+        FirmwareMeta {
+            id,
+            fwcode: code,
+            version_m: m,
+            version_n: n,
+            version_l: l,
+            fwsize: 16,
+            hash: hash.to_string(),
+            updated_at: Utc::now().naive_utc(),
+        }
+    }
+
+    fn fw_image(id: i32, hash: &str, len: usize) -> FirmwareImage {
+        let data: Vec<u8> = (0..len).map(|i| i as u8).collect();
+        FirmwareImage::new(&fw_meta(id, 0x1987, 0, 2, 0, hash), Bytes::from(data))
+    }
+
+    #[test]
+    fn find_latest_and_exact() {
+        let snapshot = FirmwareSnapshot::new(vec![
+            fw_meta(1, 0x1987, 0, 2, 0, "a"),
+            fw_meta(2, 0x1987, 0, 10, 0, "b"),
+            fw_meta(3, 0x1987, 0, 9, 9, "c"),
+            fw_meta(4, 0x2024, 1, 0, 0, "d"),
+        ]);
+
+        assert_eq!(snapshot.len(), 4);
+        assert_eq!(snapshot.find_latest(0x1987).unwrap().id, 2);
+        assert_eq!(snapshot.find_latest(0x2024).unwrap().id, 4);
+        assert!(snapshot.find_latest(0x0001).is_none());
+
+        let version = FirmwareVersion { m: 0, n: 9, l: 9 };
+        assert_eq!(snapshot.find(0x1987, &version).unwrap().id, 3);
+
+        assert!(snapshot.contains(3, "c"));
+        assert!(!snapshot.contains(3, "changed"));
+    }
+
+    #[test]
+    fn slices_share_image_buffer() {
+        let image = fw_image(1, "a", 1000);
+
+        let first = image.slice(0, 512).unwrap();
+        let last = image.slice(1, 512).unwrap();
+        assert_eq!(first.len(), 512);
+        assert_eq!(last.len(), 1000 - 512);
+        assert_eq!(last[0], (512 % 256) as u8);
+        assert!(image.slice(2, 512).is_none());
+        assert!(image.slice(0, 0).is_none());
+
+        // 切片指向同一块内存
+        assert_eq!(first.as_ptr(), image.data.as_ptr());
+    }
+
+    #[tokio::test]
+    async fn merkle_sizes_and_cache_limit() {
+        let image = fw_image(1, "a", 5000);
+
+        // 不允许的切片大小
+        for slice_size in [0, 16, 63, 100, 8192] {
+            assert!(image.merkle(slice_size).await.is_none());
+        }
+
+        let first = image.merkle(64).await.unwrap();
+        assert_eq!(first.root(), MerkleTree::build(&image.data, 64).root());
+        assert!(Arc::ptr_eq(&first, &image.merkle(64).await.unwrap()));
+
+        // 克隆之间共享，超出上限时丢弃最早构建的
+        let clone = image.clone();
+        for slice_size in (7..7 + MERKLE_CACHE_LIMIT as u32).map(|shift| 1usize << shift) {
+            let tree = clone.merkle(slice_size).await.unwrap();
+            assert!(Arc::ptr_eq(&tree, &image.merkle(slice_size).await.unwrap()));
+        }
+        assert!(!Arc::ptr_eq(&first, &image.merkle(64).await.unwrap()));
+    }
+
+    #[test]
+    fn blob_cache_evicts_least_recently_used() {
+        let mut blobs = BlobCache::new(300);
+        blobs.insert(fw_image(1, "a", 100));
+        blobs.insert(fw_image(2, "b", 100));
+        blobs.insert(fw_image(3, "c", 100));
+
+        // 访问1之后，2成为最久未使用
+        assert!(blobs.get(1, "a").is_some());
+        blobs.insert(fw_image(4, "d", 100));
+
+        assert!(blobs.get(2, "b").is_none());
+        assert!(blobs.get(1, "a").is_some());
+        assert_eq!(blobs.used_bytes(), 300);
+
+        // hash变化视为未缓存
+        assert!(blobs.get(3, "changed").is_none());
+
+        // 超过容量的固件单独保存，不占容量，只保留最近一个
+        blobs.insert(fw_image(5, "e", 400));
+        assert!(blobs.get(5, "e").is_some());
+        assert_eq!(blobs.len(), 3);
+        assert_eq!(blobs.used_bytes(), 300);
+        blobs.insert(fw_image(6, "f", 500));
+        assert!(blobs.get(5, "e").is_none());
+        assert!(blobs.get(6, "f").is_some());
+
+        // 内容变化或删除时一并丢弃
+        blobs.retain(|image| image.id != 6);
+        assert!(blobs.get(6, "f").is_none());
+    }
+
+    #[test]
+    fn store_replaces_snapshot() {
+        let cache = FirmwareCache::new(Arc::new(DirSource::new("/nonexistent")), 1024);
+        assert!(cache.load().is_empty());
+
+        let old = cache.load();
+        cache.store(FirmwareSnapshot::new(vec![fw_meta(
+            1, 0x1987, 0, 2, 0, "a",
+        )]));
+
+        // 旧快照不受影响
+        assert!(old.is_empty());
+        assert_eq!(cache.load().len(), 1);
+    }
+
+    /// 固件1的读取一直不返回，其他固件立即返回
+    #[derive(Debug)]
+    struct StalledSource;
+
+    #[async_trait]
+    impl FirmwareSource for StalledSource {
+        async fn list_firmware(&self) -> SourceResult<Vec<FirmwareMeta>> {
+            Ok(vec![])
+        }
+
+        async fn read_firmware(&self, meta: &FirmwareMeta) -> SourceResult<Vec<u8>> {
+            if meta.id == 1 {
+                std::future::pending::<()>().await;
+            }
+            Ok(vec![0x5A; 16])
+        }
+
+        async fn list_configs(&self) -> SourceResult<Vec<ConfigHistory>> {
+            Ok(vec![])
+        }
+
+        async fn list_config_schemas(&self) -> SourceResult<Vec<ConfigSchema>> {
+            Ok(vec![])
+        }
+    }
+
+    #[tokio::test]
+    async fn slow_fetch_does_not_block_other_images() {
+        let cache = Arc::new(FirmwareCache::new(Arc::new(StalledSource), 1024));
+        let stalled = fw_meta(1, 0x1987, 0, 2, 0, "a");
+        let other = fw_meta(2, 0x2024, 1, 0, 0, "b");
+
+        let stalled_fetch = tokio::spawn({
+            let cache = cache.clone();
+            async move { cache.image(&stalled).await }
+        });
+        tokio::task::yield_now().await;
+
+        let image = timeout(Duration::from_secs(1), cache.image(&other)).await;
+        assert_eq!(image.unwrap().unwrap().data.len(), 16);
+        assert!(!stalled_fetch.is_finished());
+        stalled_fetch.abort();
+    }
+
+    /// 记录读取次数
+    #[derive(Debug, Default)]
+    struct CountingSource(AtomicUsize);
+
+    #[async_trait]
+    impl FirmwareSource for CountingSource {
+        async fn list_firmware(&self) -> SourceResult<Vec<FirmwareMeta>> {
+            Ok(vec![])
+        }
+
+        async fn read_firmware(&self, _meta: &FirmwareMeta) -> SourceResult<Vec<u8>> {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            Ok(vec![0x5A; 2048])
+        }
+
+        async fn list_configs(&self) -> SourceResult<Vec<ConfigHistory>> {
+            Ok(vec![])
+        }
+
+        async fn list_config_schemas(&self) -> SourceResult<Vec<ConfigSchema>> {
+            Ok(vec![])
+        }
+    }
+
+    #[tokio::test]
+    async fn oversized_image_read_once() {
+        let source = Arc::new(CountingSource::default());
+        let cache = FirmwareCache::new(source.clone(), 1024);
+        let meta = fw_meta(1, 0x1987, 0, 2, 0, "a");
+
+        // 超过缓存容量的固件按分片下载时只读取一次
+        for _ in 0..4 {
+            assert_eq!(cache.image(&meta).await.unwrap().data.len(), 2048);
+        }
+        assert_eq!(source.0.load(Ordering::Relaxed), 1);
+    }
+}
@@ -0,0 +1,230 @@
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+    use ota_database::models::firmware_data::{FirmwareInfo, FirmwareVersion};
+    use ota_server::{
+        handler::{ConnectionContext, HandlerRegistry, PacketHandler},
+        package::rx_package::gen_fw_query_end_package,
+        server::OtaServer,
+        source::dir::DirSource,
+        ErrorCode,
+    };
+    use ota_simulator::conformance::{crc8, error_frame, frame};
+    use std::{error::Error, fs, sync::Arc};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpStream,
+    };
+
+    /// 厂商自定义包：数据段为设备ID时记录到连接状态，为空时回复固件数量和已记录的设备ID
+    struct VendorHandler;
+
+    #[async_trait]
+    impl PacketHandler for VendorHandler {
+        fn name(&self) -> &str {
+            "Vendor"
+        }
+
+        async fn handle(
+            &self,
+            request: &[u8],
+            conn: &mut ConnectionContext<'_>,
+        ) -> Result<(), Box<dyn Error>> {
+            let payload = &request[5..request.len() - 1];
+            if !payload.is_empty() {
+                conn.session.device_id = Some(String::from_utf8(payload.to_vec())?);
+                return conn.reply(request[2], &[]).await;
+            }
+
+            let Some(device_id) = conn.session.device_id.clone() else {
+                return conn.reply_error(ErrorCode::ConfigError).await;
+            };
+            let mut response = vec![conn.server.fw_cache.load().len() as u8];
+            response.extend_from_slice(device_id.as_bytes());
+            conn.reply(request[2], &response).await
+        }
+    }
+
+    /// 替换内置的时间同步
+    struct FixedTime;
+
+    #[async_trait]
+    impl PacketHandler for FixedTime {
+        fn name(&self) -> &str {
+            "FixedTime"
+        }
+
+        async fn handle(
+            &self,
+            request: &[u8],
+            conn: &mut ConnectionContext<'_>,
+        ) -> Result<(), Box<dyn Error>> {
+            conn.reply(request[2], &[0x00, 0x00, 0x00, 0x00]).await
+        }
+    }
+
+    async fn start_server(name: &str, handlers: HandlerRegistry) -> String {
+        let path =
+            std::env::temp_dir().join(format!("ota-handler-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        fs::write(path.join("1987-0.3.0.bin"), [0x5Au8; 100]).unwrap();
+        fs::write(path.join("2024-1.0.0.bin"), [0xA5u8; 100]).unwrap();
+
+        let server = OtaServer::builder()
+            .bind("127.0.0.1:0")
+            .backend(Arc::new(DirSource::new(&path)))
+            .handlers(handlers)
+            .build()
+            .await
+            .unwrap();
+        tokio::spawn({
+            let server = server.clone();
+            async move { server.run().await }
+        });
+
+        server.local_addr().to_string()
+    }
+
+    async fn request(socket: &mut TcpStream, data: &[u8]) -> Vec<u8> {
+        socket.write_all(data).await.unwrap();
+        let mut buffer = [0u8; 1024];
+        let len = socket.read(&mut buffer).await.unwrap();
+        buffer[..len].to_vec()
+    }
+
+    #[tokio::test]
+    async fn custom_packet_types() {
+        let mut handlers = HandlerRegistry::builtin();
+        assert!(handlers.register(0x30, VendorHandler).is_none());
+        let replaced = handlers.register(0xA8, FixedTime).unwrap();
+        assert_eq!(replaced.name(), "TimeSync");
+        assert_eq!(
+            handlers.types(),
+            vec![0x30, 0xA1, 0xA2, 0xA3, 0xA4, 0xA5, 0xA6, 0xA8]
+        );
+
+        let server = start_server("custom", handlers).await;
+        let mut socket = TcpStream::connect(&server).await.unwrap();
+
+        // 还没有设备ID
+        assert_eq!(
+            request(&mut socket, &frame(0x30, &[])).await,
+            error_frame(ErrorCode::ConfigError)
+        );
+
+        // 连接状态在同一连接的请求之间共享，回复类型为 0xFF - 0x30
+        assert_eq!(
+            request(&mut socket, &frame(0x30, b"C0FFEE00")).await,
+            frame(0xCF, &[])
+        );
+        let mut expected = vec![2];
+        expected.extend_from_slice(b"C0FFEE00");
+        assert_eq!(
+            request(&mut socket, &frame(0x30, &[])).await,
+            frame(0xCF, &expected)
+        );
+
+        // 替换的内置类型
+        assert_eq!(
+            request(&mut socket, &frame(0xA8, &[])).await,
+            frame(0x57, &[0x00, 0x00, 0x00, 0x00])
+        );
+
+        // 内置类型不受影响，未注册的类型回复错误码
+        assert_eq!(
+            request(&mut socket, &frame(0xA1, &[0x20, 0x24])).await,
+            frame(
+                0x5E,
+                &[0x20, 0x24, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x64]
+            )
+        );
+        assert_eq!(
+            request(&mut socket, &frame(0x31, &[])).await,
+            error_frame(ErrorCode::UnknownPackageType)
+        );
+
+        // 长度字段与数据段不一致，不交给处理器
+        let mut wrong_len = frame(0x30, b"DEADBEEF");
+        wrong_len[4] = 3;
+        wrong_len.pop();
+        wrong_len.push(crc8(&wrong_len));
+        assert_eq!(
+            request(&mut socket, &wrong_len).await,
+            error_frame(ErrorCode::LengthError)
+        );
+        assert_eq!(
+            request(&mut socket, &frame(0x30, &[])).await,
+            frame(0xCF, &expected)
+        );
+    }
+
+    #[tokio::test]
+    async fn short_payloads() {
+        let server = start_server("short", HandlerRegistry::builtin()).await;
+        let mut socket = TcpStream::connect(&server).await.unwrap();
+        let fw_info = FirmwareInfo {
+            code: 0x1987,
+            version: FirmwareVersion { m: 0, n: 3, l: 0 },
+            size: 100,
+            path: String::new(),
+        };
+
+        // 数据段短于各类型的最小长度时回复长度错误，连接保持
+        let short = [
+            frame(0xA1, &[]),
+            frame(0xA1, &[0x19]),
+            frame(0xA2, &[0x19, 0x87, 0x00, 0x03, 0x00, 0x00, 0x00, 0x02]),
+            frame(0xA3, &[]),
+            frame(0xA3, &[0x19, 0x87, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00]),
+            gen_fw_query_end_package(&fw_info),
+            frame(0xA5, &[0x00; 9]),
+        ];
+        for request_frame in &short {
+            assert_eq!(
+                request(&mut socket, request_frame).await,
+                error_frame(ErrorCode::LengthError),
+                "{:02X?}",
+                request_frame
+            );
+        }
+
+        // 数据段可以为空的类型
+        assert_eq!(
+            request(&mut socket, &frame(0xA4, &[])).await,
+            error_frame(ErrorCode::NoFirmwareFound)
+        );
+        assert_eq!(
+            request(&mut socket, &frame(0xA6, &[])).await,
+            frame(0x59, &[])
+        );
+        assert_eq!(request(&mut socket, &frame(0xA8, &[])).await[2], 0x57);
+
+        // 最小长度的请求正常处理
+        assert_eq!(
+            request(&mut socket, &frame(0xA1, &[0x19, 0x87])).await,
+            frame(
+                0x5E,
+                &[0x19, 0x87, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x64]
+            )
+        );
+        assert_eq!(
+            request(
+                &mut socket,
+                &frame(
+                    0xA2,
+                    &[0x19, 0x87, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x02]
+                )
+            )
+            .await[..5],
+            [0xAA, 0x55, 0x5D, 0x00, 0x09]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "conflicts with error codes")]
+    fn reject_error_code_types() {
+        // 回复类型 0xF5 与配置错误冲突
+        HandlerRegistry::new().register(0x0A, FixedTime);
+    }
+}
@@ -0,0 +1,154 @@
+#[cfg(test)]
+mod tests {
+    use ota_server::{
+        merkle::{slice_size_allowed, verify, MerkleTree, HASH_LEN},
+        server::OtaServer,
+        source::dir::DirSource,
+    };
+    use ota_simulator::conformance::frame;
+    use std::{fs, sync::Arc};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpStream,
+    };
+
+    fn firmware() -> Vec<u8> {
+        (0..5000u32).map(|i| (i * 13 + i / 256) as u8).collect()
+    }
+
+    /// 在本地目录上启动服务，目录中放一个 5000 字节的固件
+    async fn start_server() -> String {
+        let path = std::env::temp_dir().join(format!("ota-merkle-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        fs::write(path.join("1987-0.3.0.bin"), firmware()).unwrap();
+
+        let server = OtaServer::builder()
+            .bind("127.0.0.1:0")
+            .backend(Arc::new(DirSource::new(&path)))
+            .build()
+            .await
+            .unwrap();
+        tokio::spawn({
+            let server = server.clone();
+            async move { server.run().await }
+        });
+
+        server.local_addr().to_string()
+    }
+
+    /// 发送请求，返回回复的包类型和数据段
+    async fn request(socket: &mut TcpStream, package_type: u8, payload: &[u8]) -> (u8, Vec<u8>) {
+        socket
+            .write_all(&frame(package_type, payload))
+            .await
+            .unwrap();
+
+        let mut header = [0u8; 5];
+        socket.read_exact(&mut header).await.unwrap();
+        let mut body = vec![0u8; u16::from_be_bytes([header[3], header[4]]) as usize + 1];
+        socket.read_exact(&mut body).await.unwrap();
+        body.pop();
+        (header[2], body)
+    }
+
+    #[test]
+    fn proofs_verify() {
+        for (len, slice_size, depth) in [(0, 512, 0), (100, 512, 0), (1500, 512, 2), (5000, 64, 7)]
+        {
+            let data: Vec<u8> = (0..len as u32).map(|i| (i + i / 256) as u8).collect();
+            let tree = MerkleTree::build(&data, slice_size);
+            let root = tree.root();
+            assert_eq!(tree.depth(), depth);
+
+            for (index, slice) in data.chunks(slice_size).enumerate() {
+                let proof = tree.proof(index).unwrap();
+                assert_eq!(proof.len(), depth);
+                assert!(verify(&root, index, slice, &proof));
+
+                // 篡改的切片、错误的序号都无法通过
+                let mut tampered = slice.to_vec();
+                tampered[0] ^= 0x01;
+                assert!(!verify(&root, index, &tampered, &proof));
+                if depth > 0 {
+                    assert!(!verify(&root, index ^ 1, slice, &proof));
+                }
+            }
+            assert_eq!(tree.proof(tree.slices()), None);
+        }
+
+        // 切片大小不同，根不同
+        let data = firmware();
+        assert_ne!(
+            MerkleTree::build(&data, 256).root(),
+            MerkleTree::build(&data, 512).root()
+        );
+    }
+
+    #[tokio::test]
+    async fn proofs_over_protocol() {
+        let server = start_server().await;
+        let mut socket = TcpStream::connect(&server).await.unwrap();
+        let tree = MerkleTree::build(&firmware(), 512);
+
+        // 旧设备：只有代号，回复不变
+        let (response, payload) = request(&mut socket, 0xA1, &[0x19, 0x87]).await;
+        assert_eq!(response, 0x5E);
+        assert_eq!(payload.len(), 9);
+
+        // 附带切片大小，回复附带切片大小和 Merkle 根
+        let (response, payload) = request(&mut socket, 0xA1, &[0x19, 0x87, 0x02, 0x00]).await;
+        assert_eq!(response, 0x5E);
+        assert_eq!(&payload[9..11], &[0x02, 0x00]);
+        assert_eq!(payload[11..], tree.root());
+
+        // 下载最后一片，标志位要求认证路径
+        let (response, payload) = request(
+            &mut socket,
+            0xA2,
+            &[0x19, 0x87, 0x00, 0x03, 0x00, 0x00, 0x09, 0x02, 0x00, 0x01],
+        )
+        .await;
+        assert_eq!(response, 0x5D);
+        let depth = *payload.last().unwrap() as usize;
+        assert_eq!(depth, 4);
+        let proof_start = payload.len() - 1 - depth * HASH_LEN;
+        let proof: Vec<[u8; HASH_LEN]> = payload[proof_start..payload.len() - 1]
+            .chunks(HASH_LEN)
+            .map(|hash| hash.try_into().unwrap())
+            .collect();
+        let slice = &payload[7..proof_start];
+        assert_eq!(slice, &firmware()[4608..]);
+        assert!(verify(&tree.root(), 9, slice, &proof));
+
+        // 不要求认证路径时与原来相同
+        let (_, payload) = request(
+            &mut socket,
+            0xA2,
+            &[0x19, 0x87, 0x00, 0x03, 0x00, 0x00, 0x09, 0x02, 0x00],
+        )
+        .await;
+        assert_eq!(&payload[7..], &firmware()[4608..]);
+
+        // 切片过小，不构建 Merkle 树，回复长度错误
+        assert!(slice_size_allowed(512));
+        assert!(!slice_size_allowed(16));
+        socket
+            .write_all(&frame(0xA1, &[0x19, 0x87, 0x00, 0x10]))
+            .await
+            .unwrap();
+        let mut error = [0u8; 4];
+        socket.read_exact(&mut error).await.unwrap();
+        assert_eq!(error[2], 0xF1);
+
+        socket
+            .write_all(&frame(
+                0xA2,
+                &[0x19, 0x87, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x10, 0x01],
+            ))
+            .await
+            .unwrap();
+        socket.read_exact(&mut error).await.unwrap();
+        assert_eq!(error[2], 0xF1);
+    }
+}
@@ -0,0 +1,82 @@
+#[cfg(test)]
+mod tests {
+    use ota_server::{
+        metrics::{metrics, observe_backend, serve_metrics, ConnectionGuard},
+        ErrorCode,
+    };
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+
+    #[test]
+    fn count_packets_and_errors() {
+        let packets = metrics().packets.with_label_values(&["TimeSync"]);
+        let errors = metrics().error_replies.with_label_values(&["CrcError"]);
+        let unknown = metrics().error_replies.with_label_values(&["EE"]);
+        let bytes = metrics().bytes_served.with_label_values(&["1987"]);
+        let (packets_before, errors_before, unknown_before, bytes_before) =
+            (packets.get(), errors.get(), unknown.get(), bytes.get());
+
+        metrics().packet("TimeSync");
+        metrics().error_reply(ErrorCode::CrcError as u8);
+        metrics().error_reply(0xEE);
+        metrics().firmware_served(0x1987, 512);
+        metrics().firmware_served(0x1987, 100);
+
+        assert_eq!(packets.get() - packets_before, 1);
+        assert_eq!(errors.get() - errors_before, 1);
+        assert_eq!(unknown.get() - unknown_before, 1);
+        assert_eq!(bytes.get() - bytes_before, 612);
+
+        let text = metrics().encode();
+        assert!(text.contains("ota_server_packets_total{type=\"TimeSync\"}"));
+        assert!(text.contains("ota_server_firmware_bytes_served_total{fwcode=\"1987\"}"));
+    }
+
+    #[tokio::test]
+    async fn backend_latency_by_outcome() {
+        let ok = metrics()
+            .backend_calls
+            .with_label_values(&["test_call", "ok"]);
+        let failed = metrics()
+            .backend_calls
+            .with_label_values(&["test_call", "error"]);
+        let (ok_before, failed_before) = (ok.get_sample_count(), failed.get_sample_count());
+
+        let result: Result<i32, String> = observe_backend("test_call", async { Ok(1) }).await;
+        assert_eq!(result, Ok(1));
+        let result: Result<i32, String> =
+            observe_backend("test_call", async { Err("down".to_string()) }).await;
+        assert!(result.is_err());
+
+        assert_eq!(ok.get_sample_count() - ok_before, 1);
+        assert_eq!(failed.get_sample_count() - failed_before, 1);
+    }
+
+    #[tokio::test]
+    async fn serve_metrics_endpoint() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve_metrics(listener));
+
+        let get = |path: &'static str| async move {
+            let mut socket = TcpStream::connect(addr).await.unwrap();
+            let request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path);
+            socket.write_all(request.as_bytes()).await.unwrap();
+            let mut response = String::new();
+            socket.read_to_string(&mut response).await.unwrap();
+            response
+        };
+
+        {
+            let _connection = ConnectionGuard::new();
+            let response = get("/metrics").await;
+            assert!(response.starts_with("HTTP/1.1 200 OK"));
+            assert!(response.contains("ota_server_connections"));
+        }
+
+        let response = get("/other").await;
+        assert!(response.starts_with("HTTP/1.1 404"));
+    }
+}
@@ -0,0 +1,29 @@
+#[cfg(test)]
+mod tests {
+    use ota_server::notify::{parse_sse_events, SseEvent};
+
+    #[test]
+    fn parse_complete_and_partial_events() {
+        let mut buffer = String::from(
+            ": ping\n\nevent: firmware_data\ndata: {\"table\":\"firmware_data\",\"op\":\"INSERT\",\"id\":1}\n\nevent: config_",
+        );
+
+        let events = parse_sse_events(&mut buffer);
+        assert_eq!(
+            events,
+            vec![SseEvent {
+                event: "firmware_data".to_string(),
+                data: "{\"table\":\"firmware_data\",\"op\":\"INSERT\",\"id\":1}".to_string(),
+            }]
+        );
+
+        // 不完整的事件保留在缓冲区
+        assert_eq!(buffer, "event: config_");
+
+        buffer.push_str("history\ndata: {}\n\n");
+        let events = parse_sse_events(&mut buffer);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event, "config_history");
+        assert!(buffer.is_empty());
+    }
+}
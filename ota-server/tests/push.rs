@@ -0,0 +1,226 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::request;
+    use chrono::Utc;
+    use ota_database::models::firmware_data::FirmwareMeta;
+    use ota_server::{
+        fw_cache::{FirmwareCache, FirmwareSnapshot},
+        registry::{SessionEvent, SessionRegistry},
+        server::OtaServer,
+        session::{DeviceSession, DOWNLOAD_IDLE_TIMEOUT},
+        source::dir::DirSource,
+        PackageType,
+    };
+    use ota_simulator::conformance::frame;
+    use std::{fs, net::SocketAddr, sync::Arc, time::Duration};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpStream,
+        time::{timeout, Instant},
+    };
+
+    fn fw_meta(id: i32, code: i32, m: i32, n: i32, l: i32) -> FirmwareMeta {
+        FirmwareMeta {
+            id,
+            fwcode: code,
+            version_m: m,
+            version_n: n,
+            version_l: l,
+            fwsize: 16,
+            hash: format!("hash-{}", id),
+            updated_at: Utc::now().naive_utc(),
+        }
+    }
+
+    #[tokio::test]
+    async fn registry_replace_and_unregister() {
+        let registry = Arc::new(SessionRegistry::new());
+        let peer: SocketAddr = "127.0.0.1:1".parse().unwrap();
+
+        let mut first_conn = registry.connect(peer);
+        let mut second_conn = registry.connect(peer);
+        let mut anonymous = registry.connect(peer);
+        let first = registry.register("00000001", first_conn.id());
+        let second = registry.register("00000002", second_conn.id());
+        assert_eq!(registry.devices(), vec!["00000001", "00000002"]);
+
+        // 未识别的连接同样收到推送
+        assert_eq!(registry.broadcast(SessionEvent::FirmwareChanged), 3);
+        assert_eq!(first_conn.recv().await, SessionEvent::FirmwareChanged);
+        assert_eq!(second_conn.recv().await, SessionEvent::FirmwareChanged);
+        assert_eq!(anonymous.recv().await, SessionEvent::FirmwareChanged);
+
+        // 同一设备重新连接，旧连接释放时不影响新连接
+        let mut reconnected_conn = registry.connect(peer);
+        let reconnected = registry.register("00000001", reconnected_conn.id());
+        drop(first);
+        drop(first_conn);
+        assert_eq!(registry.len(), 2);
+        assert!(registry.send("00000001", SessionEvent::FirmwareChanged));
+        assert_eq!(reconnected_conn.recv().await, SessionEvent::FirmwareChanged);
+
+        drop(reconnected);
+        drop(second);
+        assert!(registry.is_empty());
+        assert!(!registry.send("00000001", SessionEvent::FirmwareChanged));
+    }
+
+    #[test]
+    fn download_holds_push() {
+        let mut session = DeviceSession::default();
+        let now = Instant::now();
+        assert_eq!(session.download_idle_at(now), None);
+
+        // 分片请求之后暂缓推送，超时或下载结束后恢复
+        let download = request(0xA2, &[0x19, 0x87, 0, 3, 0, 0, 0, 0x02, 0]);
+        session.observe(PackageType::FirmwareDownload, &download);
+        let idle_at = session.download_idle_at(Instant::now()).unwrap();
+        assert!(idle_at >= now + DOWNLOAD_IDLE_TIMEOUT);
+        assert_eq!(session.download_idle_at(idle_at), None);
+
+        let end = request(
+            0xA3,
+            &[
+                0x19, 0x87, 0, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0x42, 0, 0, 0, 1, 0xA1,
+            ],
+        );
+        session.observe(PackageType::DownloadEnd, &end);
+        assert_eq!(session.download_idle_at(Instant::now()), None);
+    }
+
+    #[tokio::test]
+    async fn push_to_connections() {
+        let path = std::env::temp_dir().join(format!("ota-push-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        fs::write(path.join("1987-0.3.0.bin"), [0x5Au8; 1000]).unwrap();
+
+        let server = OtaServer::builder()
+            .bind("127.0.0.1:0")
+            .backend(Arc::new(DirSource::new(&path)))
+            .build()
+            .await
+            .unwrap();
+        tokio::spawn({
+            let server = server.clone();
+            async move { server.run().await }
+        });
+
+        // 只查询固件、没有设备ID的设备
+        let mut querying = TcpStream::connect(server.local_addr()).await.unwrap();
+        querying
+            .write_all(&frame(0xA1, &[0x19, 0x87]))
+            .await
+            .unwrap();
+        let mut response = [0u8; 5 + 9 + 1];
+        querying.read_exact(&mut response).await.unwrap();
+
+        // 正在下载的设备
+        let mut downloading = TcpStream::connect(server.local_addr()).await.unwrap();
+        downloading
+            .write_all(&frame(0xA2, &[0x19, 0x87, 0, 3, 0, 0, 0, 0x02, 0]))
+            .await
+            .unwrap();
+        let mut response = [0u8; 5 + 7 + 512 + 1];
+        downloading.read_exact(&mut response).await.unwrap();
+
+        fs::write(path.join("1987-0.4.0.bin"), [0xA5u8; 100]).unwrap();
+        server.fw_cache().refresh().await.unwrap();
+
+        let pushed = frame(0x58, &[0x19, 0x87, 0, 4, 0, 0, 0, 0, 100]);
+        let mut response = [0u8; 5 + 9 + 1];
+        timeout(Duration::from_secs(5), querying.read_exact(&mut response))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(response.to_vec(), pushed);
+
+        // 下载过程中不推送，下一个分片正常返回
+        downloading
+            .write_all(&frame(0xA2, &[0x19, 0x87, 0, 3, 0, 0, 0x01, 0x02, 0]))
+            .await
+            .unwrap();
+        let mut response = [0u8; 5 + 7 + 488 + 1];
+        downloading.read_exact(&mut response).await.unwrap();
+        assert_eq!(response[2], 0x5D);
+
+        // 下载结束后推送
+        let end = [
+            0x19, 0x87, 0, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0x42, 0, 0, 0, 1, 0xA1,
+        ];
+        downloading.write_all(&frame(0xA3, &end)).await.unwrap();
+        let mut response = [0u8; 5 + 9 + 1];
+        timeout(
+            Duration::from_secs(5),
+            downloading.read_exact(&mut response),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(response.to_vec(), pushed);
+    }
+
+    #[test]
+    fn update_available_once_per_version() {
+        let mut session = DeviceSession::default();
+        session.observe(PackageType::FirmwareQuery, &request(0xA1, &[0x19, 0x87]));
+        assert_eq!(session.fw_code, Some(0x1987));
+
+        // 设备已知当前最新固件
+        session.track_firmware(&FirmwareSnapshot::new(vec![fw_meta(1, 0x1987, 0, 2, 0)]));
+        let snapshot = FirmwareSnapshot::new(vec![fw_meta(1, 0x1987, 0, 2, 0)]);
+        assert!(session.update_available(&snapshot).is_none());
+
+        // 其它固件更新不推送
+        let snapshot = FirmwareSnapshot::new(vec![
+            fw_meta(1, 0x1987, 0, 2, 0),
+            fw_meta(2, 0x2000, 1, 0, 0),
+        ]);
+        assert!(session.update_available(&snapshot).is_none());
+
+        // 新版本只推送一次
+        let snapshot = FirmwareSnapshot::new(vec![
+            fw_meta(1, 0x1987, 0, 2, 0),
+            fw_meta(3, 0x1987, 0, 3, 0),
+        ]);
+        assert_eq!(session.update_available(&snapshot).unwrap().id, 3);
+        assert!(session.update_available(&snapshot).is_none());
+    }
+
+    #[test]
+    fn download_end_updates_known_version() {
+        let mut session = DeviceSession::default();
+        let end = request(
+            0xA3,
+            &[
+                0x19, 0x87, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0x42, 0, 0, 0, 1, 0xA1,
+            ],
+        );
+        session.observe(PackageType::DownloadEnd, &end);
+
+        let snapshot = FirmwareSnapshot::new(vec![fw_meta(4, 0x1987, 0, 4, 0)]);
+        assert!(session.update_available(&snapshot).is_none());
+    }
+
+    #[test]
+    fn cache_notifies_latest_change() {
+        let cache = FirmwareCache::new(Arc::new(DirSource::new("/nonexistent")), 1024);
+        let mut changes = cache.subscribe();
+
+        cache.store(FirmwareSnapshot::new(vec![fw_meta(1, 0x1987, 0, 2, 0)]));
+        assert!(changes.has_changed().unwrap());
+        changes.borrow_and_update();
+
+        // 只增加旧版本，最新版本不变
+        cache.store(FirmwareSnapshot::new(vec![
+            fw_meta(1, 0x1987, 0, 2, 0),
+            fw_meta(5, 0x1987, 0, 1, 0),
+        ]));
+        assert!(!changes.has_changed().unwrap());
+
+        cache.store(FirmwareSnapshot::new(vec![fw_meta(6, 0x1987, 0, 5, 0)]));
+        assert!(changes.has_changed().unwrap());
+    }
+}
@@ -0,0 +1,187 @@
+#[cfg(test)]
+mod tests {
+    use ota_server::{
+        record::{
+            read_recording, record_device, record_frame, scope, RecordDirection, RecordLine,
+            Recorder, PENDING_LIMIT,
+        },
+        ProtocolVersion,
+    };
+    use std::{fs, net::SocketAddr, path::PathBuf, sync::Arc};
+
+    fn recorder(name: &str, targets: &[&str]) -> Arc<Recorder> {
+        let dir = std::env::temp_dir().join(format!("ota-record-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let targets: Vec<String> = targets.iter().map(|target| target.to_string()).collect();
+        Arc::new(Recorder::new(dir, &targets))
+    }
+
+    fn peer(addr: &str) -> SocketAddr {
+        addr.parse().unwrap()
+    }
+
+    /// 写文件在后台进行，等待文件写完
+    async fn recording_files(recorder: &Recorder) -> Vec<PathBuf> {
+        recorder.flushed().await;
+        match fs::read_dir(recorder.dir()) {
+            Ok(entries) => entries.map(|entry| entry.unwrap().path()).collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    #[test]
+    fn targets() {
+        let recorder = recorder("targets", &["10.0.0.8", "c0ffee00", ""]);
+        assert!(!recorder.is_empty());
+        assert!(Recorder::new("recordings", &[String::new()]).is_empty());
+
+        // 没有设备ID时只录制匹配的IP
+        let ip_only = Arc::new(Recorder::new("recordings", &["10.0.0.8".to_string()]));
+        assert!(ip_only
+            .session(peer("10.0.0.9:5000"), ProtocolVersion::V1)
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn record_identified_device() {
+        let recorder = recorder("device", &["C0FFEE00"]);
+        let recording = recorder.session(peer("10.0.0.9:5000"), ProtocolVersion::V2);
+
+        scope(recording, async {
+            record_frame(RecordDirection::In, &[0xAA, 0x55, 0xA8]);
+            record_frame(RecordDirection::Out, &[0xAA, 0x55, 0xF4, 0x00]);
+            record_device("C0FFEE00");
+            record_frame(RecordDirection::In, &[0x01]);
+        })
+        .await;
+
+        let files = recording_files(&recorder).await;
+        assert_eq!(files.len(), 1);
+        let file_name = files[0].file_name().unwrap().to_str().unwrap().to_string();
+        assert!(file_name.starts_with("C0FFEE00-"));
+        assert!(file_name.ends_with("-5000.jsonl"));
+
+        // 识别前缓存的帧在文件头之后
+        let lines = read_recording(&files[0]).await.unwrap();
+        assert_eq!(lines.len(), 4);
+        match &lines[0] {
+            RecordLine::Session {
+                peer,
+                device_id,
+                protocol,
+                ..
+            } => {
+                assert_eq!(peer, "10.0.0.9:5000");
+                assert_eq!(device_id.as_deref(), Some("C0FFEE00"));
+                assert_eq!(*protocol, 2);
+            }
+            line => panic!("Unexpected header {:?}", line),
+        }
+        assert_eq!(
+            lines[2].frame(),
+            Some((RecordDirection::Out, vec![0xAA, 0x55, 0xF4, 0x00]))
+        );
+        assert_eq!(lines[3].frame(), Some((RecordDirection::In, vec![0x01])));
+    }
+
+    #[tokio::test]
+    async fn record_by_ip() {
+        let recorder = recorder("ip", &["10.0.0.9"]);
+        let recording = recorder.session(peer("10.0.0.9:5001"), ProtocolVersion::V1);
+
+        scope(recording, async {
+            record_frame(RecordDirection::In, &[0xAA]);
+            record_device("12345678");
+            record_device("12345678");
+        })
+        .await;
+
+        let files = recording_files(&recorder).await;
+        assert_eq!(files.len(), 1);
+        let lines = read_recording(&files[0]).await.unwrap();
+        assert_eq!(lines.len(), 3);
+        assert!(matches!(
+            &lines[2],
+            RecordLine::Identified { device_id, .. } if device_id == "12345678"
+        ));
+    }
+
+    #[tokio::test]
+    async fn skip_other_devices() {
+        let recorder = recorder("skip", &["C0FFEE00"]);
+
+        // 其它设备
+        let recording = recorder.session(peer("10.0.0.9:5002"), ProtocolVersion::V1);
+        scope(recording, async {
+            record_frame(RecordDirection::In, &[0xAA]);
+            record_device("12345678");
+        })
+        .await;
+
+        // 识别前数据过多，写到临时文件后识别为其它设备
+        let recording = recorder.session(peer("10.0.0.9:5003"), ProtocolVersion::V1);
+        scope(recording, async {
+            record_frame(RecordDirection::Out, &vec![0u8; PENDING_LIMIT + 1]);
+            record_device("12345678");
+            record_frame(RecordDirection::In, &[0xAA]);
+        })
+        .await;
+
+        // 直到连接结束都未识别
+        let recording = recorder.session(peer("10.0.0.9:5004"), ProtocolVersion::V1);
+        scope(recording, async {
+            record_frame(RecordDirection::Out, &vec![0u8; PENDING_LIMIT + 1]);
+        })
+        .await;
+
+        // 不在录制范围内
+        record_frame(RecordDirection::In, &[0xAA]);
+
+        assert!(recording_files(&recorder).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn record_long_unidentified_download() {
+        let recorder = recorder("long", &["C0FFEE00"]);
+        let recording = recorder.session(peer("10.0.0.9:5005"), ProtocolVersion::V1);
+
+        // 只下载固件的设备在下载结束时才带上设备ID
+        scope(recording, async {
+            record_frame(RecordDirection::In, &[0xAA, 0x55, 0xA2]);
+            record_frame(RecordDirection::Out, &vec![0x5D; PENDING_LIMIT]);
+            record_frame(RecordDirection::In, &[0xAA, 0x55, 0xA2]);
+            record_frame(RecordDirection::Out, &[0x5D; 16]);
+            record_device("C0FFEE00");
+            record_frame(RecordDirection::In, &[0xAA, 0x55, 0xA3]);
+        })
+        .await;
+
+        let files = recording_files(&recorder).await;
+        assert_eq!(files.len(), 1);
+        let file_name = files[0].file_name().unwrap().to_str().unwrap().to_string();
+        assert!(file_name.starts_with("C0FFEE00-"));
+        assert!(file_name.ends_with("-5005.jsonl"));
+
+        let lines = read_recording(&files[0]).await.unwrap();
+        assert_eq!(lines.len(), 7);
+        assert!(matches!(
+            &lines[0],
+            RecordLine::Session {
+                device_id: None,
+                ..
+            }
+        ));
+        assert_eq!(
+            lines[1].frame(),
+            Some((RecordDirection::In, vec![0xAA, 0x55, 0xA2]))
+        );
+        assert!(matches!(
+            &lines[5],
+            RecordLine::Identified { device_id, .. } if device_id == "C0FFEE00"
+        ));
+        assert_eq!(
+            lines[6].frame(),
+            Some((RecordDirection::In, vec![0xAA, 0x55, 0xA3]))
+        );
+    }
+}
@@ -0,0 +1,129 @@
+#[cfg(test)]
+mod tests {
+    use ota_server::{
+        server::{BuildError, OtaServer, ServerStats},
+        source::dir::DirSource,
+    };
+    use ota_simulator::conformance::frame;
+    use std::{fs, sync::Arc, time::Duration};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpStream,
+        time::sleep,
+    };
+
+    async fn request(socket: &mut TcpStream, data: &[u8]) -> Vec<u8> {
+        socket.write_all(data).await.unwrap();
+        let mut buffer = [0u8; 1024];
+        let len = socket.read(&mut buffer).await.unwrap();
+        buffer[..len].to_vec()
+    }
+
+    /// 连接任务异步更新计数，等待统计达到预期
+    async fn wait_stats(server: &OtaServer, expected: ServerStats) {
+        for _ in 0..100 {
+            if server.stats() == expected {
+                return;
+            }
+            sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(server.stats(), expected);
+    }
+
+    #[tokio::test]
+    async fn embedded_server() {
+        let path = std::env::temp_dir().join(format!("ota-embedded-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        fs::write(path.join("1987-0.3.0.bin"), [0x5Au8; 100]).unwrap();
+
+        let server = OtaServer::builder()
+            .bind("127.0.0.1:0")
+            .websocket("127.0.0.1:0")
+            .backend(Arc::new(DirSource::new(&path)))
+            .drain_timeout(Duration::from_secs(5))
+            .build()
+            .await
+            .unwrap();
+        assert_ne!(server.local_addr().port(), 0);
+        assert!(server.ws_local_addr().is_some());
+        assert_eq!(
+            server.stats(),
+            ServerStats {
+                firmware: 1,
+                ..Default::default()
+            }
+        );
+
+        let running = tokio::spawn({
+            let server = server.clone();
+            async move { server.run().await }
+        });
+
+        let mut socket = TcpStream::connect(server.local_addr()).await.unwrap();
+        assert_eq!(
+            request(&mut socket, &frame(0xA1, &[0x19, 0x87])).await,
+            frame(
+                0x5E,
+                &[0x19, 0x87, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x64]
+            )
+        );
+
+        // 下载结束没有回复，设备识别后登记为在线
+        // 代号(2) + 版本(3) + 设备ID(8) + SN(4) + 结果(1)
+        let payload = [
+            0x19, 0x87, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0xC0, 0xFF, 0xEE, 0x00, 0x00,
+            0x00, 0x00, 0x01, 0xA1,
+        ];
+        socket.write_all(&frame(0xA3, &payload)).await.unwrap();
+        wait_stats(
+            &server,
+            ServerStats {
+                connections: 1,
+                devices: 1,
+                downloads: 0,
+                firmware: 1,
+            },
+        )
+        .await;
+
+        // 只能运行一次
+        assert!(server.run().await.is_err());
+
+        // 退出时断开连接并上传升级记录
+        server.shutdown();
+        running.await.unwrap().unwrap();
+        wait_stats(
+            &server,
+            ServerStats {
+                firmware: 1,
+                ..Default::default()
+            },
+        )
+        .await;
+        let history = fs::read_to_string(path.join("upgrade_history.jsonl")).unwrap();
+        assert!(history.contains("C0FFEE00"));
+    }
+
+    #[tokio::test]
+    async fn build_errors() {
+        assert!(matches!(
+            OtaServer::builder().build().await,
+            Err(BuildError::NoBackend)
+        ));
+
+        let path = std::env::temp_dir().join(format!("ota-embedded-bind-{}", std::process::id()));
+        let first = OtaServer::builder()
+            .bind("127.0.0.1:0")
+            .backend(Arc::new(DirSource::new(&path)))
+            .build()
+            .await
+            .unwrap();
+        let second = OtaServer::builder()
+            .bind(first.local_addr().to_string())
+            .backend(Arc::new(DirSource::new(&path)))
+            .build()
+            .await;
+        assert!(matches!(second, Err(BuildError::Bind { .. })));
+    }
+}
@@ -0,0 +1,157 @@
+#[cfg(test)]
+mod tests {
+    use actix_web::{web, App, HttpServer};
+    use ota_database::{
+        models::{
+            basic::CrudOperations,
+            device_shadow::{DeviceShadow, ReportedConfig},
+            firmware_data::{FirmwareData, NewFirmwareData, UpdateFirmwareData},
+            upgrade_history::NewUpgradeHistory,
+        },
+        routes::total::apis,
+    };
+    use ota_server::source::{
+        dir::{parse_fw_file_name, DirSource, HISTORY_FILE, SHADOW_DIR},
+        http::HttpBackend,
+        pg::PgBackend,
+        FirmwareSource, HistorySink, ShadowStore,
+    };
+    use serde_json::json;
+    use std::{env, fs, net::TcpListener, sync::Arc};
+
+    #[test]
+    fn parse_file_name() {
+        assert_eq!(
+            parse_fw_file_name("1987-0.2.10.bin"),
+            Some((0x1987, 0, 2, 10))
+        );
+        assert_eq!(parse_fw_file_name("AB-1.0.0.bin"), Some((0xAB, 1, 0, 0)));
+        assert_eq!(parse_fw_file_name("1987-0.2.bin"), None);
+        assert_eq!(parse_fw_file_name("1987-0.2.10.bin.bak"), None);
+    }
+
+    #[tokio::test]
+    async fn dir_source_lists_and_reads() {
+        let path = std::env::temp_dir().join(format!("ota-dir-source-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        fs::write(path.join("1987-0.2.0.bin"), [1u8, 2, 3]).unwrap();
+        fs::write(path.join("readme.txt"), "ignored").unwrap();
+
+        let source = DirSource::new(&path);
+        let metas = source.list_firmware().await.unwrap();
+        assert_eq!(metas.len(), 1);
+        assert_eq!(metas[0].fwcode, 0x1987);
+        assert_eq!(metas[0].fwsize, 3);
+
+        let data = source.read_firmware(&metas[0]).await.unwrap();
+        assert_eq!(data, vec![1, 2, 3]);
+
+        // 没有配置文件时返回空列表
+        assert!(source.list_configs().await.unwrap().is_empty());
+
+        source
+            .push_history(&NewUpgradeHistory::default())
+            .await
+            .unwrap();
+        source
+            .push_history(&NewUpgradeHistory::default())
+            .await
+            .unwrap();
+        let history = fs::read_to_string(path.join(HISTORY_FILE)).unwrap();
+        assert_eq!(history.lines().count(), 2);
+
+        fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn dir_shadow_concurrent_reports() {
+        let path = std::env::temp_dir().join(format!("ota-dir-shadow-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        let source = Arc::new(DirSource::new(&path));
+
+        // 同一设备并发上报，不会读到写了一半的文件
+        let tasks: Vec<_> = (0..200)
+            .map(|i| {
+                let source = source.clone();
+                tokio::spawn(async move {
+                    let reported = ReportedConfig {
+                        schema_id: 1,
+                        reported: json!({ "interval": i }),
+                    };
+                    source.report_shadow("c0ffee00", &reported).await
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap().unwrap();
+        }
+
+        let content = fs::read(path.join(SHADOW_DIR).join("C0FFEE00.json")).unwrap();
+        let shadow: DeviceShadow = serde_json::from_slice(&content).unwrap();
+        assert_eq!(shadow.reported_schema_id, Some(1));
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a postgres database in DATABASE_URL"]
+    async fn pg_and_http_serve_same_bytes() {
+        let db_url = env::var("DATABASE_URL").expect("DATABASE_URL");
+        let pg = PgBackend::connect(&db_url).await.unwrap();
+        let pool = &pg.database().pool;
+
+        // 内容恰好是合法的 base64，两个后端都应原样返回
+        let image = b"QUJDREVG".to_vec();
+        let row = <FirmwareData as CrudOperations<
+            FirmwareData,
+            NewFirmwareData,
+            UpdateFirmwareData,
+        >>::create(
+            NewFirmwareData {
+                fwcode: 0x7E57,
+                version_m: 0,
+                version_n: 0,
+                version_l: std::process::id() as i32,
+                fwsize: image.len() as i32,
+                fwdata: image.clone(),
+            },
+            pool,
+        )
+        .await
+        .unwrap();
+
+        // 后端 REST API，HttpBackend 通过它读取同一行
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let db = web::Data::new(pg.database().clone());
+        std::thread::spawn(move || {
+            actix_rt::System::new().block_on(async move {
+                HttpServer::new(move || App::new().app_data(db.clone()).service(apis()))
+                    .listen(listener)
+                    .unwrap()
+                    .run()
+                    .await
+            })
+        });
+        let http = HttpBackend::new(&format!("http://{}", addr));
+
+        let meta = pg
+            .list_firmware()
+            .await
+            .unwrap()
+            .into_iter()
+            .find(|meta| meta.id == row.id)
+            .unwrap();
+        let from_pg = pg.read_firmware(&meta).await;
+        let from_http = http.read_firmware(&meta).await;
+
+        <FirmwareData as CrudOperations<FirmwareData, NewFirmwareData, UpdateFirmwareData>>::delete(
+            row.id, pool,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(from_pg.unwrap(), image);
+        assert_eq!(from_http.unwrap(), image);
+        assert_eq!(meta.fwsize as usize, image.len());
+    }
+}
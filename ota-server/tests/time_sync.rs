@@ -0,0 +1,46 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+    use crate::common::request;
+    use chrono::NaiveDate;
+    use ota_server::{
+        package::common::{datetime_to_vec, parse_time_sync},
+        session::DeviceSession,
+        ProtocolVersion,
+    };
+
+    #[test]
+    fn datetime_year_encoding() {
+        let datetime = NaiveDate::from_ymd_opt(2024, 3, 5)
+            .unwrap()
+            .and_hms_opt(14, 30, 9)
+            .unwrap();
+
+        // 旧设备依赖年份高字节
+        assert_eq!(
+            datetime_to_vec(datetime, ProtocolVersion::V1),
+            vec![0x07, 3, 5, 14, 30, 9]
+        );
+        assert_eq!(
+            datetime_to_vec(datetime, ProtocolVersion::V2),
+            vec![24, 3, 5, 14, 30, 9]
+        );
+    }
+
+    #[test]
+    fn parse_time_sync_version() {
+        assert_eq!(parse_time_sync(&request(0xA8, &[])), None);
+        assert_eq!(
+            parse_time_sync(&request(0xA8, &[2])),
+            Some(ProtocolVersion::V2)
+        );
+        assert_eq!(parse_time_sync(&request(0xA8, &[9])), None);
+
+        assert_eq!(
+            DeviceSession::new(ProtocolVersion::V2).protocol,
+            ProtocolVersion::V2
+        );
+        assert_eq!(DeviceSession::default().protocol, ProtocolVersion::V1);
+    }
+}
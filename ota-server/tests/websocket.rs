@@ -0,0 +1,119 @@
+#[cfg(test)]
+mod tests {
+    use futures_util::{SinkExt, StreamExt};
+    use ota_server::{server::OtaServer, source::dir::DirSource, ErrorCode};
+    use ota_simulator::conformance::{error_frame, frame};
+    use std::{fs, io, sync::Arc, time::Duration};
+    use tokio::{net::TcpStream, task::JoinHandle, time::timeout};
+    use tokio_tungstenite::{connect_async, tungstenite::Message, MaybeTlsStream, WebSocketStream};
+
+    type Client = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+    /// 在本地目录上启动 WebSocket 服务，目录中放一个 100 字节的固件
+    async fn start_server(name: &str) -> (String, OtaServer, JoinHandle<io::Result<()>>) {
+        let path = std::env::temp_dir().join(format!("ota-ws-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        fs::write(path.join("1987-0.3.0.bin"), [0x5Au8; 100]).unwrap();
+
+        let server = OtaServer::builder()
+            .bind("127.0.0.1:0")
+            .websocket("127.0.0.1:0")
+            .backend(Arc::new(DirSource::new(&path)))
+            .drain_timeout(Duration::from_secs(2))
+            .build()
+            .await
+            .unwrap();
+        let running = tokio::spawn({
+            let server = server.clone();
+            async move { server.run().await }
+        });
+
+        let url = format!("ws://{}/ota", server.ws_local_addr().unwrap());
+        (url, server, running)
+    }
+
+    async fn next_message(client: &mut Client) -> Option<Message> {
+        timeout(Duration::from_secs(2), client.next())
+            .await
+            .expect("No message from the server")
+            .map(|message| message.unwrap())
+    }
+
+    #[tokio::test]
+    async fn frames_over_websocket() {
+        let (url, _server, _running) = start_server("frames").await;
+        let (mut client, _) = connect_async(&url).await.unwrap();
+
+        // 固件查询，一帧一个二进制消息
+        client
+            .send(Message::binary(frame(0xA1, &[0x19, 0x87])))
+            .await
+            .unwrap();
+        assert_eq!(
+            next_message(&mut client).await,
+            Some(Message::binary(frame(
+                0x5E,
+                &[0x19, 0x87, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x64]
+            )))
+        );
+
+        // 下载第一片
+        client
+            .send(Message::binary(frame(
+                0xA2,
+                &[0x19, 0x87, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x40],
+            )))
+            .await
+            .unwrap();
+        let mut payload = vec![0x19, 0x87, 0x00, 0x03, 0x00, 0x00, 0x00];
+        payload.extend_from_slice(&[0x5A; 64]);
+        assert_eq!(
+            next_message(&mut client).await,
+            Some(Message::binary(frame(0x5D, &payload)))
+        );
+
+        // 文本消息忽略，Ping 自动回复
+        client.send(Message::text("hello")).await.unwrap();
+        client.send(Message::Ping(vec![1, 2].into())).await.unwrap();
+        assert_eq!(
+            next_message(&mut client).await,
+            Some(Message::Pong(vec![1, 2].into()))
+        );
+
+        // 错误的帧同样回复错误码
+        let mut bad_crc = frame(0xA8, &[]);
+        *bad_crc.last_mut().unwrap() ^= 0xFF;
+        client.send(Message::binary(bad_crc)).await.unwrap();
+        assert_eq!(
+            next_message(&mut client).await,
+            Some(Message::binary(error_frame(ErrorCode::CrcError)))
+        );
+    }
+
+    #[tokio::test]
+    async fn close_on_shutdown() {
+        let (url, server, running) = start_server("shutdown").await;
+        let (mut client, _) = connect_async(&url).await.unwrap();
+        client
+            .send(Message::binary(frame(0xA8, &[])))
+            .await
+            .unwrap();
+        assert!(matches!(
+            next_message(&mut client).await,
+            Some(Message::Binary(_))
+        ));
+
+        // 退出时连接发送关闭帧，服务排空后结束
+        server.shutdown();
+        assert!(matches!(
+            next_message(&mut client).await,
+            Some(Message::Close(_))
+        ));
+        timeout(Duration::from_secs(5), running)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+    }
+}
@@ -0,0 +1,173 @@
+use clap::{Parser, ValueEnum};
+
+#[derive(Parser, Debug, PartialEq, Clone)]
+#[clap(author, version, about)]
+/// Virtual device fleet for load testing ota-server
+pub struct Cli {
+    /// ota-server address
+    #[clap(long, default_value = "127.0.0.1:9999")]
+    pub server: String,
+
+    /// Number of virtual devices
+    #[clap(long, default_value = "100")]
+    pub devices: u32,
+
+    /// Seconds over which the devices are started
+    #[clap(long, default_value = "10")]
+    pub ramp_up: u64,
+
+    /// Upgrade flows run by each device
+    #[clap(long, default_value = "1")]
+    pub rounds: u32,
+
+    /// Firmware code (hex)
+    #[clap(long, value_parser = parse_hex_u16, default_value = "1987")]
+    pub fw_code: u16,
+
+    /// Slice size requested in each FirmwareDownload
+    #[clap(long, default_value = "512")]
+    pub slice_size: u16,
+
+    /// Think time between packets in milliseconds
+    #[clap(long, default_value = "0")]
+    pub think_time: u64,
+
+    /// Random extra think time in milliseconds
+    #[clap(long, default_value = "0")]
+    pub think_jitter: u64,
+
+    /// Probability that a response is lost and the request repeated after the timeout
+    #[clap(long, default_value = "0")]
+    pub loss: f64,
+
+    /// Probability that the connection drops before a packet
+    #[clap(long, default_value = "0")]
+    pub disconnect: f64,
+
+    /// Response timeout in milliseconds
+    #[clap(long, default_value = "3000")]
+    pub timeout: u64,
+
+    /// Attempts per packet before the device gives up the flow
+    #[clap(long, default_value = "5")]
+    pub retries: u32,
+
+    /// First device ID (hex), following devices count up
+    #[clap(long, value_parser = parse_hex_u64, default_value = "10000000")]
+    pub device_id_base: u64,
+
+    /// Group ID sent with QueryConfig
+    #[clap(long)]
+    pub group_id: Option<u32>,
+
+    /// Random seed, each device derives its own generator from it
+    #[clap(long, default_value = "0")]
+    pub seed: u64,
+}
+
+fn parse_hex_u16(value: &str) -> Result<u16, String> {
+    u16::from_str_radix(value.trim_start_matches("0x"), 16).map_err(|e| e.to_string())
+}
+
+fn parse_hex_u64(value: &str) -> Result<u64, String> {
+    u64::from_str_radix(value.trim_start_matches("0x"), 16).map_err(|e| e.to_string())
+}
+
+#[derive(Parser, Debug, PartialEq, Clone)]
+#[clap(author, version, about)]
+/// Protocol conformance check against a running ota-server
+pub struct ConformanceCli {
+    /// ota-server address
+    #[clap(long, default_value = "127.0.0.1:9999")]
+    pub server: String,
+
+    /// Reference firmware named CODE-M.N.L.bin, must be the latest firmware of its code on the server
+    #[clap(long)]
+    pub firmware: String,
+
+    /// Slice size used by the download cases
+    #[clap(long, default_value = "512")]
+    pub slice_size: u16,
+
+    /// Firmware code (hex) that has no firmware on the server
+    #[clap(long, value_parser = parse_hex_u16, default_value = "FFFF")]
+    pub missing_code: u16,
+
+    /// Device ID (hex) used by the cases, upgrade history is recorded under it
+    #[clap(long, value_parser = parse_hex_u64, default_value = "C0FFEE00")]
+    pub device_id: u64,
+
+    /// Response timeout in milliseconds
+    #[clap(long, default_value = "3000")]
+    pub timeout: u64,
+
+    /// How long to wait for unexpected responses in milliseconds
+    #[clap(long, default_value = "500")]
+    pub silence: u64,
+
+    /// Maximum difference between the server clock and the local clock in seconds
+    #[clap(long, default_value = "60")]
+    pub clock_skew: u64,
+
+    /// Write the report as JSON to this file
+    #[clap(long)]
+    pub json: Option<String>,
+}
+
+/// 解码输出格式
+#[derive(ValueEnum, Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum DecodeFormat {
+    /// One line per frame
+    #[default]
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+#[derive(Parser, Debug, PartialEq, Clone)]
+#[clap(author, version, about)]
+/// Decode OTA protocol frames from a pcap/pcapng capture or a hex dump
+pub struct DecodeCli {
+    /// Capture (pcap/pcapng) or hex text file
+    pub file: String,
+
+    /// Output format
+    #[clap(long, value_enum, default_value_t = DecodeFormat::Text)]
+    pub format: DecodeFormat,
+
+    /// Print the raw bytes of each frame in text output
+    #[clap(long)]
+    pub raw: bool,
+}
+
+#[derive(Parser, Debug, PartialEq, Clone)]
+#[clap(author, version, about)]
+/// Replay a session recorded by ota-server --record and diff the responses
+pub struct ReplayCli {
+    /// Recording file (JSON lines)
+    pub file: String,
+
+    /// ota-server address
+    #[clap(long, default_value = "127.0.0.1:9999")]
+    pub server: String,
+
+    /// Keep the recorded timing between requests
+    #[clap(long)]
+    pub realtime: bool,
+
+    /// Response timeout in milliseconds
+    #[clap(long, default_value = "3000")]
+    pub timeout: u64,
+
+    /// How long to wait for unexpected responses in milliseconds
+    #[clap(long, default_value = "500")]
+    pub silence: u64,
+
+    /// Also compare the timestamp of time sync responses
+    #[clap(long)]
+    pub strict_time: bool,
+
+    /// Write the report as JSON to this file
+    #[clap(long)]
+    pub json: Option<String>,
+}
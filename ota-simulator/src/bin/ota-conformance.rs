@@ -0,0 +1,22 @@
+use clap::Parser;
+use ota_simulator::{args::ConformanceCli, conformance::Spec};
+use std::{error::Error, fs, process::ExitCode};
+
+#[tokio::main]
+async fn main() -> Result<ExitCode, Box<dyn Error>> {
+    let cli = ConformanceCli::parse();
+
+    let spec = Spec::from_cli(&cli)?;
+    let report = spec.run().await;
+    println!("{}", report);
+
+    if let Some(path) = &cli.json {
+        fs::write(path, serde_json::to_string_pretty(&report)?)?;
+    }
+
+    // 有失败的用例时返回非0，用于发布检查
+    Ok(match report.failed() {
+        0 => ExitCode::SUCCESS,
+        _ => ExitCode::FAILURE,
+    })
+}
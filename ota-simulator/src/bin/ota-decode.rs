@@ -0,0 +1,71 @@
+use clap::Parser;
+use ota_simulator::{
+    args::{DecodeCli, DecodeFormat},
+    trace::{
+        decode,
+        frame::{DecodedFrame, FrameStatus},
+    },
+};
+use std::{
+    error::Error,
+    fs,
+    io::{self, Write},
+};
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let cli = DecodeCli::parse();
+
+    let data = fs::read(&cli.file)?;
+    let frames = decode(&data)?;
+
+    // 输出到 head 等命令时管道提前关闭不算错误
+    match print(&frames, &cli) {
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => Ok(result?),
+    }
+}
+
+fn print(frames: &[DecodedFrame], cli: &DecodeCli) -> io::Result<()> {
+    let mut out = io::stdout().lock();
+    for frame in frames {
+        match cli.format {
+            DecodeFormat::Text => writeln!(out, "{}", text_line(frame, cli.raw))?,
+            DecodeFormat::Json => writeln!(out, "{}", serde_json::to_string(frame)?)?,
+        }
+    }
+
+    if cli.format == DecodeFormat::Text {
+        let failed = frames
+            .iter()
+            .filter(|frame| frame.status != FrameStatus::Ok)
+            .count();
+        writeln!(out, "{} frames, {} with errors", frames.len(), failed)?;
+    }
+
+    Ok(())
+}
+
+fn text_line(frame: &DecodedFrame, raw: bool) -> String {
+    let mut line = String::new();
+    if let Some(time) = &frame.time {
+        line.push_str(time);
+        line.push(' ');
+    }
+    line.push_str(&format!(
+        "{} @{} {}",
+        frame.stream, frame.offset, frame.name
+    ));
+    for (name, value) in &frame.fields {
+        line.push_str(&format!(" {}={}", name, value));
+    }
+    match frame.status {
+        FrameStatus::Ok => {}
+        FrameStatus::CrcError => line.push_str(" [CRC ERROR]"),
+        FrameStatus::Truncated => line.push_str(" [TRUNCATED]"),
+        FrameStatus::Garbage => line.push_str(" [NO HEADER]"),
+    }
+    if raw || frame.status != FrameStatus::Ok {
+        line.push_str(&format!("\n    {}", frame.raw));
+    }
+    line
+}
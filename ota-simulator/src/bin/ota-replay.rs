@@ -0,0 +1,26 @@
+use clap::Parser;
+use ota_server::record::read_recording;
+use ota_simulator::{
+    args::ReplayCli,
+    replay::{Recording, Replay},
+};
+use std::{error::Error, fs, path::Path, process::ExitCode};
+
+#[tokio::main]
+async fn main() -> Result<ExitCode, Box<dyn Error>> {
+    let cli = ReplayCli::parse();
+
+    let recording = Recording::from_lines(&read_recording(Path::new(&cli.file)).await?);
+    let report = Replay::from(&cli).run(&recording).await;
+    println!("{}", report);
+
+    if let Some(path) = &cli.json {
+        fs::write(path, serde_json::to_string_pretty(&report)?)?;
+    }
+
+    // 回复与录制不一致时返回非0
+    Ok(match report.is_success() {
+        true => ExitCode::SUCCESS,
+        false => ExitCode::FAILURE,
+    })
+}
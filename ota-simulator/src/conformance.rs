@@ -0,0 +1,591 @@
+use chrono::Utc;
+use crc::{Crc, CRC_8_MAXIM_DOW};
+use ota_database::models::firmware_data::{FirmwareInfo, FirmwareVersion};
+use ota_server::{
+    package::rx_package::{
+        gen_config_query_package, gen_download_end_package, gen_fw_query_data_package,
+        gen_fw_query_info_package,
+    },
+    source::dir::parse_fw_file_name,
+    ErrorCode, PackageType,
+};
+use serde::Serialize;
+use std::{fmt, io, path::Path, time::Duration};
+use tokio::{io::AsyncWriteExt, net::TcpStream, time::timeout};
+
+use crate::{args::ConformanceCli, device::read_raw_frame};
+
+const CRC_8: Crc<u8> = Crc::<u8>::new(&CRC_8_MAXIM_DOW);
+
+/// 用例结果，成功时为说明，失败时为原因
+pub type CaseOutcome = Result<String, String>;
+
+/// 参考固件，服务器上同代号的最新固件必须与之相同
+#[derive(Debug, Clone)]
+pub struct Firmware {
+    pub code: u16,
+    pub version: [u8; 3],
+    pub data: Vec<u8>,
+}
+
+impl Firmware {
+    /// 读取 `CODE-M.N.L.bin` 文件
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Firmware> {
+        let path = path.as_ref();
+        let file_name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("");
+        let (code, m, n, l) = parse_fw_file_name(file_name).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is not named CODE-M.N.L.bin", path.display()),
+            )
+        })?;
+
+        Ok(Firmware {
+            code: code as u16,
+            version: [m as u8, n as u8, l as u8],
+            data: std::fs::read(path)?,
+        })
+    }
+
+    fn info(&self) -> FirmwareInfo {
+        FirmwareInfo {
+            code: self.code as i32,
+            version: FirmwareVersion {
+                m: self.version[0] as i32,
+                n: self.version[1] as i32,
+                l: self.version[2] as i32,
+            },
+            size: self.data.len() as i32,
+            path: String::new(),
+        }
+    }
+}
+
+/// CRC-8/MAXIM
+pub fn crc8(data: &[u8]) -> u8 {
+    CRC_8.checksum(data)
+}
+
+/// 按协议规范生成的帧：AA 55 类型 长度(2) 数据 CRC
+pub fn frame(package_type: u8, payload: &[u8]) -> Vec<u8> {
+    let mut data = vec![0xAA, 0x55, package_type];
+    data.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+    data.extend_from_slice(payload);
+    data.push(crc8(&data));
+    data
+}
+
+/// 按协议规范生成的错误帧：AA 55 错误码 CRC
+pub fn error_frame(code: ErrorCode) -> Vec<u8> {
+    let mut data = vec![0xAA, 0x55, code as u8];
+    data.push(crc8(&data));
+    data
+}
+
+/// 16进制显示
+pub fn hex(data: &[u8]) -> String {
+    data.iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// 逐字节比较，不一致时给出第一个不同的位置
+pub fn compare(expected: &[u8], actual: &[u8]) -> Result<(), String> {
+    if expected == actual {
+        return Ok(());
+    }
+
+    const CONTEXT: usize = 16;
+    let offset = expected
+        .iter()
+        .zip(actual)
+        .position(|(expected, actual)| expected != actual)
+        .unwrap_or(expected.len().min(actual.len()));
+    let window =
+        |data: &[u8]| hex(&data[offset.min(data.len())..(offset + CONTEXT).min(data.len())]);
+
+    Err(format!(
+        "{} bytes expected, {} received, first difference at byte {}: expected [{}], got [{}]",
+        expected.len(),
+        actual.len(),
+        offset,
+        window(expected),
+        window(actual)
+    ))
+}
+
+/// 一个用例的结果
+#[derive(Debug, Clone, Serialize)]
+pub struct CaseResult {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub passed: bool,
+    pub detail: String,
+}
+
+/// 一致性测试报告，有失败的用例时不能发布
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    pub server: String,
+    pub cases: Vec<CaseResult>,
+}
+
+impl Report {
+    fn push(&mut self, name: &'static str, description: &'static str, outcome: CaseOutcome) {
+        let (passed, detail) = match outcome {
+            Ok(detail) => (true, detail),
+            Err(detail) => (false, detail),
+        };
+        self.cases.push(CaseResult {
+            name,
+            description,
+            passed,
+            detail,
+        });
+    }
+
+    pub fn passed(&self) -> usize {
+        self.cases.iter().filter(|case| case.passed).count()
+    }
+
+    pub fn failed(&self) -> usize {
+        self.cases.len() - self.passed()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Conformance report for {}", self.server)?;
+        for case in &self.cases {
+            let status = if case.passed { "PASS" } else { "FAIL" };
+            writeln!(f, "[{}] {:<28} {}", status, case.name, case.description)?;
+            if !case.detail.is_empty() {
+                writeln!(f, "       {}", case.detail)?;
+            }
+        }
+        write!(
+            f,
+            "{} passed, {} failed, {} total",
+            self.passed(),
+            self.failed(),
+            self.cases.len()
+        )
+    }
+}
+
+/// 一致性测试，每个用例使用单独的连接
+#[derive(Debug, Clone)]
+pub struct Spec {
+    pub server: String,
+    pub firmware: Firmware,
+    pub slice_size: u16,
+    /// 服务器上没有固件的代号
+    pub missing_code: u16,
+    pub device_id: u64,
+    pub timeout: Duration,
+    /// 确认没有回复时等待的时间
+    pub silence: Duration,
+    /// 时间同步允许的时钟误差
+    pub clock_skew: Duration,
+}
+
+impl Spec {
+    pub fn from_cli(cli: &ConformanceCli) -> io::Result<Spec> {
+        Ok(Spec {
+            server: cli.server.clone(),
+            firmware: Firmware::load(&cli.firmware)?,
+            slice_size: cli.slice_size.max(1),
+            missing_code: cli.missing_code,
+            device_id: cli.device_id,
+            timeout: Duration::from_millis(cli.timeout),
+            silence: Duration::from_millis(cli.silence),
+            clock_skew: Duration::from_secs(cli.clock_skew),
+        })
+    }
+
+    /// 依次执行所有用例
+    pub async fn run(&self) -> Report {
+        let mut report = Report {
+            server: self.server.clone(),
+            cases: Vec::new(),
+        };
+
+        report.push(
+            "firmware_query",
+            "A1 returns the latest firmware info",
+            self.firmware_query().await,
+        );
+        report.push(
+            "firmware_query_missing",
+            "A1 for an unknown code returns F2",
+            self.firmware_query_missing().await,
+        );
+        report.push(
+            "download_first_slice",
+            "A2 returns the first slice",
+            self.download_slice(0).await,
+        );
+        report.push(
+            "download_last_slice",
+            "A2 returns the last, possibly short, slice",
+            self.download_slice(self.slices() - 1).await,
+        );
+        report.push(
+            "download_out_of_range",
+            "A2 past the last slice returns F3",
+            self.download_out_of_range(self.slices(), self.slice_size)
+                .await,
+        );
+        report.push(
+            "download_index_overflow",
+            "A2 with the largest index and slice returns F3",
+            self.download_out_of_range(u16::MAX, u16::MAX).await,
+        );
+        report.push(
+            "download_missing",
+            "A2 for an unknown firmware returns F2",
+            self.download_missing().await,
+        );
+        report.push(
+            "download_end",
+            "A3 gets no response and keeps the connection",
+            self.download_end().await,
+        );
+        report.push(
+            "query_config",
+            "A4 returns a config frame or F2",
+            self.query_config().await,
+        );
+        report.push(
+            "report_config_short",
+            "A5 without device and schema returns F1",
+            self.expect(
+                &frame(PackageType::ReportConfig as u8, &[0x00, 0x01]),
+                &error_frame(ErrorCode::LengthError),
+            )
+            .await,
+        );
+        report.push(
+            "report_config_unknown_schema",
+            "A5 with an unknown schema returns F5",
+            self.report_config_unknown_schema().await,
+        );
+        report.push(
+            "command_poll",
+            "A6 from an unidentified device returns an empty command",
+            self.expect(
+                &frame(PackageType::Command as u8, &[]),
+                &frame(PackageType::Command.to_response(), &[]),
+            )
+            .await,
+        );
+        report.push(
+            "update_available_from_device",
+            "A7 is push only, returns F4",
+            self.expect(
+                &frame(PackageType::UpdateAvailable as u8, &[]),
+                &error_frame(ErrorCode::UnknownPackageType),
+            )
+            .await,
+        );
+        report.push(
+            "time_sync",
+            "A8 returns the server clock",
+            self.time_sync(&[]).await,
+        );
+        report.push(
+            "time_sync_protocol",
+            "A8 with a protocol version returns the server clock",
+            self.time_sync(&[2]).await,
+        );
+        report.push(
+            "unknown_type",
+            "Unknown package type returns F4",
+            self.expect(
+                &frame(0xB0, &[]),
+                &error_frame(ErrorCode::UnknownPackageType),
+            )
+            .await,
+        );
+        report.push("bad_crc", "Wrong CRC returns F0", self.bad_crc().await);
+        report.push(
+            "bad_header",
+            "Header other than AA 55 returns F0",
+            self.bad_header().await,
+        );
+        report.push(
+            "short_frame",
+            "Frame shorter than 6 bytes returns F1",
+            self.expect(
+                &[0xAA, 0x55, PackageType::FirmwareQuery as u8, 0x00, 0x00],
+                &error_frame(ErrorCode::LengthError),
+            )
+            .await,
+        );
+        report.push(
+            "wrong_length",
+            "Length field not matching the payload returns F1",
+            self.wrong_length().await,
+        );
+        report.push(
+            "empty_query",
+            "A1 without a payload returns F1",
+            self.expect(
+                &frame(PackageType::FirmwareQuery as u8, &[]),
+                &error_frame(ErrorCode::LengthError),
+            )
+            .await,
+        );
+        report.push(
+            "empty_download",
+            "A2 without a payload returns F1",
+            self.expect(
+                &frame(PackageType::FirmwareDownload as u8, &[]),
+                &error_frame(ErrorCode::LengthError),
+            )
+            .await,
+        );
+        report.push(
+            "empty_download_end",
+            "A3 without a payload returns F1",
+            self.expect(
+                &frame(PackageType::DownloadEnd as u8, &[]),
+                &error_frame(ErrorCode::LengthError),
+            )
+            .await,
+        );
+        report.push(
+            "truncated_frame",
+            "Frame cut off before its CRC returns F0",
+            self.truncated_frame().await,
+        );
+
+        report
+    }
+
+    fn slices(&self) -> u16 {
+        self.firmware
+            .data
+            .len()
+            .div_ceil(self.slice_size as usize)
+            .max(1) as u16
+    }
+
+    async fn connect(&self) -> Result<TcpStream, String> {
+        let socket = timeout(self.timeout, TcpStream::connect(&self.server))
+            .await
+            .map_err(|_| "Connect timeout".to_string())?
+            .map_err(|e| format!("Connect error: {}", e))?;
+        socket.set_nodelay(true).map_err(|e| e.to_string())?;
+        Ok(socket)
+    }
+
+    /// 发送请求，读取一个回复帧
+    async fn exchange(&self, socket: &mut TcpStream, request: &[u8]) -> Result<Vec<u8>, String> {
+        socket
+            .write_all(request)
+            .await
+            .map_err(|e| format!("Send error: {}", e))?;
+
+        timeout(self.timeout, read_raw_frame(socket))
+            .await
+            .map_err(|_| format!("No response within {:?}", self.timeout))?
+            .map_err(|e| format!("Receive error: {}", e))
+    }
+
+    /// 新连接上发送请求，回复必须与期望的帧完全相同
+    async fn expect(&self, request: &[u8], expected: &[u8]) -> CaseOutcome {
+        let mut socket = self.connect().await?;
+        let response = self.exchange(&mut socket, request).await?;
+        compare(expected, &response)?;
+        Ok(String::new())
+    }
+
+    fn info_request(&self, code: u16) -> Vec<u8> {
+        let mut info = self.firmware.info();
+        info.code = code as i32;
+        gen_fw_query_info_package(&info)
+    }
+
+    async fn firmware_query(&self) -> CaseOutcome {
+        let firmware = &self.firmware;
+        let mut payload = firmware.code.to_be_bytes().to_vec();
+        payload.extend_from_slice(&firmware.version);
+        payload.extend_from_slice(&(firmware.data.len() as u32).to_be_bytes());
+
+        self.expect(
+            &self.info_request(firmware.code),
+            &frame(PackageType::FirmwareQuery.to_response(), &payload),
+        )
+        .await
+    }
+
+    async fn firmware_query_missing(&self) -> CaseOutcome {
+        self.expect(
+            &self.info_request(self.missing_code),
+            &error_frame(ErrorCode::NoFirmwareFound),
+        )
+        .await
+    }
+
+    async fn download_slice(&self, index: u16) -> CaseOutcome {
+        let firmware = &self.firmware;
+        let start = (index as usize * self.slice_size as usize).min(firmware.data.len());
+        let end = (start + self.slice_size as usize).min(firmware.data.len());
+
+        let mut payload = firmware.code.to_be_bytes().to_vec();
+        payload.extend_from_slice(&firmware.version);
+        payload.extend_from_slice(&index.to_be_bytes());
+        payload.extend_from_slice(&firmware.data[start..end]);
+
+        let request = gen_fw_query_data_package(&firmware.info(), index, self.slice_size);
+        self.expect(
+            &request,
+            &frame(PackageType::FirmwareDownload.to_response(), &payload),
+        )
+        .await?;
+        Ok(format!("index {}, {} bytes", index, end - start))
+    }
+
+    async fn download_out_of_range(&self, index: u16, slice: u16) -> CaseOutcome {
+        let request = gen_fw_query_data_package(&self.firmware.info(), index, slice);
+        self.expect(&request, &error_frame(ErrorCode::FirmwareReadError))
+            .await?;
+        Ok(format!("index {}, slice {}", index, slice))
+    }
+
+    async fn download_missing(&self) -> CaseOutcome {
+        let mut info = self.firmware.info();
+        info.code = self.missing_code as i32;
+        let request = gen_fw_query_data_package(&info, 0, self.slice_size);
+        self.expect(&request, &error_frame(ErrorCode::NoFirmwareFound))
+            .await
+    }
+
+    async fn download_end(&self) -> CaseOutcome {
+        let mut socket = self.connect().await?;
+        let request = gen_download_end_package(&self.firmware.info(), self.device_id, 0, false);
+        socket
+            .write_all(&request)
+            .await
+            .map_err(|e| format!("Send error: {}", e))?;
+
+        // 下载结束没有回复
+        if let Ok(response) = timeout(self.silence, read_raw_frame(&mut socket)).await {
+            return Err(match response {
+                Ok(response) => format!("Unexpected response [{}]", hex(&response)),
+                Err(e) => format!("Connection closed: {}", e),
+            });
+        }
+
+        // 连接仍然可用
+        let response = self
+            .exchange(&mut socket, &frame(PackageType::TimeSync as u8, &[]))
+            .await?;
+        match response[2] == PackageType::TimeSync.to_response() {
+            true => Ok(String::new()),
+            false => Err(format!(
+                "Connection unusable after A3, got [{}]",
+                hex(&response)
+            )),
+        }
+    }
+
+    async fn query_config(&self) -> CaseOutcome {
+        let mut socket = self.connect().await?;
+        let request = gen_config_query_package(Some(self.device_id), None);
+        let response = self.exchange(&mut socket, &request).await?;
+
+        // 配置内容取决于服务器数据，只检查帧格式
+        if response[2] == PackageType::QueryConfig.to_response() {
+            let payload = &response[5..response.len() - 1];
+            compare(&frame(response[2], payload), &response)?;
+            return Ok(format!("config frame, {} bytes", payload.len()));
+        }
+        compare(&error_frame(ErrorCode::NoFirmwareFound), &response)?;
+        Ok("no config for device".to_string())
+    }
+
+    async fn report_config_unknown_schema(&self) -> CaseOutcome {
+        let mut payload = self.device_id.to_be_bytes().to_vec();
+        payload.extend_from_slice(&u16::MAX.to_be_bytes());
+        payload.push(0x00);
+
+        self.expect(
+            &frame(PackageType::ReportConfig as u8, &payload),
+            &error_frame(ErrorCode::ConfigError),
+        )
+        .await
+    }
+
+    async fn time_sync(&self, payload: &[u8]) -> CaseOutcome {
+        let mut socket = self.connect().await?;
+        let response = self
+            .exchange(&mut socket, &frame(PackageType::TimeSync as u8, payload))
+            .await?;
+
+        // 数据段：UTC unix 秒(4)，可带时区偏移分钟(2)
+        let data = response.get(5..response.len() - 1).unwrap_or_default();
+        if response[2] != PackageType::TimeSync.to_response() || !matches!(data.len(), 4 | 6) {
+            return Err(format!("Unexpected response [{}]", hex(&response)));
+        }
+        compare(&frame(response[2], data), &response)?;
+
+        let timestamp = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as i64;
+        let skew = (timestamp - Utc::now().timestamp()).unsigned_abs();
+        if skew > self.clock_skew.as_secs() {
+            return Err(format!("Server clock off by {}s", skew));
+        }
+
+        Ok(match data.len() {
+            6 => format!(
+                "skew {}s, tz offset {} min",
+                skew,
+                i16::from_be_bytes([data[4], data[5]])
+            ),
+            _ => format!("skew {}s", skew),
+        })
+    }
+
+    async fn bad_crc(&self) -> CaseOutcome {
+        let mut request = self.info_request(self.firmware.code);
+        *request.last_mut().unwrap() ^= 0xFF;
+        self.expect(&request, &error_frame(ErrorCode::CrcError))
+            .await
+    }
+
+    async fn bad_header(&self) -> CaseOutcome {
+        // CRC 按错误的包头重新计算，只有包头不对
+        let mut request = self.info_request(self.firmware.code);
+        request.pop();
+        request[..2].copy_from_slice(&[0x55, 0xAA]);
+        request.push(crc8(&request));
+        self.expect(&request, &error_frame(ErrorCode::CrcError))
+            .await
+    }
+
+    async fn wrong_length(&self) -> CaseOutcome {
+        // CRC 按修改后的长度字段重新计算，只有长度字段不对
+        let mut request = self.info_request(self.firmware.code);
+        request.pop();
+        request[4] += 1;
+        request.push(crc8(&request));
+        self.expect(&request, &error_frame(ErrorCode::LengthError))
+            .await
+    }
+
+    async fn truncated_frame(&self) -> CaseOutcome {
+        let request = gen_fw_query_data_package(&self.firmware.info(), 0, self.slice_size);
+        let truncated = &request[..9];
+        if crc8(&truncated[..8]) == truncated[8] {
+            return Err("Truncated frame happens to have a valid CRC".to_string());
+        }
+
+        self.expect(truncated, &error_frame(ErrorCode::CrcError))
+            .await
+    }
+}
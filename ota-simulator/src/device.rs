@@ -0,0 +1,367 @@
+use ota_database::models::firmware_data::{FirmwareInfo, FirmwareVersion};
+use ota_server::{
+    package::{
+        common::package_check,
+        rx_package::{
+            gen_config_query_package, gen_download_end_package, gen_fw_query_data_package,
+            gen_fw_query_info_package,
+        },
+    },
+    ErrorCode, PackageType,
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::{sync::Arc, time::Duration};
+use thiserror::Error;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    time::{sleep, sleep_until, timeout_at, Instant},
+};
+use tracing::{debug, warn};
+
+use crate::{args::Cli, stats::DeviceStats};
+
+#[derive(Error, Debug)]
+pub enum SimError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Response timeout")]
+    Timeout,
+    #[error("CRC error in response 0x{0:02X}")]
+    Crc(u8),
+    #[error("Server replied error 0x{0:02X}")]
+    Rejected(u8),
+    #[error("Malformed response 0x{0:02X}")]
+    Protocol(u8),
+    #[error("{0} failed after all retries")]
+    GaveUp(&'static str),
+}
+
+/// 设备的行为参数，所有设备共用
+#[derive(Debug, Clone)]
+pub struct DeviceProfile {
+    pub server: String,
+    pub rounds: u32,
+    pub fw_code: u16,
+    pub slice_size: u16,
+    pub think_time: Duration,
+    pub think_jitter: Duration,
+    /// 响应丢失概率
+    pub loss: f64,
+    /// 发包前断开连接的概率
+    pub disconnect: f64,
+    pub timeout: Duration,
+    /// 每个包最多尝试的次数
+    pub retries: u32,
+    pub group_id: Option<u32>,
+}
+
+impl From<&Cli> for DeviceProfile {
+    fn from(cli: &Cli) -> Self {
+        DeviceProfile {
+            server: cli.server.clone(),
+            rounds: cli.rounds,
+            fw_code: cli.fw_code,
+            slice_size: cli.slice_size.max(1),
+            think_time: Duration::from_millis(cli.think_time),
+            think_jitter: Duration::from_millis(cli.think_jitter),
+            loss: cli.loss.clamp(0.0, 1.0),
+            disconnect: cli.disconnect.clamp(0.0, 1.0),
+            timeout: Duration::from_millis(cli.timeout),
+            retries: cli.retries.max(1),
+            group_id: cli.group_id,
+        }
+    }
+}
+
+/// 一次升级流程的结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Flow {
+    Completed,
+    NoFirmware,
+}
+
+/// 虚拟设备，按 FirmwareQuery → FirmwareDownload → DownloadEnd → QueryConfig 的顺序升级
+pub struct VirtualDevice {
+    id: u64,
+    profile: Arc<DeviceProfile>,
+    rng: StdRng,
+    socket: Option<TcpStream>,
+    stats: DeviceStats,
+}
+
+impl VirtualDevice {
+    pub fn new(id: u64, profile: Arc<DeviceProfile>, seed: u64) -> Self {
+        VirtualDevice {
+            id,
+            profile,
+            rng: StdRng::seed_from_u64(seed ^ id),
+            socket: None,
+            stats: DeviceStats::default(),
+        }
+    }
+
+    /// 执行所有轮次的升级，返回统计
+    pub async fn run(mut self) -> DeviceStats {
+        for round in 0..self.profile.rounds {
+            match self.upgrade(round).await {
+                Ok(Flow::Completed) => self.stats.completed += 1,
+                Ok(Flow::NoFirmware) => self.stats.no_firmware += 1,
+                Err(e) => {
+                    warn!("Device {:08X} round {} failed: {}", self.id, round, e);
+                    self.stats.failed += 1;
+                    self.socket = None;
+                }
+            }
+        }
+
+        self.stats
+    }
+
+    async fn upgrade(&mut self, round: u32) -> Result<Flow, SimError> {
+        let query = FirmwareInfo {
+            code: self.profile.fw_code as i32,
+            version: FirmwareVersion { m: 0, n: 0, l: 0 },
+            size: 0,
+            path: String::new(),
+        };
+
+        // 查询最新固件
+        let request = gen_fw_query_info_package(&query);
+        let response = self
+            .exchange("FirmwareQuery", &request, PackageType::FirmwareQuery, None)
+            .await?;
+        let fw_info = match response[2] {
+            x if x == ErrorCode::NoFirmwareFound as u8 => None,
+            x if is_error(x) => return Err(SimError::Rejected(x)),
+            // 代号(2) + 版本(3) + 大小(4)
+            x if response.len() < 5 + 9 + 1 => return Err(SimError::Protocol(x)),
+            _ => Some(FirmwareInfo {
+                code: self.profile.fw_code as i32,
+                version: FirmwareVersion {
+                    m: response[7] as i32,
+                    n: response[8] as i32,
+                    l: response[9] as i32,
+                },
+                size: i32::from_be_bytes([response[10], response[11], response[12], response[13]]),
+                path: String::new(),
+            }),
+        };
+
+        if let Some(fw_info) = &fw_info {
+            // 按切片下载
+            let slice = self.profile.slice_size;
+            let slices = (fw_info.size as usize).div_ceil(slice as usize);
+            for index in 0..slices as u16 {
+                let request = gen_fw_query_data_package(fw_info, index, slice);
+                let response = self
+                    .exchange(
+                        "FirmwareDownload",
+                        &request,
+                        PackageType::FirmwareDownload,
+                        Some(index),
+                    )
+                    .await?;
+                if is_error(response[2]) {
+                    return Err(SimError::Rejected(response[2]));
+                }
+                self.stats.bytes += (response.len() - 13) as u64;
+            }
+
+            // 下载结束没有回复，之后设备重启，断开连接
+            let request = gen_download_end_package(fw_info, self.id, round, true);
+            self.send("DownloadEnd", &request).await?;
+            self.socket = None;
+        }
+
+        // 重新连接后查询配置
+        let request = gen_config_query_package(Some(self.id), self.profile.group_id);
+        let response = self
+            .exchange("QueryConfig", &request, PackageType::QueryConfig, None)
+            .await?;
+        // 没有对应配置时服务器回复 F2
+        match response[2] {
+            x if x == ErrorCode::NoFirmwareFound as u8 => self.stats.no_config += 1,
+            x if is_error(x) => return Err(SimError::Rejected(x)),
+            _ => {}
+        }
+
+        Ok(match fw_info {
+            Some(_) => Flow::Completed,
+            None => Flow::NoFirmware,
+        })
+    }
+
+    /// 发送请求并等待回复，超时或出错时重新连接再试
+    async fn exchange(
+        &mut self,
+        package: &'static str,
+        request: &[u8],
+        package_type: PackageType,
+        index: Option<u16>,
+    ) -> Result<Vec<u8>, SimError> {
+        for attempt in 0..self.profile.retries {
+            self.before_send().await;
+
+            let start = Instant::now();
+            let deadline = start + self.profile.timeout;
+            match self
+                .try_exchange(request, package_type, index, deadline)
+                .await
+            {
+                Ok(response) => {
+                    // 模拟丢包，等到超时后重发
+                    if self.rng.gen_bool(self.profile.loss) {
+                        self.stats.lost += 1;
+                        sleep_until(deadline).await;
+                        continue;
+                    }
+                    self.stats.record(package, start.elapsed());
+                    return Ok(response);
+                }
+                Err(e) => {
+                    if matches!(e, SimError::Timeout) {
+                        self.stats.timeouts += 1;
+                    }
+                    debug!(
+                        "Device {:08X} {} attempt {} failed: {}",
+                        self.id, package, attempt, e
+                    );
+                    self.socket = None;
+                }
+            }
+        }
+
+        Err(SimError::GaveUp(package))
+    }
+
+    /// 发送不需要回复的包
+    async fn send(&mut self, package: &'static str, request: &[u8]) -> Result<(), SimError> {
+        for attempt in 0..self.profile.retries {
+            self.before_send().await;
+
+            let result = match self.connect().await {
+                Ok(socket) => socket.write_all(request).await.map_err(SimError::from),
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(()) => return Ok(()),
+                Err(e) => {
+                    debug!(
+                        "Device {:08X} {} attempt {} failed: {}",
+                        self.id, package, attempt, e
+                    );
+                    self.socket = None;
+                }
+            }
+        }
+
+        Err(SimError::GaveUp(package))
+    }
+
+    /// 思考时间，并按概率断开连接
+    async fn before_send(&mut self) {
+        let jitter = self.profile.think_jitter.as_millis() as u64;
+        let think = match jitter {
+            0 => self.profile.think_time,
+            _ => self.profile.think_time + Duration::from_millis(self.rng.gen_range(0..=jitter)),
+        };
+        if !think.is_zero() {
+            sleep(think).await;
+        }
+
+        if self.socket.is_some() && self.rng.gen_bool(self.profile.disconnect) {
+            self.stats.disconnects += 1;
+            self.socket = None;
+        }
+    }
+
+    async fn connect(&mut self) -> Result<&mut TcpStream, SimError> {
+        if self.socket.is_none() {
+            let socket = TcpStream::connect(&self.profile.server).await?;
+            socket.set_nodelay(true)?;
+            self.stats.connects += 1;
+            self.socket = Some(socket);
+        }
+
+        Ok(self.socket.as_mut().unwrap())
+    }
+
+    async fn try_exchange(
+        &mut self,
+        request: &[u8],
+        package_type: PackageType,
+        index: Option<u16>,
+        deadline: Instant,
+    ) -> Result<Vec<u8>, SimError> {
+        let socket = self.connect().await?;
+        socket.write_all(request).await?;
+
+        loop {
+            let frame = timeout_at(deadline, read_frame(socket))
+                .await
+                .map_err(|_| SimError::Timeout)??;
+
+            if is_error(frame[2]) {
+                return Ok(frame);
+            }
+
+            // 跳过服务器顺带下发的命令和推送
+            if frame[2] != package_type.to_response() {
+                continue;
+            }
+            match index {
+                None => return Ok(frame),
+                Some(index) => match slice_index(&frame) {
+                    Some(got) if got == index => return Ok(frame),
+                    Some(_) => {}
+                    None => return Err(SimError::Protocol(frame[2])),
+                },
+            }
+        }
+    }
+}
+
+/// 分片回复中的分片号：代号(2) + 版本(3) + 分片号(2) + 数据，长度不足时返回 None
+fn slice_index(frame: &[u8]) -> Option<u16> {
+    match frame.get(10..12) {
+        Some(index) if frame.len() > 12 => Some(u16::from_be_bytes([index[0], index[1]])),
+        _ => None,
+    }
+}
+
+fn is_error(package_type: u8) -> bool {
+    ErrorCode::from_u8(package_type).is_some()
+}
+
+/// 读取一个完整的帧并检查CRC
+pub async fn read_frame(socket: &mut TcpStream) -> Result<Vec<u8>, SimError> {
+    let frame = read_raw_frame(socket).await?;
+
+    match package_check(&frame, frame.len()) {
+        true => Ok(frame),
+        false => Err(SimError::Crc(frame[2])),
+    }
+}
+
+/// 按长度读取一个完整的帧，不检查包头和CRC
+/// - 错误帧：AA 55 错误码 CRC
+/// - 其它帧：AA 55 类型 长度(2) 数据 CRC
+pub async fn read_raw_frame(socket: &mut TcpStream) -> std::io::Result<Vec<u8>> {
+    let mut frame = vec![0u8; 3];
+    socket.read_exact(&mut frame).await?;
+
+    if !is_error(frame[2]) {
+        let mut len = [0u8; 2];
+        socket.read_exact(&mut len).await?;
+        frame.extend_from_slice(&len);
+        frame.resize(5 + u16::from_be_bytes(len) as usize, 0);
+        socket.read_exact(&mut frame[5..]).await?;
+    }
+
+    let mut crc = [0u8; 1];
+    socket.read_exact(&mut crc).await?;
+    frame.push(crc[0]);
+
+    Ok(frame)
+}
@@ -0,0 +1,76 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::{task::JoinSet, time::Instant};
+use tracing::error;
+
+use crate::{
+    args::Cli,
+    device::{DeviceProfile, VirtualDevice},
+    stats::{DeviceStats, FleetReport},
+};
+
+/// 设备群参数
+#[derive(Debug, Clone)]
+pub struct Fleet {
+    pub devices: u32,
+    /// 在这段时间内逐个启动设备
+    pub ramp_up: Duration,
+    pub device_id_base: u64,
+    pub seed: u64,
+    /// 已完成的设备数，用于显示进度
+    pub finished: Arc<AtomicUsize>,
+}
+
+impl From<&Cli> for Fleet {
+    fn from(cli: &Cli) -> Self {
+        Fleet {
+            devices: cli.devices,
+            ramp_up: Duration::from_secs(cli.ramp_up),
+            device_id_base: cli.device_id_base,
+            seed: cli.seed,
+            finished: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+}
+
+impl Fleet {
+    /// 启动所有设备，等待全部完成后汇总
+    pub async fn run(&self, profile: DeviceProfile) -> FleetReport {
+        let profile = Arc::new(profile);
+        let start = Instant::now();
+        let interval = self.ramp_up / self.devices.max(1);
+
+        let mut tasks = JoinSet::new();
+        for n in 0..self.devices {
+            let device =
+                VirtualDevice::new(self.device_id_base + n as u64, profile.clone(), self.seed);
+            let finished = self.finished.clone();
+            let launch = start + interval * n;
+            tasks.spawn(async move {
+                tokio::time::sleep_until(launch).await;
+                let stats = device.run().await;
+                finished.fetch_add(1, Ordering::Relaxed);
+                stats
+            });
+        }
+
+        let mut stats = DeviceStats::default();
+        while let Some(res) = tasks.join_next().await {
+            match res {
+                Ok(device_stats) => stats.merge(device_stats),
+                Err(e) => error!("Device task error: {}", e),
+            }
+        }
+
+        FleetReport {
+            devices: self.devices,
+            elapsed: start.elapsed(),
+            stats,
+        }
+    }
+}
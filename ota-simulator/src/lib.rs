@@ -0,0 +1,7 @@
+pub mod args;
+pub mod conformance;
+pub mod device;
+pub mod fleet;
+pub mod replay;
+pub mod stats;
+pub mod trace;
@@ -0,0 +1,50 @@
+use clap::Parser;
+use ota_simulator::{args::Cli, device::DeviceProfile, fleet::Fleet};
+use ota_telemetry::{init_tracing, LogFormat, TelemetryOptions};
+use std::{error::Error, sync::atomic::Ordering, time::Duration};
+use tracing::info;
+
+/// 进度输出间隔
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(5);
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let cli = Cli::parse();
+
+    let telemetry = init_tracing(&TelemetryOptions {
+        service: "ota-simulator",
+        filter_env: "RUST_LOG",
+        default_filter: "info",
+        format: LogFormat::Text,
+        otlp_endpoint: None,
+    })?;
+
+    info!(
+        "Simulating {} devices against {}, ramp-up {}s, {} rounds",
+        cli.devices, cli.server, cli.ramp_up, cli.rounds
+    );
+
+    let fleet = Fleet::from(&cli);
+    let finished = fleet.finished.clone();
+    let devices = cli.devices;
+    let progress = tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(PROGRESS_INTERVAL);
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            info!(
+                "Progress: {}/{} devices finished",
+                finished.load(Ordering::Relaxed),
+                devices
+            );
+        }
+    });
+
+    let report = fleet.run(DeviceProfile::from(&cli)).await;
+    progress.abort();
+
+    println!("{}", report);
+    telemetry.shutdown();
+
+    Ok(())
+}
@@ -0,0 +1,305 @@
+use ota_server::{
+    record::{RecordDirection, RecordLine},
+    PackageType,
+};
+use serde::Serialize;
+use std::{fmt, time::Duration};
+use tokio::{
+    io::AsyncWriteExt,
+    net::TcpStream,
+    time::{sleep_until, timeout, Instant},
+};
+
+use crate::{
+    args::ReplayCli,
+    conformance::{compare, hex},
+    device::read_raw_frame,
+    trace::frame::{split_frames, DecodedFrame},
+};
+
+/// 录制中的一次请求和服务器当时的回复
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Exchange {
+    /// 相对会话开始的时间
+    pub elapsed_us: u64,
+    /// 一次读取到的数据，可能不止一帧
+    pub request: Vec<u8>,
+    pub responses: Vec<Vec<u8>>,
+}
+
+/// 录制的会话
+#[derive(Debug, Clone, Default)]
+pub struct Recording {
+    pub peer: Option<String>,
+    pub device_id: Option<String>,
+    pub exchanges: Vec<Exchange>,
+}
+
+impl Recording {
+    /// 按请求分组，请求之后、下一个请求之前发出的帧都是它的回复
+    /// 新固件推送由服务器主动发出，回放时不会重现，不参与比较
+    pub fn from_lines(lines: &[RecordLine]) -> Recording {
+        let mut recording = Recording::default();
+
+        for line in lines {
+            match line {
+                RecordLine::Session {
+                    peer, device_id, ..
+                } => {
+                    recording.peer = Some(peer.clone());
+                    recording.device_id = device_id.clone();
+                }
+                RecordLine::Identified { device_id, .. } => {
+                    recording.device_id.get_or_insert_with(|| device_id.clone());
+                }
+                RecordLine::Frame { elapsed_us, .. } => match line.frame() {
+                    Some((RecordDirection::In, request)) => recording.exchanges.push(Exchange {
+                        elapsed_us: *elapsed_us,
+                        request,
+                        responses: Vec::new(),
+                    }),
+                    Some((RecordDirection::Out, response)) if !is_push(&response) => {
+                        if let Some(exchange) = recording.exchanges.last_mut() {
+                            exchange.responses.push(response);
+                        }
+                    }
+                    _ => {}
+                },
+            }
+        }
+
+        recording
+    }
+}
+
+fn is_push(frame: &[u8]) -> bool {
+    frame.get(2) == Some(&PackageType::UpdateAvailable.to_response())
+}
+
+/// 时间同步回复中的时间戳（和 CRC）取实际值，只比较其它字节
+fn mask_time(expected: &[u8], actual: &[u8]) -> Vec<u8> {
+    let time_sync = PackageType::TimeSync.to_response();
+    let mut masked = expected.to_vec();
+    if expected.len() == actual.len()
+        && expected.len() >= 10
+        && expected[2] == time_sync
+        && actual[2] == time_sync
+    {
+        masked[5..9].copy_from_slice(&actual[5..9]);
+        let crc = masked.len() - 1;
+        masked[crc] = actual[crc];
+    }
+    masked
+}
+
+/// 一次请求的回放结果
+#[derive(Debug, Clone, Serialize)]
+pub struct ExchangeResult {
+    pub index: usize,
+    pub elapsed_us: u64,
+    pub request: String,
+    pub passed: bool,
+    pub detail: String,
+}
+
+/// 回放报告
+#[derive(Debug, Clone, Serialize)]
+pub struct ReplayReport {
+    pub server: String,
+    pub device_id: Option<String>,
+    pub exchanges: Vec<ExchangeResult>,
+    /// 连接失败等导致回放中断
+    pub aborted: Option<String>,
+}
+
+impl ReplayReport {
+    pub fn passed(&self) -> usize {
+        self.exchanges
+            .iter()
+            .filter(|exchange| exchange.passed)
+            .count()
+    }
+
+    pub fn failed(&self) -> usize {
+        self.exchanges.len() - self.passed()
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.failed() == 0 && self.aborted.is_none()
+    }
+}
+
+impl fmt::Display for ReplayReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Replay of {} against {}",
+            self.device_id.as_deref().unwrap_or("unidentified device"),
+            self.server
+        )?;
+        for exchange in &self.exchanges {
+            let status = if exchange.passed { "PASS" } else { "FAIL" };
+            writeln!(
+                f,
+                "[{}] #{:<5} {:>10.3}s {}",
+                status,
+                exchange.index,
+                exchange.elapsed_us as f64 / 1e6,
+                exchange.request
+            )?;
+            if !exchange.detail.is_empty() {
+                writeln!(f, "       {}", exchange.detail)?;
+            }
+        }
+        if let Some(reason) = &self.aborted {
+            writeln!(f, "Aborted: {}", reason)?;
+        }
+        write!(
+            f,
+            "{} passed, {} failed, {} total",
+            self.passed(),
+            self.failed(),
+            self.exchanges.len()
+        )
+    }
+}
+
+/// 回放参数
+#[derive(Debug, Clone)]
+pub struct Replay {
+    pub server: String,
+    pub timeout: Duration,
+    /// 录制中没有回复时，确认服务器也不回复的等待时间
+    pub silence: Duration,
+    /// 按录制的时间间隔发送请求
+    pub realtime: bool,
+    /// 比较时间同步回复中的时间戳
+    pub strict_time: bool,
+}
+
+impl From<&ReplayCli> for Replay {
+    fn from(cli: &ReplayCli) -> Self {
+        Replay {
+            server: cli.server.clone(),
+            timeout: Duration::from_millis(cli.timeout),
+            silence: Duration::from_millis(cli.silence),
+            realtime: cli.realtime,
+            strict_time: cli.strict_time,
+        }
+    }
+}
+
+impl Replay {
+    /// 在一个连接上按顺序发送录制的请求，逐帧比较回复
+    pub async fn run(&self, recording: &Recording) -> ReplayReport {
+        let mut report = ReplayReport {
+            server: self.server.clone(),
+            device_id: recording.device_id.clone(),
+            exchanges: Vec::new(),
+            aborted: None,
+        };
+
+        let mut socket = match timeout(self.timeout, TcpStream::connect(&self.server)).await {
+            Ok(Ok(socket)) => socket,
+            Ok(Err(e)) => {
+                report.aborted = Some(format!("Connect error: {}", e));
+                return report;
+            }
+            Err(_) => {
+                report.aborted = Some("Connect timeout".to_string());
+                return report;
+            }
+        };
+        let _ = socket.set_nodelay(true);
+
+        let start = Instant::now();
+        for (index, exchange) in recording.exchanges.iter().enumerate() {
+            if self.realtime {
+                sleep_until(start + Duration::from_micros(exchange.elapsed_us)).await;
+            }
+
+            let outcome = self.exchange(&mut socket, exchange).await;
+            let aborted = matches!(outcome, Err((true, _)));
+            let (passed, detail) = match outcome {
+                Ok(()) => (true, String::new()),
+                Err((_, detail)) => (false, detail),
+            };
+            report.exchanges.push(ExchangeResult {
+                index,
+                elapsed_us: exchange.elapsed_us,
+                request: describe(&exchange.request),
+                passed,
+                detail,
+            });
+
+            if aborted {
+                report.aborted = Some(format!("Connection lost at request #{}", index));
+                break;
+            }
+        }
+
+        report
+    }
+
+    /// 发送一次请求，错误中的 bool 表示连接已不可用
+    async fn exchange(
+        &self,
+        socket: &mut TcpStream,
+        exchange: &Exchange,
+    ) -> Result<(), (bool, String)> {
+        socket
+            .write_all(&exchange.request)
+            .await
+            .map_err(|e| (true, format!("Send error: {}", e)))?;
+
+        for (index, expected) in exchange.responses.iter().enumerate() {
+            let actual = timeout(self.timeout, read_raw_frame(socket))
+                .await
+                .map_err(|_| {
+                    (
+                        false,
+                        format!(
+                            "Response {} missing, expected [{}]",
+                            index + 1,
+                            hex(expected)
+                        ),
+                    )
+                })?
+                .map_err(|e| (true, format!("Receive error: {}", e)))?;
+
+            let expected = match self.strict_time {
+                true => expected.clone(),
+                false => mask_time(expected, &actual),
+            };
+            compare(&expected, &actual)
+                .map_err(|detail| (false, format!("Response {}: {}", index + 1, detail)))?;
+        }
+
+        // 录制中没有回复（如下载结束），服务器也不应回复
+        if exchange.responses.is_empty() {
+            if let Ok(response) = timeout(self.silence, read_raw_frame(socket)).await {
+                return Err(match response {
+                    Ok(response) => (false, format!("Unexpected response [{}]", hex(&response))),
+                    Err(e) => (true, format!("Connection closed: {}", e)),
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// 请求的帧名，如 `FirmwareDownload index=3`
+fn describe(request: &[u8]) -> String {
+    split_frames(request)
+        .iter()
+        .map(|raw| {
+            let frame = DecodedFrame::decode("", raw, None);
+            match frame.fields.iter().find(|(name, _)| *name == "index") {
+                Some((_, index)) => format!("{} index={}", frame.name, index),
+                None => frame.name,
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
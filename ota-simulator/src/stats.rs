@@ -0,0 +1,181 @@
+use std::{collections::BTreeMap, fmt, time::Duration};
+
+/// 响应耗时样本，单位微秒
+#[derive(Debug, Default, Clone)]
+pub struct Latencies {
+    samples: Vec<u64>,
+    sorted: bool,
+}
+
+impl Latencies {
+    pub fn record(&mut self, elapsed: Duration) {
+        self.samples.push(elapsed.as_micros() as u64);
+        self.sorted = false;
+    }
+
+    pub fn merge(&mut self, other: Latencies) {
+        self.samples.extend(other.samples);
+        self.sorted = false;
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// 百分位（0 ~ 100），取最近秩
+    pub fn percentile(&mut self, percent: f64) -> Duration {
+        if self.samples.is_empty() {
+            return Duration::ZERO;
+        }
+        if !self.sorted {
+            self.samples.sort_unstable();
+            self.sorted = true;
+        }
+
+        let rank = (percent / 100.0 * self.samples.len() as f64).ceil() as usize;
+        let index = rank.clamp(1, self.samples.len()) - 1;
+        Duration::from_micros(self.samples[index])
+    }
+}
+
+/// 单个设备的统计
+#[derive(Debug, Default, Clone)]
+pub struct DeviceStats {
+    /// 按包类型统计的响应耗时
+    pub latencies: BTreeMap<String, Latencies>,
+    /// 完成的升级流程
+    pub completed: u64,
+    /// 中途放弃的升级流程
+    pub failed: u64,
+    /// 服务器没有对应固件
+    pub no_firmware: u64,
+    /// 服务器没有对应配置
+    pub no_config: u64,
+    /// 收到的固件字节数
+    pub bytes: u64,
+    /// 建立的连接数
+    pub connects: u64,
+    /// 主动断开的连接数
+    pub disconnects: u64,
+    /// 模拟丢失的响应数
+    pub lost: u64,
+    /// 超时的请求数
+    pub timeouts: u64,
+}
+
+impl DeviceStats {
+    pub fn record(&mut self, package: &str, elapsed: Duration) {
+        self.latencies
+            .entry(package.to_string())
+            .or_default()
+            .record(elapsed);
+    }
+
+    pub fn requests(&self) -> usize {
+        self.latencies.values().map(Latencies::len).sum()
+    }
+
+    pub fn merge(&mut self, other: DeviceStats) {
+        for (package, latencies) in other.latencies {
+            self.latencies.entry(package).or_default().merge(latencies);
+        }
+        self.completed += other.completed;
+        self.failed += other.failed;
+        self.no_firmware += other.no_firmware;
+        self.no_config += other.no_config;
+        self.bytes += other.bytes;
+        self.connects += other.connects;
+        self.disconnects += other.disconnects;
+        self.lost += other.lost;
+        self.timeouts += other.timeouts;
+    }
+}
+
+/// 整个设备群的测试报告
+#[derive(Debug, Clone)]
+pub struct FleetReport {
+    pub devices: u32,
+    pub elapsed: Duration,
+    pub stats: DeviceStats,
+}
+
+impl FleetReport {
+    /// 固件下载吞吐量，字节/秒
+    pub fn throughput(&self) -> f64 {
+        self.stats.bytes as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+
+    /// 请求速率，个/秒
+    pub fn request_rate(&self) -> f64 {
+        self.stats.requests() as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+impl fmt::Display for FleetReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let stats = &self.stats;
+        let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+
+        writeln!(
+            f,
+            "Devices: {}, elapsed {:.2}s",
+            self.devices,
+            self.elapsed.as_secs_f64()
+        )?;
+        writeln!(
+            f,
+            "Flows: {} completed, {} failed, {} without firmware, {} without config",
+            stats.completed, stats.failed, stats.no_firmware, stats.no_config
+        )?;
+        writeln!(
+            f,
+            "Firmware: {} bytes, {:.1} KiB/s",
+            stats.bytes,
+            self.throughput() / 1024.0
+        )?;
+        writeln!(
+            f,
+            "Requests: {}, {:.1} req/s",
+            stats.requests(),
+            self.request_rate()
+        )?;
+        writeln!(
+            f,
+            "Connections: {} opened, {} dropped, {} responses lost, {} timeouts",
+            stats.connects, stats.disconnects, stats.lost, stats.timeouts
+        )?;
+
+        writeln!(
+            f,
+            "{:<18} {:>8} {:>9} {:>9} {:>9} {:>9}",
+            "Latency (ms)", "count", "p50", "p90", "p99", "max"
+        )?;
+        let mut all = Latencies::default();
+        for latencies in stats.latencies.values() {
+            all.merge(latencies.clone());
+        }
+        let rows = stats
+            .latencies
+            .iter()
+            .map(|(package, latencies)| (package.as_str(), latencies.clone()))
+            .chain(std::iter::once(("All", all)));
+        for (package, mut latencies) in rows {
+            writeln!(
+                f,
+                "{:<18} {:>8} {:>9.2} {:>9.2} {:>9.2} {:>9.2}",
+                package,
+                latencies.len(),
+                ms(latencies.percentile(50.0)),
+                ms(latencies.percentile(90.0)),
+                ms(latencies.percentile(99.0)),
+                ms(latencies.percentile(100.0)),
+            )?;
+        }
+
+        Ok(())
+    }
+}
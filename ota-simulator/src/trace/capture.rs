@@ -0,0 +1,323 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use super::{TraceError, TraceResult};
+
+const PCAP_MAGIC: u32 = 0xA1B2_C3D4;
+const PCAP_MAGIC_NANOS: u32 = 0xA1B2_3C4D;
+const PCAPNG_SHB: u32 = 0x0A0D_0D0A;
+const PCAPNG_BYTE_ORDER: u32 = 0x1A2B_3C4D;
+
+/// 链路层类型
+const LINKTYPE_NULL: u32 = 0;
+const LINKTYPE_ETHERNET: u32 = 1;
+const LINKTYPE_RAW: u32 = 101;
+const LINKTYPE_LINUX_SLL: u32 = 113;
+const LINKTYPE_IPV4: u32 = 228;
+const LINKTYPE_IPV6: u32 = 229;
+const LINKTYPE_LINUX_SLL2: u32 = 276;
+
+/// 抓包文件中的一个 TCP 段
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TcpSegment {
+    /// 抓包时间，unix 纳秒
+    pub time: i64,
+    pub src: SocketAddr,
+    pub dst: SocketAddr,
+    pub seq: u32,
+    pub syn: bool,
+    pub payload: Vec<u8>,
+}
+
+/// 判断是否为 pcap 或 pcapng 文件
+pub fn is_capture(data: &[u8]) -> bool {
+    match data.get(0..4) {
+        Some(magic) => {
+            let le = u32::from_le_bytes([magic[0], magic[1], magic[2], magic[3]]);
+            let be = u32::from_be_bytes([magic[0], magic[1], magic[2], magic[3]]);
+            [le, be].iter().any(|magic| {
+                matches!(*magic, PCAP_MAGIC | PCAP_MAGIC_NANOS) || *magic == PCAPNG_SHB
+            })
+        }
+        None => false,
+    }
+}
+
+/// 读取 pcap / pcapng 中的 TCP 段，其它报文忽略
+pub fn read_capture(data: &[u8]) -> TraceResult<Vec<TcpSegment>> {
+    let packets = match data.get(0..4) {
+        Some(&[0x0A, 0x0D, 0x0D, 0x0A]) => read_pcapng(data)?,
+        _ => read_pcap(data)?,
+    };
+
+    Ok(packets
+        .into_iter()
+        .filter_map(|packet| parse_link(packet.link_type, packet.time, packet.data))
+        .collect())
+}
+
+/// 链路层报文
+struct Packet<'a> {
+    link_type: u32,
+    time: i64,
+    data: &'a [u8],
+}
+
+/// 按字节序读取整数
+#[derive(Clone, Copy)]
+struct Endian {
+    big: bool,
+}
+
+const LITTLE: Endian = Endian { big: false };
+const BIG: Endian = Endian { big: true };
+
+impl Endian {
+    fn u16(&self, data: &[u8], at: usize) -> TraceResult<u16> {
+        let bytes = data
+            .get(at..at + 2)
+            .ok_or_else(|| TraceError::Format("Unexpected end of file".to_string()))?;
+        let bytes = [bytes[0], bytes[1]];
+        Ok(match self.big {
+            true => u16::from_be_bytes(bytes),
+            false => u16::from_le_bytes(bytes),
+        })
+    }
+
+    fn u32(&self, data: &[u8], at: usize) -> TraceResult<u32> {
+        let bytes = data
+            .get(at..at + 4)
+            .ok_or_else(|| TraceError::Format("Unexpected end of file".to_string()))?;
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        Ok(match self.big {
+            true => u32::from_be_bytes(bytes),
+            false => u32::from_le_bytes(bytes),
+        })
+    }
+}
+
+fn read_pcap(data: &[u8]) -> TraceResult<Vec<Packet<'_>>> {
+    let magic = LITTLE.u32(data, 0)?;
+    let (endian, nanos) = match magic {
+        PCAP_MAGIC => (LITTLE, false),
+        PCAP_MAGIC_NANOS => (LITTLE, true),
+        _ => match BIG.u32(data, 0)? {
+            PCAP_MAGIC => (BIG, false),
+            PCAP_MAGIC_NANOS => (BIG, true),
+            _ => return Err(TraceError::Format("Not a pcap file".to_string())),
+        },
+    };
+    let link_type = endian.u32(data, 20)? & 0x0FFF_FFFF;
+
+    let mut packets = Vec::new();
+    let mut pos = 24;
+    while pos + 16 <= data.len() {
+        let seconds = endian.u32(data, pos)? as i64;
+        let fraction = endian.u32(data, pos + 4)? as i64;
+        let captured = endian.u32(data, pos + 8)? as usize;
+        let start = pos + 16;
+        let packet = data
+            .get(start..start + captured)
+            .ok_or_else(|| TraceError::Format(format!("Packet at {} is cut off", pos)))?;
+
+        packets.push(Packet {
+            link_type,
+            time: seconds * 1_000_000_000 + if nanos { fraction } else { fraction * 1000 },
+            data: packet,
+        });
+        pos = start + captured;
+    }
+
+    Ok(packets)
+}
+
+/// pcapng 的接口信息
+struct Interface {
+    link_type: u32,
+    /// if_tsresol，最高位为 0 时单位为 10^-n 秒，为 1 时为 2^-n 秒
+    resolution: u8,
+}
+
+fn read_pcapng(data: &[u8]) -> TraceResult<Vec<Packet<'_>>> {
+    let mut endian = LITTLE;
+    let mut interfaces: Vec<Interface> = Vec::new();
+    let mut packets = Vec::new();
+
+    let mut pos = 0;
+    while pos + 12 <= data.len() {
+        // 每个 Section Header 重新确定字节序
+        if data[pos..pos + 4] == [0x0A, 0x0D, 0x0D, 0x0A] {
+            endian = match LITTLE.u32(data, pos + 8)? {
+                PCAPNG_BYTE_ORDER => LITTLE,
+                _ => BIG,
+            };
+            interfaces.clear();
+        }
+
+        let block_type = endian.u32(data, pos)?;
+        let block_len = endian.u32(data, pos + 4)? as usize;
+        if block_len < 12 || pos + block_len > data.len() {
+            return Err(TraceError::Format(format!("Bad block length at {}", pos)));
+        }
+        let body = &data[pos + 8..pos + block_len - 4];
+
+        match block_type {
+            // Interface Description Block
+            1 => interfaces.push(Interface {
+                link_type: endian.u16(body, 0)? as u32,
+                resolution: interface_resolution(body, endian)?,
+            }),
+            // Enhanced Packet Block
+            6 => {
+                let interface = endian.u32(body, 0)? as usize;
+                let interface = interfaces.get(interface).ok_or_else(|| {
+                    TraceError::Format(format!("Unknown interface {}", interface))
+                })?;
+                let timestamp = ((endian.u32(body, 4)? as u64) << 32) | endian.u32(body, 8)? as u64;
+                let captured = endian.u32(body, 12)? as usize;
+                let packet = body
+                    .get(20..20 + captured)
+                    .ok_or_else(|| TraceError::Format(format!("Packet at {} is cut off", pos)))?;
+
+                packets.push(Packet {
+                    link_type: interface.link_type,
+                    time: to_nanos(timestamp, interface.resolution),
+                    data: packet,
+                });
+            }
+            // Simple Packet Block，没有时间戳
+            3 => {
+                let interface = interfaces
+                    .first()
+                    .ok_or_else(|| TraceError::Format("Unknown interface 0".to_string()))?;
+                let captured = (endian.u32(body, 0)? as usize).min(body.len().saturating_sub(4));
+                packets.push(Packet {
+                    link_type: interface.link_type,
+                    time: 0,
+                    data: &body[4..4 + captured],
+                });
+            }
+            _ => {}
+        }
+
+        pos += block_len;
+    }
+
+    Ok(packets)
+}
+
+/// 从 if_tsresol 选项取时间戳单位，默认微秒
+fn interface_resolution(body: &[u8], endian: Endian) -> TraceResult<u8> {
+    let mut pos = 8;
+    while pos + 4 <= body.len() {
+        let code = endian.u16(body, pos)?;
+        let len = endian.u16(body, pos + 2)? as usize;
+        match code {
+            0 => break,
+            9 if len >= 1 => return Ok(*body.get(pos + 4).unwrap_or(&6)),
+            _ => {}
+        }
+        pos += 4 + len.div_ceil(4) * 4;
+    }
+
+    Ok(6)
+}
+
+/// 时间戳换算为纳秒
+fn to_nanos(timestamp: u64, resolution: u8) -> i64 {
+    let exponent = (resolution & 0x7F) as u32;
+    let base: u128 = if resolution & 0x80 == 0 { 10 } else { 2 };
+    let nanos = match base.checked_pow(exponent) {
+        Some(units) => timestamp as u128 * 1_000_000_000 / units,
+        None => 0,
+    };
+    nanos as i64
+}
+
+/// 解析链路层、IP 和 TCP 头
+fn parse_link(link_type: u32, time: i64, data: &[u8]) -> Option<TcpSegment> {
+    let (ether_type, ip) = match link_type {
+        LINKTYPE_ETHERNET => {
+            let mut ether_type = u16::from_be_bytes([*data.get(12)?, *data.get(13)?]);
+            let mut offset = 14;
+            // VLAN
+            while ether_type == 0x8100 || ether_type == 0x88A8 {
+                ether_type = u16::from_be_bytes([*data.get(offset + 2)?, *data.get(offset + 3)?]);
+                offset += 4;
+            }
+            (Some(ether_type), data.get(offset..)?)
+        }
+        LINKTYPE_LINUX_SLL => (
+            Some(u16::from_be_bytes([*data.get(14)?, *data.get(15)?])),
+            data.get(16..)?,
+        ),
+        LINKTYPE_LINUX_SLL2 => (
+            Some(u16::from_be_bytes([*data.first()?, *data.get(1)?])),
+            data.get(20..)?,
+        ),
+        LINKTYPE_NULL => (None, data.get(4..)?),
+        LINKTYPE_RAW | LINKTYPE_IPV4 | LINKTYPE_IPV6 => (None, data),
+        _ => return None,
+    };
+
+    match ether_type {
+        Some(0x0800) | Some(0x86DD) | None => parse_ip(time, ip),
+        _ => None,
+    }
+}
+
+fn parse_ip(time: i64, data: &[u8]) -> Option<TcpSegment> {
+    let (src, dst, tcp) = match data.first()? >> 4 {
+        4 => {
+            let header_len = ((data[0] & 0x0F) as usize) * 4;
+            let total_len = u16::from_be_bytes([*data.get(2)?, *data.get(3)?]) as usize;
+            // 只处理 TCP，不处理分片
+            let fragment = u16::from_be_bytes([*data.get(6)?, *data.get(7)?]) & 0x1FFF;
+            if *data.get(9)? != 6 || fragment != 0 {
+                return None;
+            }
+            let src: [u8; 4] = data.get(12..16)?.try_into().ok()?;
+            let dst: [u8; 4] = data.get(16..20)?.try_into().ok()?;
+            // 头部长度超出数据时丢弃该包
+            let end = total_len.min(data.len());
+            if header_len < 20 || header_len > end {
+                return None;
+            }
+            (
+                IpAddr::V4(Ipv4Addr::from(src)),
+                IpAddr::V4(Ipv4Addr::from(dst)),
+                data.get(header_len..end)?,
+            )
+        }
+        6 => {
+            // 只处理没有扩展头的 TCP
+            if *data.get(6)? != 6 {
+                return None;
+            }
+            let payload_len = u16::from_be_bytes([*data.get(4)?, *data.get(5)?]) as usize;
+            let src: [u8; 16] = data.get(8..24)?.try_into().ok()?;
+            let dst: [u8; 16] = data.get(24..40)?.try_into().ok()?;
+            let end = (40 + payload_len).min(data.len());
+            (
+                IpAddr::V6(Ipv6Addr::from(src)),
+                IpAddr::V6(Ipv6Addr::from(dst)),
+                data.get(40..end)?,
+            )
+        }
+        _ => return None,
+    };
+
+    let src_port = u16::from_be_bytes([*tcp.first()?, *tcp.get(1)?]);
+    let dst_port = u16::from_be_bytes([*tcp.get(2)?, *tcp.get(3)?]);
+    let seq = u32::from_be_bytes(tcp.get(4..8)?.try_into().ok()?);
+    let header_len = ((tcp.get(12)? >> 4) as usize) * 4;
+    let flags = *tcp.get(13)?;
+
+    Some(TcpSegment {
+        time,
+        src: SocketAddr::new(src, src_port),
+        dst: SocketAddr::new(dst, dst_port),
+        seq,
+        syn: flags & 0x02 != 0,
+        payload: tcp.get(header_len..)?.to_vec(),
+    })
+}
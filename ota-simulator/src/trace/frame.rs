@@ -0,0 +1,379 @@
+use chrono::DateTime;
+use ota_server::{
+    package::common::{
+        parse_command_ack, parse_config_query, parse_config_report, parse_time_sync,
+    },
+    ErrorCode, PackageType,
+};
+use serde::{ser::SerializeMap, Serialize, Serializer};
+
+use crate::conformance::{crc8, hex};
+
+/// 帧的方向，由包类型判断
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    DeviceToServer,
+    ServerToDevice,
+    Unknown,
+}
+
+/// 帧的检查结果
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FrameStatus {
+    Ok,
+    CrcError,
+    /// 流在帧结束前中断
+    Truncated,
+    /// 找不到 AA 55 包头的字节
+    Garbage,
+}
+
+/// 流中切出的一段字节
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawFrame<'a> {
+    pub offset: usize,
+    pub bytes: &'a [u8],
+    pub status: FrameStatus,
+}
+
+/// 按 AA 55 包头切分字节流
+/// - 错误帧：AA 55 错误码 CRC
+/// - 其它帧：AA 55 类型 长度(2) 数据 CRC
+///
+/// CRC 错误时如果帧内还有包头，从该包头重新同步
+pub fn split_frames(data: &[u8]) -> Vec<RawFrame<'_>> {
+    let mut frames = Vec::new();
+    let mut garbage: Option<usize> = None;
+    let mut pos = 0;
+
+    while pos < data.len() {
+        if !data[pos..].starts_with(&[0xAA, 0x55]) {
+            garbage.get_or_insert(pos);
+            pos += 1;
+            continue;
+        }
+        flush_garbage(data, &mut frames, &mut garbage, pos);
+
+        let frame_len = match data.get(pos + 2) {
+            Some(&code) if ErrorCode::from_u8(code).is_some() => Some(4),
+            Some(_) => data
+                .get(pos + 3..pos + 5)
+                .map(|len| 5 + u16::from_be_bytes([len[0], len[1]]) as usize + 1),
+            None => None,
+        };
+        let end = match frame_len {
+            Some(frame_len) if pos + frame_len <= data.len() => pos + frame_len,
+            _ => data.len(),
+        };
+        let frame = &data[pos..end];
+
+        let mut status = match frame_len {
+            Some(frame_len) if frame.len() == frame_len => FrameStatus::Ok,
+            _ => FrameStatus::Truncated,
+        };
+        if status == FrameStatus::Ok && !crc_ok(frame) {
+            status = FrameStatus::CrcError;
+        }
+
+        // 帧不完整时，内部的包头是下一帧的开始
+        let resync = match status {
+            FrameStatus::Ok => None,
+            _ => frame[2..]
+                .windows(2)
+                .position(|header| header == [0xAA, 0x55])
+                .map(|index| pos + 2 + index),
+        };
+        let end = match resync {
+            Some(next) => {
+                status = FrameStatus::Truncated;
+                next
+            }
+            None => end,
+        };
+
+        frames.push(RawFrame {
+            offset: pos,
+            bytes: &data[pos..end],
+            status,
+        });
+        pos = end;
+    }
+    flush_garbage(data, &mut frames, &mut garbage, data.len());
+
+    frames
+}
+
+/// 包头之前的字节
+fn flush_garbage<'a>(
+    data: &'a [u8],
+    frames: &mut Vec<RawFrame<'a>>,
+    garbage: &mut Option<usize>,
+    end: usize,
+) {
+    if let Some(start) = garbage.take() {
+        frames.push(RawFrame {
+            offset: start,
+            bytes: &data[start..end],
+            status: FrameStatus::Garbage,
+        });
+    }
+}
+
+fn crc_ok(frame: &[u8]) -> bool {
+    let (data, crc) = frame.split_at(frame.len() - 1);
+    crc8(data) == crc[0]
+}
+
+/// 解码后的帧
+#[derive(Serialize, Debug, Clone)]
+pub struct DecodedFrame {
+    /// 抓包时间，16进制文本没有时间
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time: Option<String>,
+    /// 所在的流，如 `10.0.0.2:50000 -> 10.0.0.1:9999`
+    pub stream: String,
+    /// 在流中的偏移
+    pub offset: usize,
+    pub direction: Direction,
+    pub name: String,
+    pub status: FrameStatus,
+    #[serde(serialize_with = "ordered_fields")]
+    pub fields: Vec<(&'static str, String)>,
+    pub raw: String,
+}
+
+/// 按解码顺序输出字段
+fn ordered_fields<S: Serializer>(
+    fields: &[(&'static str, String)],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let mut map = serializer.serialize_map(Some(fields.len()))?;
+    for (name, value) in fields {
+        map.serialize_entry(name, value)?;
+    }
+    map.end()
+}
+
+impl DecodedFrame {
+    /// 解码一帧，字段不全时只解出能解的部分
+    pub fn decode(stream: &str, raw: &RawFrame, time: Option<i64>) -> Self {
+        let bytes = raw.bytes;
+        let (direction, name, fields) = match raw.status {
+            FrameStatus::Garbage => (
+                Direction::Unknown,
+                "Unparsed".to_string(),
+                vec![("len", bytes.len().to_string())],
+            ),
+            status => decode_fields(bytes, status != FrameStatus::Truncated),
+        };
+
+        DecodedFrame {
+            time: time.map(|nanos| {
+                DateTime::from_timestamp_nanos(nanos)
+                    .format("%Y-%m-%d %H:%M:%S%.6f")
+                    .to_string()
+            }),
+            stream: stream.to_string(),
+            offset: raw.offset,
+            direction,
+            name,
+            status: raw.status,
+            fields,
+            raw: hex(bytes),
+        }
+    }
+}
+
+fn decode_fields(frame: &[u8], complete: bool) -> (Direction, String, Vec<(&'static str, String)>) {
+    let Some(&type_code) = frame.get(2) else {
+        return (Direction::Unknown, "Header".to_string(), Vec::new());
+    };
+
+    if let Some(code) = ErrorCode::from_u8(type_code) {
+        return (
+            Direction::ServerToDevice,
+            format!("Error({:?})", code),
+            vec![("code", format!("0x{:02X}", type_code))],
+        );
+    }
+
+    // 不完整的帧没有CRC
+    let payload = match frame.len() {
+        len if complete && len > 6 => &frame[5..len - 1],
+        len if !complete && len > 5 => &frame[5..],
+        _ => &[][..],
+    };
+    let mut fields = Vec::new();
+
+    if let Some(package_type) = PackageType::from_u8(type_code) {
+        match package_type {
+            PackageType::FirmwareQuery => {
+                firmware_fields(&payload[..payload.len().min(2)], &mut fields);
+                push_u16(payload, 2, "slice", &mut fields);
+            }
+            PackageType::FirmwareDownload => {
+                firmware_fields(payload, &mut fields);
+                push_u16(payload, 5, "index", &mut fields);
+                push_u16(payload, 7, "slice", &mut fields);
+                if let Some(&flags) = payload.get(9) {
+                    fields.push(("proof", (flags & 0x01 != 0).to_string()));
+                }
+            }
+            PackageType::DownloadEnd => {
+                firmware_fields(payload, &mut fields);
+                if let Some(device_id) = read_u64(payload, 5) {
+                    fields.push(("device_id", format!("{:08X}", device_id)));
+                }
+                if let Some(sn) = payload.get(13..17) {
+                    let sn = u32::from_be_bytes([sn[0], sn[1], sn[2], sn[3]]);
+                    fields.push(("sn", sn.to_string()));
+                }
+                if let Some(&result) = payload.get(17) {
+                    let result = match result {
+                        0xA1 => "success".to_string(),
+                        other => format!("failed(0x{:02X})", other),
+                    };
+                    fields.push(("result", result));
+                }
+            }
+            PackageType::QueryConfig => {
+                let query = parse_config_query(frame);
+                if let Some(device_id) = query.device_id {
+                    fields.push(("device_id", device_id));
+                }
+                if let Some(group_id) = query.group_id {
+                    fields.push(("group_id", group_id.to_string()));
+                }
+            }
+            PackageType::ReportConfig => {
+                if let Some(report) = parse_config_report(frame) {
+                    fields.push(("device_id", report.device_id));
+                    fields.push(("schema", report.schema_id.to_string()));
+                    fields.push(("data_len", report.data.len().to_string()));
+                }
+            }
+            PackageType::Command => match parse_command_ack(frame) {
+                Some(ack) => {
+                    fields.push(("command_id", ack.command_id.to_string()));
+                    fields.push(("result", ack.result.to_string()));
+                }
+                None => fields.push(("ack", "poll".to_string())),
+            },
+            PackageType::UpdateAvailable => {}
+            PackageType::TimeSync => {
+                if let Some(version) = parse_time_sync(frame) {
+                    fields.push(("protocol", format!("{:?}", version)));
+                }
+            }
+        }
+        return (
+            Direction::DeviceToServer,
+            format!("{:?}", package_type),
+            fields,
+        );
+    }
+
+    if let Some(package_type) = PackageType::from_response(type_code) {
+        match package_type {
+            PackageType::FirmwareQuery | PackageType::UpdateAvailable => {
+                firmware_fields(payload, &mut fields);
+                if let Some(size) = payload.get(5..9) {
+                    let size = u32::from_be_bytes([size[0], size[1], size[2], size[3]]);
+                    fields.push(("size", size.to_string()));
+                }
+                push_u16(payload, 9, "slice", &mut fields);
+                if let Some(root) = payload.get(11..43) {
+                    fields.push(("merkle_root", hex(root)));
+                }
+            }
+            PackageType::FirmwareDownload => {
+                firmware_fields(payload, &mut fields);
+                push_u16(payload, 5, "index", &mut fields);
+                if payload.len() > 7 {
+                    fields.push(("data_len", (payload.len() - 7).to_string()));
+                }
+            }
+            PackageType::ReportConfig => {
+                push_u16(payload, 0, "schema", &mut fields);
+                if let Some(&changed) = payload.get(2) {
+                    fields.push(("changed", changed.to_string()));
+                }
+            }
+            PackageType::Command => match payload.get(0..9) {
+                Some(command) => {
+                    let id = u32::from_be_bytes([command[0], command[1], command[2], command[3]]);
+                    let param =
+                        u32::from_be_bytes([command[5], command[6], command[7], command[8]]);
+                    fields.push(("command_id", id.to_string()));
+                    fields.push(("command", format!("0x{:02X}", command[4])));
+                    fields.push(("param", param.to_string()));
+                }
+                None => fields.push(("command", "none".to_string())),
+            },
+            PackageType::TimeSync => {
+                if let Some(timestamp) = payload.get(0..4) {
+                    let timestamp = u32::from_be_bytes([
+                        timestamp[0],
+                        timestamp[1],
+                        timestamp[2],
+                        timestamp[3],
+                    ]);
+                    let time = DateTime::from_timestamp(timestamp as i64, 0)
+                        .map(|time| time.format("%Y-%m-%d %H:%M:%S").to_string())
+                        .unwrap_or_default();
+                    fields.push(("utc", time));
+                }
+                if let Some(offset) = payload.get(4..6) {
+                    let offset = i16::from_be_bytes([offset[0], offset[1]]);
+                    fields.push(("tz_offset", offset.to_string()));
+                }
+            }
+            PackageType::QueryConfig | PackageType::DownloadEnd => {}
+        }
+        fields.push(("payload_len", payload.len().to_string()));
+        return (
+            Direction::ServerToDevice,
+            format!("{:?}Response", package_type),
+            fields,
+        );
+    }
+
+    (
+        Direction::Unknown,
+        format!("Unknown(0x{:02X})", type_code),
+        fields,
+    )
+}
+
+/// 固件代号(2) + 版本(3)
+fn firmware_fields(payload: &[u8], fields: &mut Vec<(&'static str, String)>) {
+    if let Some(code) = payload.get(0..2) {
+        fields.push(("code", format!("0x{:02X}{:02X}", code[0], code[1])));
+    }
+    if let Some(version) = payload.get(2..5) {
+        fields.push((
+            "version",
+            format!("{}.{}.{}", version[0], version[1], version[2]),
+        ));
+    }
+}
+
+fn push_u16(
+    payload: &[u8],
+    at: usize,
+    name: &'static str,
+    fields: &mut Vec<(&'static str, String)>,
+) {
+    if let Some(value) = payload.get(at..at + 2) {
+        fields.push((name, u16::from_be_bytes([value[0], value[1]]).to_string()));
+    }
+}
+
+fn read_u64(payload: &[u8], at: usize) -> Option<u64> {
+    let bytes = payload.get(at..at + 8)?;
+    let mut value = [0u8; 8];
+    value.copy_from_slice(bytes);
+    Some(u64::from_be_bytes(value))
+}
@@ -0,0 +1,215 @@
+use std::{collections::HashMap, net::SocketAddr};
+use thiserror::Error;
+
+pub mod capture;
+pub mod frame;
+
+use capture::{is_capture, read_capture, TcpSegment};
+use frame::{split_frames, DecodedFrame};
+
+#[derive(Error, Debug)]
+pub enum TraceError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Format error: {0}")]
+    Format(String),
+}
+
+pub type TraceResult<T> = Result<T, TraceError>;
+
+/// 一个方向上按顺序拼好的字节流
+#[derive(Debug, Clone, Default)]
+pub struct Stream {
+    pub name: String,
+    pub data: Vec<u8>,
+    marks: Vec<Mark>,
+    /// 抓包中缺失的字节数
+    pub missing: u64,
+}
+
+/// 从 `offset` 开始的字节来自第 `order` 个包（或第几行）
+#[derive(Debug, Clone, Copy)]
+struct Mark {
+    offset: usize,
+    order: usize,
+    time: Option<i64>,
+}
+
+impl Stream {
+    fn new(name: String) -> Self {
+        Stream {
+            name,
+            ..Default::default()
+        }
+    }
+
+    fn append(&mut self, bytes: &[u8], order: usize, time: Option<i64>) {
+        if bytes.is_empty() {
+            return;
+        }
+        self.marks.push(Mark {
+            offset: self.data.len(),
+            order,
+            time,
+        });
+        self.data.extend_from_slice(bytes);
+    }
+
+    /// 偏移所在的包
+    fn mark(&self, offset: usize) -> Option<Mark> {
+        let index = self.marks.partition_point(|mark| mark.offset <= offset);
+        index.checked_sub(1).map(|index| self.marks[index])
+    }
+}
+
+/// 按 TCP 序号重组每个方向的字节流，去掉重传的部分
+pub fn reassemble(segments: &[TcpSegment]) -> Vec<Stream> {
+    struct Flow<'a> {
+        src: SocketAddr,
+        dst: SocketAddr,
+        base: Option<u32>,
+        segments: Vec<(usize, &'a TcpSegment)>,
+    }
+
+    let mut flows: Vec<Flow> = Vec::new();
+    let mut index: HashMap<(SocketAddr, SocketAddr), usize> = HashMap::new();
+    for (order, segment) in segments.iter().enumerate() {
+        let flow = *index.entry((segment.src, segment.dst)).or_insert_with(|| {
+            flows.push(Flow {
+                src: segment.src,
+                dst: segment.dst,
+                base: None,
+                segments: Vec::new(),
+            });
+            flows.len() - 1
+        });
+        let flow = &mut flows[flow];
+
+        // SYN 占一个序号，没有抓到 SYN 时从第一个段开始
+        if segment.syn {
+            flow.base = Some(segment.seq.wrapping_add(1));
+        } else if !segment.payload.is_empty() {
+            flow.base.get_or_insert(segment.seq);
+            flow.segments.push((order, segment));
+        }
+    }
+
+    flows
+        .into_iter()
+        .map(|flow| {
+            let mut stream = Stream::new(format!("{} -> {}", flow.src, flow.dst));
+            let base = flow.base.unwrap_or_default();
+
+            // 序号在起点之前的段（一般是抓包开始前的重传）丢弃
+            let mut segments: Vec<(u32, usize, &TcpSegment)> = flow
+                .segments
+                .into_iter()
+                .map(|(order, segment)| (segment.seq.wrapping_sub(base), order, segment))
+                .filter(|(start, _, _)| *start < 1 << 31)
+                .collect();
+            segments.sort_by_key(|(start, order, _)| (*start, *order));
+
+            let mut next: u64 = 0;
+            for (start, order, segment) in segments {
+                let start = start as u64;
+                let end = start + segment.payload.len() as u64;
+                if end <= next {
+                    continue;
+                }
+                if start > next {
+                    stream.missing += start - next;
+                    next = start;
+                }
+                let skip = (next - start) as usize;
+                stream.append(&segment.payload[skip..], order, Some(segment.time));
+                next = end;
+            }
+
+            stream
+        })
+        .filter(|stream| !stream.data.is_empty())
+        .collect()
+}
+
+/// 解析16进制文本，如串口日志
+/// - 每行可带偏移前缀，如 `00000010:`，兼容 xxd 和 hexdump -C 的输出
+/// - 带 `TX` / `RX`（或 `>` / `<`）标记的行分成两个流，设备发送为 TX
+/// - 其它不是16进制字节的内容忽略
+pub fn parse_hex_dump(text: &str) -> Vec<Stream> {
+    let mut streams = [
+        Stream::new("hex".to_string()),
+        Stream::new("hex TX".to_string()),
+        Stream::new("hex RX".to_string()),
+    ];
+
+    for (line_no, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default();
+        let mut target = 0;
+        let mut bytes = Vec::new();
+
+        let tokens: Vec<&str> = line
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|token| !token.is_empty())
+            .collect();
+
+        for (i, token) in tokens.iter().enumerate() {
+            match token.trim_end_matches(':').to_ascii_uppercase().as_str() {
+                "TX" | ">" | "->" => target = 1,
+                "RX" | "<" | "<-" => target = 2,
+                // 行首的偏移，如 `00000010:`（xxd）或 `00000010`（hexdump -C）
+                _ if i == 0 && tokens.len() > 1 && (token.ends_with(':') || token.len() >= 6) => {}
+                _ => {
+                    let token = token.trim_start_matches("0x").trim_start_matches("0X");
+                    if token.len() % 2 == 0 && token.chars().all(|c| c.is_ascii_hexdigit()) {
+                        bytes.extend(
+                            (0..token.len())
+                                .step_by(2)
+                                .filter_map(|at| u8::from_str_radix(&token[at..at + 2], 16).ok()),
+                        );
+                    }
+                }
+            }
+        }
+
+        streams[target].append(&bytes, line_no, None);
+    }
+
+    streams
+        .into_iter()
+        .filter(|stream| !stream.data.is_empty())
+        .collect()
+}
+
+/// 读取抓包或16进制文本，解码所有帧，按抓包顺序排列
+pub fn decode(data: &[u8]) -> TraceResult<Vec<DecodedFrame>> {
+    let streams = match is_capture(data) {
+        true => reassemble(&read_capture(data)?),
+        false => {
+            let text = std::str::from_utf8(data).map_err(|_| {
+                TraceError::Format("Neither a pcap/pcapng capture nor hex text".to_string())
+            })?;
+            parse_hex_dump(text)
+        }
+    };
+
+    Ok(decode_streams(&streams))
+}
+
+/// 解码多个流，按帧第一个字节所在的包排序
+pub fn decode_streams(streams: &[Stream]) -> Vec<DecodedFrame> {
+    let mut frames: Vec<((usize, usize, usize), DecodedFrame)> = Vec::new();
+    for (index, stream) in streams.iter().enumerate() {
+        for raw in split_frames(&stream.data) {
+            let mark = stream.mark(raw.offset);
+            let order = mark.map(|mark| mark.order).unwrap_or_default();
+            let time = mark.and_then(|mark| mark.time);
+            frames.push((
+                (order, index, raw.offset),
+                DecodedFrame::decode(&stream.name, &raw, time),
+            ));
+        }
+    }
+
+    frames.sort_by_key(|(key, _)| *key);
+    frames.into_iter().map(|(_, frame)| frame).collect()
+}
@@ -0,0 +1,111 @@
+#[cfg(test)]
+mod tests {
+    use ota_server::{server::OtaServer, source::dir::DirSource, ErrorCode};
+    use ota_simulator::conformance::{compare, error_frame, frame, Firmware, Spec};
+    use std::{fs, path::PathBuf, sync::Arc, time::Duration};
+
+    /// 在本地目录上启动 ota-server，目录中放一个参考固件
+    async fn start_server(name: &str, size: usize) -> (String, PathBuf) {
+        let path = std::env::temp_dir().join(format!("ota-conf-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        let firmware = path.join("1987-0.3.1.bin");
+        let data: Vec<u8> = (0..size).map(|i| (i * 7) as u8).collect();
+        fs::write(&firmware, data).unwrap();
+        fs::write(path.join("1987-0.2.0.bin"), [0u8; 10]).unwrap();
+
+        let server = OtaServer::builder()
+            .bind("127.0.0.1:0")
+            .backend(Arc::new(DirSource::new(&path)))
+            .build()
+            .await
+            .unwrap();
+        tokio::spawn({
+            let server = server.clone();
+            async move { server.run().await }
+        });
+
+        let addr = server.local_addr().to_string();
+        (addr, firmware)
+    }
+
+    fn spec(server: String, firmware: PathBuf, slice_size: u16) -> Spec {
+        Spec {
+            server,
+            firmware: Firmware::load(firmware).unwrap(),
+            slice_size,
+            missing_code: 0xFFFF,
+            device_id: 0xC0FFEE00,
+            timeout: Duration::from_secs(2),
+            silence: Duration::from_millis(100),
+            clock_skew: Duration::from_secs(60),
+        }
+    }
+
+    #[test]
+    fn spec_frames() {
+        // 与线上设备抓包一致
+        assert_eq!(
+            error_frame(ErrorCode::NoFirmwareFound),
+            [0xAA, 0x55, 0xF2, 0xDC]
+        );
+        assert_eq!(frame(0x59, &[]), [0xAA, 0x55, 0x59, 0x00, 0x00, 0x22]);
+
+        assert!(compare(&[1, 2, 3], &[1, 2, 3]).is_ok());
+        let diff = compare(&[0xAA, 0x55, 0x5E], &[0xAA, 0x55, 0xF2]).unwrap_err();
+        assert!(diff.contains("byte 2"), "{}", diff);
+        assert!(diff.contains("expected [5E]"), "{}", diff);
+        let diff = compare(&[0xAA, 0x55, 0x5E], &[0xAA, 0x55]).unwrap_err();
+        assert!(diff.contains("3 bytes expected, 2 received"), "{}", diff);
+    }
+
+    #[test]
+    fn firmware_file_name() {
+        assert!(Firmware::load("/tmp/firmware.bin").is_err());
+    }
+
+    #[tokio::test]
+    async fn server_conforms() {
+        // 最后一片不满
+        let (server, firmware) = start_server("short", 1300).await;
+        let report = spec(server, firmware, 512).run().await;
+        assert_eq!(report.failed(), 0, "{}", report);
+        assert_eq!(report.cases.len(), 24);
+
+        let last = report
+            .cases
+            .iter()
+            .find(|case| case.name == "download_last_slice")
+            .unwrap();
+        assert_eq!(last.detail, "index 2, 276 bytes");
+    }
+
+    #[tokio::test]
+    async fn server_conforms_exact_slices() {
+        // 固件大小是切片大小的整数倍
+        let (server, firmware) = start_server("exact", 1024).await;
+        let report = spec(server, firmware, 256).run().await;
+        assert_eq!(report.failed(), 0, "{}", report);
+    }
+
+    #[tokio::test]
+    async fn wrong_reference_firmware_fails() {
+        let (server, _) = start_server("wrong", 1300).await;
+
+        // 参考固件与服务器上的不同
+        let path = std::env::temp_dir().join(format!("ota-conf-ref-{}", std::process::id()));
+        fs::create_dir_all(&path).unwrap();
+        let firmware = path.join("1987-0.3.1.bin");
+        fs::write(&firmware, vec![0xEEu8; 1300]).unwrap();
+
+        let report = spec(server, firmware, 512).run().await;
+        let failed: Vec<_> = report
+            .cases
+            .iter()
+            .filter(|case| !case.passed)
+            .map(|case| case.name)
+            .collect();
+        assert_eq!(failed, vec!["download_first_slice", "download_last_slice"]);
+        assert!(report.to_string().contains("FAIL"));
+    }
+}
@@ -0,0 +1,304 @@
+#[cfg(test)]
+mod tests {
+    use ota_server::ErrorCode;
+    use ota_simulator::{
+        conformance::{error_frame, frame},
+        trace::{
+            decode,
+            frame::{split_frames, Direction, FrameStatus},
+        },
+    };
+
+    const DEVICE: ([u8; 4], u16) = ([10, 0, 0, 2], 50000);
+    const SERVER: ([u8; 4], u16) = ([10, 0, 0, 1], 9999);
+
+    /// Ethernet + IPv4 + TCP
+    fn tcp_packet(
+        src: ([u8; 4], u16),
+        dst: ([u8; 4], u16),
+        seq: u32,
+        syn: bool,
+        payload: &[u8],
+    ) -> Vec<u8> {
+        let mut packet = vec![0u8; 12];
+        packet.extend_from_slice(&[0x08, 0x00]);
+
+        let total_len = (20 + 20 + payload.len()) as u16;
+        packet.extend_from_slice(&[0x45, 0x00]);
+        packet.extend_from_slice(&total_len.to_be_bytes());
+        packet.extend_from_slice(&[0x00, 0x00, 0x40, 0x00, 0x40, 0x06, 0x00, 0x00]);
+        packet.extend_from_slice(&src.0);
+        packet.extend_from_slice(&dst.0);
+
+        packet.extend_from_slice(&src.1.to_be_bytes());
+        packet.extend_from_slice(&dst.1.to_be_bytes());
+        packet.extend_from_slice(&seq.to_be_bytes());
+        packet.extend_from_slice(&[0x00; 4]);
+        packet.extend_from_slice(&[0x50, if syn { 0x02 } else { 0x18 }]);
+        packet.extend_from_slice(&[0xFF, 0xFF, 0x00, 0x00, 0x00, 0x00]);
+        packet.extend_from_slice(payload);
+        packet
+    }
+
+    fn pcap(packets: &[(u32, Vec<u8>)]) -> Vec<u8> {
+        let mut file = Vec::new();
+        file.extend_from_slice(&0xA1B2C3D4u32.to_le_bytes());
+        file.extend_from_slice(&[0x02, 0x00, 0x04, 0x00]);
+        file.extend_from_slice(&[0x00; 8]);
+        file.extend_from_slice(&65535u32.to_le_bytes());
+        file.extend_from_slice(&1u32.to_le_bytes());
+        for (micros, packet) in packets {
+            file.extend_from_slice(&1_700_000_000u32.to_le_bytes());
+            file.extend_from_slice(&micros.to_le_bytes());
+            file.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+            file.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+            file.extend_from_slice(packet);
+        }
+        file
+    }
+
+    fn pcapng_block(block_type: u32, body: &[u8]) -> Vec<u8> {
+        let mut body = body.to_vec();
+        body.resize(body.len().div_ceil(4) * 4, 0);
+        let len = (body.len() + 12) as u32;
+
+        let mut block = block_type.to_le_bytes().to_vec();
+        block.extend_from_slice(&len.to_le_bytes());
+        block.extend_from_slice(&body);
+        block.extend_from_slice(&len.to_le_bytes());
+        block
+    }
+
+    /// 纳秒时间戳的 pcapng
+    fn pcapng(packets: &[(u64, Vec<u8>)]) -> Vec<u8> {
+        let mut shb = 0x1A2B3C4Du32.to_le_bytes().to_vec();
+        shb.extend_from_slice(&[0x01, 0x00, 0x00, 0x00]);
+        shb.extend_from_slice(&u64::MAX.to_le_bytes());
+        let mut file = pcapng_block(0x0A0D0D0A, &shb);
+
+        // if_tsresol = 9
+        let mut idb = vec![0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+        idb.extend_from_slice(&[0x09, 0x00, 0x01, 0x00, 0x09, 0x00, 0x00, 0x00]);
+        idb.extend_from_slice(&[0x00; 4]);
+        file.extend(pcapng_block(1, &idb));
+
+        for (nanos, packet) in packets {
+            let mut epb = 0u32.to_le_bytes().to_vec();
+            epb.extend_from_slice(&((nanos >> 32) as u32).to_le_bytes());
+            epb.extend_from_slice(&(*nanos as u32).to_le_bytes());
+            epb.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+            epb.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+            epb.extend_from_slice(packet);
+            file.extend(pcapng_block(6, &epb));
+        }
+        file
+    }
+
+    /// 固件下载请求：代号 0x1987，版本 0.3.0，第 2 片，切片 512
+    fn download_request() -> Vec<u8> {
+        frame(
+            0xA2,
+            &[0x19, 0x87, 0x00, 0x03, 0x00, 0x00, 0x02, 0x02, 0x00],
+        )
+    }
+
+    fn download_response() -> Vec<u8> {
+        let mut payload = vec![0x19, 0x87, 0x00, 0x03, 0x00, 0x00, 0x02];
+        payload.extend_from_slice(&[0xAA, 0x55, 0xF2, 0x00]);
+        frame(0x5D, &payload)
+    }
+
+    #[test]
+    fn split_and_resync() {
+        let mut data = vec![0x00, 0x01];
+        data.extend(download_request());
+        // 缺少 CRC 的帧，后面紧跟完整的帧
+        data.extend(&download_request()[..8]);
+        data.extend(error_frame(ErrorCode::CrcError));
+        let mut bad_crc = frame(0xA4, &[]);
+        *bad_crc.last_mut().unwrap() ^= 0x01;
+        data.extend(&bad_crc);
+        data.extend(&download_request()[..6]);
+
+        let status: Vec<FrameStatus> = split_frames(&data).iter().map(|raw| raw.status).collect();
+        assert_eq!(
+            status,
+            vec![
+                FrameStatus::Garbage,
+                FrameStatus::Ok,
+                FrameStatus::Truncated,
+                FrameStatus::Ok,
+                FrameStatus::CrcError,
+                FrameStatus::Truncated,
+            ]
+        );
+    }
+
+    #[test]
+    fn decode_pcap_streams() {
+        let request = download_request();
+        let response = download_response();
+        let mut bad_crc = frame(0xA1, &[0x19, 0x87]);
+        *bad_crc.last_mut().unwrap() ^= 0xFF;
+
+        let packets = vec![
+            (0, tcp_packet(DEVICE, SERVER, 1000, true, &[])),
+            (1, tcp_packet(SERVER, DEVICE, 5000, true, &[])),
+            // 请求分成两段，后一段先到，再加一个重传
+            (
+                2,
+                tcp_packet(DEVICE, SERVER, 1001 + 6, false, &request[6..]),
+            ),
+            (3, tcp_packet(DEVICE, SERVER, 1001, false, &request[..6])),
+            (4, tcp_packet(DEVICE, SERVER, 1001, false, &request[..6])),
+            (5, tcp_packet(SERVER, DEVICE, 5001, false, &response)),
+            (
+                6,
+                tcp_packet(DEVICE, SERVER, 1001 + request.len() as u32, false, &bad_crc),
+            ),
+            (
+                7,
+                tcp_packet(
+                    SERVER,
+                    DEVICE,
+                    5001 + response.len() as u32,
+                    false,
+                    &error_frame(ErrorCode::CrcError),
+                ),
+            ),
+        ];
+        let frames = decode(&pcap(&packets)).unwrap();
+        assert_eq!(frames.len(), 4);
+
+        let request = &frames[0];
+        assert_eq!(request.name, "FirmwareDownload");
+        assert_eq!(request.stream, "10.0.0.2:50000 -> 10.0.0.1:9999");
+        assert_eq!(request.direction, Direction::DeviceToServer);
+        assert_eq!(request.status, FrameStatus::Ok);
+        assert_eq!(
+            request.fields,
+            vec![
+                ("code", "0x1987".to_string()),
+                ("version", "0.3.0".to_string()),
+                ("index", "2".to_string()),
+                ("slice", "512".to_string()),
+            ]
+        );
+        // 帧从第二个到达的段开始
+        assert_eq!(request.time.as_deref(), Some("2023-11-14 22:13:20.000003"));
+
+        // 数据中的 AA 55 不影响切分
+        let response = &frames[1];
+        assert_eq!(response.name, "FirmwareDownloadResponse");
+        assert_eq!(response.direction, Direction::ServerToDevice);
+        assert!(response.fields.contains(&("data_len", "4".to_string())));
+
+        assert_eq!(frames[2].name, "FirmwareQuery");
+        assert_eq!(frames[2].status, FrameStatus::CrcError);
+        assert_eq!(frames[3].name, "Error(CrcError)");
+
+        let json = serde_json::to_value(&frames[0]).unwrap();
+        assert_eq!(json["fields"]["index"], "2");
+        assert_eq!(json["status"], "ok");
+        assert_eq!(json["direction"], "device_to_server");
+    }
+
+    #[test]
+    fn skip_truncated_ip_header() {
+        // IPv4 头部长度 60 字节，但包只截取了 24 字节
+        let mut truncated = tcp_packet(DEVICE, SERVER, 1, false, &[]);
+        truncated[14] = 0x4F;
+        truncated.truncate(14 + 24);
+
+        let packets = vec![
+            (0, truncated),
+            (
+                1,
+                tcp_packet(DEVICE, SERVER, 1, false, &frame(0xA8, &[0x02])),
+            ),
+        ];
+        let frames = decode(&pcap(&packets)).unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].name, "TimeSync");
+    }
+
+    #[test]
+    fn decode_pcapng() {
+        let time_sync = frame(0x57, &[0x65, 0x53, 0xF1, 0x00, 0x01, 0xE0]);
+        let packets = vec![
+            (
+                1_700_000_000_123_456_789,
+                tcp_packet(DEVICE, SERVER, 1, false, &frame(0xA8, &[0x02])),
+            ),
+            (
+                1_700_000_000_223_456_789,
+                tcp_packet(SERVER, DEVICE, 1, false, &time_sync),
+            ),
+        ];
+        let frames = decode(&pcapng(&packets)).unwrap();
+        assert_eq!(frames.len(), 2);
+
+        assert_eq!(frames[0].name, "TimeSync");
+        assert_eq!(frames[0].fields, vec![("protocol", "V2".to_string())]);
+        assert_eq!(
+            frames[0].time.as_deref(),
+            Some("2023-11-14 22:13:20.123456")
+        );
+        assert_eq!(frames[1].name, "TimeSyncResponse");
+        assert_eq!(
+            frames[1].fields[0],
+            ("utc", "2023-11-14 22:13:20".to_string())
+        );
+        assert_eq!(frames[1].fields[1], ("tz_offset", "480".to_string()));
+    }
+
+    #[test]
+    fn decode_hex_dump() {
+        let end = frame(
+            0xA3,
+            &[
+                0x19, 0x87, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0xC0, 0xFF, 0xEE, 0x00, 0x00,
+                0x00, 0x00, 0x07, 0xA1,
+            ],
+        );
+        let hex = |data: &[u8]| {
+            data.iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+
+        let text = format!(
+            "# 串口日志\n[10:00:01] TX: {}\n[10:00:01] RX: {}\n00000000: {} {}\n{}\n",
+            hex(&frame(0xA1, &[0x19, 0x87])),
+            hex(&error_frame(ErrorCode::NoFirmwareFound)),
+            hex(&end[..10]),
+            hex(&end[10..]),
+            "ff ee",
+        );
+        let frames = decode(text.as_bytes()).unwrap();
+
+        let names: Vec<&str> = frames.iter().map(|frame| frame.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "FirmwareQuery",
+                "Error(NoFirmwareFound)",
+                "DownloadEnd",
+                "Unparsed"
+            ]
+        );
+        assert_eq!(frames[0].stream, "hex TX");
+        assert_eq!(frames[1].stream, "hex RX");
+        assert_eq!(
+            frames[2].fields[2..],
+            [
+                ("device_id", "C0FFEE00".to_string()),
+                ("sn", "7".to_string()),
+                ("result", "success".to_string()),
+            ]
+        );
+        assert_eq!(frames[3].status, FrameStatus::Garbage);
+        assert!(frames.iter().all(|frame| frame.time.is_none()));
+    }
+}
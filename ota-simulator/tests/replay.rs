@@ -0,0 +1,170 @@
+#[cfg(test)]
+mod tests {
+    use ota_server::{
+        package::rx_package::gen_config_query_package,
+        record::{read_recording, RecordDirection, RecordLine, Recorder},
+        server::OtaServer,
+        source::dir::DirSource,
+    };
+    use ota_simulator::{
+        conformance::{frame, hex},
+        device::read_raw_frame,
+        replay::{Recording, Replay},
+    };
+    use std::{fs, path::PathBuf, sync::Arc, time::Duration};
+    use tokio::{io::AsyncWriteExt, net::TcpStream};
+
+    /// 开启录制的 ota-server，返回监听地址和录制目录
+    async fn start_server(name: &str) -> (String, PathBuf) {
+        let path = std::env::temp_dir().join(format!("ota-replay-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        let data: Vec<u8> = (0..1000).map(|i| (i * 3) as u8).collect();
+        fs::write(path.join("1987-0.3.0.bin"), data).unwrap();
+
+        let record_dir = path.join("recordings");
+        let recorder = Recorder::new(&record_dir, &["C0FFEE00".to_string()]);
+        let server = OtaServer::builder()
+            .bind("127.0.0.1:0")
+            .backend(Arc::new(DirSource::new(&path)))
+            .recorder(Some(Arc::new(recorder)))
+            .build()
+            .await
+            .unwrap();
+        tokio::spawn({
+            let server = server.clone();
+            async move { server.run().await }
+        });
+
+        let addr = server.local_addr().to_string();
+        (addr, record_dir)
+    }
+
+    /// 设备会话：查询固件、下载第一片、查询配置、时间同步
+    async fn device_session(server: &str, device_id: u64) {
+        let requests = vec![
+            frame(0xA1, &[0x19, 0x87]),
+            frame(
+                0xA2,
+                &[0x19, 0x87, 0x00, 0x03, 0x00, 0x00, 0x00, 0x01, 0x00],
+            ),
+            gen_config_query_package(Some(device_id), None),
+            frame(0xA8, &[]),
+        ];
+
+        let mut socket = TcpStream::connect(server).await.unwrap();
+        for request in requests {
+            socket.write_all(&request).await.unwrap();
+            read_raw_frame(&mut socket).await.unwrap();
+        }
+    }
+
+    async fn recorded(dir: &PathBuf) -> Vec<RecordLine> {
+        // 连接关闭后录制文件在后台写完
+        for _ in 0..50 {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            if let Some(entry) = fs::read_dir(dir)
+                .ok()
+                .and_then(|mut entries| entries.next())
+            {
+                let lines = read_recording(&entry.unwrap().path()).await.unwrap();
+                if lines.len() >= 9 {
+                    return lines;
+                }
+            }
+        }
+        panic!("No recording in {}", dir.display());
+    }
+
+    fn replay(server: &str) -> Replay {
+        Replay {
+            server: server.to_string(),
+            timeout: Duration::from_secs(2),
+            silence: Duration::from_millis(200),
+            realtime: false,
+            strict_time: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn record_and_replay() {
+        let (server, record_dir) = start_server("ok").await;
+        device_session(&server, 0x1234).await;
+        device_session(&server, 0xC0FFEE00).await;
+
+        // 只录制了指定的设备
+        let lines = recorded(&record_dir).await;
+        assert_eq!(fs::read_dir(&record_dir).unwrap().count(), 1);
+        let recording = Recording::from_lines(&lines);
+        assert_eq!(recording.device_id.as_deref(), Some("C0FFEE00"));
+        assert_eq!(recording.exchanges.len(), 4);
+        assert!(recording
+            .exchanges
+            .iter()
+            .all(|exchange| exchange.responses.len() == 1));
+        assert_eq!(recording.exchanges[1].responses[0].len(), 256 + 13);
+
+        // 时间同步的时间戳不参与比较
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        let report = replay(&server).run(&recording).await;
+        assert!(report.is_success(), "{}", report);
+        assert_eq!(report.passed(), 4);
+        assert_eq!(report.exchanges[1].request, "FirmwareDownload index=0");
+
+        let report = Replay {
+            strict_time: true,
+            ..replay(&server)
+        }
+        .run(&recording)
+        .await;
+        assert_eq!(report.failed(), 1);
+    }
+
+    #[tokio::test]
+    async fn replay_reports_differences() {
+        let (server, _) = start_server("diff").await;
+        let response = frame(
+            0x5E,
+            &[0x19, 0x87, 0x00, 0x03, 0x00, 0x00, 0x00, 0x03, 0xE7],
+        );
+        let line = |direction, data: &[u8]| RecordLine::Frame {
+            elapsed_us: 0,
+            direction,
+            data: hex(data).replace(' ', ""),
+        };
+        let lines = vec![
+            // 推送不参与比较
+            line(RecordDirection::Out, &frame(0x58, &[0x19, 0x87])),
+            line(RecordDirection::In, &frame(0xA1, &[0x19, 0x87])),
+            line(RecordDirection::Out, &response),
+            line(RecordDirection::Out, &frame(0x58, &[0x19, 0x87])),
+            // 下载结束没有回复
+            line(
+                RecordDirection::In,
+                &frame(
+                    0xA3,
+                    &[
+                        0x19, 0x87, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0xC0, 0xFF, 0xEE,
+                        0x00, 0x00, 0x00, 0x00, 0x01, 0xA1,
+                    ],
+                ),
+            ),
+            line(RecordDirection::In, &frame(0xA1, &[0x19, 0x87])),
+        ];
+        let recording = Recording::from_lines(&lines);
+        assert_eq!(recording.exchanges.len(), 3);
+        assert_eq!(recording.exchanges[0].responses, vec![response]);
+        assert!(recording.exchanges[1].responses.is_empty());
+
+        // 固件大小为 1000，录制中为 999；最后一个请求的回复多出来
+        let report = replay(&server).run(&recording).await;
+        assert!(!report.is_success());
+        assert_eq!(report.passed(), 1);
+        assert!(report.exchanges[0]
+            .detail
+            .contains("first difference at byte 13"));
+        assert!(report.exchanges[2]
+            .detail
+            .starts_with("Unexpected response"));
+    }
+}
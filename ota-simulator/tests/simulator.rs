@@ -0,0 +1,178 @@
+#[cfg(test)]
+mod tests {
+    use ota_server::{server::OtaServer, source::dir::DirSource};
+    use ota_simulator::{
+        conformance::frame,
+        device::DeviceProfile,
+        fleet::Fleet,
+        stats::{DeviceStats, Latencies},
+    };
+    use std::{
+        fs,
+        sync::{atomic::AtomicUsize, Arc},
+        time::Duration,
+    };
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    /// 在本地目录上启动 ota-server，返回监听地址
+    async fn start_server(name: &str, firmware: &[(&str, usize)]) -> String {
+        let path = std::env::temp_dir().join(format!("ota-sim-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        for (file_name, size) in firmware {
+            let data: Vec<u8> = (0..*size).map(|i| i as u8).collect();
+            fs::write(path.join(file_name), data).unwrap();
+        }
+
+        let server = OtaServer::builder()
+            .bind("127.0.0.1:0")
+            .backend(Arc::new(DirSource::new(&path)))
+            .build()
+            .await
+            .unwrap();
+        tokio::spawn({
+            let server = server.clone();
+            async move { server.run().await }
+        });
+
+        server.local_addr().to_string()
+    }
+
+    fn profile(server: String, fw_code: u16) -> DeviceProfile {
+        DeviceProfile {
+            server,
+            rounds: 2,
+            fw_code,
+            slice_size: 512,
+            think_time: Duration::ZERO,
+            think_jitter: Duration::from_millis(2),
+            loss: 0.0,
+            disconnect: 0.0,
+            timeout: Duration::from_millis(300),
+            retries: 10,
+            group_id: None,
+        }
+    }
+
+    fn fleet(devices: u32) -> Fleet {
+        Fleet {
+            devices,
+            ramp_up: Duration::from_millis(50),
+            device_id_base: 0x10000000,
+            seed: 7,
+            finished: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    #[test]
+    fn latency_percentiles() {
+        let mut latencies = Latencies::default();
+        assert_eq!(latencies.percentile(50.0), Duration::ZERO);
+
+        for ms in (1..=100).rev() {
+            latencies.record(Duration::from_millis(ms));
+        }
+        assert_eq!(latencies.percentile(50.0), Duration::from_millis(50));
+        assert_eq!(latencies.percentile(99.0), Duration::from_millis(99));
+        assert_eq!(latencies.percentile(100.0), Duration::from_millis(100));
+        assert_eq!(latencies.percentile(0.0), Duration::from_millis(1));
+
+        let mut stats = DeviceStats::default();
+        stats.record("FirmwareQuery", Duration::from_millis(1));
+        let mut other = DeviceStats::default();
+        other.record("FirmwareQuery", Duration::from_millis(3));
+        other.record("QueryConfig", Duration::from_millis(2));
+        other.completed = 1;
+        stats.merge(other);
+        assert_eq!(stats.requests(), 3);
+        assert_eq!(stats.latencies["FirmwareQuery"].len(), 2);
+        assert_eq!(stats.completed, 1);
+    }
+
+    #[tokio::test]
+    async fn fleet_completes_full_flow() {
+        let server = start_server("flow", &[("1987-0.2.0.bin", 1300)]).await;
+
+        let fleet = fleet(4);
+        let mut report = fleet.run(profile(server, 0x1987)).await;
+        let stats = &mut report.stats;
+
+        assert_eq!(stats.completed, 8);
+        assert_eq!(stats.failed, 0);
+        assert_eq!(stats.bytes, 8 * 1300);
+        // 1300 字节按 512 切成 3 片
+        assert_eq!(stats.latencies["FirmwareDownload"].len(), 8 * 3);
+        assert_eq!(stats.latencies["FirmwareQuery"].len(), 8);
+        // 没有配置文件，服务器回复 F2
+        assert_eq!(stats.no_config, 8);
+        // 下载结束后设备重启，每轮结束时重新连接
+        assert_eq!(stats.connects, 4 * 3);
+        assert_eq!(fleet.finished.load(std::sync::atomic::Ordering::Relaxed), 4);
+        assert!(report.to_string().contains("FirmwareDownload"));
+    }
+
+    #[tokio::test]
+    async fn fleet_recovers_from_loss_and_disconnects() {
+        let server = start_server("faults", &[("1987-0.2.0.bin", 2000)]).await;
+
+        let mut profile = profile(server, 0x1987);
+        profile.slice_size = 256;
+        profile.loss = 0.2;
+        profile.disconnect = 0.2;
+        profile.timeout = Duration::from_millis(50);
+        let report = fleet(3).run(profile).await;
+        let stats = &report.stats;
+
+        assert_eq!(stats.completed, 6);
+        assert_eq!(stats.failed, 0);
+        assert_eq!(stats.bytes, 6 * 2000);
+        assert!(stats.lost > 0);
+        assert!(stats.disconnects > 0);
+        assert!(stats.connects > 12);
+    }
+
+    #[tokio::test]
+    async fn fleet_without_firmware() {
+        let server = start_server("missing", &[("1987-0.2.0.bin", 100)]).await;
+
+        let report = fleet(2).run(profile(server, 0x2000)).await;
+        assert_eq!(report.stats.no_firmware, 4);
+        assert_eq!(report.stats.completed, 0);
+        assert_eq!(report.stats.bytes, 0);
+        assert!(!report.stats.latencies.contains_key("FirmwareDownload"));
+    }
+
+    #[tokio::test]
+    async fn short_slice_response_fails_round() {
+        // 查询正常回复，分片回复缺少分片号
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut request = [0u8; 64];
+                    while let Ok(n) = socket.read(&mut request).await {
+                        let response = match request.get(2) {
+                            _ if n == 0 => break,
+                            Some(0xA1) => frame(0x5E, &[0x19, 0x87, 0, 2, 0, 0, 0, 0x01, 0]),
+                            Some(0xA2) => frame(0x5D, &[0x19, 0x87, 0]),
+                            _ => continue,
+                        };
+                        let _ = socket.write_all(&response).await;
+                    }
+                });
+            }
+        });
+
+        let mut profile = profile(addr, 0x1987);
+        profile.rounds = 1;
+        profile.retries = 2;
+        let report = fleet(1).run(profile).await;
+        assert_eq!(report.stats.failed, 1);
+        assert_eq!(report.stats.completed, 0);
+        assert_eq!(report.stats.bytes, 0);
+    }
+}
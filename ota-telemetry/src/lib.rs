@@ -0,0 +1,132 @@
+//! 日志和链路追踪，ota-server、ota-backend、ota-gateway 共用
+
+use clap::ValueEnum;
+use opentelemetry::{global, trace::TracerProvider as _};
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{propagation::TraceContextPropagator, trace::SdkTracerProvider, Resource};
+use std::collections::HashMap;
+use thiserror::Error;
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer};
+
+/// OTLP/HTTP 的 trace 路径
+const OTLP_TRACES_PATH: &str = "/v1/traces";
+
+/// 日志输出格式
+#[derive(ValueEnum, Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum LogFormat {
+    /// Human readable text
+    #[default]
+    Text,
+    /// One JSON object per line, with the span fields
+    Json,
+}
+
+#[derive(Error, Debug)]
+pub enum TelemetryError {
+    #[error("OTLP exporter error: {0}")]
+    Exporter(#[from] opentelemetry_otlp::ExporterBuildError),
+    #[error("Tracing init error: {0}")]
+    Init(#[from] tracing_subscriber::util::TryInitError),
+}
+
+/// 日志和链路追踪配置
+#[derive(Debug, Clone)]
+pub struct TelemetryOptions<'a> {
+    /// 服务名，OTLP 中的 service.name
+    pub service: &'a str,
+    /// 过滤规则所在的环境变量
+    pub filter_env: &'a str,
+    /// 环境变量未设置时的过滤规则
+    pub default_filter: &'a str,
+    pub format: LogFormat,
+    /// OTLP/HTTP 收集器地址，如 http://localhost:4318，不设置时只输出日志
+    pub otlp_endpoint: Option<&'a str>,
+}
+
+/// 退出前调用 `shutdown` 把未发送的 span 发给收集器
+#[derive(Debug)]
+pub struct Telemetry {
+    provider: Option<SdkTracerProvider>,
+}
+
+impl Telemetry {
+    pub fn shutdown(self) {
+        if let Some(provider) = self.provider {
+            if let Err(e) = provider.shutdown() {
+                eprintln!("OTLP shutdown error: {}", e);
+            }
+        }
+    }
+}
+
+/// 初始化 tracing，`log` 宏的输出一并转到 tracing
+pub fn init_tracing(options: &TelemetryOptions) -> Result<Telemetry, TelemetryError> {
+    let filter = EnvFilter::try_from_env(options.filter_env)
+        .unwrap_or_else(|_| EnvFilter::new(options.default_filter));
+
+    let fmt_layer = match options.format {
+        LogFormat::Text => tracing_subscriber::fmt::layer().boxed(),
+        LogFormat::Json => tracing_subscriber::fmt::layer()
+            .json()
+            .with_current_span(false)
+            .with_span_list(true)
+            .boxed(),
+    };
+
+    let provider = match options.otlp_endpoint {
+        Some(endpoint) => {
+            let endpoint = match endpoint.trim_end_matches('/') {
+                endpoint if endpoint.ends_with(OTLP_TRACES_PATH) => endpoint.to_string(),
+                endpoint => format!("{}{}", endpoint, OTLP_TRACES_PATH),
+            };
+            let exporter = SpanExporter::builder()
+                .with_http()
+                .with_endpoint(endpoint)
+                .build()?;
+            let resource = Resource::builder()
+                .with_service_name(options.service.to_string())
+                .build();
+
+            Some(
+                SdkTracerProvider::builder()
+                    .with_batch_exporter(exporter)
+                    .with_resource(resource)
+                    .build(),
+            )
+        }
+        None => None,
+    };
+
+    let otel_layer = provider.as_ref().map(|provider| {
+        tracing_opentelemetry::layer().with_tracer(provider.tracer(options.service.to_string()))
+    });
+
+    // 按 W3C traceparent 格式传递 trace context
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt_layer)
+        .with(otel_layer)
+        .try_init()?;
+
+    Ok(Telemetry { provider })
+}
+
+/// 当前 span 的 trace context，作为请求头发给其它服务
+pub fn trace_headers() -> HashMap<String, String> {
+    let mut headers = HashMap::new();
+    let context = Span::current().context();
+    global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&context, &mut headers);
+    });
+    headers
+}
+
+/// 从请求头中取出上游的 trace context，作为 span 的父节点
+pub fn link_parent(span: &Span, headers: &HashMap<String, String>) {
+    let parent = global::get_text_map_propagator(|propagator| propagator.extract(headers));
+    let _ = span.set_parent(parent);
+}
@@ -0,0 +1,38 @@
+#[cfg(test)]
+mod tests {
+    use opentelemetry::{
+        global,
+        trace::{TraceContextExt, TracerProvider},
+    };
+    use opentelemetry_sdk::{propagation::TraceContextPropagator, trace::SdkTracerProvider};
+    use ota_telemetry::{link_parent, trace_headers};
+    use tracing::info_span;
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+    use tracing_subscriber::{layer::SubscriberExt, Registry};
+
+    #[test]
+    fn propagate_trace_context() {
+        let provider = SdkTracerProvider::builder().build();
+        let subscriber = Registry::default()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+        global::set_text_map_propagator(TraceContextPropagator::new());
+
+        tracing::subscriber::with_default(subscriber, || {
+            // 不在 span 中时不带请求头
+            assert!(trace_headers().is_empty());
+
+            // ota-server 的连接 span
+            let connection = info_span!("connection");
+            let headers = connection.in_scope(trace_headers);
+            let trace_id = connection.context().span().span_context().trace_id();
+            let traceparent = headers.get("traceparent").unwrap();
+            assert!(traceparent.contains(&trace_id.to_string()));
+
+            // 后端的请求 span 接到同一个 trace 中
+            let request = info_span!("http_request");
+            link_parent(&request, &headers);
+            let context = request.context();
+            assert_eq!(context.span().span_context().trace_id(), trace_id);
+        });
+    }
+}